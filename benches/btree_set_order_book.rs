@@ -1,8 +1,10 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use bid_ask_service::{
     exchanges::Exchange,
     order_book::{
+        bid_changes_best_n,
+        btree_map::{level_key, LevelKey},
         price_level::{ask::Ask, bid::Bid},
         BuySide, Order, SellSide,
     },
@@ -102,18 +104,92 @@ fn bench_update_bid(c: &mut Criterion) {
     });
 }
 
+//Update the quantity of existing bids in place in a keyed map, compared to removing and inserting them in the set.
+//The order books are dropped outside of the measurement, so that only the update is timed
+fn bench_update_bid_keyed(c: &mut Criterion) {
+    let order_book = initialize_bids();
+    let keyed_order_book = order_book
+        .iter()
+        .map(|bid| (level_key(bid), bid.clone()))
+        .collect::<BTreeMap<LevelKey, Bid>>();
+
+    let updated_bid = || {
+        let mut rng = rand::thread_rng();
+        let mut bid = get_random_bid(&order_book);
+        let new_quantity: f64 = rng.gen_range(40.0..60.0);
+        bid.set_quantity(OrderedFloat(new_quantity));
+        bid
+    };
+
+    c.bench_function("update bid remove and insert", |b| {
+        b.iter_batched_ref(
+            || (order_book.clone(), updated_bid()),
+            |(order_book, bid)| order_book.update_bids(black_box(bid.clone()), 50),
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("update bid keyed in place", |b| {
+        b.iter_batched_ref(
+            || (keyed_order_book.clone(), updated_bid()),
+            |(keyed_order_book, bid)| keyed_order_book.update_bids(black_box(bid.clone()), 50),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+//The previous update, which checked that the level exists before removing and inserting it, walking the tree once more per update
+fn contains_remove_insert_bid(order_book: &mut BTreeSet<Bid>, bid: Bid, max_depth: usize) {
+    if bid.get_quantity().0 == 0.0 {
+        order_book.remove(&bid);
+    } else if order_book.len() < max_depth {
+        if order_book.contains(&bid) {
+            order_book.remove(&bid);
+        }
+        order_book.insert(bid);
+    } else if bid > *order_book.iter().next().expect("Could not get worst bid") {
+        order_book.pop_first();
+        order_book.insert(bid);
+    }
+}
+
+//Update the quantity of existing bids by removing and inserting them in a single walk for each, compared to checking that they exist first.
+//The max depth leaves room in the book, so that both updates replace the level rather than comparing against the worst bid
+fn bench_update_bid_traversals(c: &mut Criterion) {
+    let order_book = initialize_bids();
+
+    let updated_bid = || {
+        let mut rng = rand::thread_rng();
+        let mut bid = get_random_bid(&order_book);
+        let new_quantity: f64 = rng.gen_range(40.0..60.0);
+        bid.set_quantity(OrderedFloat(new_quantity));
+        bid
+    };
+
+    c.bench_function("update bid contains check then remove and insert", |b| {
+        b.iter_batched_ref(
+            || (order_book.clone(), updated_bid()),
+            |(order_book, bid)| contains_remove_insert_bid(order_book, black_box(bid.clone()), 100),
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("update bid remove without contains check", |b| {
+        b.iter_batched_ref(
+            || (order_book.clone(), updated_bid()),
+            |(order_book, bid)| order_book.update_bids(black_box(bid.clone()), 100),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
 fn bench_get_best_bid(c: &mut Criterion) {
     let order_book = initialize_bids();
 
     c.bench_function("get best bid", |b| {
         b.iter_batched(
             || order_book.clone(),
-            |order_book| {
-                order_book
-                    .get_best_bid()
-                    .expect("Could not get best bid")
-                    .clone()
-            },
+            |order_book| order_book.get_best_bid().expect("Could not get best bid"),
             BatchSize::SmallInput,
         )
     });
@@ -204,12 +280,7 @@ fn bench_get_best_ask(c: &mut Criterion) {
     c.bench_function("get best ask", |b| {
         b.iter_batched(
             || order_book.clone(),
-            |order_book| {
-                order_book
-                    .get_best_ask()
-                    .expect("Could not get best ask")
-                    .clone()
-            },
+            |order_book| order_book.get_best_ask().expect("Could not get best ask"),
             BatchSize::SmallInput,
         )
     });
@@ -231,17 +302,73 @@ fn bench_get_best_n_asks(c: &mut Criterion) {
     });
 }
 
+//A deep book where updates only churn levels below the best n, ie. quotes being refreshed far from the top of the book
+const DEEP_BOOK_DEPTH: usize = 1000;
+const DEEP_BOOK_BEST_N: usize = 100;
+
+fn initialize_deep_bids() -> BTreeSet<Bid> {
+    let mut order_book = BTreeSet::<Bid>::new();
+    for i in 0..DEEP_BOOK_DEPTH {
+        let bid = Bid::new((DEEP_BOOK_DEPTH - i) as f64, 50.0, Exchange::Binance);
+        order_book.update_bids(bid, DEEP_BOOK_DEPTH);
+    }
+    order_book
+}
+
+fn create_deep_bid() -> Bid {
+    let mut rng = rand::thread_rng();
+    let price: f64 = rng.gen_range(1.0..(DEEP_BOOK_DEPTH - DEEP_BOOK_BEST_N) as f64);
+    let quantity: f64 = rng.gen_range(40.0..60.0);
+    Bid::new(price, quantity, Exchange::Binance)
+}
+
+fn bench_deep_churn_best_n_bids(c: &mut Criterion) {
+    let mut order_book = initialize_deep_bids();
+    c.bench_function("deep churn gather best 'n' bids", |b| {
+        b.iter_batched(
+            create_deep_bid,
+            |bid| {
+                order_book.update_bids(black_box(bid), DEEP_BOOK_DEPTH);
+                black_box(order_book.get_best_n_bids(DEEP_BOOK_BEST_N));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    //Only gather the best n bids when the update can change them
+    let mut order_book = initialize_deep_bids();
+    let worst_best_bid = order_book.get_best_n_bids(DEEP_BOOK_BEST_N)[DEEP_BOOK_BEST_N - 1]
+        .clone()
+        .expect("Could not get worst best bid");
+    c.bench_function("deep churn pre-check best 'n' bids", |b| {
+        b.iter_batched(
+            create_deep_bid,
+            |bid| {
+                let changes_best_n = bid_changes_best_n(&bid, &worst_best_bid, true);
+                order_book.update_bids(black_box(bid), DEEP_BOOK_DEPTH);
+                if changes_best_n {
+                    black_box(order_book.get_best_n_bids(DEEP_BOOK_BEST_N));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
 criterion_group!(
     benches,
     bench_insert_bid,
     bench_remove_bid,
     bench_update_bid,
+    bench_update_bid_keyed,
+    bench_update_bid_traversals,
     bench_get_best_bid,
     bench_get_best_n_bids,
     bench_insert_ask,
     bench_remove_ask,
     bench_update_ask,
     bench_get_best_ask,
-    bench_get_best_n_asks
+    bench_get_best_n_asks,
+    bench_deep_churn_best_n_bids
 );
 criterion_main!(benches);
@@ -0,0 +1,67 @@
+use std::collections::BTreeSet;
+
+use bid_ask_service::{
+    exchanges::Exchange,
+    order_book::{price_level::bid::Bid, tick_set::TickSet, BuySide},
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use rand::Rng;
+
+//Number of decimals that prices are scaled by when represented as integer ticks
+const PRICE_DECIMALS: u32 = 2;
+
+fn create_bid() -> Bid {
+    let mut rng = rand::thread_rng();
+    let price: f64 = rng.gen_range(80.0..600.0);
+    let quantity: f64 = rng.gen_range(40.0..60.0);
+    Bid::new(price, quantity, Exchange::Binance)
+}
+
+fn initialize_bids<B: BuySide>(mut order_book: B) -> B {
+    for _ in 0..50 {
+        order_book.update_bids(create_bid(), 50);
+    }
+    order_book
+}
+
+fn bench_insert_bid(c: &mut Criterion) {
+    let mut ordered_float_bids = initialize_bids(BTreeSet::<Bid>::new());
+    c.bench_function("insert bid ordered float", |b| {
+        b.iter_batched(
+            create_bid,
+            |bid| ordered_float_bids.update_bids(black_box(bid), 50),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut tick_bids = initialize_bids(TickSet::<Bid>::new(PRICE_DECIMALS));
+    c.bench_function("insert bid integer ticks", |b| {
+        b.iter_batched(
+            create_bid,
+            |bid| tick_bids.update_bids(black_box(bid), 50),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_compare_bids(c: &mut Criterion) {
+    c.bench_function("compare bids ordered float", |b| {
+        b.iter_batched(
+            || (create_bid(), create_bid()),
+            |(bid_0, bid_1)| black_box(&bid_0).cmp(black_box(&bid_1)),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let tick_bids = TickSet::<Bid>::new(PRICE_DECIMALS);
+    c.bench_function("compare bids integer ticks", |b| {
+        b.iter_batched(
+            || (tick_bids.level(create_bid()), tick_bids.level(create_bid())),
+            |(level_0, level_1)| black_box(&level_0).cmp(black_box(&level_1)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_insert_bid, bench_compare_bids);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use bid_ask_service::{
+    exchanges::Exchange,
+    order_book::{price_level::bid::Bid, sharded_set::ShardedSet, BuySide},
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::Rng;
+
+//Number of threads updating the order book concurrently, each streaming updates from one exchange
+const NUM_THREADS: usize = 4;
+const UPDATES_PER_THREAD: usize = 1000;
+//The max depth holds every level, so that the updates are not serialized by replacing the worst level across the shards
+const MAX_DEPTH: usize = 5000;
+const NUM_SHARDS: usize = 4;
+
+fn create_updates() -> Vec<Vec<Bid>> {
+    let mut rng = rand::thread_rng();
+    (0..NUM_THREADS)
+        .map(|thread| {
+            let exchange = if thread % 2 == 0 {
+                Exchange::Binance
+            } else {
+                Exchange::Bitstamp
+            };
+
+            (0..UPDATES_PER_THREAD)
+                .map(|_| {
+                    //Prices are quoted in ticks of 0.5, so that the updates replace existing levels as in a live order book
+                    let price = rng.gen_range(160..1200) as f64 * 0.5;
+                    let quantity: f64 = rng.gen_range(0.0..60.0);
+                    Bid::new(price, quantity, exchange.clone())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn bench_concurrent_updates(c: &mut Criterion) {
+    let updates = create_updates();
+
+    let merged_bids = Arc::new(Mutex::new(BTreeSet::<Bid>::new()));
+    c.bench_function("concurrent updates merged lock", |b| {
+        b.iter(|| {
+            std::thread::scope(|s| {
+                for thread_updates in updates.iter() {
+                    let merged_bids = merged_bids.clone();
+                    s.spawn(move || {
+                        for bid in thread_updates.iter() {
+                            merged_bids
+                                .lock()
+                                .unwrap()
+                                .update_bids(bid.clone(), MAX_DEPTH);
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    let sharded_bids = Arc::new(ShardedSet::<Bid>::new(NUM_SHARDS, 1.0));
+    c.bench_function("concurrent updates sharded locks", |b| {
+        b.iter(|| {
+            std::thread::scope(|s| {
+                for thread_updates in updates.iter() {
+                    let sharded_bids = sharded_bids.clone();
+                    s.spawn(move || {
+                        for bid in thread_updates.iter() {
+                            sharded_bids.update(bid.clone(), MAX_DEPTH);
+                        }
+                    });
+                }
+            })
+        })
+    });
+}
+
+criterion_group!(benches, bench_concurrent_updates);
+criterion_main!(benches);
@@ -0,0 +1,102 @@
+use bid_ask_service::{
+    display::render_summary,
+    server::{
+        orderbook_service::{ExchangeQuote, Level, Summary},
+        SUMMARY_SCHEMA_VERSION,
+    },
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use prost::Message;
+use rand::Rng;
+
+//Create a summary with the best n bids and asks from random levels across both exchanges
+fn create_summary(best_n_orders: usize) -> Summary {
+    let mut rng = rand::thread_rng();
+    let mut level = |exchange: &str| Level {
+        exchange: exchange.to_owned(),
+        price: rng.gen_range(80.0..600.0),
+        amount: rng.gen_range(40.0..10000000000.0),
+        age_ms: rng.gen_range(0..10000),
+        ..Default::default()
+    };
+
+    let bids = (0..best_n_orders)
+        .map(|i| level(if i % 2 == 0 { "binance" } else { "bitstamp" }))
+        .collect::<Vec<_>>();
+    let asks = (0..best_n_orders)
+        .map(|i| level(if i % 2 == 0 { "binance" } else { "bitstamp" }))
+        .collect::<Vec<_>>();
+
+    Summary {
+        spread: Some(asks[0].price - bids[0].price),
+        weighted_mid: Some((asks[0].price + bids[0].price) / 2.0),
+        microprice: (asks[0].price + bids[0].price) / 2.0,
+        exchange_quotes: ["binance", "bitstamp"]
+            .iter()
+            .map(|exchange| ExchangeQuote {
+                exchange: exchange.to_string(),
+                bid_price: Some(bids[0].price),
+                ask_price: Some(asks[0].price),
+            })
+            .collect(),
+        bids,
+        asks,
+        total_notional_bids: rng.gen_range(0.0..1e12),
+        total_notional_asks: rng.gen_range(0.0..1e12),
+        heartbeat: false,
+        snapshot: false,
+        schema_version: SUMMARY_SCHEMA_VERSION,
+        stale: false,
+        crossed: false,
+        as_of: Some(1_690_000_000_000_000),
+        imbalance: rng.gen_range(-1.0..1.0),
+    }
+}
+
+fn bench_encode_summary(c: &mut Criterion) {
+    let summary = create_summary(10);
+    let mut group = c.benchmark_group("encode summary");
+
+    group.bench_function("protobuf", |b| {
+        b.iter(|| black_box(&summary).encode_to_vec())
+    });
+
+    group.bench_function("terminal display", |b| {
+        b.iter(|| render_summary("eth/btc", black_box(&summary)))
+    });
+
+    group.finish();
+}
+
+fn bench_broadcast_summary(c: &mut Criterion) {
+    let summary = create_summary(10);
+    let mut group = c.benchmark_group("broadcast summary");
+
+    //Send a summary to n subscribers and receive it from each of them, as the gRPC server does for each connected client
+    for subscribers in [1, 10, 100] {
+        let (summary_tx, _) = tokio::sync::broadcast::channel::<Summary>(1);
+        let mut summary_rxs = (0..subscribers)
+            .map(|_| summary_tx.subscribe())
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subscribers),
+            &subscribers,
+            |b, _| {
+                b.iter(|| {
+                    summary_tx
+                        .send(summary.clone())
+                        .expect("Could not send summary");
+                    for summary_rx in summary_rxs.iter_mut() {
+                        black_box(summary_rx.try_recv().expect("Could not receive summary"));
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode_summary, bench_broadcast_summary);
+criterion_main!(benches);
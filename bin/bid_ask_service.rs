@@ -1,25 +1,93 @@
 use bid_ask_service::{
-    exchanges::Exchange,
+    display::spawn_summary_display,
+    error::{flatten_task_result, BidAskServiceError},
+    events::webhook::spawn_webhook_notifier,
+    exchanges::{
+        credentials::Credentials, feed_quality::FeedQuality, reconnect::ReconnectBackoff, Exchange,
+    },
+    metrics::{spawn_metrics_server, Metrics},
     order_book::{
-        price_level::{ask::Ask, bid::Bid},
-        AggregatedOrderBook,
+        builder::{
+            AggregatedOrderBookBuilder, DEFAULT_BEST_N_ORDERS, DEFAULT_EXCHANGE_STREAM_BUFFER,
+            DEFAULT_ORDER_BOOK_DEPTH, DEFAULT_PRICE_LEVEL_BUFFER,
+        },
+        level_cap::LevelCap,
+        price_level::{ask::Ask, bid::Bid, BackpressurePolicy},
+        ranker::ExchangePreference,
+        AggregatedOrderBook, AllExchangesDownBehavior, BuySide, SellSide,
     },
+    pair::{load_pair_file, parse_pair, parse_pairs},
+    profile::{profile_for, DEFAULT_PROFILE_FREQUENCY},
     server::{
-        self, orderbook_service::orderbook_aggregator_server::OrderbookAggregatorServer,
+        self,
+        orderbook_service::{orderbook_aggregator_server::OrderbookAggregatorServer, Summary},
+        recorder::spawn_recorder,
         spawn_grpc_server,
+        status::ServiceStatus,
+        ws::spawn_ws_server,
     },
+    store::{spawn_summary_archiver, FileSummaryStore},
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use futures::FutureExt;
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, path::Path, sync::Arc, time::Duration};
+use tokio::{sync::broadcast::Sender, task::JoinHandle};
 use tonic::transport::Server;
-use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::fmt::format::Format;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{layer::SubscriberExt, Layer, Registry};
+
+//Directory that the log file is written to
+const LOG_DIRECTORY: &str = "log";
+
+//Backends that published summaries can be archived to
+#[derive(ValueEnum, Clone, Debug)]
+enum SummaryStoreBackend {
+    /// Append each pair's summaries to a file in the summary store path
+    File,
+}
+
+//What the aggregated order books do once every exchange has been down for the all exchanges down timeout
+#[derive(ValueEnum, Clone, Debug)]
+enum AllExchangesDown {
+    /// Republish the last summary flagged as stale, so that clients stop trusting it
+    Stale,
+    /// Stop publishing summaries until an exchange reconnects
+    Stop,
+}
+
+//Protocols that the summaries can be streamed to clients over
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum Transport {
+    /// Stream protobuf summaries through the BookSummary RPC of the gRPC server
+    Grpc,
+    /// Push each summary as JSON to every client connected to a websocket server
+    Ws,
+}
+
+//What the exchanges' price level updates do when the aggregated order book's price level channel is full
+#[derive(ValueEnum, Clone, Debug)]
+enum Backpressure {
+    /// Wait for capacity in the channel, which stops the exchange stream from being read until the aggregated order book catches up
+    Block,
+    /// Merge each exchange's updates into a single pending update until there is capacity in the channel
+    Coalesce,
+    /// Drop updates until there is capacity in the channel, counting each dropped update in the feed quality
+    Drop,
+}
+
+//Formats that logs can be written to the log file in
+#[derive(ValueEnum, Clone, Debug)]
+enum LogFormat {
+    /// Write each event as a compact human readable line
+    Compact,
+    /// Write each event as a line of JSON, with the event's fields as keys of the object
+    Json,
+}
 
 #[derive(Parser, Debug)]
 #[clap(name = "Bid ask service")]
 struct Opts {
-    /// List of exchanges, separated by commas, ie. binance,bitstamp
+    /// List of exchanges, separated by commas, ie. binance,bitstamp,kraken,bybit,okx,gemini
     #[clap(long, short)]
     exchanges: Option<String>,
 
@@ -27,30 +95,151 @@ struct Opts {
     #[clap(long, default_value = "300")]
     summary_buffer: usize,
 
-    /// Trading pair to listen to updates to separated by commas, ie. eth,btc
+    /// Trading pairs to listen to updates to separated by commas, slashes or dashes, with multiple pairs separated by semicolons, ie. eth,btc;eth-usdt
     #[clap(long, short)]
-    pair: String,
+    pair: Option<String>,
+
+    /// Path to a file listing trading pairs to listen to updates to, one pair per line, ie. eth,btc
+    #[clap(long, conflicts_with = "pair")]
+    pair_file: Option<String>,
 
     /// The max depth of the aggregated order book
-    #[clap(long, default_value = "25")]
+    #[clap(long, default_value_t = DEFAULT_ORDER_BOOK_DEPTH)]
     order_book_depth: usize,
 
-    /// The number of best bids and asks to stream via the gRPC server
-    #[clap(long, default_value = "10")]
+    /// Depth of the order book streamed from specific exchanges, separated by commas, ie. binance=100,bitstamp=50. Other exchanges stream the order book depth
+    #[clap(long, value_parser = parse_exchange_depth, value_delimiter = ',')]
+    exchange_order_book_depth: Vec<(Exchange, usize)>,
+
+    /// The max number of price levels held across the aggregated order books of every pair, evicting the least recently updated levels when exceeded
+    #[clap(long)]
+    max_total_levels: Option<usize>,
+
+    /// Skip incoming levels priced further from the mid price than this fraction of the mid price, ie. 0.01 for 1%
+    #[clap(long)]
+    max_distance_from_mid: Option<f64>,
+
+    /// Snap incoming prices to the nearest multiple of this tick size, so that near equal prices from different exchanges are the same level
+    #[clap(long)]
+    price_tick_size: Option<f64>,
+
+    /// Tick size of specific pairs, separated by semicolons, ie. "eth,btc=0.00001;eth,usdt=0.01". Other pairs use the price tick size
+    #[clap(long, value_parser = parse_pair_tick_size, value_delimiter = ';')]
+    pair_price_tick_size: Vec<([String; 2], f64)>,
+
+    /// Compare prices by their number of ticks of this epsilon, so that near equal prices are the same level without snapping the prices
+    #[clap(
+        long,
+        value_parser = parse_price_epsilon,
+        conflicts_with_all = ["price_tick_size", "pair_price_tick_size"]
+    )]
+    price_epsilon: Option<f64>,
+
+    /// The number of best bids and asks tracked by the aggregated order book
+    #[clap(long, visible_alias = "internal-depth", default_value_t = DEFAULT_BEST_N_ORDERS)]
     best_n_orders: usize,
 
+    /// The number of best bids and asks to stream via the gRPC server, defaults to the number of best bids and asks tracked
+    #[clap(long)]
+    emit_levels: Option<usize>,
+
+    /// Merge the levels at the same price from different exchanges into one level in each summary
+    #[clap(long)]
+    merge_price_levels: bool,
+
+    /// Evict price levels that have not been updated by their exchange within this many milliseconds
+    #[clap(long)]
+    level_max_age_ms: Option<u64>,
+
+    /// Rank the most recently updated level first among levels with the same price and quantity
+    #[clap(long)]
+    recency_tie_break: bool,
+
+    /// Exchanges to prefer among levels with the same price and quantity, in order of preference separated by commas, ie. bitstamp,binance
+    #[clap(long, conflicts_with = "recency_tie_break")]
+    preferred_exchanges: Option<String>,
+
+    /// Merge price level updates while the aggregated order book is behind instead of blocking the exchange streams
+    #[clap(long)]
+    coalesce_price_levels: bool,
+
+    /// What price level updates do when the aggregated order book is behind and the price level channel is full
+    #[clap(
+        long,
+        value_enum,
+        default_value = "block",
+        conflicts_with = "coalesce_price_levels"
+    )]
+    backpressure_policy: Backpressure,
+
+    /// Only publish a summary when the spread, best levels or exchange quotes change by more than this amount
+    #[clap(long)]
+    publish_on_change_epsilon: Option<f64>,
+
+    /// Republish the last summary as a heartbeat when no summary has been published within this many milliseconds
+    #[clap(long)]
+    heartbeat_interval_ms: Option<u64>,
+
+    /// Publish at most one summary, holding the latest, every this many milliseconds, 0 publishes a summary for every update
+    #[clap(long, default_value = "0")]
+    publish_interval_ms: u64,
+
+    /// Halve each exchange's weight in the weighted mid for every this many milliseconds since the exchange last sent an update
+    #[clap(long)]
+    mid_decay_half_life_ms: Option<u64>,
+
+    /// Publish a full depth snapshot of the order book in place of the summary for every this many price level updates
+    #[clap(long)]
+    snapshot_interval_updates: Option<usize>,
+
+    /// Re-send the subscription of each exchange that is subscribed with a subscription message every this many seconds, for subscriptions that expire
+    #[clap(long)]
+    resubscribe_interval_secs: Option<u64>,
+
+    /// Re-fetch the REST snapshot of each exchange every this many seconds to resync its order book, 0 disables refreshing
+    #[clap(long, default_value = "0")]
+    snapshot_refresh_interval_secs: u64,
+
+    /// The delay in milliseconds before the first attempt to reconnect an exchange's stream, doubled with each failed attempt
+    #[clap(long, default_value = "250")]
+    reconnect_initial_delay_ms: u64,
+
+    /// The max delay in milliseconds between attempts to reconnect an exchange's stream
+    #[clap(long, default_value = "30000")]
+    reconnect_max_delay_ms: u64,
+
+    /// Reconnect an exchange's stream when no messages are received for this many milliseconds
+    #[clap(long, default_value = "30000")]
+    idle_timeout_ms: u64,
+
+    /// Apply the all exchanges down behavior once every exchange has been disconnected for this many milliseconds
+    #[clap(long)]
+    all_exchanges_down_ms: Option<u64>,
+
+    /// What to do once every exchange has been disconnected for the all exchanges down timeout
+    #[clap(long, value_enum, default_value = "stale")]
+    all_exchanges_down_behavior: AllExchangesDown,
+
     /// Channel buffer size for streaming live order book data from exchanges
-    #[clap(long, default_value = "100")]
+    #[clap(long, default_value_t = DEFAULT_EXCHANGE_STREAM_BUFFER)]
     exchange_stream_buffer: usize,
 
     /// Channel buffer size to pass the price level updates from the exchange module to the aggregated order book
-    #[clap(long, default_value = "100")]
+    #[clap(long, default_value_t = DEFAULT_PRICE_LEVEL_BUFFER)]
     price_level_channel_buffer: usize,
 
-    /// Socket address for the gRPC server
+    /// Socket address for the gRPC server, or the websocket server when streaming over ws
     #[clap(long, default_value = "[::1]:50051")]
     socket_address: String,
 
+    /// Protocol to stream the summaries to clients over
+    #[clap(long, value_enum, default_value = "grpc")]
+    transport: Transport,
+
+    /// Socket address to serve Prometheus metrics at /metrics over HTTP
+    #[clap(long)]
+    metrics_address: Option<String>,
+
     /// Level of logging, options are trace, debug, info, warn, error
     #[clap(long, default_value = "info")]
     level: tracing::metadata::LevelFilter,
@@ -58,56 +247,258 @@ struct Opts {
     /// Path to output file for logging
     #[clap(long, default_value = "output.log")]
     log_file_path: String,
+
+    /// Write logs to stdout as well as the log file
+    #[clap(long)]
+    log_stdout: bool,
+
+    /// Format to write logs in, options are compact or json
+    #[clap(long, value_enum, default_value = "compact")]
+    log_format: LogFormat,
+
+    /// Record update id gaps, resets and duplicates per exchange, reported through the GetFeedQuality RPC
+    #[clap(long)]
+    strict_update_ids: bool,
+
+    /// Render the best bids and asks of each pair to the terminal, refreshing on each update
+    #[clap(long)]
+    display: bool,
+
+    /// Sample the CPU profile of the service with pprof for this many seconds, writing it to the profile path as a flamegraph
+    #[clap(long)]
+    profile: Option<u64>,
+
+    /// Path to output file for the flamegraph svg of the CPU profile
+    #[clap(long, default_value = "profile.svg")]
+    profile_path: String,
+
+    /// List of exchanges to authenticate with credentials from the <EXCHANGE>_API_KEY and <EXCHANGE>_API_SECRET environment variables, separated by commas
+    #[clap(long)]
+    authenticate: Option<String>,
+
+    /// Archive every published summary to the backend
+    #[clap(long, value_enum)]
+    summary_store: Option<SummaryStoreBackend>,
+
+    /// Directory that the file summary store writes each pair's summaries to, as length delimited protobuf messages
+    #[clap(long, default_value = "summaries")]
+    summary_store_path: String,
+
+    /// Path to record every published summary to as newline delimited JSON, for offline analysis. Each pair is recorded to its own file when streaming multiple pairs
+    #[clap(long)]
+    record: Option<String>,
+
+    /// Url to post service events (exchange disconnects/reconnects, stale level evictions) to as JSON
+    #[clap(long)]
+    webhook_url: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    //Parse the command line args, extract the exchanges and the pair
+    //Parse the command line args and initialize tracing before running the service
     let opts = Opts::parse();
-    let _tracing_guard = initialize_tracing(&opts.log_file_path, opts.level)?;
+    let tracing_guards = initialize_tracing(
+        LOG_DIRECTORY,
+        &opts.log_file_path,
+        opts.level,
+        &opts.log_format,
+        opts.log_stdout,
+    )?;
+
+    let result = run(opts).await;
+
+    //Log the error that stopped the service, then drop the guard to flush the buffered logs to the log file before exiting
+    if let Err(e) = &result {
+        tracing::error!("Service exited with error: {e:?}");
+    }
+    drop(tracing_guards);
+
+    result
+}
 
-    let exchanges = if let Some(values) = opts.exchanges {
-        Exchange::parse_exchanges(values)?
+async fn run(opts: Opts) -> eyre::Result<()> {
+    //Extract the exchanges and the pair
+    let exchanges = if let Some(values) = &opts.exchanges {
+        Exchange::parse_exchanges(values.clone())?
     } else {
         Exchange::all_exchanges()
     };
 
-    let tickers = opts
-        .pair
-        .split(',')
-        .map(|s| s.replace(' ', "").to_lowercase())
-        .collect::<Vec<String>>();
+    //Parse the exchange preference before connecting to any exchanges
+    let preferred_exchanges = opts
+        .preferred_exchanges
+        .clone()
+        .map(Exchange::parse_exchanges)
+        .transpose()?;
 
-    let pair: [&str; 2] = [&tickers[0], &tickers[1]];
+    //Collect the pairs to subscribe to, either from the pair arg or from each line of the pair file
+    let pairs = match (&opts.pair, &opts.pair_file) {
+        (Some(pairs), None) => parse_pairs(pairs)?,
+        (None, Some(pair_file)) => {
+            let pair_list = load_pair_file(pair_file)?;
+            for (line_number, line) in pair_list.invalid_lines.iter() {
+                tracing::warn!(
+                    "Skipping invalid pair {line:?} on line {line_number} of {pair_file}"
+                );
+            }
+            pair_list.pairs
+        }
+        _ => eyre::bail!("Either a pair or a pair file must be specified"),
+    };
+
+    //Load the credentials of each exchange to authenticate with
+    let mut credentials = vec![];
+    if let Some(values) = &opts.authenticate {
+        for exchange in Exchange::parse_exchanges(values.clone())? {
+            let exchange_credentials = Credentials::from_env(&exchange)?;
+            credentials.push((exchange, exchange_credentials));
+        }
+    }
+
+    //Validate the socket address before connecting to any exchanges
+    let socket_address = server::parse_socket_address(&opts.socket_address)?;
+    let metrics_address = opts
+        .metrics_address
+        .as_deref()
+        .map(server::parse_socket_address)
+        .transpose()?;
 
-    //Create a new orderbook aggregator service and build the gRPC server
-    let (order_book_aggregator_service, summary_tx) =
-        server::OrderbookAggregatorService::new(opts.summary_buffer);
+    //Create a new orderbook aggregator service, with a summary channel for each pair
+    let (mut order_book_aggregator_service, summary_tx) =
+        server::OrderbookAggregatorService::new([&pairs[0][0], &pairs[0][1]], opts.summary_buffer);
+    let mut summary_txs = vec![summary_tx];
+    for pair in pairs.iter().skip(1) {
+        summary_txs.push(
+            order_book_aggregator_service.add_pair([&pair[0], &pair[1]], opts.summary_buffer),
+        );
+    }
+
+    //Share the feed quality counters between the exchange stream handlers and the gRPC server
+    let feed_quality = opts.strict_update_ids.then(|| Arc::new(FeedQuality::new()));
+    if let Some(feed_quality) = &feed_quality {
+        order_book_aggregator_service =
+            order_book_aggregator_service.with_feed_quality(feed_quality.clone());
+    }
+
+    //Share the service status between the aggregated order books of every pair and the gRPC server, reported through GetStatus
+    let status = Arc::new(ServiceStatus::new());
+    order_book_aggregator_service = order_book_aggregator_service.with_status(status.clone());
+
+    //Clients can request fewer levels through BookSummary, up to the levels published by each aggregated order book
+    order_book_aggregator_service = order_book_aggregator_service
+        .with_max_levels(opts.emit_levels.unwrap_or(opts.best_n_orders));
+
+    //Share the level cap between the aggregated order books of every pair
+    let level_cap = opts
+        .max_total_levels
+        .map(|max_total_levels| Arc::new(LevelCap::new(max_total_levels)));
+
+    //Sample the whole process, covering the exchange streams and the aggregated order books of every pair, writing the flamegraph once the profile duration has elapsed
+    if let Some(profile_secs) = opts.profile {
+        let profile_path = opts.profile_path.clone();
+
+        //The task is not joined with the service tasks, so finishing the profile does not stop the service
+        tokio::spawn(async move {
+            match profile_for(
+                Duration::from_secs(profile_secs),
+                DEFAULT_PROFILE_FREQUENCY,
+                &profile_path,
+            )
+            .await
+            {
+                Ok(_) => tracing::info!("Wrote CPU profile flamegraph to {profile_path}"),
+                Err(err) => tracing::error!("Could not profile the service: {err}"),
+            }
+        });
+    }
+
+    //Share the metrics between the aggregated order books of every pair, labeling each metric by pair
+    let metrics = metrics_address.map(|_| Arc::new(Metrics::new()));
+
+    //Build the gRPC server
     let router = Server::builder().add_service(OrderbookAggregatorServer::new(
         order_book_aggregator_service,
     ));
 
-    //Initialize a new aggregated orderbook, specifying the data structure to represent the bids and asks
-    let aggregated_order_book = AggregatedOrderBook::new(
-        pair,
-        exchanges,
-        BTreeSet::<Bid>::new(),
-        BTreeSet::<Ask>::new(),
-    );
+    //The websocket server streams a single pair, subscribing before the aggregated order book is spawned so that no summary is missed
+    if opts.transport == Transport::Ws && summary_txs.len() > 1 {
+        eyre::bail!("The ws transport only streams a single pair");
+    }
+    let ws_summary_rx = summary_txs[0].subscribe();
+    let record_multiple_pairs = summary_txs.len() > 1;
 
-    tracing::info!("Spawning aggregated order book bid-ask service for {pair:?}");
-    //Spawn the bid ask service from the orderbook and the gRPC server
     let mut join_handles = vec![];
-    join_handles.extend(aggregated_order_book.spawn_bid_ask_service(
-        opts.order_book_depth,
-        opts.exchange_stream_buffer,
-        opts.price_level_channel_buffer,
-        opts.best_n_orders,
-        summary_tx,
-    ));
+    let mut display_summary_rxs = vec![];
+    for (pair, summary_tx) in pairs.iter().zip(summary_txs) {
+        let pair: [&str; 2] = [&pair[0], &pair[1]];
+
+        if opts.display {
+            display_summary_rxs.push((pair.join("/"), summary_tx.subscribe()));
+        }
 
-    tracing::info!("Spawning gRPC server");
-    join_handles.push(spawn_grpc_server(router, opts.socket_address.parse()?));
+        if let Some(summary_store) = &opts.summary_store {
+            //Subscribe before the service is spawned so that the first summaries are archived
+            tracing::info!("Spawning summary archiver for {pair:?}");
+            let summary_rx = summary_tx.subscribe();
+            join_handles.push(match summary_store {
+                SummaryStoreBackend::File => {
+                    let path = Path::new(&opts.summary_store_path)
+                        .join(format!("{}_{}.summaries", pair[0], pair[1]));
+                    spawn_summary_archiver(FileSummaryStore::open(path).await?, summary_rx)
+                }
+            });
+        }
+
+        if let Some(record_path) = &opts.record {
+            //Subscribe before the service is spawned so that the first summaries are recorded
+            let record_path = if record_multiple_pairs {
+                pair_record_path(Path::new(record_path), pair)
+            } else {
+                record_path.into()
+            };
+            tracing::info!("Spawning summary recorder for {pair:?} to {record_path:?}");
+            join_handles.push(spawn_recorder(summary_tx.subscribe(), record_path));
+        }
+
+        //Initialize a new aggregated orderbook, specifying the data structure to represent the bids and asks
+        join_handles.extend(spawn_aggregated_order_book(
+            AggregatedOrderBook::new(
+                pair,
+                exchanges.clone(),
+                BTreeSet::<Bid>::new(),
+                BTreeSet::<Ask>::new(),
+            ),
+            &opts,
+            &credentials,
+            &feed_quality,
+            &status,
+            &level_cap,
+            &metrics,
+            &preferred_exchanges,
+            summary_tx,
+        ));
+    }
+
+    if opts.display {
+        tracing::info!("Spawning summary display");
+        join_handles.push(spawn_summary_display(display_summary_rxs));
+    }
+
+    match opts.transport {
+        Transport::Grpc => {
+            tracing::info!("Spawning gRPC server");
+            join_handles.push(spawn_grpc_server(router, socket_address));
+        }
+        Transport::Ws => {
+            tracing::info!("Spawning ws server");
+            join_handles.push(spawn_ws_server(ws_summary_rx, socket_address));
+        }
+    }
+
+    if let (Some(metrics), Some(metrics_address)) = (metrics, metrics_address) {
+        tracing::info!("Spawning metrics server");
+        join_handles.push(spawn_metrics_server(metrics, metrics_address));
+    }
 
     //Collect all of the join handles and await the futures to handle any errors
     let futures = join_handles
@@ -117,39 +508,473 @@ async fn main() -> eyre::Result<()> {
 
     let (future_result, _, _) = futures::future::select_all(futures).await;
 
-    match future_result {
-        Ok(task_result) => match task_result {
-            Ok(_) => {
-                eyre::bail!("Program exited unexpectedly");
-            }
-            Err(e) => Err(eyre::Report::new(e)),
-        },
-        Err(join_error) => Err(eyre::Report::new(join_error)),
+    //A panicked or cancelled task is reported as a BidAskServiceError, the same as a task that returned an error
+    match flatten_task_result(future_result) {
+        Ok(_) => {
+            eyre::bail!("Program exited unexpectedly");
+        }
+        Err(e) => Err(eyre::Report::new(e)),
     }
 }
 
+//Configure the aggregated order book from the command line args and spawn its bid ask service, returning the join handles of its tasks
+#[allow(clippy::too_many_arguments)]
+fn spawn_aggregated_order_book<B, S>(
+    mut aggregated_order_book: AggregatedOrderBook<B, S>,
+    opts: &Opts,
+    credentials: &[(Exchange, Credentials)],
+    feed_quality: &Option<Arc<FeedQuality>>,
+    status: &Arc<ServiceStatus>,
+    level_cap: &Option<Arc<LevelCap>>,
+    metrics: &Option<Arc<Metrics>>,
+    preferred_exchanges: &Option<Vec<Exchange>>,
+    summary_tx: Sender<Summary>,
+) -> Vec<JoinHandle<Result<(), BidAskServiceError>>>
+where
+    B: BuySide + Send + 'static,
+    S: SellSide + Send + 'static,
+{
+    aggregated_order_book = aggregated_order_book.with_status(status.clone());
+
+    for (exchange, exchange_credentials) in credentials.iter() {
+        aggregated_order_book =
+            aggregated_order_book.with_credentials(exchange.clone(), exchange_credentials.clone());
+    }
+
+    if let Some(resubscribe_interval_secs) = opts.resubscribe_interval_secs {
+        for exchange in aggregated_order_book.exchanges.clone() {
+            aggregated_order_book = aggregated_order_book.with_resubscribe_interval(
+                exchange,
+                Duration::from_secs(resubscribe_interval_secs),
+            );
+        }
+    }
+
+    if opts.snapshot_refresh_interval_secs > 0 {
+        for exchange in aggregated_order_book.exchanges.clone() {
+            aggregated_order_book = aggregated_order_book.with_snapshot_refresh_interval(
+                exchange,
+                Duration::from_secs(opts.snapshot_refresh_interval_secs),
+            );
+        }
+    }
+
+    aggregated_order_book = aggregated_order_book.with_reconnect_backoff(
+        ReconnectBackoff::new(
+            Duration::from_millis(opts.reconnect_initial_delay_ms),
+            Duration::from_millis(opts.reconnect_max_delay_ms),
+        )
+        .with_idle_timeout(Duration::from_millis(opts.idle_timeout_ms)),
+    );
+
+    if let Some(level_max_age_ms) = opts.level_max_age_ms {
+        aggregated_order_book =
+            aggregated_order_book.with_level_max_age(Duration::from_millis(level_max_age_ms));
+    }
+
+    if let Some(feed_quality) = feed_quality {
+        aggregated_order_book = aggregated_order_book.with_feed_quality(feed_quality.clone());
+    }
+
+    if let Some(level_cap) = level_cap {
+        aggregated_order_book = aggregated_order_book.with_level_cap(level_cap.clone());
+    }
+
+    if let Some(max_distance_from_mid) = opts.max_distance_from_mid {
+        aggregated_order_book =
+            aggregated_order_book.with_max_distance_from_mid(max_distance_from_mid);
+    }
+
+    if let Some(emit_levels) = opts.emit_levels {
+        aggregated_order_book = aggregated_order_book.with_emit_levels(emit_levels);
+    }
+
+    if opts.merge_price_levels {
+        aggregated_order_book = aggregated_order_book.with_merge_price_levels();
+    }
+
+    if let Some(metrics) = metrics {
+        aggregated_order_book = aggregated_order_book.with_metrics(metrics.clone());
+    }
+
+    //A tick size set for the pair takes precedence over the tick size of every pair
+    let price_tick_size = opts
+        .pair_price_tick_size
+        .iter()
+        .find(|(pair, _)| *pair == aggregated_order_book.pair)
+        .map(|(_, tick_size)| *tick_size)
+        .or(opts.price_tick_size);
+    if let Some(price_tick_size) = price_tick_size {
+        aggregated_order_book = aggregated_order_book.with_price_tick_size(price_tick_size);
+    }
+
+    if let Some(price_epsilon) = opts.price_epsilon {
+        aggregated_order_book = aggregated_order_book.with_price_epsilon(price_epsilon);
+    }
+
+    if let Some(heartbeat_interval_ms) = opts.heartbeat_interval_ms {
+        aggregated_order_book = aggregated_order_book
+            .with_heartbeat_interval(Duration::from_millis(heartbeat_interval_ms));
+    }
+
+    if opts.publish_interval_ms > 0 {
+        aggregated_order_book = aggregated_order_book
+            .with_publish_interval(Duration::from_millis(opts.publish_interval_ms));
+    }
+
+    if let Some(mid_decay_half_life_ms) = opts.mid_decay_half_life_ms {
+        aggregated_order_book = aggregated_order_book
+            .with_mid_decay_half_life(Duration::from_millis(mid_decay_half_life_ms));
+    }
+
+    if let Some(snapshot_interval_updates) = opts.snapshot_interval_updates {
+        aggregated_order_book =
+            aggregated_order_book.with_snapshot_interval(snapshot_interval_updates);
+    }
+
+    if let Some(all_exchanges_down_ms) = opts.all_exchanges_down_ms {
+        let behavior = match opts.all_exchanges_down_behavior {
+            AllExchangesDown::Stale => AllExchangesDownBehavior::PublishStale,
+            AllExchangesDown::Stop => AllExchangesDownBehavior::StopPublishing,
+        };
+        aggregated_order_book = aggregated_order_book
+            .with_all_exchanges_down(Duration::from_millis(all_exchanges_down_ms), behavior);
+    }
+
+    if opts.recency_tie_break {
+        aggregated_order_book = aggregated_order_book.with_recency_tie_break();
+    }
+
+    if let Some(preferred_exchanges) = preferred_exchanges {
+        aggregated_order_book = aggregated_order_book
+            .with_tie_break(Box::new(ExchangePreference(preferred_exchanges.clone())));
+    }
+
+    if let Some(epsilon) = opts.publish_on_change_epsilon {
+        aggregated_order_book = aggregated_order_book.with_publish_on_change(epsilon);
+    }
+
+    if opts.coalesce_price_levels {
+        aggregated_order_book = aggregated_order_book.with_price_level_coalescing();
+    } else {
+        aggregated_order_book =
+            aggregated_order_book.with_backpressure_policy(match opts.backpressure_policy {
+                Backpressure::Block => BackpressurePolicy::Block,
+                Backpressure::Coalesce => BackpressurePolicy::Coalesce,
+                Backpressure::Drop => BackpressurePolicy::Drop,
+            });
+    }
+
+    let pair = aggregated_order_book.pair.clone();
+    tracing::info!("Spawning aggregated order book bid-ask service for {pair:?}");
+    //Spawn the bid ask service from the orderbook
+    let mut builder = AggregatedOrderBookBuilder::new()
+        .depth(opts.order_book_depth)
+        .stream_buffer(opts.exchange_stream_buffer)
+        .price_level_buffer(opts.price_level_channel_buffer)
+        .best_n(opts.best_n_orders);
+    for (exchange, depth) in opts.exchange_order_book_depth.iter() {
+        builder = builder.exchange_depth(exchange.clone(), *depth);
+    }
+    let mut join_handles = builder.spawn(&aggregated_order_book, summary_tx);
+
+    if let Some(webhook_url) = &opts.webhook_url {
+        tracing::info!("Spawning webhook notifier for {pair:?}");
+        join_handles.push(spawn_webhook_notifier(
+            webhook_url.clone(),
+            aggregated_order_book.subscribe_events(),
+        ));
+    }
+
+    join_handles
+}
+
+//Parse an exchange and the depth of its order book, ie. binance=100
+fn parse_exchange_depth(value: &str) -> Result<(Exchange, usize), String> {
+    let (exchange, depth) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected <exchange>=<depth>, got {value:?}"))?;
+    let exchange = exchange.parse::<Exchange>().map_err(|e| e.to_string())?;
+    let depth = depth.parse::<usize>().map_err(|e| e.to_string())?;
+
+    Ok((exchange, depth))
+}
+
+//Insert the pair into the file name of the record path, ie. summaries.jsonl records eth,btc to summaries_eth_btc.jsonl
+fn pair_record_path(record_path: &Path, pair: [&str; 2]) -> std::path::PathBuf {
+    let file_stem = record_path
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match record_path.extension() {
+        Some(extension) => format!(
+            "{file_stem}_{}_{}.{}",
+            pair[0],
+            pair[1],
+            extension.to_string_lossy()
+        ),
+        None => format!("{file_stem}_{}_{}", pair[0], pair[1]),
+    };
+    record_path.with_file_name(file_name)
+}
+
+//Parse a pair and the tick size to snap its prices to, ie. eth,btc=0.00001
+fn parse_pair_tick_size(value: &str) -> Result<([String; 2], f64), String> {
+    let (pair, tick_size) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected <pair>=<tick size>, got {value:?}"))?;
+    let pair = parse_pair(pair).map_err(|e| e.to_string())?;
+    let tick_size = tick_size.parse::<f64>().map_err(|e| e.to_string())?;
+    if !(tick_size.is_finite() && tick_size > 0.0) {
+        return Err(format!("Tick size must be positive, got {tick_size}"));
+    }
+
+    Ok((pair, tick_size))
+}
+
+//Parse the epsilon that prices are compared within, which must be positive since prices are divided by it
+fn parse_price_epsilon(value: &str) -> Result<f64, String> {
+    let epsilon = value.parse::<f64>().map_err(|e| e.to_string())?;
+    if !(epsilon.is_finite() && epsilon > 0.0) {
+        return Err(format!("Price epsilon must be positive, got {epsilon}"));
+    }
+
+    Ok(epsilon)
+}
+
+//Initialize tracing to write logs to the log file, and to stdout if enabled. The returned guards flush the buffered logs of each writer when dropped,
+//so they must be held until the service exits
 fn initialize_tracing(
+    log_directory: &str,
     file_path: &str,
     level: tracing::metadata::LevelFilter,
-) -> eyre::Result<WorkerGuard> {
-    let file_appender = tracing_appender::rolling::never("log", file_path);
-    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+    log_format: &LogFormat,
+    log_stdout: bool,
+) -> eyre::Result<Vec<WorkerGuard>> {
+    let file_appender = tracing_appender::rolling::never(log_directory, file_path);
+    let (file_writer, file_guard) = tracing_appender::non_blocking(file_appender);
+    let mut writers = vec![file_writer];
+    let mut guards = vec![file_guard];
 
-    let format = Format::default()
+    if log_stdout {
+        let (stdout_writer, stdout_guard) = tracing_appender::non_blocking(std::io::stdout());
+        writers.push(stdout_writer);
+        guards.push(stdout_guard);
+    }
+
+    tracing::subscriber::set_global_default(build_subscriber(writers, level, log_format))?;
+
+    Ok(guards)
+}
+
+//Build a subscriber with a layer for each writer, so that every writer receives each event in the log format
+fn build_subscriber(
+    writers: Vec<NonBlocking>,
+    level: tracing::metadata::LevelFilter,
+    log_format: &LogFormat,
+) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    let layers = writers
+        .into_iter()
+        .map(|writer| fmt_layer(writer, level, log_format))
+        .collect::<Vec<_>>();
+
+    Box::new(Registry::default().with(layers))
+}
+
+//Build a layer writing events in the log format, with the time, level, thread ids and thread names of each event
+fn fmt_layer(
+    writer: NonBlocking,
+    level: tracing::metadata::LevelFilter,
+    log_format: &LogFormat,
+) -> Box<dyn Layer<Registry> + Send + Sync> {
+    let layer = tracing_subscriber::fmt::layer()
         .with_timer(tracing_subscriber::fmt::time::SystemTime)
         .with_ansi(false)
         .with_thread_ids(true)
         .with_thread_names(true)
         .with_level(true)
-        .compact();
+        .with_writer(writer);
 
-    let subscriber = tracing_subscriber::fmt::Subscriber::builder()
-        .with_max_level(level)
-        .event_format(format)
-        .with_writer(non_blocking)
-        .finish();
+    match log_format {
+        LogFormat::Compact => Box::new(layer.compact().with_filter(level)),
+        //The event's fields are flattened into the top level of each line, so that fields like the pair and spread can be queried directly
+        LogFormat::Json => Box::new(layer.json().flatten_event(true).with_filter(level)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
 
-    tracing::subscriber::set_global_default(subscriber)?;
+    use clap::Parser;
 
-    Ok(guard)
+    use crate::{
+        build_subscriber, initialize_tracing, pair_record_path, parse_pair_tick_size,
+        parse_price_epsilon, LogFormat, Opts,
+    };
+
+    #[test]
+    fn test_pair_record_path() {
+        assert_eq!(
+            pair_record_path(Path::new("recordings/summaries.jsonl"), ["eth", "btc"]),
+            PathBuf::from("recordings/summaries_eth_btc.jsonl")
+        );
+        assert_eq!(
+            pair_record_path(Path::new("summaries"), ["eth", "usdt"]),
+            PathBuf::from("summaries_eth_usdt")
+        );
+    }
+
+    #[test]
+    fn test_parse_pair_tick_size() {
+        assert_eq!(
+            parse_pair_tick_size("ETH,btc=0.00001"),
+            Ok((["eth".to_owned(), "btc".to_owned()], 0.00001))
+        );
+        assert_eq!(
+            parse_pair_tick_size("eth/usdt=0.01"),
+            Ok((["eth".to_owned(), "usdt".to_owned()], 0.01))
+        );
+        assert_eq!(
+            parse_pair_tick_size("SOL-usdt=0.001"),
+            Ok((["sol".to_owned(), "usdt".to_owned()], 0.001))
+        );
+
+        for invalid in [
+            "eth,btc",
+            "eth=0.01",
+            "eth,btc=tick",
+            "eth,btc=0",
+            "eth,btc=-0.01",
+        ] {
+            assert!(parse_pair_tick_size(invalid).is_err(), "Parsed {invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_price_epsilon() {
+        assert_eq!(parse_price_epsilon("0.00000001"), Ok(0.00000001));
+        for invalid in ["epsilon", "0", "-0.01", "inf"] {
+            assert!(parse_price_epsilon(invalid).is_err(), "Parsed {invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_price_epsilon_conflicts_with_tick_size() {
+        assert!(Opts::try_parse_from(["bid_ask_service", "--price-epsilon", "0.00000001"]).is_ok());
+        for tick_size in [
+            ["--price-tick-size", "0.00001"],
+            ["--pair-price-tick-size", "eth,btc=0.00001"],
+        ] {
+            let args = ["bid_ask_service", "--price-epsilon", "0.00000001"]
+                .into_iter()
+                .chain(tick_size);
+            assert!(Opts::try_parse_from(args).is_err(), "Parsed {tick_size:?}");
+        }
+    }
+
+    #[test]
+    fn test_logs_flushed_when_guard_dropped() {
+        let log_directory =
+            std::env::temp_dir().join(format!("bid_ask_service_log_{}", std::process::id()));
+        let log_directory = log_directory.to_str().expect("Invalid log directory");
+
+        let tracing_guards = initialize_tracing(
+            log_directory,
+            "test.log",
+            tracing::metadata::LevelFilter::INFO,
+            &LogFormat::Compact,
+            false,
+        )
+        .expect("Could not initialize tracing");
+        tracing::error!("Service exited with error: test error");
+        drop(tracing_guards);
+
+        let logs = std::fs::read_to_string(format!("{log_directory}/test.log"))
+            .expect("Could not read log file");
+        std::fs::remove_dir_all(log_directory).ok();
+
+        assert!(logs.contains("Service exited with error: test error"));
+    }
+
+    #[test]
+    fn test_build_subscriber() {
+        let log_directory =
+            std::env::temp_dir().join(format!("bid_ask_service_log_format_{}", std::process::id()));
+
+        for (log_format, file_path) in [
+            (LogFormat::Compact, "compact.log"),
+            (LogFormat::Json, "json.log"),
+        ] {
+            let file_appender = tracing_appender::rolling::never(&log_directory, file_path);
+            let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+            let subscriber = build_subscriber(
+                vec![non_blocking],
+                tracing::metadata::LevelFilter::INFO,
+                &log_format,
+            );
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!(pair = "eth/btc", spread = 0.5, "Updated best bid and ask");
+            });
+            drop(guard);
+        }
+
+        let compact_logs = std::fs::read_to_string(log_directory.join("compact.log"))
+            .expect("Could not read log file");
+        let json_logs = std::fs::read_to_string(log_directory.join("json.log"))
+            .expect("Could not read log file");
+        std::fs::remove_dir_all(&log_directory).ok();
+
+        assert!(compact_logs.contains("Updated best bid and ask"));
+        assert!(compact_logs.contains("spread=0.5"));
+
+        //Each json line is an object with the event's fields at the top level
+        let line: serde_json::Value =
+            serde_json::from_str(json_logs.lines().next().expect("No json log line"))
+                .expect("Could not parse json log line");
+        assert_eq!(line["message"], "Updated best bid and ask");
+        assert_eq!(line["pair"], "eth/btc");
+        assert_eq!(line["spread"], 0.5);
+        assert_eq!(line["level"], "INFO");
+    }
+
+    #[test]
+    fn test_build_subscriber_writers() {
+        let log_directory = std::env::temp_dir().join(format!(
+            "bid_ask_service_log_writers_{}",
+            std::process::id()
+        ));
+
+        //Each writer receives every event, whether it is the only writer or one of several
+        for file_paths in [vec!["single.log"], vec!["first.log", "second.log"]] {
+            let (writers, guards): (Vec<_>, Vec<_>) = file_paths
+                .iter()
+                .map(|file_path| {
+                    tracing_appender::non_blocking(tracing_appender::rolling::never(
+                        &log_directory,
+                        file_path,
+                    ))
+                })
+                .unzip();
+            let subscriber = build_subscriber(
+                writers,
+                tracing::metadata::LevelFilter::INFO,
+                &LogFormat::Compact,
+            );
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!("Logged to {} writers", file_paths.len());
+                tracing::debug!("Filtered by level");
+            });
+            drop(guards);
+
+            for file_path in file_paths.iter() {
+                let logs = std::fs::read_to_string(log_directory.join(file_path))
+                    .expect("Could not read log file");
+                assert!(logs.contains(&format!("Logged to {} writers", file_paths.len())));
+                assert!(!logs.contains("Filtered by level"));
+            }
+        }
+
+        std::fs::remove_dir_all(&log_directory).ok();
+    }
 }
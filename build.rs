@@ -1,4 +1,7 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/orderbook.proto")?;
+    //Derive Serialize on the generated messages, so that summaries can be streamed as JSON by the websocket server
+    tonic_build::configure()
+        .type_attribute(".", "#[derive(serde_derive::Serialize)]")
+        .compile(&["proto/orderbook.proto"], &["proto"])?;
     Ok(())
 }
@@ -0,0 +1,8 @@
+#![no_main]
+
+use bid_ask_service::exchanges::bitstamp::stream::parse_order_book_snapshot;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_order_book_snapshot(data);
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use bid_ask_service::exchanges::bybit::stream::parse_message;
+use libfuzzer_sys::fuzz_target;
+
+//Websocket text messages are always valid utf8, so only utf8 input reaches the parser
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = std::str::from_utf8(data) {
+        let _ = parse_message(message);
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use bid_ask_service::exchanges::exchange_utils;
+use libfuzzer_sys::fuzz_target;
+use serde_derive::Deserialize;
+
+//Exercises the string to number visitors shared by the exchange message types
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Levels {
+    #[serde(deserialize_with = "exchange_utils::convert_array_items_to_f64")]
+    levels: Vec<[f64; 2]>,
+    #[serde(deserialize_with = "exchange_utils::convert_from_string_to_u64")]
+    timestamp: u64,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json::from_slice::<Levels>(data);
+});
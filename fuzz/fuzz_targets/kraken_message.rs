@@ -0,0 +1,12 @@
+#![no_main]
+
+use bid_ask_service::exchanges::kraken::stream::parse_message;
+use libfuzzer_sys::fuzz_target;
+
+//Websocket text messages are always valid utf8, so only utf8 input reaches the parser. Levels of any length must be rejected
+//by the slice matching of the book payloads without panicking
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = std::str::from_utf8(data) {
+        let _ = parse_message(message);
+    }
+});
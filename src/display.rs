@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use tokio::{sync::broadcast::Receiver, task::JoinHandle};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt, StreamMap};
+
+use crate::{
+    error::BidAskServiceError,
+    server::orderbook_service::{Level, Summary},
+};
+
+//ANSI escape codes to clear the terminal and move the cursor to the top left, so that each render refreshes in place
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+//Render the best bids and asks of a summary side by side, with the spread above them. The spread is missing while the order book is warming
+pub fn render_summary(pair: &str, summary: &Summary) -> String {
+    let mut rendered = match summary.spread {
+        Some(spread) => format!("{pair} spread: {spread:.8}\n"),
+        None => format!("{pair} spread: -\n"),
+    };
+    rendered.push_str(&format!(
+        "{:<10} {:>14} {:>14} | {:<14} {:<14} {}\n",
+        "EXCHANGE", "AMOUNT", "BID", "ASK", "AMOUNT", "EXCHANGE"
+    ));
+
+    for i in 0..summary.bids.len().max(summary.asks.len()) {
+        let bid = match summary.bids.get(i) {
+            Some(Level {
+                exchange,
+                price,
+                amount,
+                ..
+            }) => format!("{exchange:<10} {amount:>14.8} {price:>14.8}"),
+            None => format!("{:<10} {:>14} {:>14}", "", "", ""),
+        };
+
+        let ask = match summary.asks.get(i) {
+            Some(Level {
+                exchange,
+                price,
+                amount,
+                ..
+            }) => format!("{price:<14.8} {amount:<14.8} {exchange}"),
+            None => String::new(),
+        };
+
+        rendered.push_str(format!("{bid} | {ask}").trim_end());
+        rendered.push('\n');
+    }
+
+    rendered
+}
+
+//Spawns a task that renders the latest summary of each pair to stdout, refreshing in place on each update
+pub fn spawn_summary_display(
+    summary_rxs: Vec<(String, Receiver<Summary>)>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let mut summary_streams = StreamMap::new();
+        for (pair, summary_rx) in summary_rxs {
+            summary_streams.insert(pair, BroadcastStream::new(summary_rx));
+        }
+
+        //Keep the latest summary for each pair so that every pair is rendered on each refresh
+        let mut latest_summaries = BTreeMap::new();
+        while let Some((pair, summary)) = summary_streams.next().await {
+            //A lagged display only needs the next summary, so lagged errors are skipped
+            if let Ok(summary) = summary {
+                latest_summaries.insert(pair, summary);
+
+                let mut rendered = CLEAR_SCREEN.to_owned();
+                for (pair, summary) in latest_summaries.iter() {
+                    rendered.push_str(&render_summary(pair, summary));
+                    rendered.push('\n');
+                }
+
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(rendered.as_bytes()).ok();
+                stdout.flush().ok();
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        display::render_summary,
+        server::orderbook_service::{Level, Summary},
+    };
+
+    #[test]
+    fn test_render_summary() {
+        let level = |exchange: &str, price: f64, amount: f64| Level {
+            exchange: exchange.to_owned(),
+            price,
+            amount,
+            age_ms: 0,
+            ..Default::default()
+        };
+
+        let summary = Summary {
+            spread: Some(0.0001),
+            bids: vec![
+                level("binance", 0.065, 1.5),
+                level("bitstamp", 0.0649, 12.25),
+            ],
+            asks: vec![level("bitstamp", 0.0651, 2.0)],
+            ..Default::default()
+        };
+
+        let expected = "\
+eth/btc spread: 0.00010000
+EXCHANGE           AMOUNT            BID | ASK            AMOUNT         EXCHANGE
+binance        1.50000000     0.06500000 | 0.06510000     2.00000000     bitstamp
+bitstamp      12.25000000     0.06490000 |
+";
+
+        assert_eq!(render_summary("eth/btc", &summary), expected);
+    }
+}
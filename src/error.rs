@@ -1,17 +1,85 @@
+#[cfg(feature = "exchanges")]
+use crate::exchanges::{
+    binance::error::BinanceError, bitstamp::error::BitstampError, bybit::error::BybitError,
+    error::ExchangeError, gemini::error::GeminiError, kraken::error::KrakenError,
+    okx::error::OkxError,
+};
 use crate::{
-    exchanges::{binance::error::BinanceError, bitstamp::error::BitstampError},
+    exchanges::{credentials::error::CredentialsError, ParsePairError},
     order_book::error::OrderBookError,
+    pair::error::PairError,
     server::error::ServerError,
+    store::error::SummaryStoreError,
 };
 
 #[derive(thiserror::Error, Debug)]
 pub enum BidAskServiceError {
     #[error("Order book error")]
     OrderBookError(#[from] OrderBookError),
+    #[cfg(feature = "exchanges")]
     #[error("Binance error")]
     BinanceError(#[from] BinanceError),
+    #[cfg(feature = "exchanges")]
     #[error("Bitstamp error")]
     BitstampError(#[from] BitstampError),
+    #[cfg(feature = "exchanges")]
+    #[error("Kraken error")]
+    KrakenError(#[from] KrakenError),
+    #[cfg(feature = "exchanges")]
+    #[error("Bybit error")]
+    BybitError(#[from] BybitError),
+    #[cfg(feature = "exchanges")]
+    #[error("OKX error")]
+    OkxError(#[from] OkxError),
+    #[cfg(feature = "exchanges")]
+    #[error("Gemini error")]
+    GeminiError(#[from] GeminiError),
+    #[cfg(feature = "exchanges")]
+    #[error("Exchange error")]
+    ExchangeError(#[from] ExchangeError),
     #[error("Server error")]
     ServerError(#[from] ServerError),
+    #[error("Pair error")]
+    PairError(#[from] PairError),
+    #[error("Parse pair error")]
+    ParsePairError(#[from] ParsePairError),
+    #[error("Credentials error")]
+    CredentialsError(#[from] CredentialsError),
+    #[error("Summary store error")]
+    SummaryStoreError(#[from] SummaryStoreError),
+    #[error("Join error")]
+    JoinError(#[from] tokio::task::JoinError),
+}
+
+//Flatten the result of awaiting a spawned task, so that a task that panicked or was cancelled surfaces as a JoinError.
+//The error is returned as is from the task, so it is not boxed despite its size
+#[allow(clippy::result_large_err)]
+pub fn flatten_task_result(
+    result: Result<Result<(), BidAskServiceError>, tokio::task::JoinError>,
+) -> Result<(), BidAskServiceError> {
+    result?
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{flatten_task_result, BidAskServiceError};
+
+    #[tokio::test]
+    async fn test_panicked_task() {
+        let handle = tokio::spawn(async {
+            if true {
+                panic!("Task panicked");
+            }
+            Ok::<(), BidAskServiceError>(())
+        });
+
+        match flatten_task_result(handle.await) {
+            Err(BidAskServiceError::JoinError(join_error)) => assert!(join_error.is_panic()),
+            other => panic!("Unexpected result: {other:?}"),
+        }
+
+        //Tasks that finish are passed through unchanged
+        let handle = tokio::spawn(async { Ok::<(), BidAskServiceError>(()) });
+        assert!(flatten_task_result(handle.await).is_ok());
+    }
 }
@@ -0,0 +1,99 @@
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use serde_derive::Serialize;
+use tokio::sync::broadcast::{error::RecvError, Receiver, Sender};
+
+use crate::exchanges::Exchange;
+
+//Buffer size for the broadcast channel that service events are published to
+pub const EVENT_BUFFER: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceEventKind {
+    //A websocket connection to an exchange was established
+    Connected,
+    //A websocket connection to an exchange was closed or dropped and will be reconnected
+    Disconnected,
+    //Price levels were evicted from the aggregated order book because they were not updated within the max level age
+    StaleLevelsEvicted,
+    //Price levels were evicted from the aggregated order book because the total level cap was exceeded
+    LevelCapExceeded,
+    //Every exchange of the aggregated order book has been disconnected for longer than the all exchanges down timeout
+    AllExchangesDown,
+    //The aggregated order book dropped an update from an exchange, so the exchange's stream should resync its order book from a new snapshot
+    ResyncRequested,
+}
+
+// Significant events published by the exchange streams and the aggregated order book, to be consumed by notifiers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceEvent {
+    pub event: ServiceEventKind,
+    pub exchange: Option<Exchange>,
+    pub pair: String,
+}
+
+impl ServiceEvent {
+    pub fn new(event: ServiceEventKind, exchange: Option<Exchange>, pair: &str) -> Self {
+        ServiceEvent {
+            event,
+            exchange,
+            pair: pair.to_owned(),
+        }
+    }
+}
+
+// Publishes service events for a specific exchange and pair
+#[derive(Debug, Clone)]
+pub struct EventPublisher {
+    exchange: Option<Exchange>,
+    pair: String,
+    event_tx: Sender<ServiceEvent>,
+}
+
+impl EventPublisher {
+    pub fn new(
+        exchange: Option<Exchange>,
+        pair: [&str; 2],
+        event_tx: Sender<ServiceEvent>,
+    ) -> Self {
+        EventPublisher {
+            exchange,
+            pair: pair.join("/"),
+            event_tx,
+        }
+    }
+
+    pub fn publish(&self, event: ServiceEventKind) {
+        //Sending only fails when there are no subscribers, in which case there is no one to notify
+        self.event_tx
+            .send(ServiceEvent::new(event, self.exchange.clone(), &self.pair))
+            .ok();
+    }
+
+    pub fn subscribe(&self) -> Receiver<ServiceEvent> {
+        self.event_tx.subscribe()
+    }
+
+    //Wait for a resync of the publisher's exchange and pair to be requested, ignoring the events of other exchanges and pairs.
+    //Waits forever once the channel is closed, so that it can be polled alongside the stream of a connection
+    pub async fn resync_requested(&self, event_rx: &mut Receiver<ServiceEvent>) {
+        loop {
+            match event_rx.recv().await {
+                Ok(ServiceEvent {
+                    event: ServiceEventKind::ResyncRequested,
+                    exchange,
+                    pair,
+                }) if exchange == self.exchange && pair == self.pair => return,
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        "Skipped {skipped} service events while waiting for a resync request"
+                    );
+                }
+                Err(RecvError::Closed) => std::future::pending().await,
+            }
+        }
+    }
+}
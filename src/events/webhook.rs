@@ -0,0 +1,124 @@
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    task::JoinHandle,
+};
+
+use crate::error::BidAskServiceError;
+
+use super::ServiceEvent;
+
+//Spawns a task that posts each service event as JSON to the webhook url
+pub fn spawn_webhook_notifier(
+    webhook_url: String,
+    mut event_rx: Receiver<ServiceEvent>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+
+        loop {
+            match event_rx.recv().await {
+                Ok(event) => {
+                    //A failed notification should not bring down the service, so errors are only logged
+                    match client.post(&webhook_url).json(&event).send().await {
+                        Ok(response) if !response.status().is_success() => {
+                            tracing::warn!(
+                                "Webhook responded with {} for {event:?}",
+                                response.status()
+                            );
+                        }
+                        Err(e) => {
+                            tracing::warn!("Could not send webhook for {event:?}: {e:?}");
+                        }
+                        _ => {}
+                    }
+                }
+
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Webhook notifier lagged, skipped {skipped} events");
+                }
+
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{
+        events::{webhook::spawn_webhook_notifier, EventPublisher, ServiceEventKind},
+        exchanges::Exchange,
+    };
+
+    //Accepts a single HTTP request, responding with 200 and returning the request body
+    async fn receive_webhook(listener: TcpListener) -> String {
+        let (mut socket, _) = listener
+            .accept()
+            .await
+            .expect("Could not accept connection");
+
+        let mut request = vec![];
+        let mut buffer = [0; 1024];
+        loop {
+            let n = socket
+                .read(&mut buffer)
+                .await
+                .expect("Could not read request");
+            request.extend_from_slice(&buffer[..n]);
+
+            let request = String::from_utf8_lossy(&request);
+            if let Some((headers, body)) = request.split_once("\r\n\r\n") {
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|value| value.trim().parse::<usize>().expect("Invalid length"))
+                    })
+                    .unwrap_or(0);
+
+                if body.len() >= content_length {
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                        .await
+                        .expect("Could not write response");
+                    return body.to_owned();
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_disconnect_event() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let webhook_url = format!("http://{}", listener.local_addr().expect("No local addr"));
+
+        let (event_tx, event_rx) = tokio::sync::broadcast::channel(10);
+        let _notifier_handle = spawn_webhook_notifier(webhook_url, event_rx);
+
+        let events = EventPublisher::new(Some(Exchange::Binance), ["eth", "btc"], event_tx);
+        events.publish(ServiceEventKind::Disconnected);
+
+        let body = receive_webhook(listener).await;
+        let payload: serde_json::Value =
+            serde_json::from_str(&body).expect("Could not deserialize payload");
+
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "event": "disconnected",
+                "exchange": "binance",
+                "pair": "eth/btc",
+            })
+        );
+    }
+}
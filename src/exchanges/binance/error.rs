@@ -1,6 +1,6 @@
 use tokio::sync::mpsc::error::SendError;
 
-use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{exchanges::ParsePairError, order_book::price_level::PriceLevelUpdate};
 
 use super::stream::OrderBookUpdate;
 
@@ -10,8 +10,8 @@ pub enum BinanceError {
     OrderBookUpdateSendError(#[from] SendError<OrderBookUpdate>),
     #[error("Error when sending tungstenite message")]
     MessageSendError(#[from] SendError<tungstenite::Message>),
-    #[error("Invalid update id")]
-    InvalidUpdateId,
+    #[error("Ws connection closed with terminal code {code}: {reason}")]
+    ConnectionClosed { code: u16, reason: String },
     #[error("Tungstenite error")]
     TungsteniteError(#[from] tungstenite::Error),
     #[error("Error when sending price level update")]
@@ -22,6 +22,10 @@ pub enum BinanceError {
     ReqwestError(#[from] reqwest::Error),
     #[error("HTTP error")]
     HTTPError(String),
+    #[error("Invalid pair: {0}")]
+    ParsePairError(#[from] ParsePairError),
+    #[error("Pair {0} is not listed on Binance")]
+    UnsupportedPair(String),
     #[error("Error when converting to Utf8 from string")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
 }
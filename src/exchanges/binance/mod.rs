@@ -1,42 +1,130 @@
 pub mod error;
-mod stream;
+pub mod stream;
 
-use self::stream::{spawn_order_book_stream, spawn_stream_handler};
-use super::OrderBookService;
+use self::error::BinanceError;
+use self::stream::{
+    get_snapshot_levels, spawn_order_book_stream, spawn_stream_handler,
+    ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, WS_BASE_ENDPOINT,
+};
+use super::{spawn_pair_error, validate_pair, Exchange, OrderBookService, ParsePairError};
 use crate::error::BidAskServiceError;
-use crate::order_book::price_level::PriceLevelUpdate;
+use crate::events::{EventPublisher, ServiceEvent};
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::reconnect::ReconnectBackoff;
+use crate::order_book::price_level::{ask::Ask, bid::Bid, PriceLevelUpdate};
 use async_trait::async_trait;
-use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
 
-#[derive(Default)]
-pub struct Binance;
+#[derive(Debug, Clone)]
+pub struct Binance {
+    //Base endpoint of the websocket stream, order book updates are streamed from {ws_base_endpoint}{pair}@depth
+    pub ws_base_endpoint: String,
+    //Base endpoint of the depth snapshot, snapshots are requested from {snapshot_base_endpoint}{PAIR}&limit={depth}
+    pub snapshot_base_endpoint: String,
+    //Backoff between reconnect attempts when the websocket stream is closed
+    pub reconnect_backoff: ReconnectBackoff,
+    //Interval to re-fetch the depth snapshot at, resyncing the order book in case a diff was dropped. Snapshots are only fetched on connecting when there is no interval
+    pub snapshot_refresh_interval: Option<Duration>,
+}
+
+impl Binance {
+    pub fn new() -> Self {
+        Binance {
+            ws_base_endpoint: WS_BASE_ENDPOINT.to_owned(),
+            snapshot_base_endpoint: ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT.to_owned(),
+            reconnect_backoff: ReconnectBackoff::default(),
+            snapshot_refresh_interval: None,
+        }
+    }
+
+    pub fn with_ws_base_endpoint(mut self, ws_base_endpoint: &str) -> Self {
+        self.ws_base_endpoint = ws_base_endpoint.to_owned();
+        self
+    }
+
+    pub fn with_snapshot_base_endpoint(mut self, snapshot_base_endpoint: &str) -> Self {
+        self.snapshot_base_endpoint = snapshot_base_endpoint.to_owned();
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+
+    pub fn with_snapshot_refresh_interval(mut self, snapshot_refresh_interval: Duration) -> Self {
+        self.snapshot_refresh_interval = Some(snapshot_refresh_interval);
+        self
+    }
+
+    //Fetch a depth snapshot of the pair from the snapshot endpoint, without starting a stream
+    pub async fn rest_snapshot(
+        &self,
+        pair: [&str; 2],
+        depth: usize,
+    ) -> Result<(Vec<Bid>, Vec<Ask>), BinanceError> {
+        let snapshot_pair = self.format_pair(pair)?;
+        get_snapshot_levels(&self.snapshot_base_endpoint, &snapshot_pair, depth).await
+    }
+}
+
+impl Default for Binance {
+    fn default() -> Self {
+        Binance::new()
+    }
+}
 
 #[async_trait]
 impl OrderBookService for Binance {
+    //Binance lists the pair as a single symbol in uppercase letters, ie. ETHBTC, which is how snapshots are requested
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?.join("").to_uppercase())
+    }
+
     fn spawn_order_book_service(
+        &self,
         pair: [&str; 2],
         order_book_depth: usize,
         exchange_stream_buffer: usize,
         price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        feed_quality: Option<Arc<FeedQuality>>,
     ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
-        let pair = pair.join("");
-        //When subscribing to a stream of order book updates, the pair is required to be formatted as a single string with all lowercase letters
-        let stream_pair = pair.to_lowercase();
-        //When getting a snapshot, Binance requires that the pair si a single string with all uppercase letters
-        let snapshot_pair = pair.to_uppercase();
+        let snapshot_pair = match self.format_pair(pair) {
+            Ok(snapshot_pair) => snapshot_pair,
+            Err(error) => return spawn_pair_error(Exchange::Binance, error),
+        };
+        //When subscribing to a stream of order book updates, the symbol is required to be in lowercase letters
+        let stream_pair = snapshot_pair.to_lowercase();
+        let events = EventPublisher::new(Some(Exchange::Binance), pair, event_tx);
 
         tracing::info!("Spawning Binance order book stream");
         //Spawn a task to handle a buffered stream of the order book and reconnects to the exchange
-        let (ws_stream_rx, stream_handle) =
-            spawn_order_book_stream(stream_pair, exchange_stream_buffer);
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            self.ws_base_endpoint.clone(),
+            stream_pair,
+            exchange_stream_buffer,
+            events,
+            shutdown_rx.clone(),
+            self.reconnect_backoff.clone(),
+            self.snapshot_refresh_interval,
+        );
 
         tracing::info!("Spawning Binance order book stream handler");
         //Spawn a task to handle updates from the buffered stream, cleaning the data and sending it to the aggregated order book
         let order_book_update_handle = spawn_stream_handler(
+            self.snapshot_base_endpoint.clone(),
             snapshot_pair,
             order_book_depth,
             ws_stream_rx,
             price_level_tx,
+            feed_quality,
+            shutdown_rx,
         );
 
         vec![stream_handle, order_book_update_handle]
@@ -52,10 +140,19 @@ mod tests {
 
     use crate::{
         error::BidAskServiceError,
-        exchanges::{binance::Binance, OrderBookService},
+        events::ServiceEventKind,
+        exchanges::{
+            binance::{error::BinanceError, Binance},
+            OrderBookService,
+        },
         order_book::price_level::PriceLevelUpdate,
     };
-    use futures::FutureExt;
+    use futures::{FutureExt, SinkExt, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tungstenite::{
+        handshake::server::{Request, Response},
+        Message,
+    };
 
     #[tokio::test]
 
@@ -66,10 +163,19 @@ mod tests {
         let target_counter = 50;
 
         let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(500);
-        let mut join_handles = Binance::spawn_order_book_service(["eth", "btc"], 1000, 500, tx);
+        let (event_tx, _) = tokio::sync::broadcast::channel(10);
+        let mut join_handles = Binance::new().spawn_order_book_service(
+            ["eth", "btc"],
+            1000,
+            500,
+            tx,
+            event_tx,
+            tokio::sync::watch::channel(false).1,
+            None,
+        );
 
         let price_level_update_handle = tokio::spawn(async move {
-            while let Some(_) = rx.recv().await {
+            while rx.recv().await.is_some() {
                 dbg!(atomic_counter_0.load(Ordering::Relaxed));
                 atomic_counter_0.fetch_add(1, Ordering::Relaxed);
                 if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
@@ -95,4 +201,164 @@ mod tests {
                 .expect("Error when handling WS connection");
         }
     }
+
+    #[tokio::test]
+    //Spawn a Binance instance configured with local endpoints, checking that the configured endpoints are used
+    async fn test_spawn_configured_order_book_service() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/ws/", listener.local_addr().unwrap());
+
+        //Accept a single websocket connection, sending the requested path back to the test
+        let (path_tx, path_rx) = tokio::sync::oneshot::channel();
+        let server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            //The error response type is defined by tungstenite's handshake callback
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, response: Response| {
+                path_tx.send(request.uri().path().to_owned()).ok();
+                Ok(response)
+            };
+
+            let _ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .expect("Could not complete handshake");
+            std::future::pending::<()>().await;
+        });
+
+        let binance = Binance::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            //Nothing is listening on the snapshot endpoint, so the snapshot request should fail
+            .with_snapshot_base_endpoint("http://127.0.0.1:1/api/v3/depth?symbol=");
+        assert_eq!(binance.ws_base_endpoint, ws_base_endpoint);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let (event_tx, mut event_rx) = tokio::sync::broadcast::channel(10);
+        let mut join_handles = binance.spawn_order_book_service(
+            ["eth", "btc"],
+            10,
+            10,
+            tx,
+            event_tx,
+            tokio::sync::watch::channel(false).1,
+            None,
+        );
+
+        assert_eq!(
+            path_rx.await.expect("No request received"),
+            "/ws/ethbtc@depth"
+        );
+        assert_eq!(
+            event_rx.recv().await.expect("No event received").event,
+            ServiceEventKind::Connected
+        );
+
+        let order_book_update_handle = join_handles.pop().expect("No stream handler");
+        match order_book_update_handle.await.expect("Join handle error") {
+            Err(BidAskServiceError::BinanceError(BinanceError::ReqwestError(_))) => {}
+            other => panic!("Expected snapshot request to fail, got {other:?}"),
+        }
+
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    //Consume the order book stream of a Binance instance configured with local endpoints
+    async fn test_order_book_stream() {
+        let ws_listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/ws/", ws_listener.local_addr().unwrap());
+        let snapshot_listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let snapshot_base_endpoint = format!(
+            "http://{}/api/v3/depth?symbol=",
+            snapshot_listener.local_addr().unwrap()
+        );
+
+        //Stream an update that follows the snapshot, followed by an update after a gap that straddles the resynced snapshot
+        let _ws_server_handle = tokio::spawn(async move {
+            let (stream, _) = ws_listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            for (first_update_id, final_updated_id) in [(11, 12), (20, 21)] {
+                ws_stream
+                    .send(Message::Text(format!(
+                        r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","2.0"]],"a":[]}}"#
+                    )))
+                    .await
+                    .expect("Could not send update");
+            }
+            std::future::pending::<()>().await;
+        });
+
+        //Respond to the snapshot request on connecting and the snapshot request to resync after the gap
+        let _snapshot_server_handle = tokio::spawn(async move {
+            for last_update_id in [10, 20] {
+                let (mut socket, _) = snapshot_listener.accept().await.expect("Could not accept");
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket
+                        .read(&mut buffer)
+                        .await
+                        .expect("Could not read request");
+                    request.extend_from_slice(&buffer[..n]);
+                }
+
+                let body = format!(
+                    r#"{{"lastUpdateId":{last_update_id},"bids":[["0.065","1.0"]],"asks":[["0.066","1.0"]]}}"#
+                );
+                let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            );
+                socket
+                    .write_all(response.as_bytes())
+                    .await
+                    .expect("Could not write response");
+            }
+        });
+
+        let mut order_book_stream = Binance::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .with_snapshot_base_endpoint(&snapshot_base_endpoint)
+            .order_book_stream(["eth", "btc"], 1, 10);
+
+        let snapshot = order_book_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Stream error");
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.bids[0].quantity.0, 1.0);
+
+        let update = order_book_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Stream error");
+        assert!(!update.clear);
+        assert_eq!(update.bids[0].quantity.0, 2.0);
+
+        //The gap resyncs the order book from a snapshot rather than ending the stream, and the gapped update straddles the snapshot
+        let snapshot = order_book_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Stream error");
+        assert!(snapshot.clear);
+
+        let update = order_book_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Stream error");
+        assert!(!update.clear);
+        assert_eq!(update.bids[0].quantity.0, 2.0);
+    }
 }
@@ -1,25 +1,40 @@
-use serde_derive::Deserialize;
-use tokio::{sync::mpsc::Receiver, task::JoinHandle};
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc::Receiver, watch},
+    task::JoinHandle,
+};
 
 use crate::order_book::price_level::ask::Ask;
 use crate::order_book::price_level::bid::Bid;
 use crate::order_book::price_level::PriceLevelUpdate;
 use crate::{error::BidAskServiceError, exchanges::binance::error::BinanceError};
 
+use crate::events::{EventPublisher, ServiceEventKind};
+use crate::exchanges::feed_quality::{FeedQuality, UpdateAnomaly};
+use crate::exchanges::reconnect::{
+    connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+};
+use crate::exchanges::services::shutdown_requested;
 use crate::exchanges::Exchange;
+use std::{sync::Arc, time::Duration};
 
 use futures::{SinkExt, StreamExt};
 
 use tokio::sync::mpsc::Sender;
+use tokio::time::MissedTickBehavior;
 
 use crate::exchanges::exchange_utils;
 
 use tungstenite::Message;
 
-const WS_BASE_ENDPOINT: &str = "wss://stream.binance.com:9443/ws/";
-const ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT: &str = "https://api.binance.com/api/v3/depth?symbol=";
+pub const WS_BASE_ENDPOINT: &str = "wss://stream.binance.com:9443/ws/";
+pub const ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT: &str = "https://api.binance.com/api/v3/depth?symbol=";
 const DEPTH_UPDATE_EVENT: &str = "depthUpdate";
+const DEPTH_STREAM: &str = "depth";
+const UNSUBSCRIBE_METHOD: &str = "UNSUBSCRIBE";
 const GET_ORDER_BOOK_SNAPSHOT: Vec<u8> = vec![];
+//Error code returned by the REST API for a symbol that Binance does not list
+const INVALID_SYMBOL_CODE: i64 = -1121;
 
 // Websocket Market Streams
 
@@ -31,8 +46,13 @@ const GET_ORDER_BOOK_SNAPSHOT: Vec<u8> = vec![];
 
 //Spawns a thread to stream order book updates from Binance
 pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
     pair: String,
     exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+    snapshot_refresh_interval: Option<Duration>,
 ) -> (
     Receiver<Message>,
     JoinHandle<Result<(), BidAskServiceError>>,
@@ -43,15 +63,29 @@ pub fn spawn_order_book_stream(
     //spawn a thread that handles the stream and buffers the results
     let stream_handle = tokio::spawn(async move {
         let ws_stream_tx = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
         loop {
             //Establish an infinite loop to handle a ws stream with reconnects
-            let order_book_endpoint = WS_BASE_ENDPOINT.to_owned() + &pair + "@depth";
+            let order_book_endpoint = format!("{ws_base_endpoint}{pair}@{DEPTH_STREAM}");
 
             // Connect to the order book stream endpoint and start the stream
-            let (mut order_book_stream, _) = tokio_tungstenite::connect_async(order_book_endpoint)
-                .await
-                .map_err(BinanceError::TungsteniteError)?;
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&order_book_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(BinanceError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
+            //The depth stream is subscribed to through the endpoint, but can still be unsubscribed from with a request on the connection
+            let unsubscription_message = serde_json::to_string(&StreamRequest::unsubscribe(&pair))
+                .map_err(BinanceError::SerdeJsonError)?;
             tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
 
             //Notify the stream handler to get a snapshot of the order book
             //This will be the first message that the stream handler receives, so a
@@ -61,8 +95,65 @@ pub fn spawn_order_book_stream(
                 .await
                 .map_err(BinanceError::MessageSendError)?;
 
+            //Request a fresh snapshot at the interval, starting one interval after the snapshot requested on connecting
+            let mut snapshot_refresh = snapshot_refresh_interval.map(|interval| {
+                let mut snapshot_refresh =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                snapshot_refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                snapshot_refresh
+            });
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
             //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
-            while let Some(Ok(message)) = order_book_stream.next().await {
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the Binance order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //Resync the order book from a snapshot once the aggregated order book has dropped one of its updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resyncing the Binance order book from a snapshot");
+                        ws_stream_tx
+                            .send(Message::Binary(GET_ORDER_BOOK_SNAPSHOT))
+                            .await
+                            .map_err(BinanceError::MessageSendError)?;
+                        continue;
+                    }
+
+                    //Resync the order book from a snapshot in case a diff was dropped without a detectable gap
+                    _ = exchange_utils::next_tick(&mut snapshot_refresh) => {
+                        tracing::info!("Refreshing the Binance order book snapshot");
+                        ws_stream_tx
+                            .send(Message::Binary(GET_ORDER_BOOK_SNAPSHOT))
+                            .await
+                            .map_err(BinanceError::MessageSendError)?;
+                        continue;
+                    }
+                };
+
                 match message {
                     tungstenite::Message::Text(_) => {
                         ws_stream_tx
@@ -71,14 +162,54 @@ pub fn spawn_order_book_stream(
                             .map_err(BinanceError::MessageSendError)?;
                     }
 
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => {
+                        //Empty binary messages are reserved for requesting a snapshot from the stream handler, so empty data is dropped
+                        if data.is_empty() {
+                            continue;
+                        }
+
+                        match String::from_utf8(data) {
+                            Ok(message) => {
+                                ws_stream_tx
+                                    .send(Message::Text(message))
+                                    .await
+                                    .map_err(BinanceError::MessageSendError)?;
+                            }
+                            Err(err) => {
+                                tracing::warn!("Dropping binary message that is not utf8: {err}");
+                            }
+                        }
+                    }
+
                     tungstenite::Message::Ping(_) => {
                         tracing::info!("Ping received");
                         order_book_stream.send(Message::Pong(vec![])).await.ok();
                         tracing::info!("Pong sent");
                     }
 
-                    tungstenite::Message::Close(_) => {
-                        tracing::warn!("Ws connection closed, reconnecting...");
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(BinanceError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
                         break;
                     }
 
@@ -87,91 +218,174 @@ pub fn spawn_order_book_stream(
                     }
                 }
             }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
         }
     });
 
     (ws_stream_rx, stream_handle)
 }
 
+//Check that an update follows on from the last applied update id, per the Binance docs for managing a local order book.
+//The first update after a snapshot must straddle the snapshot, ie. U <= lastUpdateId + 1 <= u, while each subsequent
+//update must start immediately after the previous update, ie. U == previous u + 1
+pub fn is_next_update(
+    order_book_update: &OrderBookUpdate,
+    last_update_id: u64,
+    first_after_snapshot: bool,
+) -> bool {
+    if first_after_snapshot {
+        order_book_update.first_update_id <= last_update_id + 1
+            && order_book_update.final_updated_id > last_update_id
+    } else {
+        order_book_update.first_update_id == last_update_id + 1
+    }
+}
+
 //Spawns a thread to handle order book updates from Binance
 pub fn spawn_stream_handler(
+    snapshot_base_endpoint: String,
     pair: String,
     order_book_depth: usize,
     mut ws_stream_rx: Receiver<Message>,
     price_level_tx: Sender<PriceLevelUpdate>,
+    feed_quality: Option<Arc<FeedQuality>>,
+    mut shutdown_rx: watch::Receiver<bool>,
 ) -> JoinHandle<Result<(), BidAskServiceError>> {
     tokio::spawn(async move {
         let mut last_update_id = 0;
+        //Whether an update has been applied since the last snapshot. Updates that precede the snapshot are expected
+        //to be dropped, so update id anomalies are only recorded once the stream is in sync
+        let mut synced = false;
+        //Number of consecutive snapshots with fewer levels than the requested depth
+        let mut short_snapshots = 0;
+        //Backoff between resyncs that follow each other without an update being applied, so that a depth stream running ahead of the
+        //snapshots does not request snapshots in a tight loop and get rate limited by the REST API
+        let mut resync_backoff = ReconnectBackoff::default();
+
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the Binance order book stream handler");
+                    break;
+                }
+            };
 
-        while let Some(message) = ws_stream_rx.recv().await {
             match message {
                 //Deserialize the event, verify the order Id is valid and and send it through to the aggregated order book
                 tungstenite::Message::Text(message) => {
-                    let order_book_event = serde_json::from_str::<OrderBookEvent>(&message)
-                        .map_err(BinanceError::SerdeJsonError)?;
-
-                    if order_book_event.event == DEPTH_UPDATE_EVENT {
-                        let order_book_update = serde_json::from_str::<OrderBookUpdate>(&message)
-                            .map_err(BinanceError::SerdeJsonError)?;
-
+                    if let Some(order_book_update) =
+                        parse_order_book_update(&message).map_err(BinanceError::SerdeJsonError)?
+                    {
                         if order_book_update.final_updated_id <= last_update_id {
                             tracing::warn!("Update id is <= last update id");
-                            continue;
-                        } else {
-                            if order_book_update.first_update_id <= last_update_id + 1
-                                && order_book_update.final_updated_id >= last_update_id + 1
-                            {
-                                //Collect bids and asks, sending the batch of price level updates through a channel to the aggregated order book
-                                let mut bids = vec![];
-                                for bid in order_book_update.bids.into_iter() {
-                                    bids.push(Bid::new(bid[0], bid[1], Exchange::Binance));
-                                }
 
-                                let mut asks = vec![];
-                                for ask in order_book_update.asks.into_iter() {
-                                    asks.push(Ask::new(ask[0], ask[1], Exchange::Binance));
+                            if let Some(feed_quality) = feed_quality.as_ref().filter(|_| synced) {
+                                if order_book_update.final_updated_id == last_update_id {
+                                    feed_quality
+                                        .record(Exchange::Binance, UpdateAnomaly::Duplicate);
+                                } else {
+                                    feed_quality.record(Exchange::Binance, UpdateAnomaly::Reset);
                                 }
-
-                                price_level_tx
-                                    .send(PriceLevelUpdate::new(bids, asks))
-                                    .await
-                                    .map_err(BinanceError::PriceLevelUpdateSendError)?;
-                            } else {
-                                return Err(BinanceError::InvalidUpdateId.into());
                             }
 
-                            last_update_id = order_book_update.final_updated_id;
+                            continue;
                         }
-                    }
-                }
 
-                tungstenite::Message::Binary(message) => {
-                    // This is an internal message signifying that the stream has reconnected so we need to get a snapshot
-                    // First get a snapshot of the order book, handle all of the bids/asks and send it through the channel to the aggregated orderbook
-                    if message.is_empty() {
-                        tracing::info!("Getting order book snapshot");
-                        let snapshot = get_order_book_snapshot(&pair, order_book_depth).await?;
+                        if !is_next_update(&order_book_update, last_update_id, !synced) {
+                            //Binance's docs resync the local order book from a fresh snapshot on a gap, rather than failing the stream
+                            tracing::warn!(
+                                "Update ids {} to {} do not follow last update id {last_update_id}, resyncing from a snapshot",
+                                order_book_update.first_update_id,
+                                order_book_update.final_updated_id
+                            );
+                            if let Some(feed_quality) = feed_quality.as_ref().filter(|_| synced) {
+                                feed_quality.record(Exchange::Binance, UpdateAnomaly::Gap);
+                            }
 
+                            //The previous snapshot has not been synced from, so back off before requesting another
+                            if !synced {
+                                let resync_delay = resync_backoff.next_delay();
+                                tracing::warn!("Stream has not synced since the last snapshot, resyncing in {resync_delay:?}");
+                                tokio::select! {
+                                    _ = tokio::time::sleep(resync_delay) => {}
+                                    _ = shutdown_requested(&mut shutdown_rx) => break,
+                                }
+                            }
+
+                            last_update_id = send_order_book_snapshot(
+                                &snapshot_base_endpoint,
+                                &pair,
+                                order_book_depth,
+                                &price_level_tx,
+                                &feed_quality,
+                                &mut short_snapshots,
+                            )
+                            .await?;
+                            synced = false;
+
+                            //The gapped update is applied if it straddles the new snapshot, otherwise it precedes the snapshot and is dropped
+                            if !is_next_update(&order_book_update, last_update_id, true) {
+                                continue;
+                            }
+                        }
+
+                        //Collect bids and asks, sending the batch of price level updates through a channel to the aggregated order book
                         let mut bids = vec![];
-                        for bid in snapshot.bids.into_iter() {
+                        for bid in order_book_update.bids.into_iter() {
                             bids.push(Bid::new(bid[0], bid[1], Exchange::Binance));
                         }
 
                         let mut asks = vec![];
-                        for ask in snapshot.asks.into_iter() {
+                        for ask in order_book_update.asks.into_iter() {
                             asks.push(Ask::new(ask[0], ask[1], Exchange::Binance));
                         }
 
+                        //The event time is in milliseconds
+                        let price_level_update =
+                            PriceLevelUpdate::new(Exchange::Binance, bids, asks)
+                                .with_exchange_timestamp(
+                                    order_book_update.event_time as u64 * 1000,
+                                );
                         price_level_tx
-                            .send(PriceLevelUpdate::new(bids, asks))
+                            .send(price_level_update)
                             .await
                             .map_err(BinanceError::PriceLevelUpdateSendError)?;
 
-                        //Update the last seen update id
-                        last_update_id = snapshot.last_update_id;
+                        synced = true;
+                        resync_backoff.reset();
+                        last_update_id = order_book_update.final_updated_id;
                     }
                 }
 
+                tungstenite::Message::Binary(message) if message.is_empty() => {
+                    // This is an internal message signifying that the stream has reconnected or the snapshot is being refreshed, so we need to get a snapshot
+                    last_update_id = send_order_book_snapshot(
+                        &snapshot_base_endpoint,
+                        &pair,
+                        order_book_depth,
+                        &price_level_tx,
+                        &feed_quality,
+                        &mut short_snapshots,
+                    )
+                    .await?;
+                    synced = false;
+                }
+
                 _ => {}
             }
         }
@@ -180,6 +394,62 @@ pub fn spawn_stream_handler(
     })
 }
 
+//Get a snapshot of the order book, handle all of the bids/asks and send it through the channel to the aggregated orderbook,
+//returning the last update id of the snapshot that the stream is resynced from
+async fn send_order_book_snapshot(
+    snapshot_base_endpoint: &str,
+    pair: &str,
+    order_book_depth: usize,
+    price_level_tx: &Sender<PriceLevelUpdate>,
+    feed_quality: &Option<Arc<FeedQuality>>,
+    short_snapshots: &mut usize,
+) -> Result<u64, BidAskServiceError> {
+    tracing::info!("Getting order book snapshot");
+    let snapshot = get_order_book_snapshot(snapshot_base_endpoint, pair, order_book_depth).await?;
+    let snapshot_last_update_id = snapshot.last_update_id;
+    let (bids, asks) = snapshot.into_levels();
+
+    //Binance returns fewer levels than requested for thin pairs, warn so that the operator knows the venue is under supplying depth
+    if bids.len() < order_book_depth || asks.len() < order_book_depth {
+        *short_snapshots += 1;
+        tracing::warn!(
+            "Binance snapshot returned {} bids and {} asks, less than the requested depth of {order_book_depth} ({short_snapshots} consecutive short snapshots)",
+            bids.len(),
+            asks.len()
+        );
+
+        if let Some(feed_quality) = feed_quality.as_ref() {
+            feed_quality.record(Exchange::Binance, UpdateAnomaly::ShortSnapshot);
+        }
+    } else {
+        *short_snapshots = 0;
+    }
+
+    price_level_tx
+        .send(PriceLevelUpdate::snapshot(Exchange::Binance, bids, asks))
+        .await
+        .map_err(BinanceError::PriceLevelUpdateSendError)?;
+
+    Ok(snapshot_last_update_id)
+}
+
+//A request to manage the streams of a connection, ie. {"method":"UNSUBSCRIBE","params":["ethbtc@depth"],"id":1}
+#[derive(Serialize, Debug)]
+pub struct StreamRequest {
+    method: String,
+    params: Vec<String>,
+    id: u64,
+}
+impl StreamRequest {
+    pub fn unsubscribe(pair: &str) -> StreamRequest {
+        StreamRequest {
+            method: UNSUBSCRIBE_METHOD.to_owned(),
+            params: vec![format!("{pair}@{DEPTH_STREAM}")],
+            id: 1,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct OrderBookSnapshot {
     #[serde(rename = "lastUpdateId")]
@@ -190,6 +460,23 @@ pub struct OrderBookSnapshot {
     asks: Vec<[f64; 2]>,
 }
 
+impl OrderBookSnapshot {
+    //Convert the levels of the snapshot into Binance's bids and asks
+    pub fn into_levels(self) -> (Vec<Bid>, Vec<Ask>) {
+        let bids = self
+            .bids
+            .into_iter()
+            .map(|[price, quantity]| Bid::new(price, quantity, Exchange::Binance))
+            .collect();
+        let asks = self
+            .asks
+            .into_iter()
+            .map(|[price, quantity]| Ask::new(price, quantity, Exchange::Binance))
+            .collect();
+        (bids, asks)
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct OrderBookUpdate {
     #[serde(rename = "E")]
@@ -235,11 +522,30 @@ pub struct OrderBookEvent {
     pub event: String,
 }
 
+//Parse a message from the order book stream, returning the order book update if the message is a depth update event
+pub fn parse_order_book_update(
+    message: &str,
+) -> Result<Option<OrderBookUpdate>, serde_json::Error> {
+    let order_book_event = serde_json::from_str::<OrderBookEvent>(message)?;
+
+    if order_book_event.event == DEPTH_UPDATE_EVENT {
+        Ok(Some(serde_json::from_str::<OrderBookUpdate>(message)?))
+    } else {
+        Ok(None)
+    }
+}
+
+//Parse the body of a depth snapshot response
+pub fn parse_order_book_snapshot(body: &[u8]) -> Result<OrderBookSnapshot, serde_json::Error> {
+    serde_json::from_slice::<OrderBookSnapshot>(body)
+}
+
 async fn get_order_book_snapshot(
+    snapshot_base_endpoint: &str,
     pair: &str,
     order_book_depth: usize,
 ) -> Result<OrderBookSnapshot, BinanceError> {
-    let snapshot_endpoint = ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT.to_owned()
+    let snapshot_endpoint = snapshot_base_endpoint.to_owned()
         + pair
         + "&limit="
         + order_book_depth.to_string().as_str();
@@ -247,15 +553,45 @@ async fn get_order_book_snapshot(
     // Get the depth snapshot, deserialize and return the result
     let snapshot_response = reqwest::get(snapshot_endpoint).await?;
 
-    if snapshot_response.status().is_success() {
-        Ok(snapshot_response.json::<OrderBookSnapshot>().await?)
+    let status = snapshot_response.status();
+    if status.is_success() {
+        Ok(parse_order_book_snapshot(
+            &snapshot_response.bytes().await?,
+        )?)
     } else {
-        Err(BinanceError::HTTPError(String::from_utf8(
-            snapshot_response.bytes().await?.to_vec(),
-        )?))
+        let body = String::from_utf8(snapshot_response.bytes().await?.to_vec())?;
+
+        //Unlisted pairs are rejected with a bad request holding the invalid symbol code, which reconnecting will not resolve
+        if status == reqwest::StatusCode::BAD_REQUEST
+            && serde_json::from_str::<ErrorResponse>(&body)
+                .is_ok_and(|error| error.code == INVALID_SYMBOL_CODE)
+        {
+            return Err(BinanceError::UnsupportedPair(pair.to_owned()));
+        }
+
+        Err(BinanceError::HTTPError(body))
     }
 }
 
+//Fetch the bids and asks of a depth snapshot, without starting a stream
+pub async fn get_snapshot_levels(
+    snapshot_base_endpoint: &str,
+    pair: &str,
+    order_book_depth: usize,
+) -> Result<(Vec<Bid>, Vec<Ask>), BinanceError> {
+    Ok(
+        get_order_book_snapshot(snapshot_base_endpoint, pair, order_book_depth)
+            .await?
+            .into_levels(),
+    )
+}
+
+//Body of an error response from the REST API, ie. {"code":-1121,"msg":"Invalid symbol."}
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    code: i64,
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::{
@@ -263,15 +599,53 @@ mod tests {
         Arc,
     };
 
-    use crate::{error::BidAskServiceError, exchanges::binance::spawn_order_book_stream};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tungstenite::{
+        protocol::{
+            frame::{
+                coding::{CloseCode, Data, OpCode},
+                Frame,
+            },
+            CloseFrame,
+        },
+        Message,
+    };
 
-    use futures::FutureExt;
+    use crate::{
+        error::BidAskServiceError,
+        events::{EventPublisher, ServiceEventKind},
+        exchanges::{
+            binance::{
+                error::BinanceError, spawn_order_book_stream, stream::spawn_stream_handler, Binance,
+            },
+            feed_quality::{FeedQuality, FeedQualityCounts},
+            reconnect::ReconnectBackoff,
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    use futures::{FutureExt, SinkExt};
 
-    use crate::exchanges::binance::stream::get_order_book_snapshot;
+    use crate::exchanges::binance::stream::{
+        get_order_book_snapshot, StreamRequest, ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, WS_BASE_ENDPOINT,
+    };
+
+    #[test]
+    fn test_unsubscribe_message() {
+        assert_eq!(
+            serde_json::to_string(&StreamRequest::unsubscribe("ethbtc"))
+                .expect("Could not serialize request"),
+            r#"{"method":"UNSUBSCRIBE","params":["ethbtc@depth"],"id":1}"#
+        );
+    }
 
     #[tokio::test]
     async fn test_get_order_book_snapshot() {
-        let snapshot = get_order_book_snapshot("ETHBTC", 50)
+        let snapshot = get_order_book_snapshot(ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, "ETHBTC", 50)
             .await
             .expect("Could not get order book snapshot");
 
@@ -288,11 +662,18 @@ mod tests {
 
         let mut join_handles = vec![];
 
-        let (mut order_book_update_rx, order_book_stream_handle) =
-            spawn_order_book_stream("ethbtc".to_owned(), 500);
+        let (mut order_book_update_rx, order_book_stream_handle) = spawn_order_book_stream(
+            WS_BASE_ENDPOINT.to_owned(),
+            "ethbtc".to_owned(),
+            500,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            None,
+        );
 
         let order_book_update_handle = tokio::spawn(async move {
-            while let Some(_) = order_book_update_rx.recv().await {
+            while order_book_update_rx.recv().await.is_some() {
                 dbg!(atomic_counter_0.load(Ordering::Relaxed));
                 atomic_counter_0.fetch_add(1, Ordering::Relaxed);
                 if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
@@ -321,4 +702,808 @@ mod tests {
             panic!("Unexpected error");
         }
     }
+
+    #[tokio::test]
+    //Inject duplicate, reset and gapped updates into the stream handler and check that each anomaly is counted
+    async fn test_feed_quality_counters() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            r#"{"lastUpdateId":11,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+        )
+        .await;
+
+        let feed_quality = Arc::new(FeedQuality::new());
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            Some(feed_quality.clone()),
+            tokio::sync::watch::channel(false).1,
+        );
+
+        let depth_update = |first_update_id: u64, final_updated_id: u64| {
+            Message::Text(format!(
+                r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","1.0"]],"a":[["0.066","2.0"]]}}"#
+            ))
+        };
+
+        //Applied update, followed by a duplicate, a reset and a gap
+        for (first_update_id, final_updated_id) in [(1, 5), (5, 5), (2, 3), (10, 12)] {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+
+        drop(ws_stream_tx);
+
+        //The first update is applied, then the gap resyncs from a snapshot that the gapped update straddles
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+        assert!(price_level_rx.recv().await.expect("No snapshot").clear);
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+        assert!(stream_handler.await.expect("Join handle error").is_ok());
+        assert!(price_level_rx.recv().await.is_none());
+
+        assert_eq!(
+            feed_quality.counts(&Exchange::Binance),
+            FeedQualityCounts {
+                gaps: 1,
+                resets: 1,
+                duplicates: 1,
+                short_snapshots: 0,
+                dropped_updates: 0,
+            }
+        );
+        assert_eq!(
+            feed_quality.counts(&Exchange::Bitstamp),
+            FeedQualityCounts::default()
+        );
+    }
+
+    //Spawns a mock snapshot endpoint that responds to a single request with the body, returning the snapshot base endpoint
+    async fn spawn_mock_snapshot_server(body: &'static str) -> String {
+        spawn_mock_snapshot_server_with_bodies(vec![body]).await
+    }
+
+    //Spawns a mock snapshot endpoint that responds to a request with each body in turn, returning the snapshot base endpoint
+    async fn spawn_mock_snapshot_server_with_bodies(bodies: Vec<&'static str>) -> String {
+        spawn_mock_snapshot_server_with_responses(
+            bodies.into_iter().map(|body| ("200 OK", body)).collect(),
+        )
+        .await
+    }
+
+    //Spawns a mock snapshot endpoint that responds to a request with each status and body in turn, returning the snapshot base endpoint
+    async fn spawn_mock_snapshot_server_with_responses(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let snapshot_base_endpoint = format!(
+            "http://{}/api/v3/depth?symbol=",
+            listener.local_addr().expect("No local addr")
+        );
+
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.expect("Could not accept");
+                //Read until the end of the request headers, the GET request has no body
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket
+                        .read(&mut buffer)
+                        .await
+                        .expect("Could not read request");
+                    request.extend_from_slice(&buffer[..n]);
+                }
+
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                socket
+                    .write_all(response.as_bytes())
+                    .await
+                    .expect("Could not write response");
+            }
+        });
+
+        snapshot_base_endpoint
+    }
+
+    #[tokio::test]
+    //Reject a pair that Binance does not list with a typed error, while other HTTP errors are reported as is
+    async fn test_unsupported_pair() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server_with_responses(vec![
+            (
+                "400 Bad Request",
+                r#"{"code":-1121,"msg":"Invalid symbol."}"#,
+            ),
+            (
+                "429 Too Many Requests",
+                r#"{"code":-1003,"msg":"Too many requests."}"#,
+            ),
+        ])
+        .await;
+
+        let result = get_order_book_snapshot(&snapshot_base_endpoint, "XYZABC", 10).await;
+        assert!(matches!(result, Err(BinanceError::UnsupportedPair(pair)) if pair == "XYZABC"));
+
+        let result = get_order_book_snapshot(&snapshot_base_endpoint, "ETHBTC", 10).await;
+        assert!(matches!(result, Err(BinanceError::HTTPError(_))));
+    }
+
+    #[tokio::test]
+    //Fetch a one-time snapshot from a mock snapshot endpoint, converting the levels into Binance's bids and asks
+    async fn test_rest_snapshot() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"],["0.064","2.0"]],"asks":[["0.066","3.0"]]}"#,
+        )
+        .await;
+
+        let (bids, asks) = Binance::new()
+            .with_snapshot_base_endpoint(&snapshot_base_endpoint)
+            .rest_snapshot(["eth", "btc"], 10)
+            .await
+            .expect("Could not get order book snapshot");
+        assert_eq!(
+            bids.iter()
+                .map(|bid| (bid.price.0, bid.quantity.0, bid.exchange.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0.065, 1.0, Exchange::Binance),
+                (0.064, 2.0, Exchange::Binance)
+            ]
+        );
+        assert_eq!(
+            asks.iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.066, 3.0)]
+        );
+    }
+
+    #[tokio::test]
+    //Serve a snapshot with less levels than the requested depth and check that it is recorded
+    async fn test_short_snapshot_detected() {
+        //Respond to a single snapshot request with one level on each side
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+        )
+        .await;
+
+        let feed_quality = Arc::new(FeedQuality::new());
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let _stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            50,
+            ws_stream_rx,
+            price_level_tx,
+            Some(feed_quality.clone()),
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //Request a snapshot, as the stream does after connecting
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.bids.len(), 1);
+        assert_eq!(snapshot.asks.len(), 1);
+        assert_eq!(feed_quality.counts(&Exchange::Binance).short_snapshots, 1);
+    }
+
+    #[tokio::test]
+    //Apply the first update after a snapshot using the boundary ids from the Binance docs, where U <= lastUpdateId + 1 <= u,
+    //and check that subsequent updates must start immediately after the previous update
+    async fn test_first_update_after_snapshot() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server_with_bodies(vec![
+            r#"{"lastUpdateId":1027024,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+            r#"{"lastUpdateId":1027040,"bids":[["0.064","3.0"]],"asks":[["0.067","4.0"]]}"#,
+        ])
+        .await;
+
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            None,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        let depth_update = |first_update_id: u64, final_updated_id: u64| {
+            Message::Text(format!(
+                r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","1.0"]],"a":[["0.066","2.0"]]}}"#
+            ))
+        };
+
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert!(snapshot.clear);
+
+        //Buffered update that precedes the snapshot, followed by the first update that ends exactly on lastUpdateId + 1
+        //and a subsequent update that starts immediately after it
+        for (first_update_id, final_updated_id) in
+            [(1027015, 1027024), (1027020, 1027025), (1027026, 1027030)]
+        {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+
+        //A subsequent update that overlaps the previous update is a gap, even though it would be a valid first update
+        ws_stream_tx
+            .send(depth_update(1027030, 1027035))
+            .await
+            .expect("Could not send update");
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.bids[0].price.0, 0.064);
+        drop(ws_stream_tx);
+        assert!(stream_handler.await.expect("Join handle error").is_ok());
+        assert!(price_level_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    //Resync from a fresh snapshot on a gap instead of failing the stream handler, dropping the gapped update when it precedes
+    //the new snapshot and applying the updates that follow on from the new snapshot
+    async fn test_gap_resyncs_from_snapshot() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server_with_bodies(vec![
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+            r#"{"lastUpdateId":40,"bids":[["0.064","3.0"]],"asks":[["0.067","4.0"]]}"#,
+        ])
+        .await;
+
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            None,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        let depth_update = |first_update_id: u64, final_updated_id: u64| {
+            Message::Text(format!(
+                r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","1.0"]],"a":[["0.066","2.0"]]}}"#
+            ))
+        };
+
+        //Sync from the first snapshot and apply an update, followed by a gap, an update preceding the new snapshot,
+        //the first update straddling the new snapshot and the update after it
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+        for (first_update_id, final_updated_id) in
+            [(11, 15), (20, 25), (26, 30), (38, 45), (46, 50)]
+        {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+        drop(ws_stream_tx);
+
+        assert_eq!(
+            price_level_rx.recv().await.expect("No snapshot").bids[0]
+                .price
+                .0,
+            0.065
+        );
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot");
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.bids[0].price.0, 0.064);
+
+        let mut updates = 0;
+        while let Some(update) = price_level_rx.recv().await {
+            assert!(!update.clear);
+            updates += 1;
+        }
+        assert_eq!(updates, 2);
+        assert!(stream_handler.await.expect("Join handle error").is_ok());
+    }
+
+    #[tokio::test]
+    //Send gaps before the stream has synced from a snapshot, checking that they are not recorded and that consecutive resyncs back off
+    async fn test_gap_before_sync() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server_with_bodies(vec![
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+            r#"{"lastUpdateId":20,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+            r#"{"lastUpdateId":40,"bids":[["0.064","3.0"]],"asks":[["0.067","4.0"]]}"#,
+        ])
+        .await;
+
+        let feed_quality = Arc::new(FeedQuality::new());
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            Some(feed_quality.clone()),
+            tokio::sync::watch::channel(false).1,
+        );
+
+        let depth_update = |first_update_id: u64, final_updated_id: u64| {
+            Message::Text(format!(
+                r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","1.0"]],"a":[["0.066","2.0"]]}}"#
+            ))
+        };
+
+        //The depth stream runs ahead of the first two snapshots, so the stream only syncs from the third snapshot
+        let started_at = std::time::Instant::now();
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+        for (first_update_id, final_updated_id) in [(30, 35), (36, 38), (39, 45)] {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+        drop(ws_stream_tx);
+
+        for _ in 0..3 {
+            assert!(price_level_rx.recv().await.expect("No snapshot").clear);
+        }
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+        assert!(stream_handler.await.expect("Join handle error").is_ok());
+
+        //Both resyncs follow a snapshot that was not synced from, so each waits for at least half of its backoff delay
+        let reconnect_backoff = ReconnectBackoff::default();
+        assert!(started_at.elapsed() >= reconnect_backoff.initial_delay / 2 * 3);
+        assert_eq!(
+            feed_quality.counts(&Exchange::Binance),
+            FeedQualityCounts::default()
+        );
+    }
+
+    #[tokio::test]
+    //Force a re-snapshot after updates have been applied, checking that the last update id is reset to the new snapshot
+    async fn test_snapshot_refresh_resets_update_id() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server_with_bodies(vec![
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+            r#"{"lastUpdateId":100,"bids":[["0.064","3.0"]],"asks":[["0.067","4.0"]]}"#,
+        ])
+        .await;
+
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            None,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        let depth_update = |first_update_id: u64, final_updated_id: u64| {
+            Message::Text(format!(
+                r#"{{"e":"depthUpdate","E":0,"s":"ETHBTC","U":{first_update_id},"u":{final_updated_id},"b":[["0.065","1.0"]],"a":[["0.066","2.0"]]}}"#
+            ))
+        };
+
+        //Sync from the first snapshot and apply two updates
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+        for (first_update_id, final_updated_id) in [(11, 15), (16, 20)] {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+        assert!(price_level_rx.recv().await.expect("No snapshot").clear);
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+        assert!(!price_level_rx.recv().await.expect("No update").clear);
+
+        //Refresh the snapshot, replacing the exchange's levels with the new snapshot
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+        let snapshot = price_level_rx.recv().await.expect("No snapshot");
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.bids[0].price.0, 0.064);
+
+        //An update that follows on from the old update id is now stale, while the first update straddling the new snapshot
+        //and the update after it are applied
+        for (first_update_id, final_updated_id) in [(21, 25), (95, 105), (106, 110)] {
+            ws_stream_tx
+                .send(depth_update(first_update_id, final_updated_id))
+                .await
+                .expect("Could not send update");
+        }
+        drop(ws_stream_tx);
+
+        let mut updates = 0;
+        while let Some(update) = price_level_rx.recv().await {
+            assert!(!update.clear);
+            updates += 1;
+        }
+        assert_eq!(updates, 2);
+        assert!(stream_handler.await.expect("Join handle error").is_ok());
+    }
+
+    #[tokio::test]
+    //Connect to a local websocket server with a snapshot refresh interval, checking that a snapshot is requested at each interval
+    async fn test_snapshot_refresh_interval() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        let _server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let _ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+            std::future::pending::<()>().await;
+        });
+
+        let (mut ws_stream_rx, _stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            Some(std::time::Duration::from_millis(50)),
+        );
+
+        //The snapshot requested on connecting is followed by a refresh at each interval, without reconnecting
+        for _ in 0..3 {
+            let message =
+                tokio::time::timeout(std::time::Duration::from_secs(5), ws_stream_rx.recv())
+                    .await
+                    .expect("No snapshot requested")
+                    .expect("Stream closed");
+            assert_eq!(message, Message::Binary(vec![]));
+        }
+    }
+
+    #[tokio::test]
+    //Connect to a local websocket server and request a resync for Binance, checking that a snapshot is requested without reconnecting
+    async fn test_resync_request() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        let _server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let _ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+            std::future::pending::<()>().await;
+        });
+
+        let (event_tx, _) = tokio::sync::broadcast::channel(10);
+        let events = EventPublisher::new(Some(Exchange::Binance), ["eth", "btc"], event_tx.clone());
+        let (mut ws_stream_rx, _stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            events.clone(),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            None,
+        );
+
+        //The snapshot requested on connecting
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), ws_stream_rx.recv())
+            .await
+            .expect("No snapshot requested")
+            .expect("Stream closed");
+        assert_eq!(message, Message::Binary(vec![]));
+
+        //A resync requested for another exchange is ignored, while a resync requested for Binance requests a snapshot
+        EventPublisher::new(Some(Exchange::Bitstamp), ["eth", "btc"], event_tx)
+            .publish(ServiceEventKind::ResyncRequested);
+        events.publish(ServiceEventKind::ResyncRequested);
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), ws_stream_rx.recv())
+            .await
+            .expect("No snapshot requested")
+            .expect("Stream closed");
+        assert_eq!(message, Message::Binary(vec![]));
+        assert!(ws_stream_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    //Connect to a local websocket server and request shutdown, checking that the stream unsubscribes and closes the connection and that both tasks stop cleanly
+    async fn test_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        //Record the messages received by the server until the connection is closed
+        let server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+            let mut messages = vec![];
+            while let Some(Ok(message)) = futures::StreamExt::next(&mut ws_stream).await {
+                let closed = message.is_close();
+                messages.push(message);
+                if closed {
+                    break;
+                }
+            }
+            messages
+        });
+
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            r#"{"lastUpdateId":10,"bids":[["0.065","1.0"]],"asks":[["0.066","2.0"]]}"#,
+        )
+        .await;
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            shutdown_rx.clone(),
+            ReconnectBackoff::default(),
+            None,
+        );
+        let stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ETHBTC".to_owned(),
+            1,
+            ws_stream_rx,
+            price_level_tx,
+            None,
+            shutdown_rx,
+        );
+
+        //The snapshot requested on connecting is sent once the stream is connected
+        tokio::time::timeout(std::time::Duration::from_secs(5), price_level_rx.recv())
+            .await
+            .expect("No snapshot received")
+            .expect("Price level channel closed");
+
+        shutdown_tx.send_replace(true);
+
+        //Both tasks return Ok rather than being aborted or failing
+        for handle in [stream_handle, stream_handler] {
+            tokio::time::timeout(std::time::Duration::from_secs(5), handle)
+                .await
+                .expect("Task did not stop")
+                .expect("Join handle error")
+                .expect("Task returned an error");
+        }
+
+        //The stream unsubscribes from the depth stream before closing the connection normally
+        let messages = tokio::time::timeout(std::time::Duration::from_secs(5), server_handle)
+            .await
+            .expect("Connection was not closed")
+            .expect("Join handle error");
+        let unsubscription_message = serde_json::to_string(&StreamRequest::unsubscribe("ethbtc"))
+            .expect("Could not serialize unsubscribe message");
+        assert_eq!(messages[0], Message::Text(unsubscription_message));
+        match &messages[1] {
+            Message::Close(Some(close_frame)) => assert_eq!(close_frame.code, CloseCode::Normal),
+            other => panic!("Expected a close frame, found {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    //Connect to a local websocket server that stops sending without closing the connection, checking that the stream reconnects once idle
+    async fn test_idle_timeout_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        //Hold every connection open without sending anything, counting the connections accepted
+        let connections = Arc::new(AtomicU32::new(0));
+        let server_connections = connections.clone();
+        let _server_handle = tokio::spawn(async move {
+            let mut ws_streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                server_connections.fetch_add(1, Ordering::SeqCst);
+                ws_streams.push(
+                    tokio_tungstenite::accept_async(stream)
+                        .await
+                        .expect("Could not complete handshake"),
+                );
+            }
+        });
+
+        let (mut ws_stream_rx, _stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::new(
+                std::time::Duration::from_millis(10),
+                std::time::Duration::from_millis(10),
+            )
+            .with_idle_timeout(std::time::Duration::from_millis(100)),
+            None,
+        );
+
+        //A snapshot is requested on each connection, so a second request means the idle connection was replaced
+        for _ in 0..2 {
+            let message =
+                tokio::time::timeout(std::time::Duration::from_secs(5), ws_stream_rx.recv())
+                    .await
+                    .expect("No snapshot requested")
+                    .expect("Stream closed");
+            assert_eq!(message, Message::Binary(vec![]));
+        }
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[tokio::test]
+    //Stream a fragmented text message and binary messages from a local websocket server, checking what is forwarded to the stream handler
+    async fn test_fragmented_and_binary_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        let _server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            for message in [
+                Message::Frame(Frame::message(
+                    br#"{"e":"depth"#.to_vec(),
+                    OpCode::Data(Data::Text),
+                    false,
+                )),
+                Message::Frame(Frame::message(
+                    br#"Update"}"#.to_vec(),
+                    OpCode::Data(Data::Continue),
+                    true,
+                )),
+                Message::Binary(br#"{"e":"binary"}"#.to_vec()),
+                Message::Binary(vec![]),
+                Message::Binary(vec![0xff, 0xfe]),
+                Message::Text("done".to_owned()),
+            ] {
+                ws_stream
+                    .send(message)
+                    .await
+                    .expect("Could not send message");
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let (mut ws_stream_rx, _stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            None,
+        );
+
+        //The snapshot request is sent first, followed by the reassembled text message and the decoded binary message.
+        //The empty and invalid binary messages are dropped
+        let mut messages = vec![];
+        for _ in 0..4 {
+            messages.push(ws_stream_rx.recv().await.expect("No message received"));
+        }
+        assert_eq!(
+            messages,
+            vec![
+                Message::Binary(vec![]),
+                Message::Text(r#"{"e":"depthUpdate"}"#.to_owned()),
+                Message::Text(r#"{"e":"binary"}"#.to_owned()),
+                Message::Text("done".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    //Close the connection from a local websocket server with a terminal code, checking that the stream fails instead of reconnecting
+    async fn test_terminal_close() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/ws/", listener.local_addr().expect("No local addr"));
+
+        let _server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            ws_stream
+                .send(Message::Close(Some(CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: "banned".into(),
+                })))
+                .await
+                .expect("Could not send close");
+            std::future::pending::<()>().await;
+        });
+
+        let (_ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            None,
+        );
+
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), stream_handle)
+            .await
+            .expect("Stream did not fail after a terminal close")
+            .expect("Join handle error");
+
+        match result {
+            Err(BidAskServiceError::BinanceError(BinanceError::ConnectionClosed {
+                code,
+                reason,
+            })) => {
+                assert_eq!(code, 1008);
+                assert_eq!(reason, "banned");
+            }
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
 }
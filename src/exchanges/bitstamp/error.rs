@@ -1,13 +1,17 @@
 use tokio::sync::mpsc::error::SendError;
 
-use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{exchanges::ParsePairError, order_book::price_level::PriceLevelUpdate};
 
 #[derive(thiserror::Error, Debug)]
 pub enum BitstampError {
     #[error("Error when sending tungstenite message")]
     MessageSendError(#[from] SendError<tungstenite::Message>),
+    #[error("Invalid pair: {0}")]
+    ParsePairError(#[from] ParsePairError),
     #[error("Invalid update id")]
     InvalidUpdateId,
+    #[error("Ws connection closed with terminal code {code}: {reason}")]
+    ConnectionClosed { code: u16, reason: String },
     #[error("Tungstenite error")]
     TungsteniteError(#[from] tungstenite::Error),
     #[error("Error when sending price level update")]
@@ -18,6 +22,8 @@ pub enum BitstampError {
     ReqwestError(#[from] reqwest::Error),
     #[error("HTTP error")]
     HTTPError(String),
+    #[error("Pair {0} is not listed on Bitstamp")]
+    UnsupportedPair(String),
     #[error("Error when converting to Utf8 from string")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
 }
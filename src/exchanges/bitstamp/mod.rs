@@ -1,41 +1,168 @@
 pub mod error;
-mod stream;
+pub mod stream;
 use crate::{
     error::BidAskServiceError,
-    exchanges::bitstamp::stream::{spawn_order_book_stream, spawn_stream_handler},
+    exchanges::bitstamp::{
+        error::BitstampError,
+        stream::{
+            get_snapshot_levels, spawn_order_book_stream, spawn_stream_handler, WsAuth,
+            ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, WS_BASE_ENDPOINT, WS_TOKEN_ENDPOINT,
+        },
+    },
 };
 
 use async_trait::async_trait;
-use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
+
+use crate::order_book::price_level::{ask::Ask, bid::Bid, PriceLevelUpdate};
+
+use super::{spawn_pair_error, validate_pair, Exchange, OrderBookService, ParsePairError};
+use crate::events::{EventPublisher, ServiceEvent};
+use crate::exchanges::credentials::Credentials;
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::reconnect::ReconnectBackoff;
+use std::{sync::Arc, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct Bitstamp {
+    //Websocket endpoint that the diff order book channel is subscribed to on
+    pub ws_base_endpoint: String,
+    //Base endpoint of the order book snapshot, snapshots are requested from {snapshot_base_endpoint}{pair}
+    pub snapshot_base_endpoint: String,
+    //Backoff between reconnect attempts when the websocket stream is closed
+    pub reconnect_backoff: ReconnectBackoff,
+    //Endpoint of the websocket token used to authenticate the subscription
+    pub token_endpoint: String,
+    //Credentials to authenticate the subscription with, the public subscription is used when there are no credentials
+    pub credentials: Option<Credentials>,
+    //Interval to re-send the subscription on the open connection at, for subscriptions that expire. Subscriptions are only sent on connecting when there is no interval
+    pub resubscribe_interval: Option<Duration>,
+    //Interval to re-fetch the order book snapshot at, resyncing the order book in case an update was missed. Snapshots are only fetched on connecting when there is no interval
+    pub snapshot_refresh_interval: Option<Duration>,
+}
+
+impl Bitstamp {
+    pub fn new() -> Self {
+        Bitstamp {
+            ws_base_endpoint: WS_BASE_ENDPOINT.to_owned(),
+            snapshot_base_endpoint: ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT.to_owned(),
+            reconnect_backoff: ReconnectBackoff::default(),
+            token_endpoint: WS_TOKEN_ENDPOINT.to_owned(),
+            credentials: None,
+            resubscribe_interval: None,
+            snapshot_refresh_interval: None,
+        }
+    }
 
-use crate::order_book::price_level::PriceLevelUpdate;
+    pub fn with_ws_base_endpoint(mut self, ws_base_endpoint: &str) -> Self {
+        self.ws_base_endpoint = ws_base_endpoint.to_owned();
+        self
+    }
 
-use super::OrderBookService;
+    pub fn with_snapshot_base_endpoint(mut self, snapshot_base_endpoint: &str) -> Self {
+        self.snapshot_base_endpoint = snapshot_base_endpoint.to_owned();
+        self
+    }
 
-#[derive(Default)]
-pub struct Bitstamp;
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn with_resubscribe_interval(mut self, resubscribe_interval: Duration) -> Self {
+        self.resubscribe_interval = Some(resubscribe_interval);
+        self
+    }
+
+    pub fn with_snapshot_refresh_interval(mut self, snapshot_refresh_interval: Duration) -> Self {
+        self.snapshot_refresh_interval = Some(snapshot_refresh_interval);
+        self
+    }
+
+    //Fetch an order book snapshot of the pair from the snapshot endpoint, keeping the best levels up to the depth, without starting a stream
+    pub async fn rest_snapshot(
+        &self,
+        pair: [&str; 2],
+        depth: usize,
+    ) -> Result<(Vec<Bid>, Vec<Ask>), BitstampError> {
+        let snapshot_pair = self.format_pair(pair)?;
+        get_snapshot_levels(&self.snapshot_base_endpoint, &snapshot_pair, depth).await
+    }
+
+    pub fn with_token_endpoint(mut self, token_endpoint: &str) -> Self {
+        self.token_endpoint = token_endpoint.to_owned();
+        self
+    }
+}
+
+impl Default for Bitstamp {
+    fn default() -> Self {
+        Bitstamp::new()
+    }
+}
 
 #[async_trait]
 impl OrderBookService for Bitstamp {
+    //Bitstamp requires the pair to be formatted as a single string with all lowercase letters, for both the stream and the snapshot
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?.join("").to_lowercase())
+    }
+
+    //Bitstamp's order book snapshot does not accept a depth and always returns its fixed snapshot depth,
+    //so the snapshot is trimmed to the order book depth by the stream handler
     fn spawn_order_book_service(
+        &self,
         pair: [&str; 2],
-        _order_book_depth: usize,
+        order_book_depth: usize,
         exchange_stream_buffer: usize,
         price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        feed_quality: Option<Arc<FeedQuality>>,
     ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
-        let pair = pair.join("");
-        let stream_pair = pair.to_lowercase();
+        let stream_pair = match self.format_pair(pair) {
+            Ok(stream_pair) => stream_pair,
+            Err(error) => return spawn_pair_error(Exchange::Bitstamp, error),
+        };
         let snapshot_pair = stream_pair.clone();
+        let events = EventPublisher::new(Some(Exchange::Bitstamp), pair, event_tx);
 
         tracing::info!("Spawning Bitstamp order book stream");
         //Spawn a task to handle a buffered stream of the order book and reconnects to the exchange
-        let (ws_stream_rx, stream_handle) =
-            spawn_order_book_stream(stream_pair, exchange_stream_buffer);
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            self.ws_base_endpoint.clone(),
+            stream_pair,
+            exchange_stream_buffer,
+            events,
+            shutdown_rx.clone(),
+            self.reconnect_backoff.clone(),
+            self.credentials.clone().map(|credentials| WsAuth {
+                token_endpoint: self.token_endpoint.clone(),
+                credentials,
+            }),
+            self.resubscribe_interval,
+            self.snapshot_refresh_interval,
+        );
 
         tracing::info!("Spawning Bitstamp order book stream handler");
         //Spawn a task to handle updates from the buffered stream, cleaning the data and sending it to the aggregated order book
-        let order_book_update_handle =
-            spawn_stream_handler(snapshot_pair, ws_stream_rx, price_level_tx);
+        let order_book_update_handle = spawn_stream_handler(
+            self.snapshot_base_endpoint.clone(),
+            snapshot_pair,
+            order_book_depth,
+            ws_stream_rx,
+            price_level_tx,
+            feed_quality,
+            shutdown_rx,
+        );
 
         vec![stream_handle, order_book_update_handle]
     }
@@ -43,17 +170,25 @@ impl OrderBookService for Bitstamp {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
     };
 
-    use crate::exchanges::OrderBookService;
+    use crate::exchanges::{credentials::Credentials, OrderBookService};
     use crate::{
         error::BidAskServiceError, exchanges::bitstamp::Bitstamp,
         order_book::price_level::PriceLevelUpdate,
     };
-    use futures::FutureExt;
+    use futures::{FutureExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tungstenite::Message;
 
     #[tokio::test]
 
@@ -63,10 +198,19 @@ mod tests {
         let target_counter = 50;
 
         let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(500);
-        let mut join_handles = Bitstamp::spawn_order_book_service(["eth", "btc"], 1000, 500, tx);
+        let (event_tx, _) = tokio::sync::broadcast::channel(10);
+        let mut join_handles = Bitstamp::new().spawn_order_book_service(
+            ["eth", "btc"],
+            1000,
+            500,
+            tx,
+            event_tx,
+            tokio::sync::watch::channel(false).1,
+            None,
+        );
 
         let price_level_update_handle = tokio::spawn(async move {
-            while let Some(_) = rx.recv().await {
+            while rx.recv().await.is_some() {
                 dbg!(atomic_counter_0.load(Ordering::Relaxed));
                 atomic_counter_0.fetch_add(1, Ordering::Relaxed);
                 if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
@@ -92,4 +236,166 @@ mod tests {
                 .expect("Error when handling WS connection");
         }
     }
+
+    //Spawns a mock Bitstamp with a token endpoint and a websocket endpoint, returning the endpoints, the headers of each token request
+    //and each subscription message received by the websocket endpoint
+    async fn spawn_mock_bitstamp() -> (
+        String,
+        String,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let token_listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let token_endpoint = format!(
+            "http://{}/api/v2/websockets_token/",
+            token_listener.local_addr().expect("No local addr")
+        );
+        let ws_listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint =
+            format!("ws://{}/", ws_listener.local_addr().expect("No local addr"));
+
+        let (token_request_tx, token_request_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = token_listener.accept().await {
+                //Read until the end of the request headers, the token request has no body
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket
+                        .read(&mut buffer)
+                        .await
+                        .expect("Could not read request");
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                token_request_tx
+                    .send(String::from_utf8_lossy(&request).to_lowercase())
+                    .ok();
+
+                let body = r#"{"token":"mock_token","valid_sec":60,"user_id":"1"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                socket
+                    .write_all(response.as_bytes())
+                    .await
+                    .expect("Could not write response");
+            }
+        });
+
+        let (subscription_tx, subscription_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = ws_listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            while let Some(Ok(Message::Text(subscription))) = ws_stream.next().await {
+                subscription_tx.send(subscription).ok();
+            }
+            std::future::pending::<()>().await;
+        });
+
+        (
+            token_endpoint,
+            ws_base_endpoint,
+            token_request_rx,
+            subscription_rx,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_subscription() {
+        //With credentials, a signed token request is made before the subscription, which is authenticated with the token
+        let (token_endpoint, ws_base_endpoint, mut token_request_rx, mut subscription_rx) =
+            spawn_mock_bitstamp().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _handles = Bitstamp::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .with_token_endpoint(&token_endpoint)
+            .with_credentials(Credentials::new("key".to_owned(), "secret".to_owned()))
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                10,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let token_request = token_request_rx
+            .recv()
+            .await
+            .expect("No token request received");
+        assert!(token_request.starts_with("post /api/v2/websockets_token/"));
+        assert!(token_request.contains("x-auth: bitstamp key"));
+        assert!(token_request.contains("x-auth-signature: "));
+        assert!(token_request.contains("x-auth-version: v2"));
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+        assert_eq!(
+            subscription,
+            r#"{"event":"bts:subscribe","data":{"channel":"diff_order_book_ethbtc","auth":"mock_token"}}"#
+        );
+
+        //Without credentials, the public subscription is used and no token is requested
+        let (token_endpoint, ws_base_endpoint, mut token_request_rx, mut subscription_rx) =
+            spawn_mock_bitstamp().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _handles = Bitstamp::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .with_token_endpoint(&token_endpoint)
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                10,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+        assert_eq!(
+            subscription,
+            r#"{"event":"bts:subscribe","data":{"channel":"diff_order_book_ethbtc"}}"#
+        );
+        assert!(token_request_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resubscribe_interval() {
+        let (token_endpoint, ws_base_endpoint, _token_request_rx, mut subscription_rx) =
+            spawn_mock_bitstamp().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let resubscribe_interval = Duration::from_millis(100);
+        let spawned_at = tokio::time::Instant::now();
+        let _handles = Bitstamp::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .with_token_endpoint(&token_endpoint)
+            .with_resubscribe_interval(resubscribe_interval)
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                10,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+
+        //The same subscription is re-sent on the open connection at each interval
+        for i in 1..=2 {
+            let resubscription = subscription_rx.recv().await.expect("No resubscription");
+            assert_eq!(resubscription, subscription);
+            assert!(spawned_at.elapsed() >= resubscribe_interval * i);
+        }
+    }
 }
@@ -1,31 +1,70 @@
 use crate::{
     error::BidAskServiceError,
-    exchanges::{exchange_utils, Exchange},
+    events::{EventPublisher, ServiceEventKind},
+    exchanges::{
+        credentials::Credentials,
+        exchange_utils,
+        feed_quality::{FeedQuality, UpdateAnomaly},
+        reconnect::{
+            connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+        },
+        services::shutdown_requested,
+        Exchange,
+    },
     order_book::price_level::{ask::Ask, bid::Bid, PriceLevelUpdate},
 };
 
 use futures::{SinkExt, StreamExt};
+use rand::{distributions::Alphanumeric, Rng};
+use ring::hmac;
 use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use tokio::{
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
     task::JoinHandle,
+    time::MissedTickBehavior,
 };
 
 use tungstenite::Message;
 
 use crate::exchanges::bitstamp::error::BitstampError;
 
-const WS_BASE_ENDPOINT: &str = "wss://ws.bitstamp.net/";
+pub const WS_BASE_ENDPOINT: &str = "wss://ws.bitstamp.net/";
 const SUBSCRIBE_EVENT: &str = "bts:subscribe";
+const UNSUBSCRIBE_EVENT: &str = "bts:unsubscribe";
 const DIFF_ORDER_BOOK: &str = "diff_order_book";
-const ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT: &str = "https://www.bitstamp.net/api/v2/order_book/";
+pub const ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT: &str = "https://www.bitstamp.net/api/v2/order_book/";
 const DATA_EVENT: &str = "data";
 const GET_ORDER_BOOK_SNAPSHOT: Vec<u8> = vec![];
+pub const WS_TOKEN_ENDPOINT: &str = "https://www.bitstamp.net/api/v2/websockets_token/";
+const AUTH_VERSION: &str = "v2";
+const AUTH_NONCE_LENGTH: usize = 36;
+
+// Credentials used to fetch a websocket token before each subscription, authenticating the connection for higher connection and rate limits
+#[derive(Debug, Clone)]
+pub struct WsAuth {
+    pub token_endpoint: String,
+    pub credentials: Credentials,
+}
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
     pair: String,
     exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+    ws_auth: Option<WsAuth>,
+    resubscribe_interval: Option<Duration>,
+    snapshot_refresh_interval: Option<Duration>,
 ) -> (
     Receiver<Message>,
     JoinHandle<Result<(), BidAskServiceError>>,
@@ -36,24 +75,35 @@ pub fn spawn_order_book_stream(
     //spawn a thread that handles the stream and buffers the results
     let stream_handle = tokio::spawn(async move {
         let ws_stream_tx: Sender<Message> = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
         loop {
             //Connect to the websocket endpoint
-            let (mut order_book_stream, _) = tokio_tungstenite::connect_async(WS_BASE_ENDPOINT)
-                .await
-                .map_err(BitstampError::TungsteniteError)?;
-
-            //Create a subscription message to notify Bitstamp to send order book updates
-            let subscription_message =
-                serde_json::to_string(&SubscribeMessage::new(&format!("{DIFF_ORDER_BOOK}_{pair}")))
-                    .map_err(BitstampError::SerdeJsonError)?;
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&ws_base_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(BitstampError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
 
-            //Send a subscribe message to start the stream
+            //Send a subscribe message to notify Bitstamp to start sending order book updates
+            let subscription_message = create_subscription_message(&pair, ws_auth.as_ref()).await?;
             order_book_stream
                 .send(tungstenite::Message::Text(subscription_message))
                 .await
                 .map_err(BitstampError::TungsteniteError)?;
+            let unsubscription_message = serde_json::to_string(&SubscribeMessage::unsubscribe(
+                &format!("{DIFF_ORDER_BOOK}_{pair}"),
+            ))
+            .map_err(BitstampError::SerdeJsonError)?;
 
             tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
 
             //Notify the stream handler to get a snapshot of the order book
             //This will be the first message that the stream handler receives, so a
@@ -63,8 +113,84 @@ pub fn spawn_order_book_stream(
                 .await
                 .map_err(BitstampError::MessageSendError)?;
 
+            //Re-send the subscription on the connection before it expires, starting one interval after subscribing
+            let mut resubscribe = resubscribe_interval.map(|interval| {
+                let mut resubscribe =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                resubscribe.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                resubscribe
+            });
+
+            //Request a fresh snapshot at the interval, starting one interval after the snapshot requested on connecting
+            let mut snapshot_refresh = snapshot_refresh_interval.map(|interval| {
+                let mut snapshot_refresh =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                snapshot_refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                snapshot_refresh
+            });
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
             //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
-            while let Some(Ok(message)) = order_book_stream.next().await {
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the Bitstamp order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //Resync the order book from a snapshot once the aggregated order book has dropped one of its updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resyncing the Bitstamp order book from a snapshot");
+                        ws_stream_tx
+                            .send(Message::Binary(GET_ORDER_BOOK_SNAPSHOT))
+                            .await
+                            .map_err(BitstampError::MessageSendError)?;
+                        continue;
+                    }
+
+                    //The connection stays open while resubscribing, so updates keep arriving in order and no new snapshot is needed
+                    _ = exchange_utils::next_tick(&mut resubscribe) => {
+                        let subscription_message = create_subscription_message(&pair, ws_auth.as_ref()).await?;
+                        order_book_stream
+                            .send(tungstenite::Message::Text(subscription_message))
+                            .await
+                            .map_err(BitstampError::TungsteniteError)?;
+                        tracing::info!("Resubscribed to the Bitstamp order book stream");
+                        continue;
+                    }
+
+                    //Resync the order book from a snapshot in case an update was missed without a detectable gap
+                    _ = exchange_utils::next_tick(&mut snapshot_refresh) => {
+                        tracing::info!("Refreshing the Bitstamp order book snapshot");
+                        ws_stream_tx
+                            .send(Message::Binary(GET_ORDER_BOOK_SNAPSHOT))
+                            .await
+                            .map_err(BitstampError::MessageSendError)?;
+                        continue;
+                    }
+                };
+
                 match message {
                     tungstenite::Message::Text(_) => {
                         ws_stream_tx
@@ -73,14 +199,54 @@ pub fn spawn_order_book_stream(
                             .map_err(BitstampError::MessageSendError)?;
                     }
 
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => {
+                        //Empty binary messages are reserved for requesting a snapshot from the stream handler, so empty data is dropped
+                        if data.is_empty() {
+                            continue;
+                        }
+
+                        match String::from_utf8(data) {
+                            Ok(message) => {
+                                ws_stream_tx
+                                    .send(Message::Text(message))
+                                    .await
+                                    .map_err(BitstampError::MessageSendError)?;
+                            }
+                            Err(err) => {
+                                tracing::warn!("Dropping binary message that is not utf8: {err}");
+                            }
+                        }
+                    }
+
                     tungstenite::Message::Ping(_) => {
                         tracing::info!("Ping received");
                         order_book_stream.send(Message::Pong(vec![])).await.ok();
                         tracing::info!("Pong sent");
                     }
 
-                    tungstenite::Message::Close(_) => {
-                        tracing::warn!("Ws connection closed, reconnecting...");
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(BitstampError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
                         break;
                     }
 
@@ -89,38 +255,85 @@ pub fn spawn_order_book_stream(
                     }
                 }
             }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
         }
     });
 
     (ws_stream_rx, stream_handle)
 }
 
+//Create the subscription message for the pair's diff order book, authenticating the subscription with a fresh token when credentials are supplied
+async fn create_subscription_message(
+    pair: &str,
+    ws_auth: Option<&WsAuth>,
+) -> Result<String, BitstampError> {
+    let mut subscribe_message = SubscribeMessage::new(&format!("{DIFF_ORDER_BOOK}_{pair}"));
+    if let Some(ws_auth) = ws_auth {
+        subscribe_message.data.auth = Some(get_ws_token(ws_auth).await?);
+        tracing::info!("Authenticating Bitstamp subscription");
+    }
+
+    serde_json::to_string(&subscribe_message).map_err(BitstampError::SerdeJsonError)
+}
+
 pub fn spawn_stream_handler(
+    snapshot_base_endpoint: String,
     pair: String,
+    order_book_depth: usize,
     mut ws_stream_rx: Receiver<Message>,
     price_level_tx: Sender<PriceLevelUpdate>,
+    feed_quality: Option<Arc<FeedQuality>>,
+    mut shutdown_rx: watch::Receiver<bool>,
 ) -> JoinHandle<Result<(), BidAskServiceError>> {
     tokio::spawn(async move {
         let mut last_microtimestamp = 0;
+        //Whether an update has been applied since the last snapshot. Updates that precede the snapshot are expected
+        //to be dropped, so out of order updates are only recorded once the stream is in sync
+        let mut synced = false;
+
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the Bitstamp order book stream handler");
+                    break;
+                }
+            };
 
-        while let Some(message) = ws_stream_rx.recv().await {
             match message {
                 tungstenite::Message::Text(message) => {
-                    //Deserialize the event and check if it is a data event
-                    let order_book_event = serde_json::from_str::<OrderBookEvent>(&message)
-                        .map_err(BitstampError::SerdeJsonError)?;
-
-                    if order_book_event.event == DATA_EVENT {
-                        //Deserialize the order book update to extract the bids and asks
-                        let order_book_update = serde_json::from_str::<OrderBookUpdate>(&message)
-                            .map_err(BitstampError::SerdeJsonError)?;
-
-                        let order_book_data = order_book_update.data;
-
+                    //Deserialize the event, extracting the bids and asks if it is a data event
+                    if let Some(order_book_data) =
+                        parse_order_book_update(&message).map_err(BitstampError::SerdeJsonError)?
+                    {
                         // If the microtimestamp of the order book data is not newer than the last microtimestamp we skip
                         //processing it and continue with the next message
                         if order_book_data.microtimestamp <= last_microtimestamp {
                             tracing::warn!("Microtimestamp is <= last microtimestamp");
+
+                            if let Some(feed_quality) = feed_quality.as_ref().filter(|_| synced) {
+                                if order_book_data.microtimestamp == last_microtimestamp {
+                                    feed_quality
+                                        .record(Exchange::Bitstamp, UpdateAnomaly::Duplicate);
+                                } else {
+                                    feed_quality.record(Exchange::Bitstamp, UpdateAnomaly::Reset);
+                                }
+                            }
+
                             continue;
                         } else {
                             //Collect all of the bids from the update
@@ -137,40 +350,38 @@ pub fn spawn_stream_handler(
 
                             //Send the batched price level update to the aggregated order book
                             price_level_tx
-                                .send(PriceLevelUpdate::new(bids, asks))
+                                .send(
+                                    PriceLevelUpdate::new(Exchange::Bitstamp, bids, asks)
+                                        .with_exchange_timestamp(order_book_data.microtimestamp),
+                                )
                                 .await
                                 .map_err(BitstampError::PriceLevelUpdateSendError)?;
 
                             last_microtimestamp = order_book_data.microtimestamp;
+                            synced = true;
                         }
                     }
                 }
 
-                tungstenite::Message::Binary(message) => {
-                    // This is an internal message signifying that the stream has reconnected so we need to get a snapshot
+                tungstenite::Message::Binary(message) if message.is_empty() => {
+                    // This is an internal message signifying that the stream has reconnected or the snapshot is being refreshed, so we need to get a snapshot
                     // First get a snapshot of the order book, handle all of the bids/asks and send it through the channel to the aggregated orderbook
-                    if message.is_empty() {
-                        tracing::info!("Getting order book snapshot");
-                        let snapshot = get_order_book_snapshot(&pair).await?;
-
-                        let mut bids = vec![];
-                        for bid in snapshot.bids.into_iter() {
-                            bids.push(Bid::new(bid[0], bid[1], Exchange::Bitstamp));
-                        }
-
-                        let mut asks = vec![];
-                        for ask in snapshot.asks.into_iter() {
-                            asks.push(Ask::new(ask[0], ask[1], Exchange::Bitstamp));
-                        }
-
-                        price_level_tx
-                            .send(PriceLevelUpdate::new(bids, asks))
-                            .await
-                            .map_err(BitstampError::PriceLevelUpdateSendError)?;
-
-                        //Update the last seen microtimestamp
-                        last_microtimestamp = snapshot.microtimestamp;
-                    }
+                    tracing::info!("Getting order book snapshot");
+                    let snapshot = get_order_book_snapshot(&snapshot_base_endpoint, &pair).await?;
+                    let snapshot_microtimestamp = snapshot.microtimestamp;
+                    let (bids, asks) = snapshot.into_levels(order_book_depth);
+
+                    price_level_tx
+                        .send(
+                            PriceLevelUpdate::snapshot(Exchange::Bitstamp, bids, asks)
+                                .with_exchange_timestamp(snapshot_microtimestamp),
+                        )
+                        .await
+                        .map_err(BitstampError::PriceLevelUpdateSendError)?;
+
+                    //Update the last seen microtimestamp
+                    last_microtimestamp = snapshot_microtimestamp;
+                    synced = false;
                 }
 
                 _ => {}
@@ -184,11 +395,14 @@ pub fn spawn_stream_handler(
 #[derive(Serialize, Debug)]
 pub struct SubscriptionData {
     channel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth: Option<String>,
 }
 impl SubscriptionData {
     pub fn new(channel: &str) -> SubscriptionData {
         SubscriptionData {
             channel: String::from(channel),
+            auth: None,
         }
     }
 }
@@ -205,6 +419,13 @@ impl SubscribeMessage {
             data: SubscriptionData::new(channel),
         }
     }
+
+    pub fn unsubscribe(channel: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            event: UNSUBSCRIBE_EVENT.to_owned(),
+            ..SubscribeMessage::new(channel)
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -226,6 +447,29 @@ pub struct OrderBookSnapshot {
     asks: Vec<[f64; 2]>,
 }
 
+impl OrderBookSnapshot {
+    //Convert the best levels of the snapshot up to the order book depth into Bitstamp's bids and asks.
+    //Bitstamp does not accept a depth for the snapshot, so the levels are sorted and the levels past the order book depth are dropped
+    pub fn into_levels(mut self, order_book_depth: usize) -> (Vec<Bid>, Vec<Ask>) {
+        self.bids.sort_by(|a, b| b[0].total_cmp(&a[0]));
+        self.asks.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+        let bids = self
+            .bids
+            .into_iter()
+            .take(order_book_depth)
+            .map(|[price, quantity]| Bid::new(price, quantity, Exchange::Bitstamp))
+            .collect();
+        let asks = self
+            .asks
+            .into_iter()
+            .take(order_book_depth)
+            .map(|[price, quantity]| Ask::new(price, quantity, Exchange::Bitstamp))
+            .collect();
+        (bids, asks)
+    }
+}
+
 #[derive(Deserialize, Debug)]
 
 pub struct OrderBookEvent {
@@ -256,13 +500,39 @@ pub struct OrderBookUpdateData {
     pub asks: Vec<[f64; 2]>,
 }
 
-async fn get_order_book_snapshot(pair: &str) -> Result<OrderBookSnapshot, BitstampError> {
-    let snapshot_endpoint = ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT.to_owned() + pair;
+//Parse a message from the order book stream, returning the order book data if the message is a data event
+pub fn parse_order_book_update(
+    message: &str,
+) -> Result<Option<OrderBookUpdateData>, serde_json::Error> {
+    let order_book_event = serde_json::from_str::<OrderBookEvent>(message)?;
+
+    if order_book_event.event == DATA_EVENT {
+        Ok(Some(serde_json::from_str::<OrderBookUpdate>(message)?.data))
+    } else {
+        Ok(None)
+    }
+}
+
+//Parse the body of an order book snapshot response
+pub fn parse_order_book_snapshot(body: &[u8]) -> Result<OrderBookSnapshot, serde_json::Error> {
+    serde_json::from_slice::<OrderBookSnapshot>(body)
+}
+
+async fn get_order_book_snapshot(
+    snapshot_base_endpoint: &str,
+    pair: &str,
+) -> Result<OrderBookSnapshot, BitstampError> {
+    let snapshot_endpoint = snapshot_base_endpoint.to_owned() + pair;
 
     // Get the depth snapshot, deserialize and return the result
     let snapshot_response = reqwest::get(snapshot_endpoint).await?;
     if snapshot_response.status().is_success() {
-        Ok(snapshot_response.json::<OrderBookSnapshot>().await?)
+        Ok(parse_order_book_snapshot(
+            &snapshot_response.bytes().await?,
+        )?)
+    } else if snapshot_response.status() == reqwest::StatusCode::NOT_FOUND {
+        //The pair is part of the path, so pairs that Bitstamp does not list are not found
+        Err(BitstampError::UnsupportedPair(pair.to_owned()))
     } else {
         Err(BitstampError::HTTPError(String::from_utf8(
             snapshot_response.bytes().await?.to_vec(),
@@ -270,6 +540,102 @@ async fn get_order_book_snapshot(pair: &str) -> Result<OrderBookSnapshot, Bitsta
     }
 }
 
+//Fetch the best bids and asks of an order book snapshot up to the order book depth, without starting a stream
+pub async fn get_snapshot_levels(
+    snapshot_base_endpoint: &str,
+    pair: &str,
+    order_book_depth: usize,
+) -> Result<(Vec<Bid>, Vec<Ask>), BitstampError> {
+    Ok(get_order_book_snapshot(snapshot_base_endpoint, pair)
+        .await?
+        .into_levels(order_book_depth))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsToken {
+    pub token: String,
+}
+
+//Sign a request with Bitstamp's v2 authentication, returning the hex encoded HMAC-SHA256 of the request using the api secret
+pub fn sign_request(
+    credentials: &Credentials,
+    method: &str,
+    url: &reqwest::Url,
+    nonce: &str,
+    timestamp: &str,
+) -> String {
+    //The request has no body, so the content type and body are empty
+    let message = format!(
+        "BITSTAMP {}{method}{}{}{}{nonce}{timestamp}{AUTH_VERSION}",
+        credentials.api_key(),
+        url.host_str().unwrap_or_default(),
+        url.path(),
+        url.query()
+            .map(|query| format!("?{query}"))
+            .unwrap_or_default(),
+    );
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, credentials.api_secret().as_bytes());
+    hmac::sign(&key, message.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+//Fetch a websocket token with a signed request, which is used to authenticate a subscription
+async fn get_ws_token(ws_auth: &WsAuth) -> Result<String, BitstampError> {
+    let nonce = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(AUTH_NONCE_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .to_string();
+
+    let client = reqwest::Client::new();
+    let mut request = client.post(&ws_auth.token_endpoint).build()?;
+    let signature = sign_request(
+        &ws_auth.credentials,
+        "POST",
+        request.url(),
+        &nonce,
+        &timestamp,
+    );
+
+    let headers = [
+        (
+            "X-Auth",
+            format!("BITSTAMP {}", ws_auth.credentials.api_key()),
+        ),
+        ("X-Auth-Signature", signature),
+        ("X-Auth-Nonce", nonce),
+        ("X-Auth-Timestamp", timestamp),
+        ("X-Auth-Version", AUTH_VERSION.to_owned()),
+    ];
+    for (name, value) in headers {
+        request.headers_mut().insert(
+            name,
+            value
+                .parse()
+                .map_err(|_| BitstampError::HTTPError(format!("Invalid {name} header")))?,
+        );
+    }
+
+    let token_response = client.execute(request).await?;
+
+    if token_response.status().is_success() {
+        Ok(serde_json::from_slice::<WsToken>(&token_response.bytes().await?)?.token)
+    } else {
+        Err(BitstampError::HTTPError(String::from_utf8(
+            token_response.bytes().await?.to_vec(),
+        )?))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::{
@@ -277,13 +643,46 @@ mod tests {
         Arc,
     };
 
-    use crate::exchanges::bitstamp::stream::get_order_book_snapshot;
-    use crate::{error::BidAskServiceError, exchanges::bitstamp::stream::spawn_order_book_stream};
-    use futures::FutureExt;
+    use crate::exchanges::bitstamp::stream::{
+        get_order_book_snapshot, sign_request, ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, WS_BASE_ENDPOINT,
+        WS_TOKEN_ENDPOINT,
+    };
+    use crate::exchanges::credentials::Credentials;
+    use crate::{
+        error::BidAskServiceError,
+        events::EventPublisher,
+        exchanges::{
+            bitstamp::{
+                error::BitstampError,
+                stream::{spawn_order_book_stream, spawn_stream_handler},
+                Bitstamp,
+            },
+            reconnect::ReconnectBackoff,
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+    use futures::{FutureExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tungstenite::{protocol::frame::coding::CloseCode, Message};
+
+    #[test]
+    fn test_sign_request() {
+        let credentials = Credentials::new("key".to_owned(), "secret".to_owned());
+        let url = reqwest::Url::parse(WS_TOKEN_ENDPOINT).expect("Could not parse url");
+
+        assert_eq!(
+            sign_request(&credentials, "POST", &url, "nonce", "1000"),
+            "03c872038d210d7d0f31418fb4e69fcd8d89d2a60660618b49502eb5c2d03130"
+        );
+    }
 
     #[tokio::test]
     async fn test_get_order_book_snapshot() {
-        let snapshot = get_order_book_snapshot("ethbtc")
+        let snapshot = get_order_book_snapshot(ORDER_BOOK_SNAPSHOT_BASE_ENDPOINT, "ethbtc")
             .await
             .expect("Could not get order book snapshot");
 
@@ -298,11 +697,20 @@ mod tests {
         let target_counter = 50;
         let mut join_handles = vec![];
 
-        let (mut order_book_update_rx, order_book_stream_handle) =
-            spawn_order_book_stream("ethbtc".to_owned(), 500);
+        let (mut order_book_update_rx, order_book_stream_handle) = spawn_order_book_stream(
+            WS_BASE_ENDPOINT.to_owned(),
+            "ethbtc".to_owned(),
+            500,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default(),
+            None,
+            None,
+            None,
+        );
 
         let order_book_update_handle = tokio::spawn(async move {
-            while let Some(_) = order_book_update_rx.recv().await {
+            while order_book_update_rx.recv().await.is_some() {
                 dbg!(atomic_counter_0.load(Ordering::Relaxed));
                 atomic_counter_0.fetch_add(1, Ordering::Relaxed);
                 if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
@@ -332,4 +740,191 @@ mod tests {
             panic!("Unexpected error");
         }
     }
+
+    #[tokio::test]
+    //Hold the connection open without sending anything, checking that the subscription is unsubscribed from and the connection
+    //is closed before reconnecting after the idle timeout
+    async fn test_unsubscribe_before_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/", listener.local_addr().expect("No local addr"));
+
+        let (message_tx, mut message_rx) = tokio::sync::mpsc::unbounded_channel();
+        let _server_handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            //Forward every message received until the client closes the connection
+            while let Some(Ok(message)) = ws_stream.next().await {
+                let closed = message.is_close();
+                message_tx.send(message).ok();
+                if closed {
+                    break;
+                }
+            }
+        });
+
+        let (_ws_stream_rx, _stream_handle) = spawn_order_book_stream(
+            ws_base_endpoint,
+            "ethbtc".to_owned(),
+            10,
+            EventPublisher::new(None, ["eth", "btc"], tokio::sync::broadcast::channel(10).0),
+            tokio::sync::watch::channel(false).1,
+            ReconnectBackoff::default().with_idle_timeout(std::time::Duration::from_millis(100)),
+            None,
+            None,
+            None,
+        );
+
+        let mut messages = vec![];
+        for _ in 0..3 {
+            messages.push(
+                tokio::time::timeout(std::time::Duration::from_secs(5), message_rx.recv())
+                    .await
+                    .expect("No message received")
+                    .expect("Server stopped"),
+            );
+        }
+
+        assert_eq!(
+            messages[0],
+            Message::Text(
+                r#"{"event":"bts:subscribe","data":{"channel":"diff_order_book_ethbtc"}}"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            messages[1],
+            Message::Text(
+                r#"{"event":"bts:unsubscribe","data":{"channel":"diff_order_book_ethbtc"}}"#
+                    .to_owned()
+            )
+        );
+        match &messages[2] {
+            Message::Close(Some(close_frame)) => assert_eq!(close_frame.code, CloseCode::Normal),
+            other => panic!("Expected a close frame, got {other:?}"),
+        }
+    }
+
+    //Spawns a mock snapshot endpoint that responds to a single request with the status and body, returning the snapshot base endpoint
+    async fn spawn_mock_snapshot_server(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let snapshot_base_endpoint = format!(
+            "http://{}/api/v2/order_book/",
+            listener.local_addr().expect("No local addr")
+        );
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("Could not accept");
+            //Read until the end of the request headers, the GET request has no body
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket
+                    .read(&mut buffer)
+                    .await
+                    .expect("Could not read request");
+                request.extend_from_slice(&buffer[..n]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            socket
+                .write_all(response.as_bytes())
+                .await
+                .expect("Could not write response");
+        });
+
+        snapshot_base_endpoint
+    }
+
+    #[tokio::test]
+    //Reject a pair that Bitstamp does not list with a typed error rather than a deserialization error
+    async fn test_unsupported_pair() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server("404 Not Found", "Not Found").await;
+
+        let result = get_order_book_snapshot(&snapshot_base_endpoint, "xyzabc").await;
+        assert!(matches!(result, Err(BitstampError::UnsupportedPair(pair)) if pair == "xyzabc"));
+    }
+
+    #[tokio::test]
+    //Fetch a one-time snapshot from a mock snapshot endpoint, keeping only the best levels up to the depth
+    async fn test_rest_snapshot() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            "200 OK",
+            r#"{"timestamp":"1","microtimestamp":"1000000","bids":[["0.0648","1.0"],["0.0650","2.0"],["0.0649","3.0"]],"asks":[["0.0653","1.0"],["0.0651","2.0"],["0.0652","3.0"]]}"#,
+        )
+        .await;
+
+        let (bids, asks) = Bitstamp::new()
+            .with_snapshot_base_endpoint(&snapshot_base_endpoint)
+            .rest_snapshot(["eth", "btc"], 2)
+            .await
+            .expect("Could not get order book snapshot");
+        assert_eq!(
+            bids.iter()
+                .map(|bid| (bid.price.0, bid.quantity.0, bid.exchange.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0.065, 2.0, Exchange::Bitstamp),
+                (0.0649, 3.0, Exchange::Bitstamp)
+            ]
+        );
+        assert_eq!(
+            asks.iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.0651, 2.0), (0.0652, 3.0)]
+        );
+    }
+
+    #[tokio::test]
+    //Serve a snapshot with more levels than the order book depth, checking that only the best levels are sent to the aggregated order book
+    async fn test_snapshot_trimmed_to_depth() {
+        let snapshot_base_endpoint = spawn_mock_snapshot_server(
+            "200 OK",
+            r#"{"timestamp":"1","microtimestamp":"1000000","bids":[["0.0650","1.0"],["0.0649","1.0"],["0.0648","1.0"],["0.0647","1.0"]],"asks":[["0.0651","1.0"],["0.0652","1.0"],["0.0653","1.0"],["0.0654","1.0"]]}"#,
+        )
+        .await;
+
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+
+        let _stream_handler = spawn_stream_handler(
+            snapshot_base_endpoint,
+            "ethbtc".to_owned(),
+            2,
+            ws_stream_rx,
+            price_level_tx,
+            None,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        ws_stream_tx
+            .send(Message::Binary(vec![]))
+            .await
+            .expect("Could not send snapshot request");
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        let bid_prices = snapshot
+            .bids
+            .iter()
+            .map(|bid| bid.price.0)
+            .collect::<Vec<_>>();
+        let ask_prices = snapshot
+            .asks
+            .iter()
+            .map(|ask| ask.price.0)
+            .collect::<Vec<_>>();
+        assert_eq!(bid_prices, vec![0.0650, 0.0649]);
+        assert_eq!(ask_prices, vec![0.0651, 0.0652]);
+    }
 }
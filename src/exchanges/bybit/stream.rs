@@ -0,0 +1,565 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use ordered_float::OrderedFloat;
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
+    task::JoinHandle,
+};
+
+use crate::order_book::price_level::ask::Ask;
+use crate::order_book::price_level::bid::Bid;
+use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{error::BidAskServiceError, exchanges::bybit::error::BybitError};
+
+use crate::events::{EventPublisher, ServiceEventKind};
+use crate::exchanges::exchange_utils;
+use crate::exchanges::reconnect::{
+    connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+};
+use crate::exchanges::services::shutdown_requested;
+use crate::exchanges::Exchange;
+
+use futures::{SinkExt, StreamExt};
+
+use tungstenite::Message;
+
+pub const WS_BASE_ENDPOINT: &str = "wss://stream.bybit.com/v5/public/spot";
+//Depth of the orderbook topic that is subscribed to
+pub const BOOK_DEPTH: usize = 50;
+const SUBSCRIBE_OP: &str = "subscribe";
+const UNSUBSCRIBE_OP: &str = "unsubscribe";
+const PING_OP: &str = "ping";
+const ORDERBOOK_TOPIC: &str = "orderbook";
+const SNAPSHOT_TYPE: &str = "snapshot";
+//Interval to send a ping message at, which Bybit recommends to keep the connection alive
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+// Websocket Public Spot Streams
+
+// Topics are subscribed to with {"op":"subscribe","args":["orderbook.{depth}.{SYMBOL}"]}, which Bybit responds to with the success of the subscription
+// The first message of the orderbook topic is a snapshot of the book, which is followed by deltas of the changed levels. A level with a quantity of 0 is removed
+// Bybit may send a new snapshot at any time, ie. when its service restarts, which replaces the book. A snapshot is also indicated by an update id of 1
+// Connections are dropped without a heartbeat, so {"op":"ping"} is sent every 20 seconds, in addition to responding to ping frames
+
+//Spawns a thread to stream order book updates from Bybit
+pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
+    pair: String,
+    exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+) -> (
+    Receiver<Message>,
+    JoinHandle<Result<(), BidAskServiceError>>,
+) {
+    let (ws_stream_tx, ws_stream_rx) =
+        tokio::sync::mpsc::channel::<Message>(exchange_stream_buffer);
+
+    //spawn a thread that handles the stream and buffers the results
+    let stream_handle = tokio::spawn(async move {
+        let ws_stream_tx = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
+        loop {
+            //Connect to the websocket endpoint
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&ws_base_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(BybitError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
+
+            //Send a subscribe message to notify Bybit to start sending the orderbook topic, which starts with a snapshot
+            let subscription_message = serde_json::to_string(&SubscribeMessage::new(&pair))
+                .map_err(BybitError::SerdeJsonError)?;
+            order_book_stream
+                .send(tungstenite::Message::Text(subscription_message))
+                .await
+                .map_err(BybitError::TungsteniteError)?;
+            let unsubscription_message =
+                serde_json::to_string(&SubscribeMessage::unsubscribe(&pair))
+                    .map_err(BybitError::SerdeJsonError)?;
+
+            tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
+            //Send a ping message at an interval, since Bybit drops connections that do not send a heartbeat
+            let mut ping_interval =
+                tokio::time::interval_at(connected_at + PING_INTERVAL, PING_INTERVAL);
+
+            //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    _ = ping_interval.tick() => {
+                        let ping_message = serde_json::to_string(&OpMessage::ping())
+                            .map_err(BybitError::SerdeJsonError)?;
+                        order_book_stream.send(Message::Text(ping_message)).await.ok();
+                        tracing::debug!("Ping sent");
+                        continue;
+                    }
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the Bybit order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //The subscription starts with a snapshot, so the order book is resynced by reconnecting once the aggregated order book has dropped one of its updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resyncing the Bybit order book, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+                };
+
+                match message {
+                    tungstenite::Message::Text(_) => {
+                        ws_stream_tx
+                            .send(message)
+                            .await
+                            .map_err(BybitError::MessageSendError)?;
+                    }
+
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => match String::from_utf8(data) {
+                        Ok(message) => {
+                            ws_stream_tx
+                                .send(Message::Text(message))
+                                .await
+                                .map_err(BybitError::MessageSendError)?;
+                        }
+                        Err(err) => {
+                            tracing::warn!("Dropping binary message that is not utf8: {err}");
+                        }
+                    },
+
+                    tungstenite::Message::Ping(_) => {
+                        tracing::info!("Ping received");
+                        order_book_stream.send(Message::Pong(vec![])).await.ok();
+                        tracing::info!("Pong sent");
+                    }
+
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(BybitError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
+                        break;
+                    }
+
+                    other => {
+                        tracing::warn!("{other:?}");
+                    }
+                }
+            }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
+        }
+    });
+
+    (ws_stream_rx, stream_handle)
+}
+
+pub fn spawn_stream_handler(
+    mut ws_stream_rx: Receiver<Message>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        //Bybit's levels up to the subscribed depth, used to remove the levels that a delta pushes outside of the depth
+        let mut book_bids: BTreeMap<OrderedFloat<f64>, f64> = BTreeMap::new();
+        let mut book_asks: BTreeMap<OrderedFloat<f64>, f64> = BTreeMap::new();
+
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the Bybit order book stream handler");
+                    break;
+                }
+            };
+
+            if let tungstenite::Message::Text(message) = message {
+                //Deserialize the message, extracting the bids and asks if it is an orderbook message
+                let order_book_message =
+                    match parse_message(&message).map_err(BybitError::SerdeJsonError)? {
+                        BybitMessage::OrderBook(order_book_message) => order_book_message,
+                        BybitMessage::Op(op_response) => {
+                            handle_op_response(op_response)?;
+                            continue;
+                        }
+                    };
+
+                //A snapshot replaces all of Bybit's levels, which happens on each (re)subscription or when Bybit's service restarts
+                let snapshot = order_book_message.is_snapshot();
+                if snapshot {
+                    book_bids.clear();
+                    book_asks.clear();
+                }
+
+                //Collect all of the bids from the update
+                let mut bids = vec![];
+                for [price, quantity] in order_book_message.data.bids.into_iter() {
+                    update_level(&mut book_bids, price, quantity);
+                    bids.push(Bid::new(price, quantity, Exchange::Bybit));
+                }
+
+                //Collect all of the asks from the update
+                let mut asks = vec![];
+                for [price, quantity] in order_book_message.data.asks.into_iter() {
+                    update_level(&mut book_asks, price, quantity);
+                    asks.push(Ask::new(price, quantity, Exchange::Bybit));
+                }
+
+                //Remove the worst levels that are outside of the depth, the lowest bids and highest asks
+                while book_bids.len() > BOOK_DEPTH {
+                    if let Some((price, _)) = book_bids.pop_first() {
+                        bids.push(Bid::new(price.0, 0.0, Exchange::Bybit));
+                    }
+                }
+                while book_asks.len() > BOOK_DEPTH {
+                    if let Some((price, _)) = book_asks.pop_last() {
+                        asks.push(Ask::new(price.0, 0.0, Exchange::Bybit));
+                    }
+                }
+
+                //Send the batched price level update to the aggregated order book, converting the timestamp from milliseconds to microseconds
+                let price_level_update = if snapshot {
+                    PriceLevelUpdate::snapshot(Exchange::Bybit, bids, asks)
+                } else {
+                    PriceLevelUpdate::new(Exchange::Bybit, bids, asks)
+                }
+                .with_exchange_timestamp(order_book_message.timestamp * 1000);
+                price_level_tx
+                    .send(price_level_update)
+                    .await
+                    .map_err(BybitError::PriceLevelUpdateSendError)?;
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+//Handle the response to a subscribe or ping message, failing if the orderbook topic could not be subscribed to.
+//The error is returned as is from the stream handler's task, so it is not boxed despite its size
+#[allow(clippy::result_large_err)]
+fn handle_op_response(op_response: OpResponse) -> Result<(), BybitError> {
+    match op_response.op.as_str() {
+        SUBSCRIBE_OP => {
+            //Retrying a rejected subscription would be rejected again, so the stream handler fails instead
+            if op_response.success == Some(false) {
+                let ret_msg = op_response.ret_msg.unwrap_or_default();
+                tracing::error!("Bybit subscription failed: {ret_msg}");
+                return Err(BybitError::SubscriptionError(ret_msg));
+            }
+            tracing::info!("Bybit subscription succeeded");
+        }
+        PING_OP => {
+            tracing::debug!("Pong received");
+        }
+        other => {
+            tracing::debug!("Dropping Bybit {other} response");
+        }
+    }
+
+    Ok(())
+}
+
+//Set the quantity of a level in Bybit's book, removing the level if the quantity is zero
+fn update_level(levels: &mut BTreeMap<OrderedFloat<f64>, f64>, price: f64, quantity: f64) {
+    if quantity == 0.0 {
+        levels.remove(&OrderedFloat(price));
+    } else {
+        levels.insert(OrderedFloat(price), quantity);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscribeMessage {
+    op: String,
+    args: Vec<String>,
+}
+impl SubscribeMessage {
+    pub fn new(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            op: SUBSCRIBE_OP.to_owned(),
+            args: vec![format!("{ORDERBOOK_TOPIC}.{BOOK_DEPTH}.{pair}")],
+        }
+    }
+
+    pub fn unsubscribe(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            op: UNSUBSCRIBE_OP.to_owned(),
+            ..SubscribeMessage::new(pair)
+        }
+    }
+}
+
+//A message sent to Bybit without any args, such as a ping
+#[derive(Serialize, Debug)]
+pub struct OpMessage {
+    op: String,
+}
+impl OpMessage {
+    pub fn ping() -> OpMessage {
+        OpMessage {
+            op: PING_OP.to_owned(),
+        }
+    }
+}
+
+//The levels of a snapshot or delta of the orderbook topic
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct OrderBookData {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(
+        rename = "b",
+        deserialize_with = "exchange_utils::convert_array_items_to_f64"
+    )]
+    pub bids: Vec<[f64; 2]>,
+    #[serde(
+        rename = "a",
+        deserialize_with = "exchange_utils::convert_array_items_to_f64"
+    )]
+    pub asks: Vec<[f64; 2]>,
+    #[serde(rename = "u")]
+    pub update_id: u64,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct OrderBookMessage {
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub message_type: String,
+    //Milliseconds since the unix epoch that Bybit generated the message at
+    #[serde(rename = "ts")]
+    pub timestamp: u64,
+    pub data: OrderBookData,
+}
+
+impl OrderBookMessage {
+    pub fn is_snapshot(&self) -> bool {
+        self.message_type == SNAPSHOT_TYPE || self.data.update_id == 1
+    }
+}
+
+//The response to a message sent to Bybit, ie. the success of a subscription or a pong
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct OpResponse {
+    pub op: String,
+    pub success: Option<bool>,
+    pub ret_msg: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum BybitMessage {
+    OrderBook(OrderBookMessage),
+    Op(OpResponse),
+}
+
+//Parse a message from the order book stream into an orderbook message, or into the response to a sent message
+pub fn parse_message(message: &str) -> Result<BybitMessage, serde_json::Error> {
+    serde_json::from_str(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::Message;
+
+    use crate::{
+        error::BidAskServiceError,
+        exchanges::{
+            bybit::{
+                error::BybitError,
+                stream::{parse_message, spawn_stream_handler, BybitMessage, OpResponse},
+            },
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[test]
+    fn test_parse_message() {
+        let message = parse_message(
+            r#"{"topic":"orderbook.50.BTCUSDT","ts":1672304484978,"type":"delta","data":{"s":"BTCUSDT","b":[["16493.50","0.006"]],"a":[["16611.00","0"]],"u":18521288,"seq":7961638724},"cts":1672304484976}"#,
+        )
+        .expect("Could not parse orderbook message");
+        let BybitMessage::OrderBook(order_book_message) = message else {
+            panic!("Expected an orderbook message, got {message:?}");
+        };
+        assert!(!order_book_message.is_snapshot());
+        assert_eq!(order_book_message.timestamp, 1672304484978);
+        assert_eq!(order_book_message.data.bids, vec![[16493.5, 0.006]]);
+        assert_eq!(order_book_message.data.asks, vec![[16611.0, 0.0]]);
+
+        //Pongs to the spot stream are sent with the op of the ping
+        let message = parse_message(
+            r#"{"success":true,"ret_msg":"pong","conn_id":"0970e817-426e-429a-a679-ff7f55e0b16a","op":"ping"}"#,
+        )
+        .expect("Could not parse op response");
+        assert_eq!(
+            message,
+            BybitMessage::Op(OpResponse {
+                op: "ping".to_owned(),
+                success: Some(true),
+                ret_msg: Some("pong".to_owned()),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_stream_handler() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _stream_handler = spawn_stream_handler(
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //The subscription response and pong are dropped, and a delta with an update id of 1 is handled as a snapshot
+        for message in [
+            r#"{"success":true,"ret_msg":"subscribe","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","req_id":"","op":"subscribe"}"#,
+            r#"{"topic":"orderbook.50.ETHBTC","ts":1690000000000,"type":"snapshot","data":{"s":"ETHBTC","b":[["0.06500","8.5"]],"a":[["0.06510","12.3"]],"u":1,"seq":100},"cts":1689999999998}"#,
+            r#"{"success":true,"ret_msg":"pong","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","op":"ping"}"#,
+            r#"{"topic":"orderbook.50.ETHBTC","ts":1690000000100,"type":"delta","data":{"s":"ETHBTC","b":[["0.06500","0"],["0.06495","1.5"]],"a":[],"u":2,"seq":101},"cts":1690000000098}"#,
+            r#"{"topic":"orderbook.50.ETHBTC","ts":1690000000200,"type":"delta","data":{"s":"ETHBTC","b":[["0.06480","2.0"]],"a":[["0.06530","1.0"]],"u":1,"seq":102},"cts":1690000000198}"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+
+        let levels = |price_level_update: &PriceLevelUpdate| {
+            (
+                price_level_update
+                    .bids
+                    .iter()
+                    .map(|bid| (bid.price.0, bid.quantity.0))
+                    .collect::<Vec<_>>(),
+                price_level_update
+                    .asks
+                    .iter()
+                    .map(|ask| (ask.price.0, ask.quantity.0))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Bybit);
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.exchange_timestamp, Some(1690000000000000));
+        assert_eq!(
+            levels(&snapshot),
+            (vec![(0.065, 8.5)], vec![(0.0651, 12.3)])
+        );
+
+        let delta = price_level_rx.recv().await.expect("No delta received");
+        assert!(!delta.clear);
+        assert_eq!(delta.exchange_timestamp, Some(1690000000100000));
+        assert_eq!(levels(&delta), (vec![(0.065, 0.0), (0.06495, 1.5)], vec![]));
+
+        let resent_snapshot = price_level_rx
+            .recv()
+            .await
+            .expect("No resent snapshot received");
+        assert!(resent_snapshot.clear);
+        assert_eq!(
+            levels(&resent_snapshot),
+            (vec![(0.0648, 2.0)], vec![(0.0653, 1.0)])
+        );
+        assert!(price_level_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_error() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, _price_level_rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let stream_handler = spawn_stream_handler(
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        ws_stream_tx
+            .send(Message::Text(
+                r#"{"success":false,"ret_msg":"Invalid symbol :[orderbook.50.ETHABC]","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","req_id":"","op":"subscribe"}"#.to_owned(),
+            ))
+            .await
+            .expect("Could not send message");
+
+        match stream_handler.await.expect("Join handle error") {
+            Err(BidAskServiceError::BybitError(BybitError::SubscriptionError(ret_msg))) => {
+                assert_eq!(ret_msg, "Invalid symbol :[orderbook.50.ETHABC]")
+            }
+            other => panic!("Expected a subscription error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+#[derive(thiserror::Error, Debug)]
+pub enum CredentialsError {
+    #[error("Missing environment variable: {0}")]
+    MissingEnvVar(String),
+}
@@ -0,0 +1,127 @@
+pub mod error;
+
+use core::fmt;
+
+use self::error::CredentialsError;
+
+use super::Exchange;
+
+// API credentials for an exchange, loaded from the environment rather than command line args which are visible in process listings.
+// The key and secret are redacted from the Debug output so that they are never logged.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    api_key: String,
+    api_secret: String,
+}
+
+impl Credentials {
+    pub fn new(api_key: String, api_secret: String) -> Self {
+        Credentials {
+            api_key,
+            api_secret,
+        }
+    }
+
+    //Load the exchange's credentials from the <EXCHANGE>_API_KEY and <EXCHANGE>_API_SECRET environment variables, ie. BINANCE_API_KEY
+    pub fn from_env(exchange: &Exchange) -> Result<Self, CredentialsError> {
+        let (api_key_var, api_secret_var) = Credentials::env_vars(exchange);
+        let api_key = std::env::var(&api_key_var)
+            .map_err(|_| CredentialsError::MissingEnvVar(api_key_var))?;
+        let api_secret = std::env::var(&api_secret_var)
+            .map_err(|_| CredentialsError::MissingEnvVar(api_secret_var))?;
+
+        tracing::info!("Loaded {exchange} credentials from the environment");
+        Ok(Credentials::new(api_key, api_secret))
+    }
+
+    //Get the names of the environment variables holding the exchange's api key and secret
+    pub fn env_vars(exchange: &Exchange) -> (String, String) {
+        let prefix = exchange.to_string().to_uppercase();
+        (format!("{prefix}_API_KEY"), format!("{prefix}_API_SECRET"))
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn api_secret(&self) -> &str {
+        &self.api_secret
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &"<redacted>")
+            .field("api_secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::exchanges::{
+        credentials::{error::CredentialsError, Credentials},
+        Exchange,
+    };
+
+    //Captures formatted log output in memory
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_credentials_from_env() {
+        //Bitstamp credentials are only set by this test, so that tests running in parallel do not share the variables
+        let (api_key_var, api_secret_var) = Credentials::env_vars(&Exchange::Bitstamp);
+        assert_eq!(api_key_var, "BITSTAMP_API_KEY");
+        assert_eq!(api_secret_var, "BITSTAMP_API_SECRET");
+
+        std::env::remove_var(&api_secret_var);
+        std::env::set_var(&api_key_var, "test-api-key");
+        assert!(matches!(
+            Credentials::from_env(&Exchange::Bitstamp),
+            Err(CredentialsError::MissingEnvVar(var)) if var == api_secret_var
+        ));
+        std::env::set_var(&api_secret_var, "test-api-secret");
+
+        let captured_logs = CapturedLogs::default();
+        let writer = captured_logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let credentials = tracing::subscriber::with_default(subscriber, || {
+            let credentials =
+                Credentials::from_env(&Exchange::Bitstamp).expect("Could not load credentials");
+            tracing::info!("Using credentials {credentials:?}");
+            credentials
+        });
+
+        assert_eq!(credentials.api_key(), "test-api-key");
+        assert_eq!(credentials.api_secret(), "test-api-secret");
+
+        let logs = String::from_utf8(captured_logs.0.lock().unwrap().clone())
+            .expect("Logs are not valid utf8");
+        assert!(logs.contains("Loaded bitstamp credentials from the environment"));
+        assert!(logs.contains("<redacted>"));
+        assert!(!logs.contains("test-api-key"));
+        assert!(!logs.contains("test-api-secret"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::exchanges::{binance::error::BinanceError, bitstamp::error::BitstampError};
+use crate::exchanges::{binance::error::BinanceError, bitstamp::error::BitstampError, Exchange};
 
 #[derive(thiserror::Error, Debug)]
 pub enum ExchangeError {
@@ -6,4 +6,6 @@ pub enum ExchangeError {
     BinanceError(#[from] BinanceError),
     #[error("Bitstamp error")]
     BitstampError(#[from] BitstampError),
+    #[error("{0} order book snapshots are only streamed, not fetched over REST")]
+    RestSnapshotUnsupported(Exchange),
 }
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use tokio::time::Interval;
+
 use serde::{
     de::{self, SeqAccess, Visitor},
     Deserialize, Deserializer,
@@ -44,3 +46,13 @@ where
     let s = String::deserialize(deserializer)?;
     s.parse::<u64>().map_err(serde::de::Error::custom)
 }
+
+//Wait for the next tick of the interval, or forever if there is no interval, so that optional intervals can be awaited in a select
+pub async fn next_tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use super::Exchange;
+
+// Anomalies detected in the sequence of updates received from an exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateAnomaly {
+    //An update was skipped, ie. the first update id is past the next expected update id
+    Gap,
+    //The sequence went backwards, ie. the update is older than the last update applied
+    Reset,
+    //The update was already applied
+    Duplicate,
+    //A snapshot contained fewer levels than the requested order book depth
+    ShortSnapshot,
+    //An update was dropped because the aggregated order book's price level channel was full
+    DroppedUpdate,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedQualityCounts {
+    pub gaps: u64,
+    pub resets: u64,
+    pub duplicates: u64,
+    pub short_snapshots: u64,
+    pub dropped_updates: u64,
+}
+
+// Records update anomalies per exchange so that feed quality can be quantified rather than silently recovered from
+#[derive(Debug, Default)]
+pub struct FeedQuality {
+    counts: Mutex<HashMap<Exchange, FeedQualityCounts>>,
+}
+
+impl FeedQuality {
+    pub fn new() -> Self {
+        FeedQuality::default()
+    }
+
+    pub fn record(&self, exchange: Exchange, anomaly: UpdateAnomaly) {
+        tracing::warn!("{anomaly:?} detected in {exchange} updates");
+
+        //The lock is only held to increment a counter, so a poisoned lock still holds valid counts
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let exchange_counts = counts.entry(exchange).or_default();
+
+        match anomaly {
+            UpdateAnomaly::Gap => exchange_counts.gaps += 1,
+            UpdateAnomaly::Reset => exchange_counts.resets += 1,
+            UpdateAnomaly::Duplicate => exchange_counts.duplicates += 1,
+            UpdateAnomaly::ShortSnapshot => exchange_counts.short_snapshots += 1,
+            UpdateAnomaly::DroppedUpdate => exchange_counts.dropped_updates += 1,
+        }
+    }
+
+    //Get the anomaly counts for a specific exchange
+    pub fn counts(&self, exchange: &Exchange) -> FeedQualityCounts {
+        self.all_counts().remove(exchange).unwrap_or_default()
+    }
+
+    //Get the anomaly counts for every exchange that has recorded an anomaly
+    pub fn all_counts(&self) -> HashMap<Exchange, FeedQualityCounts> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
@@ -0,0 +1,243 @@
+pub mod error;
+pub mod stream;
+
+use self::stream::{spawn_order_book_stream, spawn_stream_handler, WS_BASE_ENDPOINT};
+use super::{spawn_pair_error, validate_pair, Exchange, OrderBookService, ParsePairError};
+use crate::error::BidAskServiceError;
+use crate::events::{EventPublisher, ServiceEvent};
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::reconnect::ReconnectBackoff;
+use crate::order_book::price_level::PriceLevelUpdate;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct Gemini {
+    //Websocket endpoint of the v2 market data feed that the l2 channel is subscribed to on
+    pub ws_base_endpoint: String,
+    //Backoff between reconnect attempts when the websocket stream is closed
+    pub reconnect_backoff: ReconnectBackoff,
+}
+
+impl Gemini {
+    pub fn new() -> Self {
+        Gemini {
+            ws_base_endpoint: WS_BASE_ENDPOINT.to_owned(),
+            reconnect_backoff: ReconnectBackoff::default(),
+        }
+    }
+
+    pub fn with_ws_base_endpoint(mut self, ws_base_endpoint: &str) -> Self {
+        self.ws_base_endpoint = ws_base_endpoint.to_owned();
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+}
+
+impl Default for Gemini {
+    fn default() -> Self {
+        Gemini::new()
+    }
+}
+
+#[async_trait]
+impl OrderBookService for Gemini {
+    //Gemini requires the pair to be formatted as a symbol with uppercase tickers, ie. ETHBTC
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?.concat().to_uppercase())
+    }
+
+    //Gemini sends a snapshot of the full book on subscribing, so no snapshot is requested over REST and the order book depth is applied by the aggregated order book
+    fn spawn_order_book_service(
+        &self,
+        pair: [&str; 2],
+        _order_book_depth: usize,
+        exchange_stream_buffer: usize,
+        price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        _feed_quality: Option<Arc<FeedQuality>>,
+    ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
+        let stream_pair = match self.format_pair(pair) {
+            Ok(stream_pair) => stream_pair,
+            Err(error) => return spawn_pair_error(Exchange::Gemini, error),
+        };
+        let events = EventPublisher::new(Some(Exchange::Gemini), pair, event_tx);
+
+        tracing::info!("Spawning Gemini order book stream");
+        //Spawn a task to handle a buffered stream of the order book and reconnects to the exchange
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            self.ws_base_endpoint.clone(),
+            stream_pair,
+            exchange_stream_buffer,
+            events,
+            shutdown_rx.clone(),
+            self.reconnect_backoff.clone(),
+        );
+
+        tracing::info!("Spawning Gemini order book stream handler");
+        //Spawn a task to handle updates from the buffered stream, cleaning the data and sending it to the aggregated order book
+        let order_book_update_handle =
+            spawn_stream_handler(ws_stream_rx, price_level_tx, shutdown_rx);
+
+        vec![stream_handle, order_book_update_handle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use futures::{FutureExt, SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tungstenite::Message;
+
+    use crate::{
+        error::BidAskServiceError,
+        exchanges::{gemini::Gemini, Exchange, OrderBookService},
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[tokio::test]
+    async fn test_spawn_order_book_service() {
+        let atomic_counter_0 = Arc::new(AtomicU32::new(0));
+        let atomic_counter_1 = atomic_counter_0.clone();
+        let target_counter = 50;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(500);
+        let (event_tx, _) = tokio::sync::broadcast::channel(10);
+        let mut join_handles = Gemini::new().spawn_order_book_service(
+            ["eth", "btc"],
+            1000,
+            500,
+            tx,
+            event_tx,
+            tokio::sync::watch::channel(false).1,
+            None,
+        );
+
+        let price_level_update_handle = tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                atomic_counter_0.fetch_add(1, Ordering::Relaxed);
+                if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
+                    break;
+                }
+            }
+
+            Ok::<(), BidAskServiceError>(())
+        });
+
+        join_handles.push(price_level_update_handle);
+
+        let futures = join_handles
+            .into_iter()
+            .map(|handle| handle.boxed())
+            .collect::<Vec<_>>();
+
+        //Wait for the first future to be finished
+        let (result, _, _) = futures::future::select_all(futures).await;
+        if atomic_counter_1.load(Ordering::Relaxed) != target_counter {
+            result
+                .expect("Join handle error")
+                .expect("Error when handling WS connection");
+        }
+
+        assert_eq!(atomic_counter_1.load(Ordering::Relaxed), target_counter);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_mock_order_book_service() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/", listener.local_addr().expect("No local addr"));
+
+        //Serve a mock Gemini that sends a snapshot and an update once the l2 channel is subscribed to
+        let (subscription_tx, mut subscription_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            if let Some(Ok(Message::Text(subscription))) = ws_stream.next().await {
+                subscription_tx.send(subscription).ok();
+            }
+
+            for message in [
+                r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["buy","0.065","3"],["buy","0.0649","4"],["sell","0.0651","1"],["sell","0.0652","2"]],"trades":[],"auction_events":[]}"#,
+                r#"{"type":"heartbeat","timestamp":1690000000000}"#,
+                r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["buy","0.065","0"]]}"#,
+            ] {
+                ws_stream
+                    .send(Message::Text(message.to_owned()))
+                    .await
+                    .expect("Could not send message");
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _handles = Gemini::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                25,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+        assert_eq!(
+            subscription,
+            r#"{"type":"subscribe","subscriptions":[{"name":"l2","symbols":["ETHBTC"]}]}"#
+        );
+
+        //The snapshot replaces Gemini's levels, while the update removes the bid with a zero quantity
+        let snapshot = rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Gemini);
+        assert!(snapshot.clear);
+        assert_eq!(
+            snapshot
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 3.0), (0.0649, 4.0)]
+        );
+        assert_eq!(
+            snapshot
+                .asks
+                .iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.0651, 1.0), (0.0652, 2.0)]
+        );
+
+        let update = rx.recv().await.expect("No update received");
+        assert!(!update.clear);
+        assert_eq!(
+            update
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 0.0)]
+        );
+        assert!(update.asks.is_empty());
+    }
+}
@@ -0,0 +1,474 @@
+use serde::{de, de::IgnoredAny, Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
+    task::JoinHandle,
+};
+
+use crate::order_book::price_level::ask::Ask;
+use crate::order_book::price_level::bid::Bid;
+use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{error::BidAskServiceError, exchanges::gemini::error::GeminiError};
+
+use crate::events::{EventPublisher, ServiceEventKind};
+use crate::exchanges::reconnect::{
+    connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+};
+use crate::exchanges::services::shutdown_requested;
+use crate::exchanges::Exchange;
+
+use futures::{SinkExt, StreamExt};
+
+use tungstenite::Message;
+
+pub const WS_BASE_ENDPOINT: &str = "wss://api.gemini.com/v2/marketdata";
+const SUBSCRIBE_TYPE: &str = "subscribe";
+const UNSUBSCRIBE_TYPE: &str = "unsubscribe";
+const L2_CHANNEL: &str = "l2";
+
+// Websocket Market Data v2
+
+// Channels are subscribed to with {"type":"subscribe","subscriptions":[{"name":"l2","symbols":["ETHBTC"]}]}
+// The first l2_updates message of the l2 channel is a snapshot of the full book, which also holds the recent trades and auction events,
+// and is followed by l2_updates messages that only hold the changed levels
+// Levels are sent as changes of [side, price, quantity], where the side is buy or sell, and a level with a quantity of 0 is removed
+// Trades are also sent as trade messages, and a heartbeat message is sent every 5 seconds, which are both dropped
+
+//Spawns a thread to stream order book updates from Gemini
+pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
+    pair: String,
+    exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+) -> (
+    Receiver<Message>,
+    JoinHandle<Result<(), BidAskServiceError>>,
+) {
+    let (ws_stream_tx, ws_stream_rx) =
+        tokio::sync::mpsc::channel::<Message>(exchange_stream_buffer);
+
+    //spawn a thread that handles the stream and buffers the results
+    let stream_handle = tokio::spawn(async move {
+        let ws_stream_tx = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
+        loop {
+            //Connect to the websocket endpoint
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&ws_base_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(GeminiError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
+
+            //Send a subscribe message to notify Gemini to start sending the l2 channel, which starts with a snapshot
+            let subscription_message = serde_json::to_string(&SubscribeMessage::new(&pair))
+                .map_err(GeminiError::SerdeJsonError)?;
+            order_book_stream
+                .send(tungstenite::Message::Text(subscription_message))
+                .await
+                .map_err(GeminiError::TungsteniteError)?;
+            let unsubscription_message =
+                serde_json::to_string(&SubscribeMessage::unsubscribe(&pair))
+                    .map_err(GeminiError::SerdeJsonError)?;
+
+            tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
+            //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the Gemini order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //The subscription starts with a snapshot, so the order book is resynced by reconnecting once the aggregated order book has dropped one of its updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resyncing the Gemini order book, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+                };
+
+                match message {
+                    tungstenite::Message::Text(_) => {
+                        ws_stream_tx
+                            .send(message)
+                            .await
+                            .map_err(GeminiError::MessageSendError)?;
+                    }
+
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => match String::from_utf8(data) {
+                        Ok(message) => {
+                            ws_stream_tx
+                                .send(Message::Text(message))
+                                .await
+                                .map_err(GeminiError::MessageSendError)?;
+                        }
+                        Err(err) => {
+                            tracing::warn!("Dropping binary message that is not utf8: {err}");
+                        }
+                    },
+
+                    tungstenite::Message::Ping(_) => {
+                        tracing::info!("Ping received");
+                        order_book_stream.send(Message::Pong(vec![])).await.ok();
+                        tracing::info!("Pong sent");
+                    }
+
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(GeminiError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
+                        break;
+                    }
+
+                    other => {
+                        tracing::warn!("{other:?}");
+                    }
+                }
+            }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
+        }
+    });
+
+    (ws_stream_rx, stream_handle)
+}
+
+//Handle the messages from the order book stream, sending the changes of each l2_updates message as a price level update
+pub fn spawn_stream_handler(
+    mut ws_stream_rx: Receiver<Message>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the Gemini order book stream handler");
+                    break;
+                }
+            };
+
+            if let tungstenite::Message::Text(message) = message {
+                //Deserialize the message, extracting the changes if it is an l2_updates message
+                let l2_updates =
+                    match parse_message(&message).map_err(GeminiError::SerdeJsonError)? {
+                        GeminiMessage::L2Updates(l2_updates) => l2_updates,
+                        GeminiMessage::Heartbeat => {
+                            tracing::debug!("Heartbeat received");
+                            continue;
+                        }
+                        GeminiMessage::Other => continue,
+                    };
+
+                //A snapshot replaces all of Gemini's levels, which happens on each (re)subscription
+                let snapshot = l2_updates.is_snapshot();
+
+                //Collect all of the bids and asks from the changes
+                let mut bids = vec![];
+                let mut asks = vec![];
+                for change in l2_updates.changes.into_iter() {
+                    match change.side {
+                        Side::Buy => {
+                            bids.push(Bid::new(change.price, change.quantity, Exchange::Gemini))
+                        }
+                        Side::Sell => {
+                            asks.push(Ask::new(change.price, change.quantity, Exchange::Gemini))
+                        }
+                    }
+                }
+
+                let price_level_update = if snapshot {
+                    PriceLevelUpdate::snapshot(Exchange::Gemini, bids, asks)
+                } else {
+                    PriceLevelUpdate::new(Exchange::Gemini, bids, asks)
+                };
+                price_level_tx
+                    .send(price_level_update)
+                    .await
+                    .map_err(GeminiError::PriceLevelUpdateSendError)?;
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Subscription {
+    pub name: String,
+    pub symbols: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscribeMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    subscriptions: Vec<Subscription>,
+}
+impl SubscribeMessage {
+    pub fn new(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            message_type: SUBSCRIBE_TYPE.to_owned(),
+            subscriptions: vec![Subscription {
+                name: L2_CHANNEL.to_owned(),
+                symbols: vec![pair.to_owned()],
+            }],
+        }
+    }
+
+    pub fn unsubscribe(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            message_type: UNSUBSCRIBE_TYPE.to_owned(),
+            ..SubscribeMessage::new(pair)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+//A change of a level of the book, sent as [side, price, quantity]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub side: Side,
+    pub price: f64,
+    pub quantity: f64,
+}
+
+//Deserialize changes sent as [side, price, quantity], parsing the price and quantity from strings
+fn deserialize_changes<'de, D>(deserializer: D) -> Result<Vec<Change>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<(Side, String, String)>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(side, price, quantity)| {
+            Ok(Change {
+                side,
+                price: price.parse().map_err(de::Error::custom)?,
+                quantity: quantity.parse().map_err(de::Error::custom)?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct L2UpdatesMessage {
+    pub symbol: String,
+    #[serde(deserialize_with = "deserialize_changes")]
+    pub changes: Vec<Change>,
+    //The recent trades, which are only sent with the snapshot
+    pub trades: Option<IgnoredAny>,
+}
+
+impl L2UpdatesMessage {
+    //Gemini does not mark the snapshot, but only the snapshot holds the recent trades
+    pub fn is_snapshot(&self) -> bool {
+        self.trades.is_some()
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeminiMessage {
+    L2Updates(L2UpdatesMessage),
+    Heartbeat,
+    //Trade messages and any other messages that do not change the book
+    #[serde(other)]
+    Other,
+}
+
+//Parse a message from the order book stream into an l2_updates message, a heartbeat or a message that does not change the book
+pub fn parse_message(message: &str) -> Result<GeminiMessage, serde_json::Error> {
+    serde_json::from_str(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::Message;
+
+    use crate::{
+        exchanges::{
+            gemini::stream::{parse_message, spawn_stream_handler, Change, GeminiMessage, Side},
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[test]
+    fn test_parse_message() {
+        let message = parse_message(
+            r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["buy","0.065","3.5"],["sell","0.0651","0.00000000"]],"trades":[{"type":"trade","symbol":"ETHBTC","event_id":169841458,"timestamp":1560976400428,"price":"0.065","quantity":"0.0073173","side":"sell"}],"auction_events":[]}"#,
+        )
+        .expect("Could not parse l2_updates message");
+        let GeminiMessage::L2Updates(l2_updates) = message else {
+            panic!("Expected an l2_updates message, got {message:?}");
+        };
+        assert!(l2_updates.is_snapshot());
+        assert_eq!(l2_updates.symbol, "ETHBTC");
+        assert_eq!(
+            l2_updates.changes,
+            vec![
+                Change {
+                    side: Side::Buy,
+                    price: 0.065,
+                    quantity: 3.5
+                },
+                Change {
+                    side: Side::Sell,
+                    price: 0.0651,
+                    quantity: 0.0
+                },
+            ]
+        );
+
+        //Updates only hold the changed levels
+        let message = parse_message(
+            r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["sell","0.0651","1"]]}"#,
+        )
+        .expect("Could not parse l2_updates message");
+        let GeminiMessage::L2Updates(l2_updates) = message else {
+            panic!("Expected an l2_updates message, got {message:?}");
+        };
+        assert!(!l2_updates.is_snapshot());
+
+        for (message, expected) in [
+            (
+                r#"{"type":"heartbeat","timestamp":1560976400428}"#,
+                GeminiMessage::Heartbeat,
+            ),
+            (
+                r#"{"type":"trade","symbol":"ETHBTC","event_id":169841458,"timestamp":1560976400428,"price":"0.065","quantity":"0.0073173","side":"sell"}"#,
+                GeminiMessage::Other,
+            ),
+        ] {
+            assert_eq!(
+                parse_message(message).expect("Could not parse message"),
+                expected
+            );
+        }
+
+        //A change with an unknown side is rejected
+        assert!(parse_message(
+            r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["bid","0.065","1"]]}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_stream_handler() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _stream_handler = spawn_stream_handler(
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //The heartbeat and trade are dropped
+        for message in [
+            r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["buy","0.065","3"],["sell","0.0651","1"]],"trades":[],"auction_events":[]}"#,
+            r#"{"type":"heartbeat","timestamp":1690000000000}"#,
+            r#"{"type":"trade","symbol":"ETHBTC","event_id":1,"timestamp":1690000000000,"price":"0.065","quantity":"1","side":"sell"}"#,
+            r#"{"type":"l2_updates","symbol":"ETHBTC","changes":[["sell","0.0651","0"],["sell","0.0652","2"]]}"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Gemini);
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.bids.len(), 1);
+        assert_eq!(snapshot.asks.len(), 1);
+
+        let update = price_level_rx.recv().await.expect("No update received");
+        assert!(!update.clear);
+        assert!(update.bids.is_empty());
+        assert_eq!(
+            update
+                .asks
+                .iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.0651, 0.0), (0.0652, 2.0)]
+        );
+        assert!(price_level_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,223 @@
+pub mod error;
+pub mod stream;
+
+use self::stream::{
+    spawn_order_book_stream, spawn_stream_handler, subscription_depth, WS_BASE_ENDPOINT,
+};
+use super::{spawn_pair_error, validate_pair, Exchange, OrderBookService, ParsePairError};
+use crate::error::BidAskServiceError;
+use crate::events::{EventPublisher, ServiceEvent};
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::reconnect::ReconnectBackoff;
+use crate::order_book::price_level::PriceLevelUpdate;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct Kraken {
+    //Websocket endpoint that the book channel is subscribed to on
+    pub ws_base_endpoint: String,
+    //Backoff between reconnect attempts when the websocket stream is closed
+    pub reconnect_backoff: ReconnectBackoff,
+}
+
+impl Kraken {
+    pub fn new() -> Self {
+        Kraken {
+            ws_base_endpoint: WS_BASE_ENDPOINT.to_owned(),
+            reconnect_backoff: ReconnectBackoff::default(),
+        }
+    }
+
+    pub fn with_ws_base_endpoint(mut self, ws_base_endpoint: &str) -> Self {
+        self.ws_base_endpoint = ws_base_endpoint.to_owned();
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+}
+
+impl Default for Kraken {
+    fn default() -> Self {
+        Kraken::new()
+    }
+}
+
+#[async_trait]
+impl OrderBookService for Kraken {
+    //Kraken requires the pair to be formatted as BASE/QUOTE in uppercase letters, with Bitcoin as XBT
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?
+            .map(|asset| match asset.to_uppercase().as_str() {
+                "BTC" => "XBT".to_owned(),
+                asset => asset.to_owned(),
+            })
+            .join("/"))
+    }
+
+    //Kraken sends a snapshot of the book on subscribing, so no snapshot is requested over REST.
+    //The book is subscribed at the smallest depth that Kraken supports which holds the order book depth
+    fn spawn_order_book_service(
+        &self,
+        pair: [&str; 2],
+        order_book_depth: usize,
+        exchange_stream_buffer: usize,
+        price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        _feed_quality: Option<Arc<FeedQuality>>,
+    ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
+        let stream_pair = match self.format_pair(pair) {
+            Ok(stream_pair) => stream_pair,
+            Err(error) => return spawn_pair_error(Exchange::Kraken, error),
+        };
+        let events = EventPublisher::new(Some(Exchange::Kraken), pair, event_tx);
+        let depth = subscription_depth(order_book_depth);
+        //The stream handler requests a resubscribe when an update's checksum does not match its book
+        let (resubscribe_tx, resubscribe_rx) = tokio::sync::mpsc::channel::<()>(1);
+
+        tracing::info!("Spawning Kraken order book stream");
+        //Spawn a task to handle a buffered stream of the order book and reconnects to the exchange
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            self.ws_base_endpoint.clone(),
+            stream_pair,
+            depth,
+            exchange_stream_buffer,
+            events,
+            shutdown_rx.clone(),
+            self.reconnect_backoff.clone(),
+            resubscribe_rx,
+        );
+
+        tracing::info!("Spawning Kraken order book stream handler");
+        //Spawn a task to handle updates from the buffered stream, cleaning the data and sending it to the aggregated order book
+        let order_book_update_handle = spawn_stream_handler(
+            depth,
+            ws_stream_rx,
+            price_level_tx,
+            resubscribe_tx,
+            shutdown_rx,
+        );
+
+        vec![stream_handle, order_book_update_handle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tungstenite::Message;
+
+    use crate::{
+        exchanges::{kraken::Kraken, Exchange, OrderBookService},
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[tokio::test]
+    async fn test_spawn_order_book_service() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/", listener.local_addr().expect("No local addr"));
+
+        //Serve a mock Kraken that sends a snapshot and an update once the book is subscribed to, followed by an update with a checksum that does not match
+        let (subscription_tx, mut subscription_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            if let Some(Ok(Message::Text(subscription))) = ws_stream.next().await {
+                subscription_tx.send(subscription).ok();
+            }
+
+            for message in [
+                r#"[0,{"as":[["0.06510","1.0","1.1"],["0.06520","2.0","1.2"]],"bs":[["0.06500","3.0","1.3"],["0.06490","4.0","1.4"]]},"book-10","ETH/XBT"]"#,
+                r#"[0,{"b":[["0.06500","0.00000000","1.5"]],"c":"1208324492"},"book-10","ETH/XBT"]"#,
+                r#"[0,{"b":[["0.06480","1.0","1.6"]],"c":"1"},"book-10","ETH/XBT"]"#,
+            ] {
+                ws_stream
+                    .send(Message::Text(message.to_owned()))
+                    .await
+                    .expect("Could not send message");
+            }
+
+            //Forward the messages sent to resubscribe
+            while let Some(Ok(Message::Text(message))) = ws_stream.next().await {
+                subscription_tx.send(message).ok();
+            }
+        });
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _handles = Kraken::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                5,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+        assert_eq!(
+            subscription,
+            r#"{"event":"subscribe","pair":["ETH/XBT"],"subscription":{"name":"book","depth":10}}"#
+        );
+
+        //The snapshot replaces Kraken's levels, while the update removes the bid with a zero quantity
+        let snapshot = rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Kraken);
+        assert!(snapshot.clear);
+        assert_eq!(
+            snapshot
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 3.0), (0.0649, 4.0)]
+        );
+        assert_eq!(
+            snapshot
+                .asks
+                .iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.0651, 1.0), (0.0652, 2.0)]
+        );
+
+        let update = rx.recv().await.expect("No update received");
+        assert!(!update.clear);
+        assert_eq!(
+            update
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 0.0)]
+        );
+        assert!(update.asks.is_empty());
+
+        //The mismatched checksum resubscribes to the book, unsubscribing first
+        rx.recv().await.expect("No update received");
+        assert_eq!(
+            subscription_rx.recv().await.expect("No unsubscription"),
+            r#"{"event":"unsubscribe","pair":["ETH/XBT"],"subscription":{"name":"book","depth":10}}"#
+        );
+        assert_eq!(
+            subscription_rx.recv().await.expect("No subscription"),
+            subscription
+        );
+    }
+}
@@ -0,0 +1,795 @@
+use std::collections::BTreeMap;
+
+use ordered_float::OrderedFloat;
+use serde::{de, Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
+    task::JoinHandle,
+};
+
+use crate::order_book::price_level::ask::Ask;
+use crate::order_book::price_level::bid::Bid;
+use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{error::BidAskServiceError, exchanges::kraken::error::KrakenError};
+
+use crate::events::{EventPublisher, ServiceEventKind};
+use crate::exchanges::reconnect::{
+    connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+};
+use crate::exchanges::services::shutdown_requested;
+use crate::exchanges::Exchange;
+
+use futures::{SinkExt, StreamExt};
+
+use tungstenite::Message;
+
+pub const WS_BASE_ENDPOINT: &str = "wss://ws.kraken.com/";
+const SUBSCRIBE_EVENT: &str = "subscribe";
+const UNSUBSCRIBE_EVENT: &str = "unsubscribe";
+const BOOK_CHANNEL: &str = "book";
+const HEARTBEAT_EVENT: &str = "heartbeat";
+const SUBSCRIPTION_STATUS_EVENT: &str = "subscriptionStatus";
+const SYSTEM_STATUS_EVENT: &str = "systemStatus";
+const ERROR_STATUS: &str = "error";
+//Depths that Kraken accepts when subscribing to the book channel
+const BOOK_DEPTHS: [usize; 5] = [10, 25, 100, 500, 1000];
+//Number of levels of each side that the checksum is computed from
+const CHECKSUM_DEPTH: usize = 10;
+
+// Websocket Public Market Data
+
+// Payloads of subscribed channels are sent as arrays of [channelID, payload..., channelName, pair], while events such as heartbeats are sent as objects
+// The first book payload after subscribing is a snapshot of the book holding "as" and "bs", which is followed by updates holding "a" and/or "b"
+// Bids and asks of an update can be sent as two separate payloads in the same array
+// Levels that fall outside of the subscribed depth are not removed by an update, so the book is truncated to the depth after each update
+// Updates hold a CRC32 checksum "c" of the top 10 levels of the book after the update is applied, which is computed from the prices and volumes as they are sent
+// The book is resubscribed to on a checksum mismatch, unsubscribing first since Kraken rejects a subscription to a book that is already subscribed to
+
+//Get the smallest book depth that Kraken accepts which holds the order book depth, or the largest depth if the order book depth is larger
+pub fn subscription_depth(order_book_depth: usize) -> usize {
+    BOOK_DEPTHS
+        .into_iter()
+        .find(|depth| *depth >= order_book_depth)
+        .unwrap_or(BOOK_DEPTHS[BOOK_DEPTHS.len() - 1])
+}
+
+//Spawns a thread to stream order book updates from Kraken
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
+    pair: String,
+    depth: usize,
+    exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+    mut resubscribe_rx: Receiver<()>,
+) -> (
+    Receiver<Message>,
+    JoinHandle<Result<(), BidAskServiceError>>,
+) {
+    let (ws_stream_tx, ws_stream_rx) =
+        tokio::sync::mpsc::channel::<Message>(exchange_stream_buffer);
+
+    //spawn a thread that handles the stream and buffers the results
+    let stream_handle = tokio::spawn(async move {
+        let ws_stream_tx = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
+        loop {
+            //Connect to the websocket endpoint
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&ws_base_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(KrakenError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
+
+            //Send a subscribe message to notify Kraken to start sending the book, which starts with a snapshot
+            let subscription_message = serde_json::to_string(&SubscribeMessage::new(&pair, depth))
+                .map_err(KrakenError::SerdeJsonError)?;
+            order_book_stream
+                .send(tungstenite::Message::Text(subscription_message.clone()))
+                .await
+                .map_err(KrakenError::TungsteniteError)?;
+            let unsubscription_message =
+                serde_json::to_string(&SubscribeMessage::unsubscribe(&pair, depth))
+                    .map_err(KrakenError::SerdeJsonError)?;
+
+            //A resubscribe requested for the previous connection is already handled by the new subscription
+            while resubscribe_rx.try_recv().is_ok() {}
+
+            tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
+            //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    Some(()) = resubscribe_rx.recv() => {
+                        tracing::warn!("Resubscribing to the Kraken book for a new snapshot");
+                        for message in [&unsubscription_message, &subscription_message] {
+                            order_book_stream
+                                .send(tungstenite::Message::Text(message.clone()))
+                                .await
+                                .map_err(KrakenError::TungsteniteError)?;
+                        }
+                        continue;
+                    }
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the Kraken order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //Resubscribe for a new snapshot once the aggregated order book has dropped one of the book's updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resubscribing to the Kraken book to resync from a new snapshot");
+                        for message in [&unsubscription_message, &subscription_message] {
+                            order_book_stream
+                                .send(tungstenite::Message::Text(message.clone()))
+                                .await
+                                .map_err(KrakenError::TungsteniteError)?;
+                        }
+                        continue;
+                    }
+                };
+
+                match message {
+                    tungstenite::Message::Text(_) => {
+                        ws_stream_tx
+                            .send(message)
+                            .await
+                            .map_err(KrakenError::MessageSendError)?;
+                    }
+
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => match String::from_utf8(data) {
+                        Ok(message) => {
+                            ws_stream_tx
+                                .send(Message::Text(message))
+                                .await
+                                .map_err(KrakenError::MessageSendError)?;
+                        }
+                        Err(err) => {
+                            tracing::warn!("Dropping binary message that is not utf8: {err}");
+                        }
+                    },
+
+                    tungstenite::Message::Ping(_) => {
+                        tracing::info!("Ping received");
+                        order_book_stream.send(Message::Pong(vec![])).await.ok();
+                        tracing::info!("Pong sent");
+                    }
+
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(KrakenError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
+                        break;
+                    }
+
+                    other => {
+                        tracing::warn!("{other:?}");
+                    }
+                }
+            }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
+        }
+    });
+
+    (ws_stream_rx, stream_handle)
+}
+
+//Handle the messages from the order book stream, requesting a resubscribe through the resubscribe channel when an update's checksum does not match the book
+pub fn spawn_stream_handler(
+    depth: usize,
+    mut ws_stream_rx: Receiver<Message>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+    resubscribe_tx: Sender<()>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        //Kraken's levels up to the subscribed depth, used to remove the levels that an update pushes outside of the depth and to verify the checksum
+        let mut book_bids: BTreeMap<OrderedFloat<f64>, BookLevel> = BTreeMap::new();
+        let mut book_asks: BTreeMap<OrderedFloat<f64>, BookLevel> = BTreeMap::new();
+        //Checksums are not verified after a mismatch until the snapshot from resubscribing replaces the book
+        let mut resubscribing = false;
+
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the Kraken order book stream handler");
+                    break;
+                }
+            };
+
+            if let tungstenite::Message::Text(message) = message {
+                //Deserialize the message, extracting the bids and asks if it is a book payload
+                let book_data =
+                    match parse_message(&message).map_err(KrakenError::SerdeJsonError)? {
+                        KrakenMessage::Book(book_data) => book_data,
+                        KrakenMessage::Event(event) => {
+                            handle_event(event)?;
+                            continue;
+                        }
+                    };
+
+                //A snapshot replaces all of Kraken's levels, which happens on each (re)subscription
+                if book_data.snapshot {
+                    book_bids.clear();
+                    book_asks.clear();
+                    resubscribing = false;
+                }
+
+                //Collect all of the bids from the update
+                let mut bids = vec![];
+                for level in book_data.bids.into_iter() {
+                    bids.push(Bid::new(level.price, level.quantity, Exchange::Kraken));
+                    update_level(&mut book_bids, level);
+                }
+
+                //Collect all of the asks from the update
+                let mut asks = vec![];
+                for level in book_data.asks.into_iter() {
+                    asks.push(Ask::new(level.price, level.quantity, Exchange::Kraken));
+                    update_level(&mut book_asks, level);
+                }
+
+                //Remove the worst levels that are outside of the depth, the lowest bids and highest asks
+                while book_bids.len() > depth {
+                    if let Some((price, _)) = book_bids.pop_first() {
+                        bids.push(Bid::new(price.0, 0.0, Exchange::Kraken));
+                    }
+                }
+                while book_asks.len() > depth {
+                    if let Some((price, _)) = book_asks.pop_last() {
+                        asks.push(Ask::new(price.0, 0.0, Exchange::Kraken));
+                    }
+                }
+
+                //Resubscribe for a new snapshot if the book has drifted from Kraken's, still sending the update since the snapshot replaces Kraken's levels
+                if let Some(checksum) = book_data.checksum {
+                    let book_checksum = book_checksum(&book_bids, &book_asks);
+                    if !resubscribing && checksum != book_checksum {
+                        tracing::warn!(
+                            "Kraken checksum mismatch, expected {checksum} but the book has {book_checksum}, resubscribing..."
+                        );
+                        resubscribing = true;
+                        //A full channel already holds a resubscribe request
+                        resubscribe_tx.try_send(()).ok();
+                    }
+                }
+
+                //Send the batched price level update to the aggregated order book
+                let mut price_level_update = if book_data.snapshot {
+                    PriceLevelUpdate::snapshot(Exchange::Kraken, bids, asks)
+                } else {
+                    PriceLevelUpdate::new(Exchange::Kraken, bids, asks)
+                };
+                if let Some(timestamp) = book_data.timestamp {
+                    price_level_update = price_level_update.with_exchange_timestamp(timestamp);
+                }
+                price_level_tx
+                    .send(price_level_update)
+                    .await
+                    .map_err(KrakenError::PriceLevelUpdateSendError)?;
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+//Handle an event from the stream, failing if the book could not be subscribed to. Heartbeats are sent once a second
+//while no book payloads are sent, and are dropped along with the other events.
+//The error is returned as is from the stream handler's task, so it is not boxed despite its size
+#[allow(clippy::result_large_err)]
+fn handle_event(event: KrakenEvent) -> Result<(), KrakenError> {
+    match event.event.as_str() {
+        HEARTBEAT_EVENT => {}
+        SUBSCRIPTION_STATUS_EVENT => {
+            //Retrying a rejected subscription would be rejected again, so the stream handler fails instead
+            if event.status.as_deref() == Some(ERROR_STATUS) {
+                let error_message = event.error_message.unwrap_or_default();
+                tracing::error!("Kraken subscription failed: {error_message}");
+                return Err(KrakenError::SubscriptionError(error_message));
+            }
+            tracing::info!("Kraken subscription status: {:?}", event.status);
+        }
+        SYSTEM_STATUS_EVENT => {
+            tracing::info!("Kraken system status: {:?}", event.status);
+        }
+        other => {
+            tracing::debug!("Dropping Kraken {other} event");
+        }
+    }
+
+    Ok(())
+}
+
+//Set a level in Kraken's book, removing the level if the quantity is zero
+pub fn update_level(levels: &mut BTreeMap<OrderedFloat<f64>, BookLevel>, level: BookLevel) {
+    if level.quantity == 0.0 {
+        levels.remove(&OrderedFloat(level.price));
+    } else {
+        levels.insert(OrderedFloat(level.price), level);
+    }
+}
+
+//Compute Kraken's checksum of the book, the CRC32 of the price and volume of the top 10 asks from the lowest price followed by the top 10 bids
+//from the highest price, where each price and volume is concatenated as sent with the decimal point and leading zeros removed
+pub fn book_checksum(
+    book_bids: &BTreeMap<OrderedFloat<f64>, BookLevel>,
+    book_asks: &BTreeMap<OrderedFloat<f64>, BookLevel>,
+) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for level in book_asks
+        .values()
+        .take(CHECKSUM_DEPTH)
+        .chain(book_bids.values().rev().take(CHECKSUM_DEPTH))
+    {
+        for value in [&level.price_text, &level.quantity_text] {
+            hasher.update(value.replace('.', "").trim_start_matches('0').as_bytes());
+        }
+    }
+    hasher.finalize()
+}
+
+#[derive(Serialize, Debug)]
+pub struct Subscription {
+    name: String,
+    depth: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscribeMessage {
+    event: String,
+    pair: Vec<String>,
+    subscription: Subscription,
+}
+impl SubscribeMessage {
+    pub fn new(pair: &str, depth: usize) -> SubscribeMessage {
+        SubscribeMessage {
+            event: SUBSCRIBE_EVENT.to_owned(),
+            pair: vec![pair.to_owned()],
+            subscription: Subscription {
+                name: BOOK_CHANNEL.to_owned(),
+                depth,
+            },
+        }
+    }
+
+    pub fn unsubscribe(pair: &str, depth: usize) -> SubscribeMessage {
+        SubscribeMessage {
+            event: UNSUBSCRIBE_EVENT.to_owned(),
+            ..SubscribeMessage::new(pair, depth)
+        }
+    }
+}
+
+//A level of a book payload, keeping the price and volume as they are sent so that the checksum can be computed from them
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookLevel {
+    pub price: f64,
+    pub quantity: f64,
+    pub price_text: String,
+    pub quantity_text: String,
+}
+
+//Deserialize levels sent as [price, volume, timestamp, ...], ignoring any trailing items such as the timestamp
+fn deserialize_levels<'de, D>(deserializer: D) -> Result<Vec<BookLevel>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<Vec<String>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|level| match level.as_slice() {
+            [price_text, quantity_text, ..] => Ok(BookLevel {
+                price: price_text.parse().map_err(de::Error::custom)?,
+                quantity: quantity_text.parse().map_err(de::Error::custom)?,
+                price_text: price_text.to_owned(),
+                quantity_text: quantity_text.to_owned(),
+            }),
+            _ => Err(de::Error::invalid_length(
+                level.len(),
+                &"a level starting with a price and volume",
+            )),
+        })
+        .collect()
+}
+
+//A payload of the book channel, holding the snapshot levels or the updated levels
+#[derive(Deserialize, Debug)]
+pub struct BookPayload {
+    #[serde(rename = "as", default, deserialize_with = "deserialize_levels")]
+    snapshot_asks: Vec<BookLevel>,
+    #[serde(rename = "bs", default, deserialize_with = "deserialize_levels")]
+    snapshot_bids: Vec<BookLevel>,
+    #[serde(rename = "a", default, deserialize_with = "deserialize_levels")]
+    asks: Vec<BookLevel>,
+    #[serde(rename = "b", default, deserialize_with = "deserialize_levels")]
+    bids: Vec<BookLevel>,
+    #[serde(rename = "c")]
+    checksum: Option<String>,
+}
+
+//The levels of the book payloads in a message from the book channel
+#[derive(Debug, Default, PartialEq)]
+pub struct BookData {
+    pub snapshot: bool,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+    //The newest timestamp of the levels in microseconds since the unix epoch
+    pub timestamp: Option<u64>,
+    //The checksum of the book after the update is applied, which is only sent with updates
+    pub checksum: Option<u32>,
+}
+
+//An event sent by Kraken, such as a heartbeat or the status of a subscription
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct KrakenEvent {
+    pub event: String,
+    pub status: Option<String>,
+    #[serde(rename = "errorMessage")]
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KrakenMessage {
+    Book(BookData),
+    Event(KrakenEvent),
+}
+
+//Parse a message from the order book stream into the levels of a book payload, or into an event
+pub fn parse_message(message: &str) -> Result<KrakenMessage, serde_json::Error> {
+    //Events are sent as objects, while channel payloads are sent as heterogeneous arrays
+    let items = match serde_json::from_str::<serde_json::Value>(message)? {
+        serde_json::Value::Array(items) => items,
+        event => return Ok(KrakenMessage::Event(serde_json::from_value(event)?)),
+    };
+
+    //The payloads are the objects between the channel id and the channel name and pair
+    let mut book_data = BookData::default();
+    for item in items.into_iter().filter(|item| item.is_object()) {
+        //Snapshots are identified by their keys, so that an empty side still replaces the existing levels
+        if item.get("as").is_some() || item.get("bs").is_some() {
+            book_data.snapshot = true;
+        }
+        book_data.timestamp = book_data.timestamp.max(latest_level_timestamp(&item));
+        let payload = serde_json::from_value::<BookPayload>(item)?;
+
+        book_data.bids.extend(payload.snapshot_bids);
+        book_data.bids.extend(payload.bids);
+        book_data.asks.extend(payload.snapshot_asks);
+        book_data.asks.extend(payload.asks);
+        if let Some(checksum) = payload.checksum {
+            book_data.checksum = Some(checksum.parse().map_err(de::Error::custom)?);
+        }
+    }
+
+    Ok(KrakenMessage::Book(book_data))
+}
+
+//Get the newest timestamp of the levels in a book payload in microseconds. Each level is sent as [price, volume, timestamp, ...] with the timestamp in seconds
+fn latest_level_timestamp(payload: &serde_json::Value) -> Option<u64> {
+    ["as", "bs", "a", "b"]
+        .iter()
+        .filter_map(|key| payload.get(key)?.as_array())
+        .flatten()
+        .filter_map(|level| parse_timestamp_micros(level.get(2)?.as_str()?))
+        .max()
+}
+
+//Parse a timestamp in seconds with up to microsecond precision, ie. "1534614248.456738", into microseconds without rounding it through a float
+fn parse_timestamp_micros(timestamp: &str) -> Option<u64> {
+    let (seconds, fraction) = timestamp.split_once('.').unwrap_or((timestamp, ""));
+    let micros = format!("{:0<6}", fraction.get(..6).unwrap_or(fraction));
+    Some(seconds.parse::<u64>().ok()? * 1_000_000 + micros.parse::<u64>().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tungstenite::Message;
+
+    use crate::{
+        error::BidAskServiceError,
+        exchanges::{
+            kraken::{
+                error::KrakenError,
+                stream::{
+                    book_checksum, parse_message, spawn_stream_handler, subscription_depth,
+                    update_level, BookData, BookLevel, KrakenEvent, KrakenMessage,
+                },
+            },
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[test]
+    fn test_subscription_depth() {
+        assert_eq!(subscription_depth(1), 10);
+        assert_eq!(subscription_depth(10), 10);
+        assert_eq!(subscription_depth(50), 100);
+        assert_eq!(subscription_depth(5000), 1000);
+    }
+
+    #[test]
+    fn test_parse_message() {
+        //The bids and asks of an update can be sent as separate payloads, with republished levels marked by a trailing "r"
+        let message = parse_message(
+            r#"[1234,{"a":[["5541.30000","2.50700000","1534614248.456738"]]},{"b":[["5541.20000","1.52900000","1534614248.765567","r"]],"c":"974942666"},"book-10","XBT/USD"]"#,
+        )
+        .expect("Could not parse book message");
+        assert_eq!(
+            message,
+            KrakenMessage::Book(BookData {
+                snapshot: false,
+                bids: vec![BookLevel {
+                    price: 5541.2,
+                    quantity: 1.529,
+                    price_text: "5541.20000".to_owned(),
+                    quantity_text: "1.52900000".to_owned(),
+                }],
+                asks: vec![BookLevel {
+                    price: 5541.3,
+                    quantity: 2.507,
+                    price_text: "5541.30000".to_owned(),
+                    quantity_text: "2.50700000".to_owned(),
+                }],
+                timestamp: Some(1534614248765567),
+                checksum: Some(974942666),
+            })
+        );
+
+        let message = parse_message(
+            r#"{"channelName":"book-10","event":"subscriptionStatus","pair":"XBT/USD","status":"error","errorMessage":"Currency pair not supported"}"#,
+        )
+        .expect("Could not parse event");
+        assert_eq!(
+            message,
+            KrakenMessage::Event(KrakenEvent {
+                event: "subscriptionStatus".to_owned(),
+                status: Some("error".to_owned()),
+                error_message: Some("Currency pair not supported".to_owned()),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_stream_handler() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let (resubscribe_tx, mut resubscribe_rx) = tokio::sync::mpsc::channel::<()>(1);
+        let _stream_handler = spawn_stream_handler(
+            2,
+            ws_stream_rx,
+            price_level_tx,
+            resubscribe_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //Captured frames from the book channel, where events are dropped and the snapshot is followed by an update
+        for message in [
+            r#"{"connectionID":8628615390848610000,"event":"systemStatus","status":"online","version":"1.9.0"}"#,
+            r#"{"channelID":336,"channelName":"book-10","event":"subscriptionStatus","pair":"ETH/XBT","status":"subscribed","subscription":{"depth":10,"name":"book"}}"#,
+            r#"[336,{"as":[["0.06510","12.30000000","1690000000.100000"],["0.06520","4.10000000","1690000000.200000"]],"bs":[["0.06500","8.50000000","1690000000.300000"],["0.06490","1.00000000","1690000000.400000"]]},"book-10","ETH/XBT"]"#,
+            r#"{"event":"heartbeat"}"#,
+            r#"[336,{"a":[["0.06515","2.00000000","1690000001.100000"]]},{"b":[["0.06500","0.00000000","1690000001.200000"]],"c":"3352175854"},"book-10","ETH/XBT"]"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+
+        let levels = |price_level_update: &PriceLevelUpdate| {
+            (
+                price_level_update
+                    .bids
+                    .iter()
+                    .map(|bid| (bid.price.0, bid.quantity.0))
+                    .collect::<Vec<_>>(),
+                price_level_update
+                    .asks
+                    .iter()
+                    .map(|ask| (ask.price.0, ask.quantity.0))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Kraken);
+        assert!(snapshot.clear);
+        //The newest level timestamp is used as the exchange timestamp
+        assert_eq!(snapshot.exchange_timestamp, Some(1690000000400000));
+        assert_eq!(
+            levels(&snapshot),
+            (
+                vec![(0.065, 8.5), (0.0649, 1.0)],
+                vec![(0.0651, 12.3), (0.0652, 4.1)]
+            )
+        );
+
+        //The new ask pushes the worst ask outside of the depth, so it is removed along with the removed bid
+        let update = price_level_rx.recv().await.expect("No update received");
+        assert_eq!(update.exchange, Exchange::Kraken);
+        assert!(!update.clear);
+        assert_eq!(update.exchange_timestamp, Some(1690000001200000));
+        assert_eq!(
+            levels(&update),
+            (vec![(0.065, 0.0)], vec![(0.06515, 2.0), (0.0652, 0.0)])
+        );
+        assert!(price_level_rx.try_recv().is_err());
+        //The checksum matches the book truncated to the depth, so no resubscribe is requested
+        assert!(resubscribe_rx.try_recv().is_err());
+    }
+
+    //A snapshot of 11 levels on each side, where the 11th levels are outside of the checksum's top 10 levels
+    const CHECKSUM_SNAPSHOT: &str = r#"[336,{"as":[["0.06510","1.00000000","1690000000.000000"],["0.06511","2.00012345","1690000000.000001"],["0.06512","3.00024690","1690000000.000002"],["0.06513","4.00037035","1690000000.000003"],["0.06514","5.00049380","1690000000.000004"],["0.06515","6.00061725","1690000000.000005"],["0.06516","7.00074070","1690000000.000006"],["0.06517","8.00086415","1690000000.000007"],["0.06518","9.00098760","1690000000.000008"],["0.06519","10.00111105","1690000000.000009"],["0.06520","11.00123450","1690000000.000010"]],"bs":[["0.06500","2.00000000","1690000000.000000"],["0.06499","3.00054321","1690000000.000001"],["0.06498","4.00108642","1690000000.000002"],["0.06497","5.00162963","1690000000.000003"],["0.06496","6.00217284","1690000000.000004"],["0.06495","7.00271605","1690000000.000005"],["0.06494","8.00325926","1690000000.000006"],["0.06493","9.00380247","1690000000.000007"],["0.06492","10.00434568","1690000000.000008"],["0.06491","11.00488889","1690000000.000009"],["0.06490","12.00543210","1690000000.000010"]]},"book-25","ETH/XBT"]"#;
+
+    #[test]
+    fn test_book_checksum() {
+        let KrakenMessage::Book(book_data) =
+            parse_message(CHECKSUM_SNAPSHOT).expect("Could not parse snapshot")
+        else {
+            panic!("Expected a book payload");
+        };
+
+        let mut book_bids = BTreeMap::new();
+        let mut book_asks = BTreeMap::new();
+        for level in book_data.bids {
+            update_level(&mut book_bids, level);
+        }
+        for level in book_data.asks {
+            update_level(&mut book_asks, level);
+        }
+
+        //The CRC32 of "6510" "100000000" "6511" "200012345" ... "6491" "1100488889", with the decimal points and leading zeros removed
+        assert_eq!(book_checksum(&book_bids, &book_asks), 72833546);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let (resubscribe_tx, mut resubscribe_rx) = tokio::sync::mpsc::channel::<()>(1);
+        let _stream_handler = spawn_stream_handler(
+            25,
+            ws_stream_rx,
+            price_level_tx,
+            resubscribe_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //The first update removes the best bid and changes the volume of an ask, matching the checksum of the book.
+        //The following updates hold checksums that do not match, as if an update was dropped
+        for message in [
+            CHECKSUM_SNAPSHOT,
+            r#"[336,{"a":[["0.06512","0.50000000","1690000001.000000"]]},{"b":[["0.06500","0.00000000","1690000001.000000"]],"c":"2365665319"},"book-25","ETH/XBT"]"#,
+            r#"[336,{"a":[["0.06513","1.00000000","1690000002.000000"]],"c":"1"},"book-25","ETH/XBT"]"#,
+            r#"[336,{"a":[["0.06514","1.00000000","1690000003.000000"]],"c":"1"},"book-25","ETH/XBT"]"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+        for _ in 0..4 {
+            price_level_rx.recv().await.expect("No update received");
+        }
+
+        //A resubscribe is only requested once until the snapshot from resubscribing is received
+        assert!(resubscribe_rx.try_recv().is_ok());
+        assert!(resubscribe_rx.try_recv().is_err());
+
+        for message in [
+            CHECKSUM_SNAPSHOT,
+            r#"[336,{"a":[["0.06513","1.00000000","1690000004.000000"]],"c":"1"},"book-25","ETH/XBT"]"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+        for _ in 0..2 {
+            price_level_rx.recv().await.expect("No update received");
+        }
+        assert!(resubscribe_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_error() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, _price_level_rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let stream_handler = spawn_stream_handler(
+            10,
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::mpsc::channel(1).0,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        ws_stream_tx
+            .send(Message::Text(
+                r#"{"channelName":"book-10","event":"subscriptionStatus","pair":"ETH/ABC","status":"error","errorMessage":"Currency pair not supported ETH/ABC"}"#.to_owned(),
+            ))
+            .await
+            .expect("Could not send message");
+
+        match stream_handler.await.expect("Join handle error") {
+            Err(BidAskServiceError::KrakenError(KrakenError::SubscriptionError(error_message))) => {
+                assert_eq!(error_message, "Currency pair not supported ETH/ABC")
+            }
+            other => panic!("Expected a subscription error, got {other:?}"),
+        }
+    }
+}
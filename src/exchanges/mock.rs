@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
+
+use super::{feed_quality::FeedQuality, services::shutdown_requested, OrderBookService};
+use crate::{
+    error::BidAskServiceError,
+    events::{EventPublisher, ServiceEvent, ServiceEventKind},
+    order_book::{error::OrderBookError, price_level::PriceLevelUpdate},
+};
+
+// An exchange that replays a fixed sequence of price level updates instead of connecting to a websocket,
+// so that the aggregation pipeline can be tested deterministically without network access
+#[derive(Debug, Clone)]
+pub struct MockExchange {
+    //Updates to send to the aggregated order book, in order
+    pub updates: Vec<PriceLevelUpdate>,
+    //Delay before each update is sent
+    pub interval: Duration,
+}
+
+impl MockExchange {
+    pub fn from_updates(updates: Vec<PriceLevelUpdate>, interval: Duration) -> Self {
+        MockExchange { updates, interval }
+    }
+}
+
+#[async_trait]
+impl OrderBookService for MockExchange {
+    //The depth and buffer are ignored, since the updates are replayed as they were provided.
+    //The exchange of the first update is published as connected before replaying, like a stream connecting to the exchange.
+    //Once every update has been replayed the service idles like a quiet stream, rather than finishing and being treated as stopped, until shutdown
+    fn spawn_order_book_service(
+        &self,
+        pair: [&str; 2],
+        _order_book_depth: usize,
+        _exchange_stream_buffer: usize,
+        price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        mut shutdown_rx: watch::Receiver<bool>,
+        _feed_quality: Option<Arc<FeedQuality>>,
+    ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
+        let updates = self.updates.clone();
+        let interval = self.interval;
+        if let Some(update) = updates.first() {
+            EventPublisher::new(Some(update.exchange.clone()), pair, event_tx)
+                .publish(ServiceEventKind::Connected);
+        }
+
+        vec![tokio::spawn(async move {
+            let replay = async {
+                for update in updates {
+                    tokio::time::sleep(interval).await;
+                    price_level_tx
+                        .send(update)
+                        .await
+                        .map_err(OrderBookError::PriceLevelUpdateSendError)?;
+                }
+
+                std::future::pending().await
+            };
+
+            tokio::select! {
+                result = replay => result,
+                _ = shutdown_requested(&mut shutdown_rx) => Ok(()),
+            }
+        })]
+    }
+}
@@ -1,86 +1,391 @@
+#[cfg(feature = "exchanges")]
 pub mod binance;
+#[cfg(feature = "exchanges")]
 pub mod error;
 
+#[cfg(feature = "exchanges")]
 pub mod bitstamp;
+#[cfg(feature = "exchanges")]
+pub mod bybit;
+pub mod credentials;
+#[cfg(feature = "exchanges")]
 pub mod exchange_utils;
+pub mod feed_quality;
+#[cfg(feature = "exchanges")]
+pub mod gemini;
+#[cfg(feature = "exchanges")]
+pub mod kraken;
+#[cfg(feature = "test-util")]
+pub mod mock;
+#[cfg(feature = "exchanges")]
+pub mod okx;
+pub mod order_book_stream;
+#[cfg(feature = "exchanges")]
+pub mod reconnect;
+pub mod services;
 
 use core::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(feature = "exchanges")]
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::sync::mpsc::Sender;
+use serde_derive::Serialize;
+use tokio::sync::{broadcast, mpsc::Sender, watch};
 use tokio::task::JoinHandle;
 
 use crate::error::BidAskServiceError;
+use crate::events::{ServiceEvent, EVENT_BUFFER};
+#[cfg(feature = "exchanges")]
+use crate::exchanges::credentials::Credentials;
+#[cfg(feature = "exchanges")]
+use crate::exchanges::error::ExchangeError;
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::order_book_stream::OrderBookStream;
+#[cfg(feature = "exchanges")]
+use crate::exchanges::reconnect::ReconnectBackoff;
 use crate::order_book::price_level::PriceLevelUpdate;
+#[cfg(feature = "exchanges")]
+use crate::order_book::price_level::{ask::Ask, bid::Bid};
 
+#[cfg(feature = "exchanges")]
 use self::binance::Binance;
+#[cfg(feature = "exchanges")]
 use self::bitstamp::Bitstamp;
+#[cfg(feature = "exchanges")]
+use self::bybit::Bybit;
+#[cfg(feature = "exchanges")]
+use self::gemini::Gemini;
+#[cfg(feature = "exchanges")]
+use self::kraken::Kraken;
+#[cfg(feature = "exchanges")]
+use self::okx::Okx;
 
 const BINANCE: &str = "binance";
 const BITSTAMP: &str = "bitstamp";
+const KRAKEN: &str = "kraken";
+const BYBIT: &str = "bybit";
+const OKX: &str = "okx";
+const GEMINI: &str = "gemini";
 
 #[async_trait]
 pub trait OrderBookService {
-    /// Spawns an order book service to stream order book data and handle stream events for a specified pair.
+    /// Formats the pair as the exchange expects it when subscribing to the order book, rejecting empty or malformed tickers.
+    /// By default the tickers are joined into a single lowercase string, ie. ethbtc
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?.join("").to_lowercase())
+    }
+
+    /// Spawns an order book service to stream order book data and handle stream events for a specified pair,
+    /// using the exchange's configuration. Once shutdown is signalled through the shutdown receiver, the service
+    /// unsubscribes, closes its connection and its tasks return `Ok(())`.
+    #[allow(clippy::too_many_arguments)]
     fn spawn_order_book_service(
+        &self,
         pair: [&str; 2],
         order_book_depth: usize,
         exchange_stream_buffer: usize,
         price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        feed_quality: Option<Arc<FeedQuality>>,
     ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>>;
+
+    /// Streams the price level updates from the exchange's order book service for a specified pair, so that they can be
+    /// filtered or merged with stream combinators before aggregation. The service is stopped when the stream is dropped.
+    fn order_book_stream(
+        &self,
+        pair: [&str; 2],
+        order_book_depth: usize,
+        exchange_stream_buffer: usize,
+    ) -> OrderBookStream {
+        let (price_level_tx, price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(exchange_stream_buffer);
+        let handles = self.spawn_order_book_service(
+            pair,
+            order_book_depth,
+            exchange_stream_buffer,
+            price_level_tx,
+            broadcast::channel(EVENT_BUFFER).0,
+            watch::channel(false).1,
+            None,
+        );
+
+        OrderBookStream::new(price_level_rx, handles)
+    }
+}
+
+//Check that both tickers of the pair are non-empty and alphanumeric, returning the trimmed tickers.
+//A pair passed as a single ticker with a separator, ie. ["ETH-BTC", ""] or ["eth/btc", ""], is rejected rather than being subscribed to as is
+pub fn validate_pair(pair: [&str; 2]) -> Result<[&str; 2], ParsePairError> {
+    let pair = pair.map(str::trim);
+    for ticker in pair {
+        if ticker.is_empty() {
+            return Err(ParsePairError::EmptyTicker);
+        }
+        if !ticker.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ParsePairError::InvalidTicker(ticker.to_owned()));
+        }
+    }
+
+    Ok(pair)
+}
+
+//Spawn a task that fails with the pair error, so that an exchange which could not format the pair surfaces the error through its handles
+//in place of subscribing to a malformed pair
+pub fn spawn_pair_error(
+    exchange: Exchange,
+    error: ParsePairError,
+) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
+    tracing::error!("Could not format the pair for {exchange}: {error}");
+    vec![tokio::spawn(async move { Err(error.into()) })]
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Exchange {
     Bitstamp,
     Binance,
+    Kraken,
+    Bybit,
+    Okx,
+    Gemini,
 }
 
 impl Exchange {
-    //Spawn the order book service for the specified exchange
+    //Spawn the order book service for the specified exchange, authenticating with the credentials, resubscribing and refreshing the snapshot at the intervals if the exchange supports it.
+    //The args mirror OrderBookService::spawn_order_book_service, with the credentials, intervals and reconnect backoff to configure the exchange with
+    #[cfg(feature = "exchanges")]
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn_order_book_service(
         &self,
         pair: [&str; 2],
         order_book_depth: usize,
         exchange_stream_buffer: usize,
         price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        feed_quality: Option<Arc<FeedQuality>>,
+        credentials: Option<Credentials>,
+        resubscribe_interval: Option<Duration>,
+        reconnect_backoff: ReconnectBackoff,
+        snapshot_refresh_interval: Option<Duration>,
     ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
         match self {
-            Exchange::Binance => Binance::spawn_order_book_service(
-                pair,
-                order_book_depth,
-                exchange_stream_buffer,
-                price_level_tx,
-            ),
-            Exchange::Bitstamp => Bitstamp::spawn_order_book_service(
-                pair,
-                order_book_depth,
-                exchange_stream_buffer,
-                price_level_tx,
-            ),
+            Exchange::Binance => {
+                if credentials.is_some() {
+                    tracing::warn!("Binance order book streams are public, ignoring credentials");
+                }
+                if resubscribe_interval.is_some() {
+                    tracing::debug!(
+                        "Binance order book streams are subscribed through the endpoint, ignoring resubscribe interval"
+                    );
+                }
+
+                let mut binance = Binance::new().with_reconnect_backoff(reconnect_backoff);
+                if let Some(snapshot_refresh_interval) = snapshot_refresh_interval {
+                    binance = binance.with_snapshot_refresh_interval(snapshot_refresh_interval);
+                }
+
+                binance.spawn_order_book_service(
+                    pair,
+                    order_book_depth,
+                    exchange_stream_buffer,
+                    price_level_tx,
+                    event_tx,
+                    shutdown_rx,
+                    feed_quality,
+                )
+            }
+            Exchange::Bitstamp => {
+                let mut bitstamp = Bitstamp::new().with_reconnect_backoff(reconnect_backoff);
+                if let Some(credentials) = credentials {
+                    bitstamp = bitstamp.with_credentials(credentials);
+                }
+                if let Some(resubscribe_interval) = resubscribe_interval {
+                    bitstamp = bitstamp.with_resubscribe_interval(resubscribe_interval);
+                }
+                if let Some(snapshot_refresh_interval) = snapshot_refresh_interval {
+                    bitstamp = bitstamp.with_snapshot_refresh_interval(snapshot_refresh_interval);
+                }
+
+                bitstamp.spawn_order_book_service(
+                    pair,
+                    order_book_depth,
+                    exchange_stream_buffer,
+                    price_level_tx,
+                    event_tx,
+                    shutdown_rx,
+                    feed_quality,
+                )
+            }
+            Exchange::Kraken => {
+                if credentials.is_some() {
+                    tracing::warn!("Kraken order book streams are public, ignoring credentials");
+                }
+                if resubscribe_interval.is_some() {
+                    tracing::debug!(
+                        "Kraken order book subscriptions do not expire, ignoring resubscribe interval"
+                    );
+                }
+                if snapshot_refresh_interval.is_some() {
+                    tracing::debug!(
+                        "Kraken order book snapshots are only sent on subscribing, ignoring snapshot refresh interval"
+                    );
+                }
+
+                Kraken::new()
+                    .with_reconnect_backoff(reconnect_backoff)
+                    .spawn_order_book_service(
+                        pair,
+                        order_book_depth,
+                        exchange_stream_buffer,
+                        price_level_tx,
+                        event_tx,
+                        shutdown_rx,
+                        feed_quality,
+                    )
+            }
+            Exchange::Bybit => {
+                if credentials.is_some() {
+                    tracing::warn!("Bybit order book streams are public, ignoring credentials");
+                }
+                if resubscribe_interval.is_some() {
+                    tracing::debug!(
+                        "Bybit order book subscriptions do not expire, ignoring resubscribe interval"
+                    );
+                }
+                if snapshot_refresh_interval.is_some() {
+                    tracing::debug!(
+                        "Bybit order book snapshots are sent by the stream, ignoring snapshot refresh interval"
+                    );
+                }
+
+                Bybit::new()
+                    .with_reconnect_backoff(reconnect_backoff)
+                    .spawn_order_book_service(
+                        pair,
+                        order_book_depth,
+                        exchange_stream_buffer,
+                        price_level_tx,
+                        event_tx,
+                        shutdown_rx,
+                        feed_quality,
+                    )
+            }
+            Exchange::Okx => {
+                if credentials.is_some() {
+                    tracing::warn!("OKX order book streams are public, ignoring credentials");
+                }
+                if resubscribe_interval.is_some() {
+                    tracing::debug!(
+                        "OKX order book subscriptions do not expire, ignoring resubscribe interval"
+                    );
+                }
+                if snapshot_refresh_interval.is_some() {
+                    tracing::debug!(
+                        "OKX order book snapshots are sent by the stream, ignoring snapshot refresh interval"
+                    );
+                }
+
+                Okx::new()
+                    .with_reconnect_backoff(reconnect_backoff)
+                    .spawn_order_book_service(
+                        pair,
+                        order_book_depth,
+                        exchange_stream_buffer,
+                        price_level_tx,
+                        event_tx,
+                        shutdown_rx,
+                        feed_quality,
+                    )
+            }
+            Exchange::Gemini => {
+                if credentials.is_some() {
+                    tracing::warn!("Gemini order book streams are public, ignoring credentials");
+                }
+                if resubscribe_interval.is_some() {
+                    tracing::debug!(
+                        "Gemini order book subscriptions do not expire, ignoring resubscribe interval"
+                    );
+                }
+                if snapshot_refresh_interval.is_some() {
+                    tracing::debug!(
+                        "Gemini order book snapshots are sent by the stream, ignoring snapshot refresh interval"
+                    );
+                }
+
+                Gemini::new()
+                    .with_reconnect_backoff(reconnect_backoff)
+                    .spawn_order_book_service(
+                        pair,
+                        order_book_depth,
+                        exchange_stream_buffer,
+                        price_level_tx,
+                        event_tx,
+                        shutdown_rx,
+                        feed_quality,
+                    )
+            }
+        }
+    }
+
+    //Fetch a one-time snapshot of the exchange's order book for the pair, up to the depth on each side, without starting a stream.
+    //Kraken, Bybit, OKX and Gemini only send snapshots over their streams, so fetching their snapshot fails
+    #[cfg(feature = "exchanges")]
+    pub async fn rest_snapshot(
+        &self,
+        pair: [&str; 2],
+        depth: usize,
+    ) -> Result<(Vec<Bid>, Vec<Ask>), BidAskServiceError> {
+        match self {
+            Exchange::Binance => Ok(Binance::new().rest_snapshot(pair, depth).await?),
+            Exchange::Bitstamp => Ok(Bitstamp::new().rest_snapshot(pair, depth).await?),
+            Exchange::Kraken | Exchange::Bybit | Exchange::Okx | Exchange::Gemini => {
+                Err(ExchangeError::RestSnapshotUnsupported(self.clone()).into())
+            }
         }
     }
 
     //Return all available exchanges
     pub fn all_exchanges() -> Vec<Exchange> {
-        vec![Exchange::Bitstamp, Exchange::Binance]
+        vec![
+            Exchange::Bitstamp,
+            Exchange::Binance,
+            Exchange::Kraken,
+            Exchange::Bybit,
+            Exchange::Okx,
+            Exchange::Gemini,
+        ]
     }
 
-    //Parse a list of exchanges from a comma separated String into a Vec<Exchange>
+    //Parse a list of exchanges from a comma separated String into a Vec<Exchange>.
+    //Duplicate exchanges are skipped, keeping the order that each exchange first appears, so that an exchange is only subscribed to once
     pub fn parse_exchanges(exchanges: String) -> Result<Vec<Exchange>, ParseExchangeError> {
-        exchanges
-            .split(',')
-            .map(|s| s.parse::<Exchange>())
-            .collect::<Result<Vec<_>, _>>()
+        let mut parsed_exchanges = vec![];
+        for exchange in exchanges.split(',') {
+            let exchange = exchange.parse::<Exchange>()?;
+            if parsed_exchanges.contains(&exchange) {
+                tracing::warn!("Skipping duplicate exchange {exchange}");
+            } else {
+                parsed_exchanges.push(exchange);
+            }
+        }
+
+        Ok(parsed_exchanges)
     }
 }
 
-impl ToString for Exchange {
-    fn to_string(&self) -> String {
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Exchange::Bitstamp => BITSTAMP.to_owned(),
-            Exchange::Binance => BINANCE.to_owned(),
+            Exchange::Bitstamp => write!(f, "{BITSTAMP}"),
+            Exchange::Binance => write!(f, "{BINANCE}"),
+            Exchange::Kraken => write!(f, "{KRAKEN}"),
+            Exchange::Bybit => write!(f, "{BYBIT}"),
+            Exchange::Okx => write!(f, "{OKX}"),
+            Exchange::Gemini => write!(f, "{GEMINI}"),
         }
     }
 }
@@ -92,6 +397,10 @@ impl FromStr for Exchange {
         match s.to_lowercase().as_str() {
             "bitstamp" => Ok(Exchange::Bitstamp),
             "binance" => Ok(Exchange::Binance),
+            "kraken" => Ok(Exchange::Kraken),
+            "bybit" => Ok(Exchange::Bybit),
+            "okx" => Ok(Exchange::Okx),
+            "gemini" => Ok(Exchange::Gemini),
             _ => Err(ParseExchangeError::UnrecognizedExchange),
         }
     }
@@ -109,3 +418,154 @@ impl fmt::Display for ParseExchangeError {
 }
 
 impl std::error::Error for ParseExchangeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsePairError {
+    EmptyTicker,
+    InvalidTicker(String),
+}
+
+impl fmt::Display for ParsePairError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsePairError::EmptyTicker => write!(f, "Could not parse the pair, a ticker is empty"),
+            ParsePairError::InvalidTicker(ticker) => write!(
+                f,
+                "Could not parse the pair, ticker {ticker:?} is not alphanumeric"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParsePairError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::BidAskServiceError,
+        exchanges::{
+            binance::Binance, bitstamp::Bitstamp, bybit::Bybit, error::ExchangeError,
+            gemini::Gemini, kraken::Kraken, okx::Okx, Exchange, OrderBookService, ParsePairError,
+        },
+    };
+
+    #[test]
+    fn test_format_pair() {
+        let pair = [" eth", "BTC "];
+        assert_eq!(Binance::new().format_pair(pair), Ok("ETHBTC".to_owned()));
+        assert_eq!(Bitstamp::new().format_pair(pair), Ok("ethbtc".to_owned()));
+        assert_eq!(Kraken::new().format_pair(pair), Ok("ETH/XBT".to_owned()));
+        assert_eq!(Bybit::new().format_pair(pair), Ok("ETHBTC".to_owned()));
+        assert_eq!(Okx::new().format_pair(pair), Ok("ETH-BTC".to_owned()));
+        assert_eq!(Gemini::new().format_pair(pair), Ok("ETHBTC".to_owned()));
+
+        //A pair passed with its separator, or with a missing ticker, is rejected by every exchange
+        let exchanges: [&dyn OrderBookService; 6] = [
+            &Binance::new(),
+            &Bitstamp::new(),
+            &Kraken::new(),
+            &Bybit::new(),
+            &Okx::new(),
+            &Gemini::new(),
+        ];
+        for exchange in exchanges {
+            assert_eq!(
+                exchange.format_pair(["ETH-BTC", "usdt"]),
+                Err(ParsePairError::InvalidTicker("ETH-BTC".to_owned()))
+            );
+            assert_eq!(
+                exchange.format_pair(["eth", " "]),
+                Err(ParsePairError::EmptyTicker)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spawn_malformed_pair() {
+        //The service fails with the pair error rather than subscribing to the malformed pair
+        let handles = Kraken::new().spawn_order_book_service(
+            ["eth", "b tc"],
+            10,
+            10,
+            tokio::sync::mpsc::channel(10).0,
+            tokio::sync::broadcast::channel(10).0,
+            tokio::sync::watch::channel(false).1,
+            None,
+        );
+        assert_eq!(handles.len(), 1);
+        for handle in handles {
+            assert!(matches!(
+                handle.await.expect("Task panicked"),
+                Err(BidAskServiceError::ParsePairError(
+                    ParsePairError::InvalidTicker(_)
+                ))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rest_snapshot() {
+        for exchange in [Exchange::Binance, Exchange::Bitstamp] {
+            let (bids, asks) = exchange
+                .rest_snapshot(["eth", "btc"], 10)
+                .await
+                .expect("Could not get order book snapshot");
+
+            assert!(!bids.is_empty() && bids.len() <= 10);
+            assert!(!asks.is_empty() && asks.len() <= 10);
+            assert!(bids.iter().all(|bid| bid.exchange == exchange));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rest_snapshot_unsupported() {
+        for exchange in [
+            Exchange::Kraken,
+            Exchange::Bybit,
+            Exchange::Okx,
+            Exchange::Gemini,
+        ] {
+            match exchange.rest_snapshot(["eth", "btc"], 10).await {
+                Err(BidAskServiceError::ExchangeError(ExchangeError::RestSnapshotUnsupported(
+                    unsupported,
+                ))) => assert_eq!(unsupported, exchange),
+                other => panic!("Expected an unsupported snapshot, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_exchanges_skips_duplicates() {
+        let exchanges = Exchange::parse_exchanges("binance,bitstamp,Binance,binance".to_owned())
+            .expect("Could not parse exchanges");
+        assert_eq!(exchanges, vec![Exchange::Binance, Exchange::Bitstamp]);
+
+        assert!(Exchange::parse_exchanges("binance,coinbase".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_parse_mixed_exchanges() {
+        let exchanges =
+            Exchange::parse_exchanges("binance,kraken,bitstamp,bybit,okx,gemini".to_owned())
+                .expect("Could not parse exchanges");
+        assert_eq!(
+            exchanges,
+            vec![
+                Exchange::Binance,
+                Exchange::Kraken,
+                Exchange::Bitstamp,
+                Exchange::Bybit,
+                Exchange::Okx,
+                Exchange::Gemini
+            ]
+        );
+
+        //Each exchange is displayed as the name that it is parsed from
+        for exchange in Exchange::all_exchanges() {
+            assert_eq!(
+                exchange.to_string().parse::<Exchange>().ok(),
+                Some(exchange)
+            );
+        }
+    }
+}
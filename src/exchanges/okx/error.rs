@@ -0,0 +1,19 @@
+use tokio::sync::mpsc::error::SendError;
+
+use crate::order_book::price_level::PriceLevelUpdate;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OkxError {
+    #[error("Error when sending tungstenite message")]
+    MessageSendError(#[from] SendError<tungstenite::Message>),
+    #[error("Ws connection closed with terminal code {code}: {reason}")]
+    ConnectionClosed { code: u16, reason: String },
+    #[error("Tungstenite error")]
+    TungsteniteError(#[from] tungstenite::Error),
+    #[error("Error when sending price level update")]
+    PriceLevelUpdateSendError(#[from] tokio::sync::mpsc::error::SendError<PriceLevelUpdate>),
+    #[error("Subscription failed with code {code}: {msg}")]
+    SubscriptionError { code: String, msg: String },
+    #[error("Serde json error")]
+    SerdeJsonError(#[from] serde_json::Error),
+}
@@ -0,0 +1,196 @@
+pub mod error;
+pub mod stream;
+
+use self::stream::{spawn_order_book_stream, spawn_stream_handler, BOOK_DEPTH, WS_BASE_ENDPOINT};
+use super::{spawn_pair_error, validate_pair, Exchange, OrderBookService, ParsePairError};
+use crate::error::BidAskServiceError;
+use crate::events::{EventPublisher, ServiceEvent};
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::reconnect::ReconnectBackoff;
+use crate::order_book::price_level::PriceLevelUpdate;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::{
+    sync::{broadcast, mpsc::Sender, watch},
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct Okx {
+    //Websocket endpoint of the public channels that the books channel is subscribed to on
+    pub ws_base_endpoint: String,
+    //Backoff between reconnect attempts when the websocket stream is closed
+    pub reconnect_backoff: ReconnectBackoff,
+}
+
+impl Okx {
+    pub fn new() -> Self {
+        Okx {
+            ws_base_endpoint: WS_BASE_ENDPOINT.to_owned(),
+            reconnect_backoff: ReconnectBackoff::default(),
+        }
+    }
+
+    pub fn with_ws_base_endpoint(mut self, ws_base_endpoint: &str) -> Self {
+        self.ws_base_endpoint = ws_base_endpoint.to_owned();
+        self
+    }
+
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+}
+
+impl Default for Okx {
+    fn default() -> Self {
+        Okx::new()
+    }
+}
+
+#[async_trait]
+impl OrderBookService for Okx {
+    //OKX requires the pair to be formatted as an instrument id with uppercase tickers separated by a dash, ie. ETH-BTC
+    fn format_pair(&self, pair: [&str; 2]) -> Result<String, ParsePairError> {
+        Ok(validate_pair(pair)?.join("-").to_uppercase())
+    }
+
+    //OKX sends a snapshot of the book on subscribing, so no snapshot is requested over REST.
+    //The books channel is always streamed at a depth of 400 levels, so a larger order book depth is capped at 400 levels from OKX
+    fn spawn_order_book_service(
+        &self,
+        pair: [&str; 2],
+        order_book_depth: usize,
+        exchange_stream_buffer: usize,
+        price_level_tx: Sender<PriceLevelUpdate>,
+        event_tx: broadcast::Sender<ServiceEvent>,
+        shutdown_rx: watch::Receiver<bool>,
+        _feed_quality: Option<Arc<FeedQuality>>,
+    ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
+        let stream_pair = match self.format_pair(pair) {
+            Ok(stream_pair) => stream_pair,
+            Err(error) => return spawn_pair_error(Exchange::Okx, error),
+        };
+        let events = EventPublisher::new(Some(Exchange::Okx), pair, event_tx);
+        if order_book_depth > BOOK_DEPTH {
+            tracing::warn!(
+                "OKX order books are streamed at a depth of {BOOK_DEPTH}, capping the order book depth of {order_book_depth}"
+            );
+        }
+
+        tracing::info!("Spawning OKX order book stream");
+        //Spawn a task to handle a buffered stream of the order book and reconnects to the exchange
+        let (ws_stream_rx, stream_handle) = spawn_order_book_stream(
+            self.ws_base_endpoint.clone(),
+            stream_pair,
+            exchange_stream_buffer,
+            events,
+            shutdown_rx.clone(),
+            self.reconnect_backoff.clone(),
+        );
+
+        tracing::info!("Spawning OKX order book stream handler");
+        //Spawn a task to handle updates from the buffered stream, cleaning the data and sending it to the aggregated order book
+        let order_book_update_handle =
+            spawn_stream_handler(ws_stream_rx, price_level_tx, shutdown_rx);
+
+        vec![stream_handle, order_book_update_handle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tungstenite::Message;
+
+    use crate::{
+        exchanges::{okx::Okx, Exchange, OrderBookService},
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    #[tokio::test]
+    async fn test_spawn_order_book_service() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let ws_base_endpoint = format!("ws://{}/", listener.local_addr().expect("No local addr"));
+
+        //Serve a mock OKX that sends a snapshot and an update once the books channel is subscribed to
+        let (subscription_tx, mut subscription_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut ws_stream = tokio_tungstenite::accept_async(stream)
+                .await
+                .expect("Could not complete handshake");
+
+            if let Some(Ok(Message::Text(subscription))) = ws_stream.next().await {
+                subscription_tx.send(subscription).ok();
+            }
+
+            for message in [
+                r#"{"event":"subscribe","arg":{"channel":"books","instId":"ETH-BTC"},"connId":"a4d3ae55"}"#,
+                r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"snapshot","data":[{"asks":[["0.0651","1","0","1"],["0.0652","2","0","1"]],"bids":[["0.065","3","0","1"],["0.0649","4","0","1"]],"ts":"1690000000000","checksum":-1955476496,"prevSeqId":-1,"seqId":100}]}"#,
+                r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[],"bids":[["0.065","0","0","0"]],"ts":"1690000000100","checksum":-693854098,"prevSeqId":100,"seqId":101}]}"#,
+            ] {
+                ws_stream
+                    .send(Message::Text(message.to_owned()))
+                    .await
+                    .expect("Could not send message");
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _handles = Okx::new()
+            .with_ws_base_endpoint(&ws_base_endpoint)
+            .spawn_order_book_service(
+                ["eth", "btc"],
+                25,
+                10,
+                tx,
+                tokio::sync::broadcast::channel(10).0,
+                tokio::sync::watch::channel(false).1,
+                None,
+            );
+
+        let subscription = subscription_rx.recv().await.expect("No subscription");
+        assert_eq!(
+            subscription,
+            r#"{"op":"subscribe","args":[{"channel":"books","instId":"ETH-BTC"}]}"#
+        );
+
+        //The snapshot replaces OKX's levels, while the update removes the bid with a zero quantity
+        let snapshot = rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Okx);
+        assert!(snapshot.clear);
+        assert_eq!(
+            snapshot
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 3.0), (0.0649, 4.0)]
+        );
+        assert_eq!(
+            snapshot
+                .asks
+                .iter()
+                .map(|ask| (ask.price.0, ask.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.0651, 1.0), (0.0652, 2.0)]
+        );
+
+        let update = rx.recv().await.expect("No update received");
+        assert!(!update.clear);
+        assert_eq!(
+            update
+                .bids
+                .iter()
+                .map(|bid| (bid.price.0, bid.quantity.0))
+                .collect::<Vec<_>>(),
+            vec![(0.065, 0.0)]
+        );
+        assert!(update.asks.is_empty());
+    }
+}
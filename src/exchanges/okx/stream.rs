@@ -0,0 +1,682 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use ordered_float::OrderedFloat;
+use serde::{de, Deserialize as _, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{Receiver, Sender},
+        watch,
+    },
+    task::JoinHandle,
+};
+
+use crate::order_book::price_level::ask::Ask;
+use crate::order_book::price_level::bid::Bid;
+use crate::order_book::price_level::PriceLevelUpdate;
+use crate::{error::BidAskServiceError, exchanges::okx::error::OkxError};
+
+use crate::events::{EventPublisher, ServiceEventKind};
+use crate::exchanges::exchange_utils;
+use crate::exchanges::reconnect::{
+    connect_with_backoff, is_terminal_close, unsubscribe_and_close, ReconnectBackoff,
+};
+use crate::exchanges::services::shutdown_requested;
+use crate::exchanges::Exchange;
+
+use futures::{SinkExt, StreamExt};
+
+use tungstenite::Message;
+
+pub const WS_BASE_ENDPOINT: &str = "wss://ws.okx.com:8443/ws/v5/public";
+//Depth of the books channel
+pub const BOOK_DEPTH: usize = 400;
+const SUBSCRIBE_OP: &str = "subscribe";
+const UNSUBSCRIBE_OP: &str = "unsubscribe";
+const BOOKS_CHANNEL: &str = "books";
+const SUBSCRIBE_EVENT: &str = "subscribe";
+const ERROR_EVENT: &str = "error";
+const SNAPSHOT_ACTION: &str = "snapshot";
+const PING_MESSAGE: &str = "ping";
+const PONG_MESSAGE: &str = "pong";
+//Number of levels of each side that the checksum is computed from
+const CHECKSUM_DEPTH: usize = 25;
+//Interval to send a ping message at, since OKX drops connections that do not send or receive data within 30 seconds
+const PING_INTERVAL: Duration = Duration::from_secs(25);
+
+// Websocket Public Channel
+
+// Channels are subscribed to with {"op":"subscribe","args":[{"channel":"books","instId":"ETH-BTC"}]}, which OKX responds to with a subscribe or error event
+// The first message of the books channel is a snapshot of the top 400 levels with an action of "snapshot", which is followed by updates with an action of "update"
+// Levels are sent as [price, quantity, deprecated, number of orders], and a level with a quantity of 0 is removed
+// Each message holds a signed CRC32 checksum of the top 25 levels of the book after the message is applied, computed from the prices and quantities as they are sent
+// Connections are dropped without any data for 30 seconds, so "ping" is sent every 25 seconds, which OKX responds to with "pong"
+
+//Spawns a thread to stream order book updates from OKX
+pub fn spawn_order_book_stream(
+    ws_base_endpoint: String,
+    pair: String,
+    exchange_stream_buffer: usize,
+    events: EventPublisher,
+    mut shutdown_rx: watch::Receiver<bool>,
+    mut reconnect_backoff: ReconnectBackoff,
+) -> (
+    Receiver<Message>,
+    JoinHandle<Result<(), BidAskServiceError>>,
+) {
+    let (ws_stream_tx, ws_stream_rx) =
+        tokio::sync::mpsc::channel::<Message>(exchange_stream_buffer);
+
+    //spawn a thread that handles the stream and buffers the results
+    let stream_handle = tokio::spawn(async move {
+        let ws_stream_tx = ws_stream_tx.clone();
+        //Resyncs are requested by the aggregated order book after dropping an update, see spawn_dropping_relay
+        let mut resync_rx = events.subscribe();
+        //Failed connections are only retried once the stream has connected, see connect_with_backoff
+        let mut reconnecting = false;
+        loop {
+            //Connect to the websocket endpoint
+            //Stop rather than connecting once shutdown has been requested, since connecting may be retried with a backoff
+            let mut order_book_stream = tokio::select! {
+                order_book_stream = connect_with_backoff(&ws_base_endpoint, &mut reconnect_backoff, reconnecting) => {
+                    order_book_stream.map_err(OkxError::TungsteniteError)?
+                }
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            };
+            reconnecting = true;
+
+            //Send a subscribe message to notify OKX to start sending the books channel, which starts with a snapshot
+            let subscription_message = serde_json::to_string(&SubscribeMessage::new(&pair))
+                .map_err(OkxError::SerdeJsonError)?;
+            order_book_stream
+                .send(tungstenite::Message::Text(subscription_message))
+                .await
+                .map_err(OkxError::TungsteniteError)?;
+            let unsubscription_message =
+                serde_json::to_string(&SubscribeMessage::unsubscribe(&pair))
+                    .map_err(OkxError::SerdeJsonError)?;
+
+            tracing::info!("Ws connection established");
+            events.publish(ServiceEventKind::Connected);
+            let connected_at = tokio::time::Instant::now();
+
+            //Reconnect if the connection wedges without a close frame, resetting the deadline on each message received
+            let idle_timeout = reconnect_backoff.idle_timeout;
+            let idle = tokio::time::sleep(idle_timeout);
+            tokio::pin!(idle);
+
+            //Send a ping message at an interval, since OKX drops connections that are idle for 30 seconds
+            let mut ping_interval =
+                tokio::time::interval_at(connected_at + PING_INTERVAL, PING_INTERVAL);
+
+            //Send messages through a channel to be handled by the stream handler, respond to ping requests and handle reconnects
+            loop {
+                let message = tokio::select! {
+                    message = order_book_stream.next() => match message {
+                        Some(Ok(message)) => {
+                            idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                            message
+                        }
+                        _ => break,
+                    },
+
+                    _ = ping_interval.tick() => {
+                        order_book_stream.send(Message::Text(PING_MESSAGE.to_owned())).await.ok();
+                        tracing::debug!("Ping sent");
+                        continue;
+                    }
+
+                    _ = &mut idle => {
+                        tracing::warn!("No messages received for {idle_timeout:?}, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+
+                    //Unsubscribe and close the connection on shutdown, rather than dropping it with the subscription open
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Shutting down the OKX order book stream");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        events.publish(ServiceEventKind::Disconnected);
+                        return Ok(());
+                    }
+
+                    //The subscription starts with a snapshot, so the order book is resynced by reconnecting once the aggregated order book has dropped one of its updates
+                    _ = events.resync_requested(&mut resync_rx) => {
+                        tracing::warn!("Resyncing the OKX order book, reconnecting...");
+                        unsubscribe_and_close(&mut order_book_stream, unsubscription_message).await;
+                        break;
+                    }
+                };
+
+                match message {
+                    tungstenite::Message::Text(_) => {
+                        ws_stream_tx
+                            .send(message)
+                            .await
+                            .map_err(OkxError::MessageSendError)?;
+                    }
+
+                    //Some exchanges send data as binary messages, which are decoded so that they are handled the same as text messages.
+                    //Fragmented messages are reassembled by tungstenite, so only complete messages are received here
+                    tungstenite::Message::Binary(data) => match String::from_utf8(data) {
+                        Ok(message) => {
+                            ws_stream_tx
+                                .send(Message::Text(message))
+                                .await
+                                .map_err(OkxError::MessageSendError)?;
+                        }
+                        Err(err) => {
+                            tracing::warn!("Dropping binary message that is not utf8: {err}");
+                        }
+                    },
+
+                    tungstenite::Message::Ping(_) => {
+                        tracing::info!("Ping received");
+                        order_book_stream.send(Message::Pong(vec![])).await.ok();
+                        tracing::info!("Pong sent");
+                    }
+
+                    tungstenite::Message::Close(close_frame) => {
+                        match close_frame {
+                            //Reconnecting after a terminal close would loop forever, so the stream fails instead
+                            Some(close_frame) if is_terminal_close(&close_frame) => {
+                                let code = u16::from(close_frame.code);
+                                let reason = close_frame.reason.into_owned();
+                                tracing::error!(
+                                    "Ws connection closed with terminal code {code}: {reason}"
+                                );
+                                events.publish(ServiceEventKind::Disconnected);
+                                return Err(OkxError::ConnectionClosed { code, reason }.into());
+                            }
+                            Some(close_frame) => {
+                                tracing::warn!(
+                                    "Ws connection closed with code {}: {}, reconnecting...",
+                                    u16::from(close_frame.code),
+                                    close_frame.reason
+                                );
+                            }
+                            None => tracing::warn!("Ws connection closed, reconnecting..."),
+                        }
+                        break;
+                    }
+
+                    other => {
+                        tracing::warn!("{other:?}");
+                    }
+                }
+            }
+
+            events.publish(ServiceEventKind::Disconnected);
+
+            //Back off before reconnecting, resetting the backoff if the connection was sustained
+            reconnect_backoff.connection_closed(connected_at.elapsed());
+            let reconnect_delay = reconnect_backoff.next_delay();
+            tracing::info!("Reconnecting in {reconnect_delay:?}");
+            tokio::select! {
+                _ = tokio::time::sleep(reconnect_delay) => {}
+                _ = shutdown_requested(&mut shutdown_rx) => return Ok(()),
+            }
+        }
+    });
+
+    (ws_stream_rx, stream_handle)
+}
+
+//Handle the messages from the order book stream, logging when a message's checksum does not match the book
+pub fn spawn_stream_handler(
+    mut ws_stream_rx: Receiver<Message>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        //OKX's levels up to the depth of the books channel, used to remove the levels that an update pushes outside of the depth and to verify the checksum
+        let mut book_bids: BTreeMap<OrderedFloat<f64>, BookLevel> = BTreeMap::new();
+        let mut book_asks: BTreeMap<OrderedFloat<f64>, BookLevel> = BTreeMap::new();
+
+        loop {
+            //Stop handling messages on shutdown, while the stream unsubscribes and closes its connection
+            let message = tokio::select! {
+                message = ws_stream_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown_requested(&mut shutdown_rx) => {
+                    tracing::info!("Shutting down the OKX order book stream handler");
+                    break;
+                }
+            };
+
+            if let tungstenite::Message::Text(message) = message {
+                //Deserialize the message, extracting the bids and asks if it is a books message
+                let order_book_message =
+                    match parse_message(&message).map_err(OkxError::SerdeJsonError)? {
+                        OkxMessage::OrderBook(order_book_message) => order_book_message,
+                        OkxMessage::Event(event) => {
+                            handle_event(event)?;
+                            continue;
+                        }
+                        OkxMessage::Pong => {
+                            tracing::debug!("Pong received");
+                            continue;
+                        }
+                    };
+
+                //A snapshot replaces all of OKX's levels, which happens on each (re)subscription
+                let snapshot = order_book_message.is_snapshot();
+                for book_data in order_book_message.data {
+                    if snapshot {
+                        book_bids.clear();
+                        book_asks.clear();
+                    }
+
+                    //Collect all of the bids from the update
+                    let mut bids = vec![];
+                    for level in book_data.bids.into_iter() {
+                        bids.push(Bid::new(level.price, level.quantity, Exchange::Okx));
+                        update_level(&mut book_bids, level);
+                    }
+
+                    //Collect all of the asks from the update
+                    let mut asks = vec![];
+                    for level in book_data.asks.into_iter() {
+                        asks.push(Ask::new(level.price, level.quantity, Exchange::Okx));
+                        update_level(&mut book_asks, level);
+                    }
+
+                    //Remove the worst levels that are outside of the depth, the lowest bids and highest asks
+                    while book_bids.len() > BOOK_DEPTH {
+                        if let Some((price, _)) = book_bids.pop_first() {
+                            bids.push(Bid::new(price.0, 0.0, Exchange::Okx));
+                        }
+                    }
+                    while book_asks.len() > BOOK_DEPTH {
+                        if let Some((price, _)) = book_asks.pop_last() {
+                            asks.push(Ask::new(price.0, 0.0, Exchange::Okx));
+                        }
+                    }
+
+                    //A mismatch means that the book has drifted from OKX's, which is logged while the update is still sent
+                    if let Some(checksum) = book_data.checksum {
+                        let book_checksum = book_checksum(&book_bids, &book_asks);
+                        if checksum != book_checksum {
+                            tracing::warn!(
+                                "OKX checksum mismatch, expected {checksum} but the book has {book_checksum}"
+                            );
+                        }
+                    }
+
+                    //Send the batched price level update to the aggregated order book, converting the timestamp from milliseconds to microseconds
+                    let price_level_update = if snapshot {
+                        PriceLevelUpdate::snapshot(Exchange::Okx, bids, asks)
+                    } else {
+                        PriceLevelUpdate::new(Exchange::Okx, bids, asks)
+                    }
+                    .with_exchange_timestamp(book_data.timestamp * 1000);
+                    price_level_tx
+                        .send(price_level_update)
+                        .await
+                        .map_err(OkxError::PriceLevelUpdateSendError)?;
+                }
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+//Handle an event sent in response to the subscription, failing if the books channel could not be subscribed to.
+//The error is returned as is from the stream handler's task, so it is not boxed despite its size
+#[allow(clippy::result_large_err)]
+fn handle_event(event: OkxEvent) -> Result<(), OkxError> {
+    match event.event.as_str() {
+        SUBSCRIBE_EVENT => {
+            tracing::info!("OKX subscription succeeded");
+        }
+        //Retrying a rejected subscription would be rejected again, so the stream handler fails instead
+        ERROR_EVENT => {
+            let code = event.code.unwrap_or_default();
+            let msg = event.msg.unwrap_or_default();
+            tracing::error!("OKX subscription failed with code {code}: {msg}");
+            return Err(OkxError::SubscriptionError { code, msg });
+        }
+        other => {
+            tracing::debug!("Dropping OKX {other} event");
+        }
+    }
+
+    Ok(())
+}
+
+//Set a level in OKX's book, removing the level if the quantity is zero
+pub fn update_level(levels: &mut BTreeMap<OrderedFloat<f64>, BookLevel>, level: BookLevel) {
+    if level.quantity == 0.0 {
+        levels.remove(&OrderedFloat(level.price));
+    } else {
+        levels.insert(OrderedFloat(level.price), level);
+    }
+}
+
+//Compute OKX's checksum of the book, the signed CRC32 of the top 25 levels of each side joined by colons, alternating the best bid and ask from the best price
+//as bid price:bid quantity:ask price:ask quantity. Once one side runs out of levels, the remaining levels of the other side are joined on their own
+pub fn book_checksum(
+    book_bids: &BTreeMap<OrderedFloat<f64>, BookLevel>,
+    book_asks: &BTreeMap<OrderedFloat<f64>, BookLevel>,
+) -> i32 {
+    let mut bids = book_bids.values().rev().take(CHECKSUM_DEPTH);
+    let mut asks = book_asks.values().take(CHECKSUM_DEPTH);
+
+    let mut values = vec![];
+    for _ in 0..CHECKSUM_DEPTH {
+        for level in [bids.next(), asks.next()].into_iter().flatten() {
+            values.push(level.price_text.as_str());
+            values.push(level.quantity_text.as_str());
+        }
+    }
+
+    crc32fast::hash(values.join(":").as_bytes()) as i32
+}
+
+#[derive(Serialize, Debug, Deserialize, PartialEq)]
+pub struct ChannelArg {
+    pub channel: String,
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscribeMessage {
+    op: String,
+    args: Vec<ChannelArg>,
+}
+impl SubscribeMessage {
+    pub fn new(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            op: SUBSCRIBE_OP.to_owned(),
+            args: vec![ChannelArg {
+                channel: BOOKS_CHANNEL.to_owned(),
+                inst_id: pair.to_owned(),
+            }],
+        }
+    }
+
+    pub fn unsubscribe(pair: &str) -> SubscribeMessage {
+        SubscribeMessage {
+            op: UNSUBSCRIBE_OP.to_owned(),
+            ..SubscribeMessage::new(pair)
+        }
+    }
+}
+
+//A level of a books message, keeping the price and quantity as they are sent so that the checksum can be computed from them
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookLevel {
+    pub price: f64,
+    pub quantity: f64,
+    pub price_text: String,
+    pub quantity_text: String,
+}
+
+//Deserialize levels sent as [price, quantity, deprecated, number of orders], ignoring the trailing items
+fn deserialize_levels<'de, D>(deserializer: D) -> Result<Vec<BookLevel>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<Vec<String>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|level| match level.as_slice() {
+            [price_text, quantity_text, ..] => Ok(BookLevel {
+                price: price_text.parse().map_err(de::Error::custom)?,
+                quantity: quantity_text.parse().map_err(de::Error::custom)?,
+                price_text: price_text.to_owned(),
+                quantity_text: quantity_text.to_owned(),
+            }),
+            _ => Err(de::Error::invalid_length(
+                level.len(),
+                &"a level starting with a price and quantity",
+            )),
+        })
+        .collect()
+}
+
+//The levels of a snapshot or update of the books channel
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct BookData {
+    #[serde(deserialize_with = "deserialize_levels")]
+    pub bids: Vec<BookLevel>,
+    #[serde(deserialize_with = "deserialize_levels")]
+    pub asks: Vec<BookLevel>,
+    //Milliseconds since the unix epoch that OKX generated the message at
+    #[serde(
+        rename = "ts",
+        deserialize_with = "exchange_utils::convert_from_string_to_u64"
+    )]
+    pub timestamp: u64,
+    //The checksum of the book after the message is applied
+    pub checksum: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct OrderBookMessage {
+    pub arg: ChannelArg,
+    pub action: String,
+    pub data: Vec<BookData>,
+}
+
+impl OrderBookMessage {
+    pub fn is_snapshot(&self) -> bool {
+        self.action == SNAPSHOT_ACTION
+    }
+}
+
+//An event sent by OKX in response to a subscription, ie. its success or an error
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct OkxEvent {
+    pub event: String,
+    pub code: Option<String>,
+    pub msg: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum OkxMessage {
+    OrderBook(OrderBookMessage),
+    Event(OkxEvent),
+    #[serde(skip)]
+    Pong,
+}
+
+//Parse a message from the order book stream into a books message, an event or a pong, which is sent as plain text rather than json
+pub fn parse_message(message: &str) -> Result<OkxMessage, serde_json::Error> {
+    if message == PONG_MESSAGE {
+        return Ok(OkxMessage::Pong);
+    }
+
+    serde_json::from_str(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ordered_float::OrderedFloat;
+    use tungstenite::Message;
+
+    use crate::{
+        error::BidAskServiceError,
+        exchanges::{
+            okx::{
+                error::OkxError,
+                stream::{
+                    book_checksum, parse_message, spawn_stream_handler, update_level, BookLevel,
+                    OkxEvent, OkxMessage,
+                },
+            },
+            Exchange,
+        },
+        order_book::price_level::PriceLevelUpdate,
+    };
+
+    //Build a level as it is sent by OKX
+    fn book_level(price: &str, quantity: &str) -> BookLevel {
+        BookLevel {
+            price: price.parse().unwrap(),
+            quantity: quantity.parse().unwrap(),
+            price_text: price.to_owned(),
+            quantity_text: quantity.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = parse_message(
+            r#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"update","data":[{"asks":[["8476.98","415","0","13"]],"bids":[["8476.97","0","0","0"]],"ts":"1597026383085","checksum":-1200119424,"prevSeqId":123455,"seqId":123456}]}"#,
+        )
+        .expect("Could not parse books message");
+        let OkxMessage::OrderBook(order_book_message) = message else {
+            panic!("Expected a books message, got {message:?}");
+        };
+        assert!(!order_book_message.is_snapshot());
+        assert_eq!(order_book_message.arg.inst_id, "BTC-USDT");
+        let book_data = &order_book_message.data[0];
+        assert_eq!(book_data.timestamp, 1597026383085);
+        assert_eq!(book_data.checksum, Some(-1200119424));
+        assert_eq!(book_data.asks, vec![book_level("8476.98", "415")]);
+        assert_eq!(book_data.bids, vec![book_level("8476.97", "0")]);
+
+        let message = parse_message(
+            r#"{"event":"error","code":"60018","msg":"Wrong URL or channel:books,instId:ETH-ABC doesn't exist.","connId":"a4d3ae55"}"#,
+        )
+        .expect("Could not parse event");
+        assert_eq!(
+            message,
+            OkxMessage::Event(OkxEvent {
+                event: "error".to_owned(),
+                code: Some("60018".to_owned()),
+                msg: Some("Wrong URL or channel:books,instId:ETH-ABC doesn't exist.".to_owned()),
+            })
+        );
+
+        //Pongs are sent as plain text
+        assert_eq!(
+            parse_message("pong").expect("Could not parse pong"),
+            OkxMessage::Pong
+        );
+    }
+
+    //The example book from OKX's documentation, with the bids and asks alternating in the checksum
+    #[test]
+    fn test_book_checksum() {
+        let mut book_bids = BTreeMap::new();
+        let mut book_asks = BTreeMap::new();
+        for level in [book_level("3366.1", "7"), book_level("3366", "6")] {
+            update_level(&mut book_bids, level);
+        }
+        for level in [book_level("3366.8", "9"), book_level("3368", "8")] {
+            update_level(&mut book_asks, level);
+        }
+        //3366.1:7:3366.8:9:3366:6:3368:8
+        assert_eq!(book_checksum(&book_bids, &book_asks), -1881014294);
+
+        //Once the bids run out, the remaining asks are joined on their own, ie. 3366.1:7:3366.8:9:3368:8
+        book_bids.remove(&OrderedFloat(3366.0));
+        assert_eq!(
+            book_checksum(&book_bids, &book_asks),
+            crc32fast::hash("3366.1:7:3366.8:9:3368:8".as_bytes()) as i32
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_stream_handler() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, mut price_level_rx) =
+            tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let _stream_handler = spawn_stream_handler(
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        //The subscribe event and pong are dropped, and the update with a checksum that does not match the book is still sent
+        for message in [
+            r#"{"event":"subscribe","arg":{"channel":"books","instId":"ETH-BTC"},"connId":"a4d3ae55"}"#,
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"snapshot","data":[{"asks":[["0.0651","1","0","1"],["0.0652","2","0","1"]],"bids":[["0.065","3","0","1"],["0.0649","4","0","1"]],"ts":"1690000000000","checksum":-1955476496,"prevSeqId":-1,"seqId":100}]}"#,
+            "pong",
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[],"bids":[["0.065","0","0","0"]],"ts":"1690000000100","checksum":-693854098,"prevSeqId":100,"seqId":101}]}"#,
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[["0.0651","0","0","0"]],"bids":[],"ts":"1690000000200","checksum":12345,"prevSeqId":101,"seqId":102}]}"#,
+        ] {
+            ws_stream_tx
+                .send(Message::Text(message.to_owned()))
+                .await
+                .expect("Could not send message");
+        }
+
+        let levels = |price_level_update: &PriceLevelUpdate| {
+            (
+                price_level_update
+                    .bids
+                    .iter()
+                    .map(|bid| (bid.price.0, bid.quantity.0))
+                    .collect::<Vec<_>>(),
+                price_level_update
+                    .asks
+                    .iter()
+                    .map(|ask| (ask.price.0, ask.quantity.0))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let snapshot = price_level_rx.recv().await.expect("No snapshot received");
+        assert_eq!(snapshot.exchange, Exchange::Okx);
+        assert!(snapshot.clear);
+        assert_eq!(snapshot.exchange_timestamp, Some(1690000000000000));
+        assert_eq!(
+            levels(&snapshot),
+            (
+                vec![(0.065, 3.0), (0.0649, 4.0)],
+                vec![(0.0651, 1.0), (0.0652, 2.0)]
+            )
+        );
+
+        let update = price_level_rx.recv().await.expect("No update received");
+        assert!(!update.clear);
+        assert_eq!(update.exchange_timestamp, Some(1690000000100000));
+        assert_eq!(levels(&update), (vec![(0.065, 0.0)], vec![]));
+
+        let mismatched_update = price_level_rx
+            .recv()
+            .await
+            .expect("No mismatched update received");
+        assert_eq!(levels(&mismatched_update), (vec![], vec![(0.0651, 0.0)]));
+        assert!(price_level_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_error() {
+        let (ws_stream_tx, ws_stream_rx) = tokio::sync::mpsc::channel::<Message>(10);
+        let (price_level_tx, _price_level_rx) = tokio::sync::mpsc::channel::<PriceLevelUpdate>(10);
+        let stream_handler = spawn_stream_handler(
+            ws_stream_rx,
+            price_level_tx,
+            tokio::sync::watch::channel(false).1,
+        );
+
+        ws_stream_tx
+            .send(Message::Text(
+                r#"{"event":"error","code":"60018","msg":"Wrong URL or channel:books,instId:ETH-ABC doesn't exist.","connId":"a4d3ae55"}"#.to_owned(),
+            ))
+            .await
+            .expect("Could not send message");
+
+        match stream_handler.await.expect("Join handle error") {
+            Err(BidAskServiceError::OkxError(OkxError::SubscriptionError { code, msg })) => {
+                assert_eq!(code, "60018");
+                assert_eq!(
+                    msg,
+                    "Wrong URL or channel:books,instId:ETH-ABC doesn't exist."
+                );
+            }
+            other => panic!("Expected a subscription error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FuturesUnordered, Stream, StreamExt};
+use tokio::{sync::mpsc::Receiver, task::JoinHandle};
+
+use crate::{error::BidAskServiceError, order_book::price_level::PriceLevelUpdate};
+
+// A stream of the price level updates from an exchange's order book service, yielding an error if any of the service's tasks fail.
+// The service's tasks are aborted when the stream is dropped.
+pub struct OrderBookStream {
+    price_level_rx: Receiver<PriceLevelUpdate>,
+    handles: FuturesUnordered<JoinHandle<Result<(), BidAskServiceError>>>,
+    failed: bool,
+}
+
+impl OrderBookStream {
+    pub fn new(
+        price_level_rx: Receiver<PriceLevelUpdate>,
+        handles: Vec<JoinHandle<Result<(), BidAskServiceError>>>,
+    ) -> Self {
+        OrderBookStream {
+            price_level_rx,
+            handles: handles.into_iter().collect(),
+            failed: false,
+        }
+    }
+}
+
+impl Stream for OrderBookStream {
+    type Item = Result<PriceLevelUpdate, BidAskServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        //The stream ends after yielding the first task error, since the service can no longer produce a consistent order book
+        if self.failed {
+            return Poll::Ready(None);
+        }
+
+        //Yield any pending updates before checking the tasks, so that updates sent before a task failed are not lost
+        let closed = match self.price_level_rx.poll_recv(cx) {
+            Poll::Ready(Some(price_level_update)) => {
+                return Poll::Ready(Some(Ok(price_level_update)))
+            }
+            Poll::Ready(None) => true,
+            Poll::Pending => false,
+        };
+
+        loop {
+            match self.handles.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(Ok(())))) if !closed => continue,
+                Poll::Ready(Some(Ok(Err(err)))) => {
+                    self.failed = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(Some(Err(join_error))) => {
+                    self.failed = true;
+                    return Poll::Ready(Some(Err(join_error.into())));
+                }
+                //The channel is closed once the task sending the updates has finished, so the stream ends with that task
+                Poll::Ready(Some(Ok(Ok(())))) | Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Drop for OrderBookStream {
+    fn drop(&mut self) {
+        for handle in self.handles.iter() {
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use futures::SinkExt;
+use rand::Rng;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+};
+
+pub const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(250);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+pub const DEFAULT_SUSTAINED_CONNECTION: Duration = Duration::from_secs(30);
+//Exchanges send updates or heartbeats well within this window, ie. Binance pings every 3 minutes but pushes depth updates every second
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+//Time allowed to send the unsubscribe message and close frame before a planned reconnect, so that a wedged connection does not hold up reconnecting
+pub const CLOSE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Exponential backoff with jitter between reconnect attempts to an exchange. The delay doubles with each attempt up to the max delay,
+// and the attempts are reset once a connection has been held for the sustained connection duration.
+// Connections that receive no messages for the idle timeout are treated as wedged and reconnected.
+#[derive(Debug, Clone)]
+pub struct ReconnectBackoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub sustained_connection: Duration,
+    pub idle_timeout: Duration,
+    attempt: u32,
+}
+
+impl ReconnectBackoff {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        ReconnectBackoff {
+            initial_delay,
+            max_delay,
+            sustained_connection: DEFAULT_SUSTAINED_CONNECTION,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            attempt: 0,
+        }
+    }
+
+    pub fn with_sustained_connection(mut self, sustained_connection: Duration) -> Self {
+        self.sustained_connection = sustained_connection;
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    //Get the number of reconnect attempts since the last sustained connection
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    //Get the delay before the next reconnect attempt without jitter, ie. min(initial_delay * 2^attempt, max_delay)
+    pub fn base_delay(&self) -> Duration {
+        self.initial_delay
+            .checked_mul(2_u32.saturating_pow(self.attempt))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    //Get the delay before the next reconnect attempt and count the attempt. The delay is jittered between half and all of the base delay,
+    //so that streams disconnected at the same time do not reconnect in lockstep
+    pub fn next_delay(&mut self) -> Duration {
+        let base_delay = self.base_delay();
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half_delay = base_delay / 2;
+        half_delay + rand::thread_rng().gen_range(Duration::ZERO..=half_delay)
+    }
+
+    //Reset the attempts, so that the next delay is the initial delay
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    //Record that a connection was closed after being connected for the duration, resetting the attempts if the connection was sustained
+    pub fn connection_closed(&mut self, connected_for: Duration) {
+        if connected_for >= self.sustained_connection {
+            self.attempt = 0;
+        }
+    }
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        ReconnectBackoff::new(DEFAULT_INITIAL_DELAY, DEFAULT_MAX_DELAY)
+    }
+}
+
+//Connect to the websocket endpoint. Once the stream has connected before, failed attempts are retried after the backoff delay,
+//so that an exchange outage is waited out without hammering the exchange. The first connection is not retried, so that an
+//unreachable or misconfigured endpoint fails the stream when it is spawned
+pub async fn connect_with_backoff(
+    endpoint: &str,
+    reconnect_backoff: &mut ReconnectBackoff,
+    reconnecting: bool,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Error> {
+    loop {
+        match tokio_tungstenite::connect_async(endpoint).await {
+            Ok((ws_stream, _)) => return Ok(ws_stream),
+            Err(err) if reconnecting => {
+                let reconnect_delay = reconnect_backoff.next_delay();
+                tracing::warn!("Could not reconnect: {err}, retrying in {reconnect_delay:?}");
+                tokio::time::sleep(reconnect_delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+//Send the unsubscribe message followed by a normal close frame before dropping the connection on a planned reconnect,
+//so that the subscription does not linger on the exchange and count against its connection limits.
+//The connection is dropped either way, so failing to unsubscribe is only logged
+pub async fn unsubscribe_and_close(
+    ws_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    unsubscribe_message: String,
+) {
+    let close_handshake = async {
+        ws_stream.send(Message::Text(unsubscribe_message)).await?;
+        ws_stream
+            .close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "Reconnecting".into(),
+            }))
+            .await
+    };
+
+    match tokio::time::timeout(CLOSE_HANDSHAKE_TIMEOUT, close_handshake).await {
+        Ok(Ok(_)) => tracing::info!("Unsubscribed and closed the ws connection"),
+        Ok(Err(err)) => tracing::warn!("Could not unsubscribe before closing the ws connection: {err}"),
+        Err(_) => tracing::warn!(
+            "Could not unsubscribe before closing the ws connection within {CLOSE_HANDSHAKE_TIMEOUT:?}"
+        ),
+    }
+}
+
+//Check if an exchange closed the connection with a code that will not be resolved by reconnecting,
+//eg. a policy violation when the connection is banned or invalid data when the subscription is rejected
+pub fn is_terminal_close(close_frame: &CloseFrame) -> bool {
+    matches!(close_frame.code, CloseCode::Policy | CloseCode::Invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use crate::exchanges::reconnect::{connect_with_backoff, ReconnectBackoff};
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let mut backoff = ReconnectBackoff::new(Duration::from_millis(100), Duration::from_secs(5))
+            .with_sustained_connection(Duration::from_secs(30));
+
+        //The delay doubles with each attempt until it is capped, jittered between half and all of the base delay
+        let mut base_delays = vec![];
+        for _ in 0..8 {
+            let base_delay = backoff.base_delay();
+            let delay = backoff.next_delay();
+            assert!(delay >= base_delay / 2 && delay <= base_delay);
+            base_delays.push(base_delay.as_millis());
+
+            //Connections that drop quickly keep backing off
+            backoff.connection_closed(Duration::from_secs(1));
+        }
+        assert_eq!(
+            base_delays,
+            vec![100, 200, 400, 800, 1600, 3200, 5000, 5000]
+        );
+
+        //A sustained connection resets the backoff
+        backoff.connection_closed(Duration::from_secs(30));
+        assert_eq!(backoff.attempt(), 0);
+        assert_eq!(backoff.base_delay(), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_backoff() {
+        //Serve an endpoint that drops each connection before the websocket handshake, recording when each attempt is made
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind listener");
+        let endpoint = format!("ws://{}/", listener.local_addr().expect("No local addr"));
+        let (attempt_tx, mut attempt_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                attempt_tx.send(tokio::time::Instant::now()).ok();
+                drop(stream);
+            }
+        });
+
+        //The first connection is not retried
+        let mut backoff = ReconnectBackoff::new(Duration::from_millis(10), Duration::from_secs(1));
+        assert!(connect_with_backoff(&endpoint, &mut backoff, false)
+            .await
+            .is_err());
+        assert_eq!(backoff.attempt(), 0);
+        attempt_rx.recv().await.expect("No connection attempt");
+
+        //Reconnects are retried, backing off for longer after each failed attempt
+        let connect_handle =
+            tokio::spawn(async move { connect_with_backoff(&endpoint, &mut backoff, true).await });
+        let mut attempts = vec![];
+        for _ in 0..5 {
+            attempts.push(attempt_rx.recv().await.expect("No connection attempt"));
+        }
+        connect_handle.abort();
+
+        let delays = attempts
+            .windows(2)
+            .map(|attempts| attempts[1] - attempts[0])
+            .collect::<Vec<_>>();
+        assert!(delays[0] >= Duration::from_millis(5));
+        assert!(delays[2] > delays[0]);
+        assert!(delays[3] > delays[1]);
+    }
+}
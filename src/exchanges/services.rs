@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+
+use crate::{
+    error::{flatten_task_result, BidAskServiceError},
+    exchanges::Exchange,
+};
+
+type ServiceResult = Result<Result<(), BidAskServiceError>, JoinError>;
+
+//Wait until shutdown has been requested, or forever if the aggregated order book has been dropped without requesting shutdown
+pub async fn shutdown_requested(shutdown_rx: &mut watch::Receiver<bool>) {
+    if shutdown_rx.wait_for(|shutdown| *shutdown).await.is_err() {
+        std::future::pending().await
+    }
+}
+
+// The tasks of each exchange's order book service, which can be started and stopped individually while the aggregated order book is running.
+// Each start is tagged with a generation, so that the tasks of a stopped exchange are not mistaken for the tasks of the exchange once it is restarted
+#[derive(Default)]
+pub struct ExchangeServices {
+    running: HashMap<Exchange, (u64, Vec<AbortHandle>)>,
+    tasks: FuturesUnordered<BoxFuture<'static, (Exchange, u64, ServiceResult)>>,
+    generation: u64,
+}
+
+impl ExchangeServices {
+    pub fn new() -> Self {
+        ExchangeServices::default()
+    }
+
+    //Start tracking the tasks of the exchange's order book service
+    pub fn start(
+        &mut self,
+        exchange: &Exchange,
+        handles: Vec<JoinHandle<Result<(), BidAskServiceError>>>,
+    ) {
+        self.generation += 1;
+        let generation = self.generation;
+
+        let abort_handles = handles.iter().map(JoinHandle::abort_handle).collect();
+        self.running
+            .insert(exchange.clone(), (generation, abort_handles));
+
+        for handle in handles {
+            let exchange = exchange.clone();
+            self.tasks.push(
+                handle
+                    .map(move |result| (exchange, generation, result))
+                    .boxed(),
+            );
+        }
+    }
+
+    //Abort the tasks of the exchange's order book service, returning false if the exchange was not running
+    pub fn stop(&mut self, exchange: &Exchange) -> bool {
+        match self.running.remove(exchange) {
+            Some((_, abort_handles)) => {
+                for abort_handle in abort_handles {
+                    abort_handle.abort();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    //Abort the tasks of every running exchange's order book service
+    pub fn stop_all(&mut self) {
+        for (_, (_, abort_handles)) in self.running.drain() {
+            for abort_handle in abort_handles {
+                abort_handle.abort();
+            }
+        }
+    }
+
+    //Wait for the tasks of every running exchange's order book service to finish, returning the first error.
+    //Used on shutdown, once the services have been signalled to unsubscribe and close their connections
+    pub async fn join_all(&mut self) -> Result<(), BidAskServiceError> {
+        let mut result = Ok(());
+        while let Some((exchange, generation, task_result)) = self.tasks.next().await {
+            if self.running.get(&exchange).map(|(current, _)| *current) != Some(generation) {
+                continue;
+            }
+
+            if let Err(err) = flatten_task_result(task_result) {
+                tracing::error!("{exchange} order book service failed while stopping: {err}");
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        self.running.clear();
+        result
+    }
+
+    //Get the exchanges with a running order book service
+    pub fn running(&self) -> impl Iterator<Item = &Exchange> {
+        self.running.keys()
+    }
+
+    //Wait for the next task of a running exchange to finish, skipping the tasks of stopped exchanges.
+    //Waits forever if no exchange is running, so that it can be selected on alongside the changes to the exchanges
+    pub async fn next(&mut self) -> (Exchange, ServiceResult) {
+        loop {
+            match self.tasks.next().await {
+                Some((exchange, generation, result)) => {
+                    if self.running.get(&exchange).map(|(current, _)| *current) == Some(generation)
+                    {
+                        return (exchange, result);
+                    }
+                }
+                None => std::future::pending().await,
+            }
+        }
+    }
+}
@@ -1,4 +1,11 @@
+pub mod display;
 pub mod error;
+pub mod events;
 pub mod exchanges;
+pub mod metrics;
 pub mod order_book;
+pub mod pair;
+#[cfg(feature = "profile")]
+pub mod profile;
 pub mod server;
+pub mod store;
@@ -0,0 +1,227 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use tokio::task::JoinHandle;
+
+use crate::{error::BidAskServiceError, exchanges::Exchange, server::error::ServerError};
+
+const METRICS_PATH: &str = "/metrics";
+
+//Buckets for the summary publish latency in seconds, from 10 microseconds up to 100 milliseconds
+const SUMMARY_PUBLISH_LATENCY_BUCKETS: &[f64] = &[
+    0.00001, 0.000025, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05,
+    0.1,
+];
+
+// Prometheus metrics for the update throughput of each exchange and the spread of each pair, served in the text exposition format
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    //Price levels received from each exchange, labeled by pair and exchange
+    price_level_updates: IntCounterVec,
+    //Spread of the latest summary, labeled by pair
+    spread: GaugeVec,
+    //Time from receiving a price level update to publishing the resulting summary, labeled by pair
+    summary_publish_latency: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let price_level_updates = IntCounterVec::new(
+            Opts::new(
+                "bid_ask_service_price_level_updates_total",
+                "Price levels received from each exchange",
+            ),
+            &["pair", "exchange"],
+        )
+        .expect("Invalid price level updates metric");
+        let spread = GaugeVec::new(
+            Opts::new("bid_ask_service_spread", "Spread of the latest summary"),
+            &["pair"],
+        )
+        .expect("Invalid spread metric");
+        let summary_publish_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "bid_ask_service_summary_publish_latency_seconds",
+                "Time from receiving a price level update to publishing the resulting summary",
+            )
+            .buckets(SUMMARY_PUBLISH_LATENCY_BUCKETS.to_vec()),
+            &["pair"],
+        )
+        .expect("Invalid summary publish latency metric");
+
+        //Registering only fails for duplicate metric names, which are fixed above
+        let registry = Registry::new();
+        registry
+            .register(Box::new(price_level_updates.clone()))
+            .expect("Could not register price level updates metric");
+        registry
+            .register(Box::new(spread.clone()))
+            .expect("Could not register spread metric");
+        registry
+            .register(Box::new(summary_publish_latency.clone()))
+            .expect("Could not register summary publish latency metric");
+
+        Metrics {
+            registry,
+            price_level_updates,
+            spread,
+            summary_publish_latency,
+        }
+    }
+
+    pub fn record_price_level_updates(&self, pair: &str, exchange: &Exchange, levels: usize) {
+        self.price_level_updates
+            .with_label_values(&[pair, &exchange.to_string()])
+            .inc_by(levels as u64);
+    }
+
+    pub fn set_spread(&self, pair: &str, spread: f64) {
+        self.spread.with_label_values(&[pair]).set(spread);
+    }
+
+    pub fn observe_summary_publish_latency(&self, pair: &str, latency: Duration) {
+        self.summary_publish_latency
+            .with_label_values(&[pair])
+            .observe(latency.as_secs_f64());
+    }
+
+    //Render every metric in the Prometheus text exposition format
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+        //Encoding into a Vec can only fail for invalid metric families, which the registry never gathers
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Could not encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not valid utf8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+//Serve the metrics at /metrics over HTTP, so that they can be scraped by Prometheus alongside the gRPC server
+pub fn spawn_metrics_server(
+    metrics: Arc<Metrics>,
+    socket_address: SocketAddr,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = handle_metrics_request(&metrics, request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+
+        Server::try_bind(&socket_address)
+            .map_err(ServerError::MetricsServerError)?
+            .serve(make_service)
+            .await
+            .map_err(ServerError::MetricsServerError)?;
+        Ok::<_, BidAskServiceError>(())
+    })
+}
+
+fn handle_metrics_request(metrics: &Metrics, request: Request<Body>) -> Response<Body> {
+    let mut response = Response::default();
+    if request.method() == Method::GET && request.uri().path() == METRICS_PATH {
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            TextEncoder::new()
+                .format_type()
+                .parse()
+                .expect("Invalid metrics content type"),
+        );
+        *response.body_mut() = Body::from(metrics.encode());
+    } else {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use hyper::{body, Client, StatusCode};
+
+    use crate::{
+        exchanges::Exchange,
+        metrics::{spawn_metrics_server, Metrics},
+    };
+
+    #[tokio::test]
+    async fn test_metrics_server() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_price_level_updates("eth/btc", &Exchange::Binance, 3);
+        metrics.record_price_level_updates("eth/btc", &Exchange::Binance, 2);
+        metrics.record_price_level_updates("eth/btc", &Exchange::Bitstamp, 1);
+        metrics.set_spread("eth/btc", 0.25);
+        metrics.observe_summary_publish_latency("eth/btc", Duration::from_micros(200));
+
+        //Bind to a free port, then release it for the metrics server
+        let socket_address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Could not find a free port");
+        let handle = spawn_metrics_server(metrics, socket_address);
+
+        //Retry until the server is listening
+        let client = Client::new();
+        let uri: hyper::Uri = format!("http://{socket_address}/metrics")
+            .parse()
+            .expect("Invalid uri");
+        let mut response = None;
+        for _ in 0..50 {
+            match client.get(uri.clone()).await {
+                Ok(r) => {
+                    response = Some(r);
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+        let response = response.expect("Could not reach the metrics server");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = body::to_bytes(response.into_body())
+            .await
+            .expect("Could not read metrics");
+        let body = String::from_utf8(body.to_vec()).expect("Metrics are not valid utf8");
+        assert!(body.contains(
+            r#"bid_ask_service_price_level_updates_total{exchange="binance",pair="eth/btc"} 5"#
+        ));
+        assert!(body.contains(
+            r#"bid_ask_service_price_level_updates_total{exchange="bitstamp",pair="eth/btc"} 1"#
+        ));
+        assert!(body.contains(r#"bid_ask_service_spread{pair="eth/btc"} 0.25"#));
+        assert!(body.contains(
+            r#"bid_ask_service_summary_publish_latency_seconds_count{pair="eth/btc"} 1"#
+        ));
+
+        //Other paths are not served
+        let response = client
+            .get(
+                format!("http://{socket_address}/")
+                    .parse()
+                    .expect("Invalid uri"),
+            )
+            .await
+            .expect("Could not reach the metrics server");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        handle.abort();
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use ordered_float::OrderedFloat;
+
+use crate::exchanges::Exchange;
+
+use super::{
+    best_n_by_exchange, duplicate_levels,
+    price_level::{ask::Ask, bid::Bid},
+    ranker::{rank_best_n, LevelRanker, RankedLevel},
+    total_notional, BuySide, BuySideView, Order, SellSide, SellSideView,
+};
+
+// The identity of a price level, so that a quantity update is applied to the level in place without reordering the tree
+pub type LevelKey = (OrderedFloat<f64>, Exchange);
+
+pub fn level_key<O: Order>(order: &O) -> LevelKey {
+    (*order.get_price(), order.get_exchange().clone())
+}
+
+//Compare levels at the same price by the order's own ordering, which breaks ties by quantity. Levels with the same quantity
+//are compared by exchange, since the order's ordering does not give a total order between them
+fn cmp_same_price<O: Order>(a: &O, b: &O) -> Ordering {
+    if a.get_quantity() == b.get_quantity() {
+        a.get_exchange().cmp(b.get_exchange())
+    } else {
+        a.cmp(b)
+    }
+}
+
+//Iterate over the levels in the order of the BTreeSet, sorting each run of levels at the same price. The map is keyed by exchange
+//within a price, so only the few levels at the same price are sorted as they are reached
+pub fn sorted_by_price<'a, O: Order + 'a>(
+    levels: impl Iterator<Item = &'a O>,
+    descending: bool,
+) -> impl Iterator<Item = &'a O> {
+    let mut levels = levels.peekable();
+    let mut run: Vec<&O> = vec![];
+
+    std::iter::from_fn(move || {
+        if run.is_empty() {
+            let first = levels.next()?;
+            run.push(first);
+            while let Some(level) = levels.next_if(|level| level.get_price() == first.get_price()) {
+                run.push(level);
+            }
+
+            //The run is popped from the back, so it is sorted in reverse of the order that it is iterated in
+            if descending {
+                run.sort_by(|a, b| cmp_same_price(*a, *b));
+            } else {
+                run.sort_by(|a, b| cmp_same_price(*b, *a));
+            }
+        }
+
+        run.pop()
+    })
+}
+
+//Remove the n least recently updated levels, given from the worst level. The stable sort keeps that order between levels updated at the same time
+fn evict_levels<O: Order + Clone>(
+    levels: &mut BTreeMap<LevelKey, O>,
+    mut worst_first: Vec<O>,
+    n: usize,
+    last_updated: impl Fn(&O) -> tokio::time::Instant,
+) -> usize {
+    worst_first.sort_by_key(|level| last_updated(level));
+
+    let mut removed = 0;
+    for level in worst_first.iter().take(n) {
+        if levels.remove(&level_key(level)).is_some() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+impl BuySide for BTreeMap<LevelKey, Bid> {
+    //Update the bids in the order book with the new bid. A bid already in the order book is replaced in place,
+    //since its key does not change with its quantity
+    fn update_bids(&mut self, bid: Bid, max_depth: usize) {
+        let key = level_key(&bid);
+
+        if bid.get_quantity().0 == 0.0 {
+            self.remove(&key);
+        } else if let Some(level) = self.get_mut(&key) {
+            *level = bid;
+        } else if self.len() < max_depth {
+            self.insert(key, bid);
+        } else {
+            //We can unwrap this because the bids are at the max depth, signifying that there is at least one value
+            let worst_bid = sorted_by_price(self.values(), false).next().unwrap();
+
+            if bid > *worst_bid {
+                let worst_key = level_key(worst_bid);
+                self.remove(&worst_key);
+                self.insert(key, bid);
+            }
+        }
+    }
+
+    //Get the best bid in the data structure
+    fn get_best_bid(&self) -> Option<Bid> {
+        sorted_by_price(self.values().rev(), true).next().cloned()
+    }
+
+    //Get the best "n" bids in the data structure
+    fn get_top_bids(&self, n: usize) -> Vec<Bid> {
+        sorted_by_price(self.values().rev(), true)
+            .take(n)
+            .cloned()
+            .collect()
+    }
+
+    //Get the best bid from the exchange
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid> {
+        self.values()
+            .rev()
+            .find(|bid| bid.exchange == *exchange)
+            .cloned()
+    }
+
+    //Get the best "n" bids from each exchange in the data structure
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>> {
+        best_n_by_exchange(sorted_by_price(self.values().rev(), true), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has a bid at the price
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.get(&(OrderedFloat(price), exchange.clone()))
+            .map(|bid| bid.quantity.0)
+    }
+
+    //Get the best n bids ranked by a custom ranker. Bids with equal priority keep the order of the best bids in the data structure
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid> {
+        rank_best_n(
+            sorted_by_price(self.values().rev(), true),
+            n,
+            ranker,
+            RankedLevel::Bid,
+        )
+    }
+
+    //Remove all bids that have not been updated within the max age, returning the number of bids removed
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize {
+        let len = self.len();
+        self.retain(|_, bid| bid.age() <= max_age);
+        len - self.len()
+    }
+
+    //Remove all bids from the exchange, returning the number of bids removed
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize {
+        let len = self.len();
+        self.retain(|_, bid| bid.exchange != *exchange);
+        len - self.len()
+    }
+
+    //Get the number of bids in the data structure
+    fn num_bids(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one bid
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.values())
+    }
+
+    //Remove the n least recently updated bids, evicting the worst priced bids first when they were updated at the same time.
+    //Returns the number of bids removed
+    fn evict_bids(&mut self, n: usize) -> usize {
+        let bids = sorted_by_price(self.values(), false)
+            .cloned()
+            .collect::<Vec<_>>();
+        evict_levels(self, bids, n, |bid| bid.last_updated)
+    }
+
+    //Get the sum of price * quantity across all bids in the data structure
+    fn total_notional_bids(&self) -> f64 {
+        total_notional(self.values())
+    }
+}
+
+impl SellSide for BTreeMap<LevelKey, Ask> {
+    //Update the asks in the order book with the new ask. An ask already in the order book is replaced in place,
+    //since its key does not change with its quantity
+    fn update_asks(&mut self, ask: Ask, max_depth: usize) {
+        let key = level_key(&ask);
+
+        if ask.get_quantity().0 == 0.0 {
+            self.remove(&key);
+        } else if let Some(level) = self.get_mut(&key) {
+            *level = ask;
+        } else if self.len() < max_depth {
+            self.insert(key, ask);
+        } else {
+            //We can unwrap this because the asks are at the max depth, signifying that there is at least one value
+            let worst_ask = sorted_by_price(self.values().rev(), true).next().unwrap();
+
+            if ask < *worst_ask {
+                let worst_key = level_key(worst_ask);
+                self.remove(&worst_key);
+                self.insert(key, ask);
+            }
+        }
+    }
+
+    //Get the best ask in the data structure
+    fn get_best_ask(&self) -> Option<Ask> {
+        sorted_by_price(self.values(), false).next().cloned()
+    }
+
+    //Get the best "n" asks in the data structure
+    fn get_top_asks(&self, n: usize) -> Vec<Ask> {
+        sorted_by_price(self.values(), false)
+            .take(n)
+            .cloned()
+            .collect()
+    }
+
+    //Get the best ask from the exchange
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask> {
+        self.values().find(|ask| ask.exchange == *exchange).cloned()
+    }
+
+    //Get the best "n" asks from each exchange in the data structure
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>> {
+        best_n_by_exchange(sorted_by_price(self.values(), false), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an ask at the price
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.get(&(OrderedFloat(price), exchange.clone()))
+            .map(|ask| ask.quantity.0)
+    }
+
+    //Get the best n asks ranked by a custom ranker. Asks with equal priority keep the order of the best asks in the data structure
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask> {
+        rank_best_n(
+            sorted_by_price(self.values(), false),
+            n,
+            ranker,
+            RankedLevel::Ask,
+        )
+    }
+
+    //Remove all asks that have not been updated within the max age, returning the number of asks removed
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize {
+        let len = self.len();
+        self.retain(|_, ask| ask.age() <= max_age);
+        len - self.len()
+    }
+
+    //Remove all asks from the exchange, returning the number of asks removed
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize {
+        let len = self.len();
+        self.retain(|_, ask| ask.exchange != *exchange);
+        len - self.len()
+    }
+
+    //Get the number of asks in the data structure
+    fn num_asks(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one ask
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.values())
+    }
+
+    //Remove the n least recently updated asks, evicting the worst priced asks first when they were updated at the same time.
+    //Returns the number of asks removed
+    fn evict_asks(&mut self, n: usize) -> usize {
+        let asks = sorted_by_price(self.values().rev(), true)
+            .cloned()
+            .collect::<Vec<_>>();
+        evict_levels(self, asks, n, |ask| ask.last_updated)
+    }
+
+    //Get the sum of price * quantity across all asks in the data structure
+    fn total_notional_asks(&self) -> f64 {
+        total_notional(self.values())
+    }
+}
+
+impl BuySideView for BTreeMap<LevelKey, Bid> {
+    //Iterate over the bids from the best bid to the worst bid
+    fn iter_bids(&self) -> impl Iterator<Item = &Bid> {
+        sorted_by_price(self.values().rev(), true)
+    }
+}
+
+impl SellSideView for BTreeMap<LevelKey, Ask> {
+    //Iterate over the asks from the best ask to the worst ask
+    fn iter_asks(&self) -> impl Iterator<Item = &Ask> {
+        sorted_by_price(self.values(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use crate::{
+        exchanges::Exchange,
+        order_book::{
+            btree_map::LevelKey,
+            price_level::{ask::Ask, bid::Bid},
+            BuySide, SellSide,
+        },
+    };
+
+    #[test]
+    fn test_matches_btree_set() {
+        let mut set_bids = BTreeSet::<Bid>::new();
+        let mut map_bids = BTreeMap::<LevelKey, Bid>::new();
+        let mut set_asks = BTreeSet::<Ask>::new();
+        let mut map_asks = BTreeMap::<LevelKey, Ask>::new();
+
+        //Levels at the same price from different exchanges are ordered by quantity, as in the BTreeSet
+        for (price, quantity, exchange) in [
+            (100.0, 5.0, Exchange::Binance),
+            (100.0, 7.0, Exchange::Bitstamp),
+            (101.0, 1.0, Exchange::Binance),
+            (99.0, 2.0, Exchange::Bitstamp),
+            (99.0, 1.0, Exchange::Binance),
+            (102.0, 3.0, Exchange::Bitstamp),
+            (99.0, 0.0, Exchange::Binance),
+            (103.0, 3.0, Exchange::Binance),
+        ] {
+            set_bids.update_bids(Bid::new(price, quantity, exchange.clone()), 5);
+            map_bids.update_bids(Bid::new(price, quantity, exchange.clone()), 5);
+            set_asks.update_asks(Ask::new(price, quantity, exchange.clone()), 5);
+            map_asks.update_asks(Ask::new(price, quantity, exchange), 5);
+        }
+
+        assert_eq!(map_bids.get_best_n_bids(6), set_bids.get_best_n_bids(6));
+        assert_eq!(map_asks.get_best_n_asks(6), set_asks.get_best_n_asks(6));
+        assert_eq!(map_bids.get_best_bid(), set_bids.get_best_bid());
+        assert_eq!(map_asks.get_best_ask(), set_asks.get_best_ask());
+        assert_eq!(
+            map_bids.total_notional_bids(),
+            set_bids.total_notional_bids()
+        );
+        assert_eq!(map_asks.evict_asks(2), set_asks.evict_asks(2));
+        assert_eq!(map_asks.get_best_n_asks(4), set_asks.get_best_n_asks(4));
+    }
+
+    #[test]
+    fn test_update_in_place() {
+        let mut bids = BTreeMap::<LevelKey, Bid>::new();
+
+        bids.update_bids(Bid::new(100.0, 1.0, Exchange::Binance), 10);
+        bids.update_bids(Bid::new(100.0, 2.0, Exchange::Bitstamp), 10);
+
+        //The quantity update moves the level past the other exchange's level at the same price, which is reflected in the order
+        bids.update_bids(Bid::new(100.0, 3.0, Exchange::Binance), 10);
+        assert_eq!(bids.num_bids(), 2);
+        assert_eq!(
+            bids.get_best_n_bids(2),
+            vec![
+                Some(Bid::new(100.0, 3.0, Exchange::Binance)),
+                Some(Bid::new(100.0, 2.0, Exchange::Bitstamp)),
+            ]
+        );
+        assert_eq!(
+            bids.get_exchange_bid_quantity(100.0, &Exchange::Binance),
+            Some(3.0)
+        );
+
+        //A level already in the order book is updated at the max depth, even if it is the worst level
+        bids.update_bids(Bid::new(100.0, 1.0, Exchange::Bitstamp), 2);
+        assert_eq!(
+            bids.get_exchange_bid_quantity(100.0, &Exchange::Bitstamp),
+            Some(1.0)
+        );
+        assert!(bids.duplicate_bids().is_empty());
+    }
+}
@@ -1,24 +1,30 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use crate::exchanges::Exchange;
 
 use super::{
+    best_n_by_exchange, duplicate_levels,
     price_level::{ask::Ask, bid::Bid},
-    BuySide, Order, SellSide,
+    ranker::{rank_best_n, LevelRanker, RankedLevel},
+    total_notional, BuySide, BuySideView, Order, SellSide, SellSideView,
 };
 
 impl BuySide for BTreeSet<Bid> {
     //Update the bids in the order book with the new bid
     fn update_bids(&mut self, bid: Bid, max_depth: usize) {
+        //The exchange's existing level at the price compares as equal regardless of its quantity, so it is removed in the same traversal
+        //that checks for it. It can not be replaced in place, since the quantity orders levels of other exchanges at the same price
+        self.remove(&bid);
         if bid.get_quantity().0 == 0.0 {
-            self.remove(&bid);
-        } else if self.len() < max_depth {
-            if self.contains(&bid) {
-                //We have to remove and insert because the replace method replaces the value at the pointer.
-                //Since the two are seen as equal, it does not reorder the tree
-                self.remove(&bid);
-                self.insert(bid);
-            } else {
-                self.insert(bid);
-            }
+            return;
+        }
+
+        //Removing an existing level always leaves room for its update
+        if self.len() < max_depth {
+            self.insert(bid);
         } else {
             // check if the bid is better than the worst bid
             let bid_is_better = {
@@ -36,44 +42,106 @@ impl BuySide for BTreeSet<Bid> {
     }
 
     //Get the best bid in the data structure
-    fn get_best_bid(&self) -> Option<&Bid> {
-        self.last()
+    fn get_best_bid(&self) -> Option<Bid> {
+        self.last().cloned()
     }
 
     //Get the best "n" bids in the data structure
-    fn get_best_n_bids(&self, n: usize) -> Vec<Option<Bid>> {
-        let mut best_bids = Vec::new();
+    fn get_top_bids(&self, n: usize) -> Vec<Bid> {
+        self.iter().rev().take(n).cloned().collect()
+    }
 
-        for bid in self.iter().rev().take(n) {
-            best_bids.push(Some(bid.clone()));
-        }
+    //Get the best bid from the exchange
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid> {
+        self.iter()
+            .rev()
+            .find(|bid| bid.exchange == *exchange)
+            .cloned()
+    }
+
+    //Get the best "n" bids from each exchange in the data structure
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>> {
+        best_n_by_exchange(self.iter().rev(), n)
+    }
 
-        while best_bids.len() < n {
-            best_bids.push(None);
+    //Get the exchange's quantity at the price level, if the exchange has a bid at the price.
+    //The price is compared the same way as the bids are ordered, so a price within the epsilon of a level finds the level
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.iter()
+            .find(|bid| {
+                bid.price_key() == bid.price_comparison.key(price) && bid.exchange == *exchange
+            })
+            .map(|bid| bid.quantity.0)
+    }
+
+    //Get the best n bids ranked by a custom ranker. Bids with equal priority keep the order of the best bids in the data structure
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid> {
+        rank_best_n(self.iter().rev(), n, ranker, RankedLevel::Bid)
+    }
+
+    //Remove all bids that have not been updated within the max age, returning the number of bids removed
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize {
+        let len = self.len();
+        self.retain(|bid| bid.age() <= max_age);
+        len - self.len()
+    }
+
+    //Remove all bids from the exchange, returning the number of bids removed
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize {
+        let len = self.len();
+        self.retain(|bid| bid.exchange != *exchange);
+        len - self.len()
+    }
+
+    //Get the number of bids in the data structure
+    fn num_bids(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one bid
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.iter())
+    }
+
+    //Remove the n least recently updated bids, evicting the worst priced bids first when they were updated at the same time.
+    //Returns the number of bids removed
+    fn evict_bids(&mut self, n: usize) -> usize {
+        //The set is iterated from the worst bid, and the stable sort keeps that order between bids updated at the same time
+        let mut bids = self.iter().cloned().collect::<Vec<_>>();
+        bids.sort_by_key(|bid| bid.last_updated);
+
+        let mut removed = 0;
+        for bid in bids.iter().take(n) {
+            if self.remove(bid) {
+                removed += 1;
+            }
         }
+        removed
+    }
 
-        best_bids
+    //Get the sum of price * quantity across all bids in the data structure
+    fn total_notional_bids(&self) -> f64 {
+        total_notional(self.iter())
     }
 }
 
 impl SellSide for BTreeSet<Ask> {
     //Update the asks in the order book with the new bid
     fn update_asks(&mut self, ask: Ask, max_depth: usize) {
+        //The exchange's existing level at the price compares as equal regardless of its quantity, so it is removed in the same traversal
+        //that checks for it. It can not be replaced in place, since the quantity orders levels of other exchanges at the same price
+        self.remove(&ask);
         if ask.get_quantity().0 == 0.0 {
-            self.remove(&ask);
-        } else if self.len() < max_depth {
-            if self.contains(&ask) {
-                //We have to remove and insert because the replace method replaces the value at the pointer.
-                //Since the two are seen as equal, it does not reorder the tree
-                self.remove(&ask);
-                self.insert(ask);
-            } else {
-                self.insert(ask);
-            }
+            return;
+        }
+
+        //Removing an existing level always leaves room for its update
+        if self.len() < max_depth {
+            self.insert(ask);
         } else {
-            // check if the bid is better than the worst bid
+            // check if the ask is better than the worst ask
             let ask_is_better = {
-                //We can unwrap this because we have already asserted that the bids.len() is not less than the max depth
+                //We can unwrap this because we have already asserted that the asks.len() is not less than the max depth
                 //signifying that there is at least one value
                 let worst_ask = self.iter().next_back().unwrap();
                 ask < *worst_ask
@@ -87,29 +155,103 @@ impl SellSide for BTreeSet<Ask> {
     }
 
     //Get the best ask in the data structure
-    fn get_best_ask(&self) -> Option<&Ask> {
-        self.first()
+    fn get_best_ask(&self) -> Option<Ask> {
+        self.first().cloned()
     }
 
     //Get the best "n" asks in the data structure
-    fn get_best_n_asks(&self, n: usize) -> Vec<Option<Ask>> {
-        let mut best_asks = Vec::new();
+    fn get_top_asks(&self, n: usize) -> Vec<Ask> {
+        self.iter().take(n).cloned().collect()
+    }
 
-        for ask in self.iter().take(n) {
-            best_asks.push(Some(ask.clone()));
-        }
+    //Get the best ask from the exchange
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask> {
+        self.iter().find(|ask| ask.exchange == *exchange).cloned()
+    }
+
+    //Get the best "n" asks from each exchange in the data structure
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>> {
+        best_n_by_exchange(self.iter(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an ask at the price.
+    //The price is compared the same way as the asks are ordered, so a price within the epsilon of a level finds the level
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.iter()
+            .find(|ask| {
+                ask.price_key() == ask.price_comparison.key(price) && ask.exchange == *exchange
+            })
+            .map(|ask| ask.quantity.0)
+    }
+
+    //Get the best n asks ranked by a custom ranker. Asks with equal priority keep the order of the best asks in the data structure
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask> {
+        rank_best_n(self.iter(), n, ranker, RankedLevel::Ask)
+    }
+
+    //Remove all asks that have not been updated within the max age, returning the number of asks removed
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize {
+        let len = self.len();
+        self.retain(|ask| ask.age() <= max_age);
+        len - self.len()
+    }
+
+    //Remove all asks from the exchange, returning the number of asks removed
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize {
+        let len = self.len();
+        self.retain(|ask| ask.exchange != *exchange);
+        len - self.len()
+    }
+
+    //Get the number of asks in the data structure
+    fn num_asks(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one ask
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.iter())
+    }
 
-        while best_asks.len() < n {
-            best_asks.push(None);
+    //Remove the n least recently updated asks, evicting the worst priced asks first when they were updated at the same time.
+    //Returns the number of asks removed
+    fn evict_asks(&mut self, n: usize) -> usize {
+        //The set is iterated in reverse from the worst ask, and the stable sort keeps that order between asks updated at the same time
+        let mut asks = self.iter().rev().cloned().collect::<Vec<_>>();
+        asks.sort_by_key(|ask| ask.last_updated);
+
+        let mut removed = 0;
+        for ask in asks.iter().take(n) {
+            if self.remove(ask) {
+                removed += 1;
+            }
         }
+        removed
+    }
+
+    //Get the sum of price * quantity across all asks in the data structure
+    fn total_notional_asks(&self) -> f64 {
+        total_notional(self.iter())
+    }
+}
+
+impl BuySideView for BTreeSet<Bid> {
+    //Iterate over the bids from the best bid to the worst bid
+    fn iter_bids(&self) -> impl Iterator<Item = &Bid> {
+        self.iter().rev()
+    }
+}
 
-        best_asks
+impl SellSideView for BTreeSet<Ask> {
+    //Iterate over the asks from the best ask to the worst ask
+    fn iter_asks(&self) -> impl Iterator<Item = &Ask> {
+        self.iter()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
+    use std::{collections::BTreeSet, time::Duration};
 
     use ordered_float::OrderedFloat;
 
@@ -117,6 +259,7 @@ mod tests {
         exchanges::Exchange,
         order_book::{
             price_level::{ask::Ask, bid::Bid},
+            ranker::DefaultRanker,
             BuySide, Order, SellSide,
         },
     };
@@ -158,7 +301,7 @@ mod tests {
         let actual_bids: Vec<Bid> = order_book.iter().cloned().collect();
 
         let best_bid = order_book.get_best_bid();
-        assert!(*best_bid.expect("Could not get best bid") == bid_6);
+        assert!(best_bid.expect("Could not get best bid") == bid_6);
 
         assert_eq!(actual_bids, expected_bids);
     }
@@ -198,7 +341,7 @@ mod tests {
         let actual_bids: Vec<Bid> = order_book.iter().cloned().collect();
 
         let best_bid = order_book.get_best_bid();
-        assert!(*best_bid.expect("Could not get best bid") == bid_6);
+        assert!(best_bid.expect("Could not get best bid") == bid_6);
         assert!(order_book.len() == 5);
         assert_eq!(actual_bids, expected_bids);
     }
@@ -241,7 +384,7 @@ mod tests {
         let actual_bids: Vec<Bid> = order_book.iter().cloned().collect();
 
         let best_bid = order_book.get_best_bid();
-        assert!(*best_bid.expect("Could not get best bid") == bid_5);
+        assert!(best_bid.expect("Could not get best bid") == bid_5);
 
         assert_eq!(actual_bids, expected_bids);
     }
@@ -293,11 +436,45 @@ mod tests {
         let actual_bids: Vec<Bid> = order_book.iter().cloned().collect();
 
         let best_bid = order_book.get_best_bid();
-        assert!(*best_bid.expect("Could not get best bid") == replacement_bid_6);
+        assert!(best_bid.expect("Could not get best bid") == replacement_bid_6);
 
         assert_eq!(actual_bids, expected_bids);
     }
 
+    #[test]
+    fn test_update_level_at_max_depth() {
+        //Updating an existing level of a full book replaces its quantity without evicting the worst level
+        let mut bids = BTreeSet::<Bid>::new();
+        for price in [100.0, 101.0, 102.0] {
+            bids.update_bids(Bid::new(price, 50.0, Exchange::Binance), 3);
+        }
+        bids.update_bids(Bid::new(101.0, 10.0, Exchange::Binance), 3);
+        assert_eq!(bids.len(), 3);
+        assert_eq!(
+            bids.get_exchange_bid_quantity(101.0, &Exchange::Binance),
+            Some(10.0)
+        );
+        assert_eq!(
+            bids.get_exchange_bid_quantity(100.0, &Exchange::Binance),
+            Some(50.0)
+        );
+
+        let mut asks = BTreeSet::<Ask>::new();
+        for price in [103.0, 104.0, 105.0] {
+            asks.update_asks(Ask::new(price, 50.0, Exchange::Binance), 3);
+        }
+        asks.update_asks(Ask::new(104.0, 10.0, Exchange::Binance), 3);
+        assert_eq!(asks.len(), 3);
+        assert_eq!(
+            asks.get_exchange_ask_quantity(104.0, &Exchange::Binance),
+            Some(10.0)
+        );
+        assert_eq!(
+            asks.get_exchange_ask_quantity(105.0, &Exchange::Binance),
+            Some(50.0)
+        );
+    }
+
     #[test]
     fn test_get_best_n_bids() {
         let mut order_book = BTreeSet::<Bid>::new();
@@ -345,6 +522,48 @@ mod tests {
         assert_eq!(best_bids, expected_bids);
     }
 
+    #[test]
+    fn test_get_top_levels() {
+        let mut bids = BTreeSet::<Bid>::new();
+        let mut asks = BTreeSet::<Ask>::new();
+        for price in [100.0, 101.0, 102.0] {
+            bids.update_bids(Bid::new(price, 1.0, Exchange::Binance), 10);
+            asks.update_asks(Ask::new(price + 3.0, 1.0, Exchange::Binance), 10);
+        }
+
+        let bid_prices = |bids: Vec<Bid>| bids.iter().map(|bid| bid.price.0).collect::<Vec<_>>();
+        let ask_prices = |asks: Vec<Ask>| asks.iter().map(|ask| ask.price.0).collect::<Vec<_>>();
+
+        //A full book holds at least n levels, so exactly the best n levels are returned
+        assert_eq!(bid_prices(bids.get_top_bids(2)), vec![102.0, 101.0]);
+        assert_eq!(ask_prices(asks.get_top_asks(2)), vec![103.0, 104.0]);
+
+        //A partial book holds fewer than n levels, so only the available levels are returned without padding
+        assert_eq!(bid_prices(bids.get_top_bids(5)), vec![102.0, 101.0, 100.0]);
+        assert_eq!(ask_prices(asks.get_top_asks(5)), vec![103.0, 104.0, 105.0]);
+        assert_eq!(
+            bid_prices(bids.get_top_bids_ranked(5, &DefaultRanker)),
+            vec![102.0, 101.0, 100.0]
+        );
+        assert!(BTreeSet::<Bid>::new().get_top_bids(5).is_empty());
+        assert!(BTreeSet::<Ask>::new().get_top_asks(5).is_empty());
+
+        //The padded variants hold the same levels followed by None up to n levels
+        let best_bids = bids.get_best_n_bids(5);
+        assert_eq!(best_bids.len(), 5);
+        assert_eq!(
+            best_bids.iter().flatten().cloned().collect::<Vec<_>>(),
+            bids.get_top_bids(5)
+        );
+        assert_eq!(best_bids[3..], [None, None]);
+        let best_asks = asks.get_best_n_asks_ranked(4, &DefaultRanker);
+        assert_eq!(
+            best_asks[..3].iter().flatten().cloned().collect::<Vec<_>>(),
+            asks.get_top_asks(3)
+        );
+        assert_eq!(best_asks[3], None);
+    }
+
     #[test]
     fn test_insert_ask() {
         let mut order_book = BTreeSet::<Ask>::new();
@@ -382,7 +601,7 @@ mod tests {
         let actual_asks: Vec<Ask> = order_book.iter().cloned().collect();
 
         let best_ask = order_book.get_best_ask();
-        assert!(*best_ask.expect("Could not get best ask") == ask_1);
+        assert!(best_ask.expect("Could not get best ask") == ask_1);
 
         assert_eq!(actual_asks, expected_asks);
     }
@@ -421,7 +640,7 @@ mod tests {
         let actual_asks: Vec<Ask> = order_book.iter().cloned().collect();
 
         let best_ask = order_book.get_best_ask();
-        assert!(*best_ask.expect("Could not get best ask") == ask_1);
+        assert!(best_ask.expect("Could not get best ask") == ask_1);
         assert!(order_book.len() == 5);
         assert_eq!(actual_asks, expected_asks);
     }
@@ -464,7 +683,7 @@ mod tests {
         let actual_asks: Vec<Ask> = order_book.iter().cloned().collect();
 
         let best_ask = order_book.get_best_ask();
-        assert!(*best_ask.expect("Could not get best ask") == ask_0);
+        assert!(best_ask.expect("Could not get best ask") == ask_0);
 
         assert_eq!(actual_asks, expected_asks);
     }
@@ -516,8 +735,8 @@ mod tests {
 
         let best_ask = order_book.get_best_ask();
 
-        dbg!(best_ask);
-        assert!(*best_ask.expect("Could not get best ask") == replacement_ask_1);
+        dbg!(&best_ask);
+        assert!(best_ask.expect("Could not get best ask") == replacement_ask_1);
 
         assert_eq!(actual_asks, expected_asks);
     }
@@ -571,4 +790,153 @@ mod tests {
 
         assert_eq!(best_asks, expected_asks);
     }
+
+    #[test]
+    fn test_get_best_n_by_exchange() {
+        let mut bids = BTreeSet::<Bid>::new();
+        let bid_0 = Bid::new(104.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(103.00, 50.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(102.00, 50.0, Exchange::Binance);
+        let bid_3 = Bid::new(101.00, 50.0, Exchange::Binance);
+        let bid_4 = Bid::new(100.00, 50.0, Exchange::Bitstamp);
+        let bid_5 = Bid::new(99.00, 50.0, Exchange::Bitstamp);
+
+        for bid in [&bid_0, &bid_1, &bid_2, &bid_3, &bid_4, &bid_5] {
+            bids.update_bids(bid.clone(), 10);
+        }
+
+        //Each exchange gets its own best bids, ordered from best to worst
+        let best_bids = bids.get_best_n_bids_by_exchange(2);
+        assert_eq!(best_bids.len(), 2);
+        assert_eq!(best_bids[&Exchange::Binance], vec![bid_0, bid_2]);
+        assert_eq!(best_bids[&Exchange::Bitstamp], vec![bid_1, bid_4]);
+
+        let mut asks = BTreeSet::<Ask>::new();
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_2 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(103.00, 50.0, Exchange::Bitstamp);
+
+        for ask in [&ask_0, &ask_1, &ask_2, &ask_3] {
+            asks.update_asks(ask.clone(), 10);
+        }
+
+        let best_asks = asks.get_best_n_asks_by_exchange(2);
+        assert_eq!(best_asks.len(), 2);
+        assert_eq!(best_asks[&Exchange::Binance], vec![ask_0, ask_1]);
+        assert_eq!(best_asks[&Exchange::Bitstamp], vec![ask_3]);
+
+        assert!(BTreeSet::<Ask>::new()
+            .get_best_n_asks_by_exchange(2)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_three_exchanges_at_same_price() {
+        //Levels from each exchange at the same price are kept as separate levels, ordered by quantity so the largest level is the best
+        let mut bids = BTreeSet::<Bid>::new();
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Kraken);
+        let bid_1 = Bid::new(100.00, 20.0, Exchange::Binance);
+        let bid_2 = Bid::new(100.00, 30.0, Exchange::Bitstamp);
+        for bid in [&bid_0, &bid_1, &bid_2] {
+            bids.update_bids(bid.clone(), 10);
+        }
+        assert_eq!(bids.len(), 3);
+        assert_eq!(
+            bids.get_best_n_bids(3),
+            vec![Some(bid_0.clone()), Some(bid_2.clone()), Some(bid_1)]
+        );
+
+        //Updating a level replaces the exchange's quantity at the price, reordering it among the other exchanges
+        let replacement_bid_1 = Bid::new(100.00, 60.0, Exchange::Binance);
+        bids.update_bids(replacement_bid_1.clone(), 10);
+        bids.update_bids(Bid::new(100.00, 0.0, Exchange::Bitstamp), 10);
+        assert_eq!(bids.len(), 2);
+        assert_eq!(
+            bids.get_best_n_bids(2),
+            vec![Some(replacement_bid_1), Some(bid_0)]
+        );
+
+        let mut asks = BTreeSet::<Ask>::new();
+        let ask_0 = Ask::new(101.00, 10.0, Exchange::Bitstamp);
+        let ask_1 = Ask::new(101.00, 40.0, Exchange::Kraken);
+        let ask_2 = Ask::new(101.00, 25.0, Exchange::Binance);
+        for ask in [&ask_0, &ask_1, &ask_2] {
+            asks.update_asks(ask.clone(), 10);
+        }
+        assert_eq!(asks.len(), 3);
+        assert_eq!(
+            asks.get_best_n_asks(3),
+            vec![Some(ask_1.clone()), Some(ask_2.clone()), Some(ask_0)]
+        );
+
+        let replacement_ask_1 = Ask::new(101.00, 5.0, Exchange::Kraken);
+        asks.update_asks(replacement_ask_1.clone(), 10);
+        assert_eq!(asks.len(), 3);
+        assert_eq!(
+            asks.get_exchange_ask_quantity(101.00, &Exchange::Kraken),
+            Some(5.0)
+        );
+        assert_eq!(asks.get_best_ask(), Some(ask_2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_remove_stale_bids() {
+        let mut order_book = BTreeSet::<Bid>::new();
+
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(101.00, 50.0, Exchange::Bitstamp);
+        order_book.update_bids(bid_0, 10);
+        order_book.update_bids(bid_1, 10);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+
+        //The age of each level should increase as time passes
+        for bid in order_book.iter() {
+            assert_eq!(bid.age(), Duration::from_secs(5));
+        }
+
+        //Refresh one of the levels, resetting its age
+        let bid_2 = Bid::new(101.00, 25.0, Exchange::Bitstamp);
+        order_book.update_bids(bid_2.clone(), 10);
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+
+        let removed = order_book.remove_stale_bids(Duration::from_secs(10));
+        let actual_bids: Vec<Bid> = order_book.iter().cloned().collect();
+
+        assert_eq!(removed, 1);
+        assert_eq!(actual_bids, vec![bid_2]);
+        assert_eq!(actual_bids[0].age(), Duration::from_secs(6));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_remove_stale_asks() {
+        let mut order_book = BTreeSet::<Ask>::new();
+
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(101.00, 50.0, Exchange::Bitstamp);
+        order_book.update_asks(ask_0, 10);
+        order_book.update_asks(ask_1, 10);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+
+        //The age of each level should increase as time passes
+        for ask in order_book.iter() {
+            assert_eq!(ask.age(), Duration::from_secs(5));
+        }
+
+        //Refresh one of the levels, resetting its age
+        let ask_2 = Ask::new(100.00, 25.0, Exchange::Binance);
+        order_book.update_asks(ask_2.clone(), 10);
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+
+        let removed = order_book.remove_stale_asks(Duration::from_secs(10));
+        let actual_asks: Vec<Ask> = order_book.iter().cloned().collect();
+
+        assert_eq!(removed, 1);
+        assert_eq!(actual_asks, vec![ask_2]);
+        assert_eq!(actual_asks[0].age(), Duration::from_secs(6));
+    }
 }
@@ -0,0 +1,131 @@
+#[cfg(feature = "exchanges")]
+use tokio::{sync::broadcast::Sender, task::JoinHandle};
+
+use crate::exchanges::Exchange;
+#[cfg(feature = "exchanges")]
+use crate::{
+    error::BidAskServiceError,
+    order_book::{AggregatedOrderBook, BuySide, SellSide},
+    server::orderbook_service::Summary,
+};
+
+use super::DepthConfig;
+
+//Defaults of the bid-ask service, which match the defaults of the cli
+pub const DEFAULT_ORDER_BOOK_DEPTH: usize = 25;
+pub const DEFAULT_EXCHANGE_STREAM_BUFFER: usize = 100;
+pub const DEFAULT_PRICE_LEVEL_BUFFER: usize = 100;
+pub const DEFAULT_BEST_N_ORDERS: usize = 10;
+
+// Named configuration for spawning the bid-ask service of an aggregated order book, in place of the positional args of spawn_bid_ask_service.
+// Any setting that is not set keeps its default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregatedOrderBookBuilder {
+    pub depth_config: DepthConfig,
+    pub stream_buffer: usize,
+    pub price_level_buffer: usize,
+    pub best_n: usize,
+}
+
+impl AggregatedOrderBookBuilder {
+    pub fn new() -> Self {
+        AggregatedOrderBookBuilder {
+            depth_config: DepthConfig::uniform(DEFAULT_ORDER_BOOK_DEPTH),
+            stream_buffer: DEFAULT_EXCHANGE_STREAM_BUFFER,
+            price_level_buffer: DEFAULT_PRICE_LEVEL_BUFFER,
+            best_n: DEFAULT_BEST_N_ORDERS,
+        }
+    }
+
+    //Set the max depth of each side of the aggregated order book, which is also streamed from exchanges without their own depth
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth_config.max_order_book_depth = depth;
+        self
+    }
+
+    //Set the depth of the order book streamed from the exchange
+    pub fn exchange_depth(mut self, exchange: Exchange, depth: usize) -> Self {
+        self.depth_config = self.depth_config.with_exchange_depth(exchange, depth);
+        self
+    }
+
+    //Set the channel buffer size for streaming order book data from each exchange
+    pub fn stream_buffer(mut self, stream_buffer: usize) -> Self {
+        self.stream_buffer = stream_buffer;
+        self
+    }
+
+    //Set the channel buffer size for passing price level updates from the exchanges to the aggregated order book
+    pub fn price_level_buffer(mut self, price_level_buffer: usize) -> Self {
+        self.price_level_buffer = price_level_buffer;
+        self
+    }
+
+    //Set the number of best bids and asks tracked by the aggregated order book
+    pub fn best_n(mut self, best_n: usize) -> Self {
+        self.best_n = best_n;
+        self
+    }
+
+    //Spawn the bid-ask service of the aggregated order book with the configuration, see AggregatedOrderBook::spawn_bid_ask_service
+    #[cfg(feature = "exchanges")]
+    pub fn spawn<B, S>(
+        &self,
+        aggregated_order_book: &AggregatedOrderBook<B, S>,
+        summary_tx: Sender<Summary>,
+    ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>>
+    where
+        B: BuySide + Send + 'static,
+        S: SellSide + Send + 'static,
+    {
+        aggregated_order_book.spawn_bid_ask_service(
+            self.depth_config.clone(),
+            self.stream_buffer,
+            self.price_level_buffer,
+            self.best_n,
+            summary_tx,
+        )
+    }
+}
+
+impl Default for AggregatedOrderBookBuilder {
+    fn default() -> Self {
+        AggregatedOrderBookBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchanges::Exchange,
+        order_book::{builder::AggregatedOrderBookBuilder, DepthConfig},
+    };
+
+    #[test]
+    fn test_builder() {
+        //The defaults are the documented defaults of --order_book_depth, --exchange_stream_buffer, --price_level_channel_buffer and --best_n_orders
+        let builder = AggregatedOrderBookBuilder::new();
+        assert_eq!(builder.depth_config, DepthConfig::uniform(25));
+        assert_eq!(builder.stream_buffer, 100);
+        assert_eq!(builder.price_level_buffer, 100);
+        assert_eq!(builder.best_n, 10);
+
+        //Setting the depth keeps the depths of specific exchanges
+        let builder = AggregatedOrderBookBuilder::new()
+            .exchange_depth(Exchange::Binance, 100)
+            .depth(50)
+            .stream_buffer(10)
+            .price_level_buffer(20)
+            .best_n(5);
+        assert_eq!(
+            builder,
+            AggregatedOrderBookBuilder {
+                depth_config: DepthConfig::uniform(50).with_exchange_depth(Exchange::Binance, 100),
+                stream_buffer: 10,
+                price_level_buffer: 20,
+                best_n: 5,
+            }
+        );
+        assert_eq!(builder.depth_config.exchange_depth(&Exchange::Bitstamp), 50);
+    }
+}
@@ -1,9 +1,32 @@
-use crate::server::orderbook_service::Summary;
+use crate::{
+    exchanges::Exchange, order_book::price_level::PriceLevelUpdate,
+    server::orderbook_service::Summary,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum OrderBookError {
     #[error("Poisoned lock")]
     PoisonedLock,
-    #[error("Error when sending summary through channel")]
-    SummarySendError(#[from] tokio::sync::broadcast::error::SendError<Summary>),
+    #[error("Price level channel closed")]
+    PriceLevelChannelClosed,
+    #[error("Error when sending price level update through channel")]
+    PriceLevelUpdateSendError(#[from] tokio::sync::mpsc::error::SendError<PriceLevelUpdate>),
+    #[error("Duplicate price levels from the same exchange, bids: {bids:?}, asks: {asks:?}")]
+    DuplicateLevels {
+        bids: Vec<(f64, Exchange)>,
+        asks: Vec<(f64, Exchange)>,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SummaryError {
+    #[error("No subscribers to receive the summary")]
+    NoSubscribers,
+}
+
+//Sending through a broadcast channel only fails when there are no receivers
+impl From<tokio::sync::broadcast::error::SendError<Summary>> for SummaryError {
+    fn from(_: tokio::sync::broadcast::error::SendError<Summary>) -> Self {
+        SummaryError::NoSubscribers
+    }
 }
@@ -0,0 +1,734 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use ordered_float::OrderedFloat;
+
+use crate::exchanges::Exchange;
+
+use super::{
+    best_n_by_exchange,
+    btree_map::{level_key, sorted_by_price, LevelKey},
+    duplicate_levels,
+    price_level::{ask::Ask, bid::Bid},
+    ranker::{rank_best_n, LevelRanker, RankedLevel},
+    total_notional, BuySide, BuySideView, Order, SellSide, SellSideView,
+};
+
+// An alternative representation of one side of the order book, holding each price level in a hash map keyed by its price and exchange,
+// so that a quantity update finds and replaces the level in constant time. A sorted index of the keys is only changed when a level
+// is inserted or removed, and is used to walk the levels in price order when gathering the best levels.
+#[derive(Debug, Clone)]
+pub struct HashMapOrderBook<O> {
+    levels: HashMap<LevelKey, O>,
+    index: BTreeSet<LevelKey>,
+}
+
+impl<O: Order + Clone> HashMapOrderBook<O> {
+    pub fn new() -> Self {
+        HashMapOrderBook {
+            levels: HashMap::new(),
+            index: BTreeSet::new(),
+        }
+    }
+
+    //Get the number of levels in the order book
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    //Iterate over the levels from the lowest price, with the levels at each price in the order of the BTreeSet
+    fn ascending(&self) -> impl Iterator<Item = &O> {
+        sorted_by_price(self.index.iter().map(|key| &self.levels[key]), false)
+    }
+
+    //Iterate over the levels from the highest price, with the levels at each price in the order of the BTreeSet
+    fn descending(&self) -> impl Iterator<Item = &O> {
+        sorted_by_price(self.index.iter().rev().map(|key| &self.levels[key]), true)
+    }
+
+    //Update the level in the order book, inserting it in place of the worst level at the max depth if it is better.
+    //The worst level is the lowest priced level if lowest_is_worst is set, otherwise the highest priced level
+    fn update(
+        &mut self,
+        order: O,
+        max_depth: usize,
+        lowest_is_worst: bool,
+        is_better: fn(&O, &O) -> bool,
+    ) {
+        let key = level_key(&order);
+
+        if order.get_quantity().0 == 0.0 {
+            if self.levels.remove(&key).is_some() {
+                self.index.remove(&key);
+            }
+        } else if let Some(level) = self.levels.get_mut(&key) {
+            //The key does not change with the quantity, so the index is left as it is
+            *level = order;
+        } else if self.levels.len() < max_depth {
+            self.index.insert(key.clone());
+            self.levels.insert(key, order);
+        } else {
+            //We can unwrap this because the levels are at the max depth, signifying that there is at least one value
+            let worst = if lowest_is_worst {
+                self.ascending().next().unwrap()
+            } else {
+                self.descending().next().unwrap()
+            };
+
+            if is_better(&order, worst) {
+                let worst_key = level_key(worst);
+                self.levels.remove(&worst_key);
+                self.index.remove(&worst_key);
+                self.index.insert(key.clone());
+                self.levels.insert(key, order);
+            }
+        }
+    }
+
+    //Remove every level that does not satisfy the predicate, returning the number of levels removed
+    fn retain(&mut self, f: impl Fn(&O) -> bool) -> usize {
+        let len = self.levels.len();
+        self.levels.retain(|_, level| f(level));
+        self.index.retain(|key| self.levels.contains_key(key));
+        len - self.levels.len()
+    }
+
+    //Remove the n least recently updated levels, given from the worst level. The stable sort keeps that order between levels updated at the same time
+    fn evict(
+        &mut self,
+        mut worst_first: Vec<O>,
+        n: usize,
+        last_updated: impl Fn(&O) -> tokio::time::Instant,
+    ) -> usize {
+        worst_first.sort_by_key(|level| last_updated(level));
+
+        let mut removed = 0;
+        for level in worst_first.iter().take(n) {
+            let key = level_key(level);
+            if self.levels.remove(&key).is_some() {
+                self.index.remove(&key);
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+impl<O: Order + Clone> Default for HashMapOrderBook<O> {
+    fn default() -> Self {
+        HashMapOrderBook::new()
+    }
+}
+
+impl BuySide for HashMapOrderBook<Bid> {
+    //Update the bids in the order book with the new bid. A bid already in the order book is replaced in place
+    fn update_bids(&mut self, bid: Bid, max_depth: usize) {
+        self.update(bid, max_depth, true, |bid, worst_bid| bid > worst_bid);
+    }
+
+    //Get the best bid in the data structure
+    fn get_best_bid(&self) -> Option<Bid> {
+        self.descending().next().cloned()
+    }
+
+    //Get the best "n" bids in the data structure
+    fn get_top_bids(&self, n: usize) -> Vec<Bid> {
+        self.descending().take(n).cloned().collect()
+    }
+
+    //Get the best bid from the exchange
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid> {
+        self.descending()
+            .find(|bid| bid.exchange == *exchange)
+            .cloned()
+    }
+
+    //Get the best "n" bids from each exchange in the data structure
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>> {
+        best_n_by_exchange(self.descending(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has a bid at the price
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.levels
+            .get(&(OrderedFloat(price), exchange.clone()))
+            .map(|bid| bid.quantity.0)
+    }
+
+    //Get the best n bids ranked by a custom ranker. Bids with equal priority keep the order of the best bids in the data structure
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid> {
+        rank_best_n(self.descending(), n, ranker, RankedLevel::Bid)
+    }
+
+    //Remove all bids that have not been updated within the max age, returning the number of bids removed
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize {
+        self.retain(|bid| bid.age() <= max_age)
+    }
+
+    //Remove all bids from the exchange, returning the number of bids removed
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize {
+        self.retain(|bid| bid.exchange != *exchange)
+    }
+
+    //Get the number of bids in the data structure
+    fn num_bids(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one bid
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.ascending())
+    }
+
+    //Remove the n least recently updated bids, evicting the worst priced bids first when they were updated at the same time.
+    //Returns the number of bids removed
+    fn evict_bids(&mut self, n: usize) -> usize {
+        let bids = self.ascending().cloned().collect::<Vec<_>>();
+        self.evict(bids, n, |bid| bid.last_updated)
+    }
+
+    //Get the sum of price * quantity across all bids in the data structure
+    fn total_notional_bids(&self) -> f64 {
+        total_notional(self.ascending())
+    }
+}
+
+impl SellSide for HashMapOrderBook<Ask> {
+    //Update the asks in the order book with the new ask. An ask already in the order book is replaced in place
+    fn update_asks(&mut self, ask: Ask, max_depth: usize) {
+        self.update(ask, max_depth, false, |ask, worst_ask| ask < worst_ask);
+    }
+
+    //Get the best ask in the data structure
+    fn get_best_ask(&self) -> Option<Ask> {
+        self.ascending().next().cloned()
+    }
+
+    //Get the best "n" asks in the data structure
+    fn get_top_asks(&self, n: usize) -> Vec<Ask> {
+        self.ascending().take(n).cloned().collect()
+    }
+
+    //Get the best ask from the exchange
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask> {
+        self.ascending()
+            .find(|ask| ask.exchange == *exchange)
+            .cloned()
+    }
+
+    //Get the best "n" asks from each exchange in the data structure
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>> {
+        best_n_by_exchange(self.ascending(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an ask at the price
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.levels
+            .get(&(OrderedFloat(price), exchange.clone()))
+            .map(|ask| ask.quantity.0)
+    }
+
+    //Get the best n asks ranked by a custom ranker. Asks with equal priority keep the order of the best asks in the data structure
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask> {
+        rank_best_n(self.ascending(), n, ranker, RankedLevel::Ask)
+    }
+
+    //Remove all asks that have not been updated within the max age, returning the number of asks removed
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize {
+        self.retain(|ask| ask.age() <= max_age)
+    }
+
+    //Remove all asks from the exchange, returning the number of asks removed
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize {
+        self.retain(|ask| ask.exchange != *exchange)
+    }
+
+    //Get the number of asks in the data structure
+    fn num_asks(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one ask
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.ascending())
+    }
+
+    //Remove the n least recently updated asks, evicting the worst priced asks first when they were updated at the same time.
+    //Returns the number of asks removed
+    fn evict_asks(&mut self, n: usize) -> usize {
+        let asks = self.descending().cloned().collect::<Vec<_>>();
+        self.evict(asks, n, |ask| ask.last_updated)
+    }
+
+    //Get the sum of price * quantity across all asks in the data structure
+    fn total_notional_asks(&self) -> f64 {
+        total_notional(self.ascending())
+    }
+}
+
+impl BuySideView for HashMapOrderBook<Bid> {
+    //Iterate over the bids from the best bid to the worst bid
+    fn iter_bids(&self) -> impl Iterator<Item = &Bid> {
+        self.descending()
+    }
+}
+
+impl SellSideView for HashMapOrderBook<Ask> {
+    //Iterate over the asks from the best ask to the worst ask
+    fn iter_asks(&self) -> impl Iterator<Item = &Ask> {
+        self.ascending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use ordered_float::OrderedFloat;
+
+    use crate::{
+        exchanges::Exchange,
+        order_book::{
+            hashmap::HashMapOrderBook,
+            price_level::{ask::Ask, bid::Bid},
+            BuySide, Order, SellSide,
+        },
+    };
+
+    //Apply the updates to a hash map order book and a BTreeSet, checking that both hold the same bids in the same order
+    fn update_bids(updates: Vec<(Bid, usize)>) -> HashMapOrderBook<Bid> {
+        let mut order_book = HashMapOrderBook::<Bid>::new();
+        let mut btree_set = BTreeSet::<Bid>::new();
+        for (bid, max_depth) in updates {
+            order_book.update_bids(bid.clone(), max_depth);
+            btree_set.update_bids(bid, max_depth);
+        }
+
+        let depth = btree_set.len() + 1;
+        assert_eq!(
+            order_book.get_best_n_bids(depth),
+            btree_set.get_best_n_bids(depth)
+        );
+        order_book
+    }
+
+    //Apply the updates to a hash map order book and a BTreeSet, checking that both hold the same asks in the same order
+    fn update_asks(updates: Vec<(Ask, usize)>) -> HashMapOrderBook<Ask> {
+        let mut order_book = HashMapOrderBook::<Ask>::new();
+        let mut btree_set = BTreeSet::<Ask>::new();
+        for (ask, max_depth) in updates {
+            order_book.update_asks(ask.clone(), max_depth);
+            btree_set.update_asks(ask, max_depth);
+        }
+
+        let depth = btree_set.len() + 1;
+        assert_eq!(
+            order_book.get_best_n_asks(depth),
+            btree_set.get_best_n_asks(depth)
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_insert_bid() {
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(100.00, 60.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(101.00, 50.0, Exchange::Binance);
+        let bid_3 = Bid::new(101.00, 60.0, Exchange::Bitstamp);
+        let bid_4 = Bid::new(103.00, 50.0, Exchange::Binance);
+        let bid_5 = Bid::new(102.00, 50.0, Exchange::Binance);
+        let bid_6 = Bid::new(104.00, 50.0, Exchange::Binance);
+
+        let order_book = update_bids(vec![
+            (bid_0.clone(), 10),
+            (bid_1.clone(), 10),
+            (bid_2.clone(), 10),
+            (bid_3.clone(), 10),
+            (bid_4.clone(), 10),
+            (bid_5.clone(), 10),
+            (bid_6.clone(), 10),
+        ]);
+
+        assert_eq!(order_book.get_best_bid(), Some(bid_6.clone()));
+        assert_eq!(
+            order_book.get_best_n_bids(7),
+            vec![bid_6, bid_4, bid_5, bid_3, bid_2, bid_1, bid_0]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_insert_bid_past_max_depth() {
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(100.00, 60.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(101.00, 50.0, Exchange::Binance);
+        let bid_3 = Bid::new(101.00, 60.0, Exchange::Bitstamp);
+        let bid_4 = Bid::new(103.00, 50.0, Exchange::Binance);
+        let bid_5 = Bid::new(102.00, 50.0, Exchange::Binance);
+        let bid_6 = Bid::new(104.00, 50.0, Exchange::Binance);
+
+        let order_book = update_bids(vec![
+            (bid_0, 5),
+            (bid_1, 5),
+            (bid_2.clone(), 5),
+            (bid_3.clone(), 5),
+            (bid_4.clone(), 5),
+            (bid_5.clone(), 5),
+            (bid_6.clone(), 5),
+        ]);
+
+        //The worst bids at 100 are evicted to hold the better bids at the max depth
+        assert_eq!(order_book.num_bids(), 5);
+        assert_eq!(order_book.get_best_bid(), Some(bid_6.clone()));
+        assert_eq!(
+            order_book.get_best_n_bids(5),
+            vec![bid_6, bid_4, bid_5, bid_3, bid_2]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_remove_bid() {
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let mut bid_1 = Bid::new(100.50, 50.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(101.00, 50.0, Exchange::Binance);
+        let bid_3 = Bid::new(101.00, 60.0, Exchange::Bitstamp);
+        let mut bid_4 = Bid::new(103.00, 50.0, Exchange::Binance);
+        let bid_5 = Bid::new(103.50, 50.0, Exchange::Binance);
+        let mut bid_6 = Bid::new(104.00, 50.0, Exchange::Binance);
+
+        let mut updates = vec![
+            (bid_0.clone(), 10),
+            (bid_1.clone(), 10),
+            (bid_2.clone(), 10),
+            (bid_3.clone(), 10),
+            (bid_4.clone(), 10),
+            (bid_5.clone(), 10),
+            (bid_6.clone(), 10),
+        ];
+
+        bid_1.set_quantity(OrderedFloat(0.0));
+        bid_4.set_quantity(OrderedFloat(0.0));
+        bid_6.set_quantity(OrderedFloat(0.0));
+        updates.extend([(bid_1, 10), (bid_4, 10), (bid_6, 10)]);
+
+        let order_book = update_bids(updates);
+
+        assert_eq!(order_book.get_best_bid(), Some(bid_5.clone()));
+        assert_eq!(
+            order_book.get_best_n_bids(5),
+            vec![Some(bid_5), Some(bid_3), Some(bid_2), Some(bid_0), None]
+        );
+        assert_eq!(
+            order_book.get_exchange_bid_quantity(104.0, &Exchange::Binance),
+            None
+        );
+    }
+
+    #[test]
+    fn test_update_bid() {
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(100.00, 60.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(100.50, 400.0, Exchange::Bitstamp);
+        let bid_3 = Bid::new(101.00, 499.0, Exchange::Binance);
+        let bid_4 = Bid::new(103.00, 50.0, Exchange::Binance);
+        let bid_5 = Bid::new(102.00, 50.0, Exchange::Binance);
+        let bid_6 = Bid::new(104.00, 50.0, Exchange::Bitstamp);
+
+        let replacement_bid_1 = Bid::new(100.00, 3404.0, Exchange::Bitstamp);
+        let replacement_bid_3 = Bid::new(101.00, 250.0, Exchange::Binance);
+        let replacement_bid_6 = Bid::new(104.00, 20.0, Exchange::Bitstamp);
+
+        let order_book = update_bids(vec![
+            (bid_0.clone(), 10),
+            (bid_1, 10),
+            (bid_2.clone(), 10),
+            (bid_3, 10),
+            (bid_4.clone(), 10),
+            (bid_5.clone(), 10),
+            (bid_6, 10),
+            (replacement_bid_6.clone(), 10),
+            (replacement_bid_3.clone(), 10),
+            (replacement_bid_1.clone(), 10),
+        ]);
+
+        //Each replacement updates the level in place without adding a level
+        assert_eq!(order_book.num_bids(), 7);
+        assert_eq!(order_book.get_best_bid(), Some(replacement_bid_6.clone()));
+        assert_eq!(
+            order_book.get_best_n_bids(7),
+            vec![
+                replacement_bid_6,
+                bid_4,
+                bid_5,
+                replacement_bid_3,
+                bid_2,
+                replacement_bid_1,
+                bid_0
+            ]
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>()
+        );
+        assert!(order_book.duplicate_bids().is_empty());
+    }
+
+    #[test]
+    fn test_get_best_n_bids() {
+        let bid_0 = Bid::new(100.00, 50.0, Exchange::Binance);
+        let bid_1 = Bid::new(100.00, 1000.0, Exchange::Bitstamp);
+        let bid_2 = Bid::new(101.00, 50.0, Exchange::Binance);
+        let bid_3 = Bid::new(101.00, 60.0, Exchange::Bitstamp);
+        let bid_4 = Bid::new(103.00, 50.0, Exchange::Binance);
+        let bid_5 = Bid::new(102.00, 50.0, Exchange::Binance);
+        let bid_6 = Bid::new(104.00, 50.0, Exchange::Binance);
+
+        let replacement_bid_1 = Bid::new(100.00, 3404.0, Exchange::Bitstamp);
+        let replacement_bid_3 = Bid::new(101.00, 250.0, Exchange::Bitstamp);
+        let replacement_bid_6 = Bid::new(104.00, 20.0, Exchange::Binance);
+
+        let order_book = update_bids(vec![
+            (bid_4.clone(), 5),
+            (bid_5.clone(), 5),
+            (bid_6, 5),
+            (bid_0, 5),
+            (bid_1, 5),
+            (bid_2, 5),
+            (bid_3, 5),
+            (replacement_bid_6.clone(), 10),
+            (replacement_bid_3, 10),
+            (replacement_bid_1, 10),
+        ]);
+
+        assert_eq!(
+            order_book.get_best_n_bids(3),
+            vec![Some(replacement_bid_6), Some(bid_4), Some(bid_5)]
+        );
+
+        let empty_order_book = HashMapOrderBook::<Bid>::new();
+        assert_eq!(empty_order_book.get_best_n_bids(10), vec![None; 10]);
+    }
+
+    #[test]
+    fn test_insert_ask() {
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(100.00, 1000.0, Exchange::Bitstamp);
+        let ask_2 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(101.00, 60.0, Exchange::Bitstamp);
+        let ask_4 = Ask::new(103.00, 50.0, Exchange::Binance);
+        let ask_5 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let ask_6 = Ask::new(104.00, 50.0, Exchange::Binance);
+
+        let order_book = update_asks(vec![
+            (ask_0.clone(), 10),
+            (ask_1.clone(), 10),
+            (ask_2.clone(), 10),
+            (ask_3.clone(), 10),
+            (ask_4.clone(), 10),
+            (ask_5.clone(), 10),
+            (ask_6.clone(), 10),
+        ]);
+
+        //Asks at the same price are ordered by the larger quantity first, as in the BTreeSet
+        assert_eq!(order_book.get_best_ask(), Some(ask_1.clone()));
+        assert_eq!(
+            order_book.get_best_n_asks(7),
+            vec![ask_1, ask_0, ask_3, ask_2, ask_5, ask_4, ask_6]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_insert_ask_past_max_depth() {
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(100.00, 1000.0, Exchange::Bitstamp);
+        let ask_2 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(101.00, 60.0, Exchange::Bitstamp);
+        let ask_4 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let ask_5 = Ask::new(103.00, 50.0, Exchange::Binance);
+        let ask_6 = Ask::new(104.00, 50.0, Exchange::Binance);
+
+        let order_book = update_asks(vec![
+            (ask_6, 5),
+            (ask_5, 5),
+            (ask_2.clone(), 5),
+            (ask_3.clone(), 5),
+            (ask_4.clone(), 5),
+            (ask_0.clone(), 5),
+            (ask_1.clone(), 5),
+        ]);
+
+        //The worst asks at 103 and 104 are evicted to hold the better asks at the max depth
+        assert_eq!(order_book.num_asks(), 5);
+        assert_eq!(order_book.get_best_ask(), Some(ask_1.clone()));
+        assert_eq!(
+            order_book.get_best_n_asks(5),
+            vec![ask_1, ask_0, ask_3, ask_2, ask_4]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_remove_ask() {
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let mut ask_1 = Ask::new(100.00, 1000.0, Exchange::Bitstamp);
+        let ask_2 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(101.00, 60.0, Exchange::Bitstamp);
+        let mut ask_4 = Ask::new(103.00, 50.0, Exchange::Binance);
+        let ask_5 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let mut ask_6 = Ask::new(104.00, 50.0, Exchange::Binance);
+
+        let mut updates = vec![
+            (ask_0.clone(), 10),
+            (ask_1.clone(), 10),
+            (ask_2.clone(), 10),
+            (ask_3.clone(), 10),
+            (ask_4.clone(), 10),
+            (ask_5.clone(), 10),
+            (ask_6.clone(), 10),
+        ];
+
+        ask_1.set_quantity(OrderedFloat(0.0));
+        ask_4.set_quantity(OrderedFloat(0.0));
+        ask_6.set_quantity(OrderedFloat(0.0));
+        updates.extend([(ask_1, 10), (ask_4, 10), (ask_6, 10)]);
+
+        let order_book = update_asks(updates);
+
+        assert_eq!(order_book.get_best_ask(), Some(ask_0.clone()));
+        assert_eq!(
+            order_book.get_best_n_asks(5),
+            vec![Some(ask_0), Some(ask_3), Some(ask_2), Some(ask_5), None]
+        );
+        assert_eq!(
+            order_book.get_exchange_ask_quantity(100.0, &Exchange::Bitstamp),
+            None
+        );
+    }
+
+    #[test]
+    fn test_update_ask() {
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(100.00, 1000.0, Exchange::Bitstamp);
+        let ask_2 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(101.00, 60.0, Exchange::Bitstamp);
+        let ask_4 = Ask::new(103.00, 50.0, Exchange::Binance);
+        let ask_5 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let ask_6 = Ask::new(104.00, 50.0, Exchange::Binance);
+
+        let replacement_ask_1 = Ask::new(100.00, 3404.0, Exchange::Bitstamp);
+        let replacement_ask_3 = Ask::new(101.00, 250.0, Exchange::Bitstamp);
+        let replacement_ask_6 = Ask::new(104.00, 20.0, Exchange::Binance);
+
+        let order_book = update_asks(vec![
+            (ask_0.clone(), 10),
+            (ask_1, 10),
+            (ask_2.clone(), 10),
+            (ask_3, 10),
+            (ask_4.clone(), 10),
+            (ask_5.clone(), 10),
+            (ask_6, 10),
+            (replacement_ask_6.clone(), 10),
+            (replacement_ask_3.clone(), 10),
+            (replacement_ask_1.clone(), 10),
+        ]);
+
+        //Each replacement updates the level in place without adding a level
+        assert_eq!(order_book.num_asks(), 7);
+        assert_eq!(order_book.get_best_ask(), Some(replacement_ask_1.clone()));
+        assert_eq!(
+            order_book.get_best_n_asks(7),
+            vec![
+                replacement_ask_1,
+                ask_0,
+                replacement_ask_3,
+                ask_2,
+                ask_5,
+                ask_4,
+                replacement_ask_6
+            ]
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>()
+        );
+        assert!(order_book.duplicate_asks().is_empty());
+    }
+
+    #[test]
+    fn test_get_best_n_asks() {
+        let ask_0 = Ask::new(100.00, 50.0, Exchange::Binance);
+        let ask_1 = Ask::new(100.00, 1000.0, Exchange::Bitstamp);
+        let ask_2 = Ask::new(101.00, 50.0, Exchange::Binance);
+        let ask_3 = Ask::new(101.00, 60.0, Exchange::Bitstamp);
+        let ask_4 = Ask::new(103.00, 50.0, Exchange::Binance);
+        let ask_5 = Ask::new(102.00, 50.0, Exchange::Binance);
+        let ask_6 = Ask::new(104.00, 50.0, Exchange::Binance);
+
+        let replacement_ask_1 = Ask::new(100.00, 3404.0, Exchange::Bitstamp);
+        let replacement_ask_3 = Ask::new(101.00, 250.0, Exchange::Bitstamp);
+        let replacement_ask_6 = Ask::new(104.00, 20.0, Exchange::Binance);
+
+        let order_book = update_asks(vec![
+            (ask_4, 5),
+            (ask_5, 5),
+            (ask_6, 5),
+            (ask_0.clone(), 5),
+            (ask_1, 5),
+            (ask_2, 5),
+            (ask_3, 5),
+            (replacement_ask_6, 10),
+            (replacement_ask_3.clone(), 10),
+            (replacement_ask_1.clone(), 10),
+        ]);
+
+        assert_eq!(
+            order_book.get_best_n_asks(3),
+            vec![
+                Some(replacement_ask_1),
+                Some(ask_0),
+                Some(replacement_ask_3)
+            ]
+        );
+
+        let empty_order_book = HashMapOrderBook::<Ask>::new();
+        assert_eq!(empty_order_book.get_best_n_asks(10), vec![None; 10]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_remove_stale_and_exchange_levels() {
+        let mut order_book = HashMapOrderBook::<Bid>::new();
+        order_book.update_bids(Bid::new(100.0, 1.0, Exchange::Binance), 10);
+        order_book.update_bids(Bid::new(99.0, 1.0, Exchange::Bitstamp), 10);
+
+        tokio::time::advance(std::time::Duration::from_secs(10)).await;
+        order_book.update_bids(Bid::new(98.0, 1.0, Exchange::Binance), 10);
+
+        //The index is kept in step with the levels, so the removed bids are no longer gathered
+        assert_eq!(
+            order_book.remove_stale_bids(std::time::Duration::from_secs(5)),
+            2
+        );
+        assert_eq!(
+            order_book.get_best_n_bids(2),
+            vec![Some(Bid::new(98.0, 1.0, Exchange::Binance)), None]
+        );
+
+        assert_eq!(order_book.clear_exchange_bids(&Exchange::Binance), 1);
+        assert!(order_book.is_empty());
+        assert_eq!(order_book.get_best_bid(), None);
+    }
+}
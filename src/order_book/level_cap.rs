@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A cap on the total number of price levels held across every aggregated order book that shares it,
+// bounding memory regardless of how many pairs, exchanges and levels are being streamed
+#[derive(Debug)]
+pub struct LevelCap {
+    max_levels: usize,
+    levels: AtomicUsize,
+}
+
+impl LevelCap {
+    pub fn new(max_levels: usize) -> Self {
+        LevelCap {
+            max_levels,
+            levels: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn max_levels(&self) -> usize {
+        self.max_levels
+    }
+
+    //Get the total number of levels held across every order book sharing the cap
+    pub fn levels(&self) -> usize {
+        self.levels.load(Ordering::SeqCst)
+    }
+
+    //Update the number of levels held by one side of an order book, where tracked is the number of levels last reported by that side.
+    //Returns the number of levels that the side must evict to bring the total back within the cap.
+    pub fn track(&self, tracked: &mut usize, levels: usize) -> usize {
+        let total = if levels >= *tracked {
+            let added = levels - *tracked;
+            self.levels.fetch_add(added, Ordering::SeqCst) + added
+        } else {
+            let removed = *tracked - levels;
+            self.levels.fetch_sub(removed, Ordering::SeqCst) - removed
+        };
+        *tracked = levels;
+
+        total.saturating_sub(self.max_levels).min(levels)
+    }
+}
@@ -1,29 +1,69 @@
+pub mod btree_map;
 pub mod btree_set;
+pub mod builder;
 pub mod error;
+pub mod hashmap;
+pub mod level_cap;
 pub mod price_level;
+pub mod ranker;
+pub mod sharded_set;
+pub mod tick_set;
 
 use async_trait::async_trait;
 use ordered_float::OrderedFloat;
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
-    sync::{broadcast::Sender, mpsc::Receiver, Mutex},
+    sync::{
+        broadcast::{self, error::RecvError, Sender},
+        mpsc::Receiver,
+        watch, Mutex,
+    },
     task::JoinHandle,
+    time::{Instant, Interval, MissedTickBehavior},
 };
 
 use crate::{
-    error::BidAskServiceError,
-    exchanges::Exchange,
-    server::orderbook_service::{Level, Summary},
+    error::{flatten_task_result, BidAskServiceError},
+    events::{EventPublisher, ServiceEvent, ServiceEventKind, EVENT_BUFFER},
+    exchanges::{
+        credentials::Credentials,
+        feed_quality::FeedQuality,
+        services::{shutdown_requested, ExchangeServices},
+        Exchange, OrderBookService,
+    },
+    metrics::Metrics,
+    server::{
+        orderbook_service::{ExchangeId, ExchangeQuote, Level, Side, Summary},
+        status::ServiceStatus,
+        SUMMARY_SCHEMA_VERSION,
+    },
 };
 
+#[cfg(feature = "exchanges")]
+use crate::exchanges::reconnect::ReconnectBackoff;
+
 use self::{
-    error::OrderBookError,
-    price_level::{ask::Ask, bid::Bid, PriceLevelUpdate},
+    error::{OrderBookError, SummaryError},
+    level_cap::LevelCap,
+    price_level::{
+        ask::Ask, bid::Bid, price::PriceComparison, snap_to_grid, BackpressurePolicy, OrderType,
+        PriceLevelUpdate, QuantitySemantics,
+    },
+    ranker::{DefaultRanker, LevelRanker, RecencyTieBreak, TieBreak, TieBreakRanker},
 };
 
+//Time to wait for the exchange services to unsubscribe and close their connections on shutdown before they are aborted
+const EXCHANGE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub trait Order: Ord {
     fn get_price(&self) -> &OrderedFloat<f64>;
     fn get_quantity(&self) -> &OrderedFloat<f64>;
+    fn set_price(&mut self, price: OrderedFloat<f64>);
     fn set_quantity(&mut self, quantity: OrderedFloat<f64>);
     fn get_exchange(&self) -> &Exchange;
 }
@@ -38,16 +78,481 @@ pub trait OrderBook: Debug {
     fn get_best_n_asks(&self, n: usize) -> Vec<Option<Ask>>;
 }
 
+// The get_top_* methods return at most n levels, holding fewer than n levels when the side does. The get_best_n_* methods pad the
+// same levels with None up to n levels, and are kept for callers that index into the best n
 pub trait BuySide: Debug {
     fn update_bids(&mut self, bid: Bid, max_depth: usize);
-    fn get_best_bid(&self) -> Option<&Bid>;
-    fn get_best_n_bids(&self, n: usize) -> Vec<Option<Bid>>;
+    fn get_best_bid(&self) -> Option<Bid>;
+    fn get_top_bids(&self, n: usize) -> Vec<Bid>;
+    fn get_best_n_bids(&self, n: usize) -> Vec<Option<Bid>> {
+        pad_levels(self.get_top_bids(n), n)
+    }
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid>;
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>>;
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64>;
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid>;
+    fn get_best_n_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Option<Bid>> {
+        pad_levels(self.get_top_bids_ranked(n, ranker), n)
+    }
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize;
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize;
+    fn num_bids(&self) -> usize;
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)>;
+    fn evict_bids(&mut self, n: usize) -> usize;
+    fn total_notional_bids(&self) -> f64;
 }
 
 pub trait SellSide: Debug {
     fn update_asks(&mut self, ask: Ask, max_depth: usize);
-    fn get_best_ask(&self) -> Option<&Ask>;
-    fn get_best_n_asks(&self, n: usize) -> Vec<Option<Ask>>;
+    fn get_best_ask(&self) -> Option<Ask>;
+    fn get_top_asks(&self, n: usize) -> Vec<Ask>;
+    fn get_best_n_asks(&self, n: usize) -> Vec<Option<Ask>> {
+        pad_levels(self.get_top_asks(n), n)
+    }
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask>;
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>>;
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64>;
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask>;
+    fn get_best_n_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Option<Ask>> {
+        pad_levels(self.get_top_asks_ranked(n, ranker), n)
+    }
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize;
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize;
+    fn num_asks(&self) -> usize;
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)>;
+    fn evict_asks(&mut self, n: usize) -> usize;
+    fn total_notional_asks(&self) -> f64;
+}
+
+// Iterates over every level of a side from the best level, for callers that scan the whole book without the allocation and padding of get_best_n_*.
+// The sharded set holds its levels behind a lock per shard, so it can not lend references to its levels and does not implement the views
+pub trait BuySideView: BuySide {
+    fn iter_bids(&self) -> impl Iterator<Item = &Bid>;
+}
+
+pub trait SellSideView: SellSide {
+    fn iter_asks(&self) -> impl Iterator<Item = &Ask>;
+}
+
+//Pad the levels with None up to n levels
+pub fn pad_levels<O: Clone>(levels: Vec<O>, n: usize) -> Vec<Option<O>> {
+    let mut padded = levels.into_iter().map(Some).collect::<Vec<_>>();
+    padded.resize(n, None);
+    padded
+}
+
+//Sum the notional value (price * quantity) of each order, using Kahan summation to limit the floating point error accumulated across many levels
+pub fn total_notional<'a, O: Order + 'a>(orders: impl Iterator<Item = &'a O>) -> f64 {
+    let mut sum = 0.0;
+    let mut compensation = 0.0;
+
+    for order in orders {
+        let notional = order.get_price().0 * order.get_quantity().0 - compensation;
+        let next_sum = sum + notional;
+        compensation = (next_sum - sum) - notional;
+        sum = next_sum;
+    }
+
+    sum
+}
+
+//Check if a bid can change the best n bids, so that the best n bids are only gathered when they may have changed.
+//Once there are n best bids, a bid worse than the worst of them cannot change them. A removal is compared by price,
+//since the zero quantity of a removal does not reflect where the removed level was ranked among levels at the same price
+pub fn bid_changes_best_n(bid: &Bid, worst_best_bid: &Bid, best_n_full: bool) -> bool {
+    if !best_n_full {
+        true
+    } else if bid.quantity.0 == 0.0 {
+        bid.price >= worst_best_bid.price
+    } else {
+        bid >= worst_best_bid
+    }
+}
+
+//Check if an ask can change the best n asks, see bid_changes_best_n
+pub fn ask_changes_best_n(ask: &Ask, worst_best_ask: &Ask, best_n_full: bool) -> bool {
+    if !best_n_full {
+        true
+    } else if ask.quantity.0 == 0.0 {
+        ask.price <= worst_best_ask.price
+    } else {
+        ask <= worst_best_ask
+    }
+}
+
+//Partition the best orders by exchange in a single scan, keeping up to n orders for each exchange.
+//Orders are expected from best to worst, so each exchange's orders are also ordered from best to worst
+pub fn best_n_by_exchange<'a, O: Order + Clone + 'a>(
+    orders: impl Iterator<Item = &'a O>,
+    n: usize,
+) -> HashMap<Exchange, Vec<O>> {
+    let mut best_n: HashMap<Exchange, Vec<O>> = HashMap::new();
+
+    for order in orders {
+        let exchange_orders = best_n.entry(order.get_exchange().clone()).or_default();
+        if exchange_orders.len() < n {
+            exchange_orders.push(order.clone());
+        }
+    }
+
+    best_n
+}
+
+//Find each price and exchange with more than one level, which would double count the exchange's liquidity at that price.
+//Orders are expected in price order, so that the levels at each price are adjacent
+pub fn duplicate_levels<'a, O: Order + 'a>(
+    orders: impl Iterator<Item = &'a O>,
+) -> Vec<(f64, Exchange)> {
+    let mut duplicates = vec![];
+    let mut price = None;
+    let mut exchanges = vec![];
+
+    for order in orders {
+        if price != Some(order.get_price()) {
+            price = Some(order.get_price());
+            exchanges.clear();
+        }
+
+        let exchange = order.get_exchange();
+        if !exchanges.contains(&exchange) {
+            exchanges.push(exchange);
+        } else if !duplicates.contains(&(order.get_price().0, exchange.clone())) {
+            duplicates.push((order.get_price().0, exchange.clone()));
+        }
+    }
+
+    duplicates
+}
+
+//Callback invoked with each summary published by the aggregated order book
+pub type SummaryCallback = Arc<dyn Fn(&Summary) + Send + Sync>;
+
+// The max depth of each side of the aggregated order book, and the depth of the order book that each exchange streams.
+// Exchanges without their own depth stream the max depth of the aggregated order book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthConfig {
+    pub max_order_book_depth: usize,
+    pub exchange_depths: HashMap<Exchange, usize>,
+}
+
+impl DepthConfig {
+    //Use the same depth for the aggregated order book and every exchange
+    pub fn uniform(depth: usize) -> Self {
+        DepthConfig {
+            max_order_book_depth: depth,
+            exchange_depths: HashMap::new(),
+        }
+    }
+
+    pub fn with_exchange_depth(mut self, exchange: Exchange, depth: usize) -> Self {
+        self.exchange_depths.insert(exchange, depth);
+        self
+    }
+
+    //Get the depth of the order book that the exchange streams
+    pub fn exchange_depth(&self, exchange: &Exchange) -> usize {
+        self.exchange_depths
+            .get(exchange)
+            .copied()
+            .unwrap_or(self.max_order_book_depth)
+    }
+}
+
+// What the aggregated order book does once every exchange has been disconnected for longer than the all exchanges down timeout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AllExchangesDownBehavior {
+    //Republish the last summary flagged as stale, so that clients stop trusting it
+    #[default]
+    PublishStale,
+    //Stop publishing summaries, including heartbeats, until an exchange reconnects
+    StopPublishing,
+}
+
+//Check if the spread, best n levels or exchange quotes of a summary differ from the last summary by more than the epsilon
+pub fn summary_changed(last: &Summary, summary: &Summary, epsilon: f64) -> bool {
+    let differs = |a: f64, b: f64| (a - b).abs() > epsilon;
+    let levels_changed = |last: &[Level], levels: &[Level]| {
+        last.len() != levels.len()
+            || last.iter().zip(levels.iter()).any(|(a, b)| {
+                a.exchange != b.exchange || differs(a.price, b.price) || differs(a.amount, b.amount)
+            })
+    };
+    let optional_changed = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => differs(a, b),
+        (None, None) => false,
+        _ => true,
+    };
+
+    optional_changed(last.spread, summary.spread)
+        || levels_changed(&last.bids, &summary.bids)
+        || levels_changed(&last.asks, &summary.asks)
+        || last.exchange_quotes.len() != summary.exchange_quotes.len()
+        || last
+            .exchange_quotes
+            .iter()
+            .zip(summary.exchange_quotes.iter())
+            .any(|(a, b)| {
+                a.exchange != b.exchange
+                    || optional_changed(a.bid_price, b.bid_price)
+                    || optional_changed(a.ask_price, b.ask_price)
+            })
+}
+
+//Average the mid price of each exchange, given with the time since the exchange last sent an update. With a decay half life,
+//each exchange's weight halves for every half life since its last update, so that stale exchanges are smoothly down weighted
+pub fn weighted_mid(
+    exchange_mids: impl Iterator<Item = (f64, Duration)>,
+    decay_half_life: Option<Duration>,
+) -> Option<f64> {
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+
+    for (mid, age) in exchange_mids {
+        let weight = decay_half_life.map_or(1.0, |half_life| {
+            0.5_f64.powf(age.as_secs_f64() / half_life.as_secs_f64())
+        });
+        weighted_sum += mid * weight;
+        total_weight += weight;
+    }
+
+    (total_weight > 0.0).then(|| weighted_sum / total_weight)
+}
+
+//Bid volume minus ask volume over their sum across the levels, from -1 when there are only asks to 1 when there are only bids.
+//Returns 0.0 when there is no volume on either side, rather than dividing by zero
+pub fn imbalance(bids: &[Level], asks: &[Level]) -> f64 {
+    let bid_volume = bids.iter().map(|level| level.amount).sum::<f64>();
+    let ask_volume = asks.iter().map(|level| level.amount).sum::<f64>();
+    let total_volume = bid_volume + ask_volume;
+
+    if total_volume > 0.0 {
+        (bid_volume - ask_volume) / total_volume
+    } else {
+        0.0
+    }
+}
+
+//Weight the best bid and ask prices by the quantity on the opposite side, so that the price leans towards the side with less quantity.
+//Returns 0.0 when either side is empty or the best levels have no quantity, rather than dividing by zero
+pub fn microprice(bids: &[Level], asks: &[Level]) -> f64 {
+    match (bids.first(), asks.first()) {
+        (Some(bid), Some(ask)) if bid.amount + ask.amount > 0.0 => {
+            (bid.price * ask.amount + ask.price * bid.amount) / (bid.amount + ask.amount)
+        }
+        _ => 0.0,
+    }
+}
+
+//Convert a bid into a level of the summary
+fn bid_level(bid: &Bid) -> Level {
+    Level {
+        price: bid.price.0,
+        amount: bid.quantity.0,
+        exchange: bid.exchange.to_string(),
+        age_ms: bid.age().as_millis() as u64,
+        exchange_id: ExchangeId::from(bid.exchange.clone()).into(),
+        side: Side::Bid.into(),
+    }
+}
+
+//Convert an ask into a level of the summary
+fn ask_level(ask: &Ask) -> Level {
+    Level {
+        price: ask.price.0,
+        amount: ask.quantity.0,
+        exchange: ask.exchange.to_string(),
+        age_ms: ask.age().as_millis() as u64,
+        exchange_id: ExchangeId::from(ask.exchange.clone()).into(),
+        side: Side::Ask.into(),
+    }
+}
+
+//Collapse levels at the same price into one level with the summed amount, attributed to each of the exchanges as a comma separated list.
+//Each merged level takes the place of the first level at its price, with the age of the most recently updated level
+pub fn merge_levels_by_price(levels: impl IntoIterator<Item = Level>) -> Vec<Level> {
+    let mut merged: Vec<Level> = vec![];
+    for level in levels {
+        match merged.iter_mut().find(|merged| merged.price == level.price) {
+            Some(merged) => {
+                merged.amount += level.amount;
+                merged.exchange = format!("{},{}", merged.exchange, level.exchange);
+                merged.age_ms = merged.age_ms.min(level.age_ms);
+                if merged.exchange_id != level.exchange_id {
+                    merged.set_exchange_id(ExchangeId::Unspecified);
+                }
+            }
+            None => merged.push(level),
+        }
+    }
+    merged
+}
+
+//Sort the levels of each side of the summary by price, with bids descending and asks ascending.
+//The sort is stable, so levels at the same price keep their ranked order. With epsilon price comparison, levels within the same tick are
+//ranked by quantity rather than price, so the first level after sorting can differ from the best ranked level and the spread is recomputed from it
+pub fn sort_summary_levels(summary: &mut Summary) {
+    summary.bids.sort_by(|a, b| b.price.total_cmp(&a.price));
+    summary.asks.sort_by(|a, b| a.price.total_cmp(&b.price));
+
+    if let (Some(best_bid), Some(best_ask), Some(_)) =
+        (summary.bids.first(), summary.asks.first(), summary.spread)
+    {
+        let spread = best_ask.price - best_bid.price;
+        summary.spread = Some(spread);
+        summary.crossed = spread < 0.0;
+    }
+}
+
+//Check that the bids are ordered by descending price, the asks by ascending price, and that the spread is the difference between the best ask and best bid
+fn summary_is_ordered(summary: &Summary) -> bool {
+    let bids_ordered = summary.bids.windows(2).all(|w| w[0].price >= w[1].price);
+    let asks_ordered = summary.asks.windows(2).all(|w| w[0].price <= w[1].price);
+    let spread_matches = match (summary.bids.first(), summary.asks.first(), summary.spread) {
+        (Some(bid), Some(ask), Some(spread)) => spread == ask.price - bid.price,
+        (Some(_), Some(_), None) => false,
+        _ => true,
+    };
+
+    bids_ordered && asks_ordered && spread_matches
+}
+
+//Build a summary of the best n bids and asks of the order book with the total notional of each side.
+//The spread is only set once there is a bid and an ask, like the summary published while the order book is warming, and the summary is flagged as crossed if the spread is negative
+pub fn build_summary<B: BuySide + ?Sized, S: SellSide + ?Sized>(
+    bids: &B,
+    asks: &S,
+    n: usize,
+) -> Summary {
+    let best_bids = bids
+        .get_top_bids(n)
+        .iter()
+        .map(bid_level)
+        .collect::<Vec<_>>();
+    let best_asks = asks
+        .get_top_asks(n)
+        .iter()
+        .map(ask_level)
+        .collect::<Vec<_>>();
+
+    let spread = match (best_bids.first(), best_asks.first()) {
+        (Some(best_bid), Some(best_ask)) => Some(best_ask.price - best_bid.price),
+        _ => None,
+    };
+
+    Summary {
+        spread,
+        crossed: spread.is_some_and(|spread| spread < 0.0),
+        imbalance: imbalance(&best_bids, &best_asks),
+        microprice: microprice(&best_bids, &best_asks),
+        bids: best_bids,
+        asks: best_asks,
+        total_notional_bids: bids.total_notional_bids(),
+        total_notional_asks: asks.total_notional_asks(),
+        schema_version: SUMMARY_SCHEMA_VERSION,
+        ..Default::default()
+    }
+}
+
+// The result of walking one side of the order book to fill a quantity, for sizing an order against the current book
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    //Volume weighted average price of the filled quantity
+    pub avg_price: f64,
+    pub filled_quantity: f64,
+    //Number of levels that were at least partially consumed
+    pub levels_consumed: usize,
+    //Set when the side of the book ran out of levels before the quantity was filled, in which case the quote is a partial fill
+    pub exhausted: bool,
+}
+
+//Walk the levels from best to worst, consuming each level until the quantity is filled.
+//Returns None if the quantity is not positive or there are no levels to fill against
+pub fn quote<'a, O: Order + 'a>(
+    levels: impl IntoIterator<Item = &'a O>,
+    quantity: f64,
+) -> Option<Quote> {
+    if quantity <= 0.0 {
+        return None;
+    }
+
+    let mut filled_quantity = 0.0;
+    let mut notional = 0.0;
+    let mut levels_consumed = 0;
+
+    for level in levels {
+        if filled_quantity >= quantity {
+            break;
+        }
+
+        let fill = level.get_quantity().0.min(quantity - filled_quantity);
+        filled_quantity += fill;
+        notional += fill * level.get_price().0;
+        levels_consumed += 1;
+    }
+
+    if levels_consumed == 0 {
+        return None;
+    }
+
+    Some(Quote {
+        avg_price: notional / filled_quantity,
+        filled_quantity,
+        levels_consumed,
+        exhausted: filled_quantity < quantity,
+    })
+}
+
+//Walk the levels from best to worst, accumulating the quantity at each price into (price, cumulative quantity) points of a depth chart.
+//Levels from different exchanges at the same price are one point, and at most n points are returned
+pub fn depth_curve<'a, O: Order + 'a>(
+    levels: impl IntoIterator<Item = &'a O>,
+    n: usize,
+) -> Vec<(f64, f64)> {
+    let mut curve: Vec<(f64, f64)> = vec![];
+    let mut cumulative_quantity = 0.0;
+
+    for level in levels {
+        let price = level.get_price().0;
+        cumulative_quantity += level.get_quantity().0;
+
+        if let Some(point) = curve.last_mut().filter(|point| point.0 == price) {
+            point.1 = cumulative_quantity;
+        } else if curve.len() < n {
+            curve.push((price, cumulative_quantity));
+        } else {
+            break;
+        }
+    }
+
+    curve
+}
+
+//Receive the next service event, or wait forever if service events are not being tracked
+async fn next_event(
+    event_rx: &mut Option<broadcast::Receiver<ServiceEvent>>,
+) -> Result<ServiceEvent, RecvError> {
+    match event_rx {
+        Some(event_rx) => event_rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+//Wait until the deadline, or forever if there is no deadline
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+//Wait for the next tick of the interval, or forever if the interval is disabled
+async fn next_tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
 }
 
 pub struct AggregatedOrderBook<B: BuySide + Send, S: SellSide + Send> {
@@ -55,6 +560,37 @@ pub struct AggregatedOrderBook<B: BuySide + Send, S: SellSide + Send> {
     pub exchanges: Vec<Exchange>,
     pub bids: Arc<Mutex<B>>,
     pub asks: Arc<Mutex<S>>,
+    pub level_max_age: Option<Duration>,
+    pub event_tx: Sender<ServiceEvent>,
+    pub feed_quality: Option<Arc<FeedQuality>>,
+    pub status: Option<Arc<ServiceStatus>>,
+    pub ranker: Option<Arc<dyn LevelRanker>>,
+    pub summary_callback: Option<SummaryCallback>,
+    pub quantity_semantics: HashMap<Exchange, QuantitySemantics>,
+    pub backpressure_policy: BackpressurePolicy,
+    pub publish_on_change_epsilon: Option<f64>,
+    pub level_cap: Option<Arc<LevelCap>>,
+    pub max_distance_from_mid: Option<f64>,
+    pub emit_levels: Option<usize>,
+    pub merge_price_levels: bool,
+    pub metrics: Option<Arc<Metrics>>,
+    pub price_tick_size: Option<f64>,
+    pub price_epsilon: Option<f64>,
+    pub heartbeat_interval: Option<Duration>,
+    pub publish_interval: Option<Duration>,
+    pub mid_decay_half_life: Option<Duration>,
+    pub snapshot_interval: Option<usize>,
+    pub credentials: HashMap<Exchange, Credentials>,
+    pub resubscribe_intervals: HashMap<Exchange, Duration>,
+    pub snapshot_refresh_intervals: HashMap<Exchange, Duration>,
+    pub order_book_services: HashMap<Exchange, Arc<dyn OrderBookService + Send + Sync>>,
+    pub all_exchanges_down: Option<(Duration, AllExchangesDownBehavior)>,
+    #[cfg(feature = "exchanges")]
+    pub reconnect_backoff: ReconnectBackoff,
+    //Notifies the running exchange services when the exchanges are swapped
+    exchanges_tx: watch::Sender<Vec<Exchange>>,
+    //Notifies the spawned tasks when shutdown is requested
+    shutdown_tx: watch::Sender<bool>,
 }
 
 impl<B, S> AggregatedOrderBook<B, S>
@@ -66,17 +602,366 @@ where
     pub fn new(pair: [&str; 2], exchanges: Vec<Exchange>, bids: B, asks: S) -> Self {
         AggregatedOrderBook {
             pair: [pair[0].to_string(), pair[1].to_string()],
+            exchanges_tx: watch::channel(exchanges.clone()).0,
             exchanges,
             bids: Arc::new(Mutex::new(bids)),
             asks: Arc::new(Mutex::new(asks)),
+            level_max_age: None,
+            event_tx: tokio::sync::broadcast::channel(EVENT_BUFFER).0,
+            feed_quality: None,
+            status: None,
+            ranker: None,
+            summary_callback: None,
+            quantity_semantics: HashMap::new(),
+            backpressure_policy: BackpressurePolicy::Block,
+            publish_on_change_epsilon: None,
+            level_cap: None,
+            max_distance_from_mid: None,
+            emit_levels: None,
+            merge_price_levels: false,
+            metrics: None,
+            price_tick_size: None,
+            price_epsilon: None,
+            heartbeat_interval: None,
+            publish_interval: None,
+            mid_decay_half_life: None,
+            snapshot_interval: None,
+            credentials: HashMap::new(),
+            resubscribe_intervals: HashMap::new(),
+            snapshot_refresh_intervals: HashMap::new(),
+            order_book_services: HashMap::new(),
+            all_exchanges_down: None,
+            #[cfg(feature = "exchanges")]
+            reconnect_backoff: ReconnectBackoff::default(),
+            shutdown_tx: watch::channel(false).0,
+        }
+    }
+
+    /// Subscribes to significant events published by the exchange streams and the aggregated order book
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<ServiceEvent> {
+        self.event_tx.subscribe()
+    }
+
+    /// Evicts price levels that have not been updated by their exchange within the max age.
+    /// Stale levels are checked each time a price level update is handled by the aggregated order book.
+    pub fn with_level_max_age(mut self, level_max_age: Duration) -> Self {
+        self.level_max_age = Some(level_max_age);
+        self
+    }
+
+    /// Records update id gaps, resets and duplicates from the exchange streams into the shared feed quality counters.
+    /// Streams still fail on a gap, anomalies are only counted so they can be reported.
+    pub fn with_feed_quality(mut self, feed_quality: Arc<FeedQuality>) -> Self {
+        self.feed_quality = Some(feed_quality);
+        self
+    }
+
+    /// Records the connection state of each exchange, whether any update has been received and when the last summary was published
+    /// into the shared service status, so that the health of the service can be reported.
+    pub fn with_status(mut self, status: Arc<ServiceStatus>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Ranks the best n bids and asks streamed in each summary with a custom ranker instead of the order book's ordering.
+    /// The spread is calculated from the top ranked bid and ask.
+    pub fn with_ranker(mut self, ranker: Box<dyn LevelRanker>) -> Self {
+        self.ranker = Some(Arc::from(ranker));
+        self
+    }
+
+    /// Breaks ties between levels with the same price and quantity in favor of the most recently updated level,
+    /// wrapping the ranker that has been set so far or the default ranker if none has been set.
+    pub fn with_recency_tie_break(mut self) -> Self {
+        let ranker = self
+            .ranker
+            .take()
+            .unwrap_or_else(|| Arc::new(DefaultRanker));
+        self.ranker = Some(Arc::new(RecencyTieBreak(ranker)));
+        self
+    }
+
+    /// Chooses between levels with the same price and quantity with the tie break policy when selecting the best n levels of each summary,
+    /// wrapping the ranker that has been set so far or the default ranker if none has been set. The order book's ordering is unchanged.
+    pub fn with_tie_break(mut self, tie_break: Box<dyn TieBreak>) -> Self {
+        let ranker = self
+            .ranker
+            .take()
+            .unwrap_or_else(|| Arc::new(DefaultRanker));
+        self.ranker = Some(Arc::new(TieBreakRanker {
+            ranker,
+            tie_break: Arc::from(tie_break),
+        }));
+        self
+    }
+
+    /// Sets how the quantities sent by an exchange are applied to the order book. Exchanges default to absolute quantities.
+    /// Delta quantities are added to the exchange's current quantity at the price level, so a level that has been dropped
+    /// from the book by the max depth is accumulated from zero.
+    pub fn with_quantity_semantics(
+        mut self,
+        exchange: Exchange,
+        quantity_semantics: QuantitySemantics,
+    ) -> Self {
+        self.quantity_semantics.insert(exchange, quantity_semantics);
+        self
+    }
+
+    /// Relays each exchange's price level updates through a task that merges updates while the aggregated order book is behind,
+    /// instead of blocking the exchange's stream handler until there is capacity in the price level channel.
+    pub fn with_price_level_coalescing(self) -> Self {
+        self.with_backpressure_policy(BackpressurePolicy::Coalesce)
+    }
+
+    /// Sets what each exchange's price level updates do when the price level channel is full. By default the exchange's stream handler
+    /// waits for capacity, while the coalesce and drop policies relay the updates through a task so the stream handler keeps reading its stream.
+    pub fn with_backpressure_policy(mut self, backpressure_policy: BackpressurePolicy) -> Self {
+        self.backpressure_policy = backpressure_policy;
+        self
+    }
+
+    /// Only publishes a summary when the spread, best n levels or exchange quotes differ from the last published summary
+    /// by more than the epsilon. Level ages and total notional are not considered, so churn deeper in the book is not republished.
+    pub fn with_publish_on_change(mut self, epsilon: f64) -> Self {
+        self.publish_on_change_epsilon = Some(epsilon);
+        self
+    }
+
+    /// Bounds the total number of levels held across every aggregated order book sharing the level cap, for running many pairs and exchanges
+    /// within a fixed memory budget. When the cap is exceeded, the side being updated evicts its least recently updated levels, worst priced first.
+    pub fn with_level_cap(mut self, level_cap: Arc<LevelCap>) -> Self {
+        self.level_cap = Some(level_cap);
+        self
+    }
+
+    /// Skips incoming levels priced further from the mid price than the max distance, as a fraction of the mid price, for consumers that only need the top of the book.
+    /// Removals are still applied so that levels the mid has moved away from are not left behind, and exchanges with delta quantity semantics are not filtered
+    /// since a skipped delta would corrupt the quantity of a level that is later kept.
+    pub fn with_max_distance_from_mid(mut self, max_distance_from_mid: f64) -> Self {
+        self.max_distance_from_mid = Some(max_distance_from_mid);
+        self
+    }
+
+    /// Limits the number of bids and asks in each published summary, while the best n orders are still tracked internally.
+    /// This keeps the summary small for clients without reducing the depth that is tracked for ranking and analytics.
+    pub fn with_emit_levels(mut self, emit_levels: usize) -> Self {
+        self.emit_levels = Some(emit_levels);
+        self
+    }
+
+    /// Collapses the levels at the same price from different exchanges into one level in each published summary, with the summed amount
+    /// and the exchanges as a comma separated list. Levels are merged before they are limited by the emit levels, so a summary may hold fewer levels than the best n orders.
+    pub fn with_merge_price_levels(mut self) -> Self {
+        self.merge_price_levels = true;
+        self
+    }
+
+    /// Snaps the price of each incoming level to the nearest multiple of the tick size, so that prices from different exchanges
+    /// that are economically identical but differ by float noise are treated as the same price level.
+    pub fn with_price_tick_size(mut self, price_tick_size: f64) -> Self {
+        self.price_tick_size = Some(price_tick_size);
+        self
+    }
+
+    /// Compares the prices of levels by their number of ticks of the epsilon, so that prices within float noise of each other are the same
+    /// price level while each level keeps the price that it was received at. See PriceKey for the tradeoff against exact comparison.
+    /// Only sides that order levels by their Ord compare within the epsilon, while the map-backed sides key levels by their exact price.
+    /// The epsilon is ignored when a price tick size is set, since snapped prices are already the same level when they are on the same tick.
+    pub fn with_price_epsilon(mut self, price_epsilon: f64) -> Self {
+        self.price_epsilon = Some(price_epsilon);
+        self
+    }
+
+    /// Authenticates the exchange's order book stream with the credentials, for higher connection and rate limits where the exchange supports it.
+    /// Streams from exchanges without credentials use the public endpoints.
+    pub fn with_credentials(mut self, exchange: Exchange, credentials: Credentials) -> Self {
+        self.credentials.insert(exchange, credentials);
+        self
+    }
+
+    /// Re-sends the exchange's subscription on the open connection at the interval, for exchanges that expire subscriptions after a fixed period.
+    /// Exchanges that are subscribed through the stream endpoint rather than a subscription message ignore the interval.
+    pub fn with_resubscribe_interval(
+        mut self,
+        exchange: Exchange,
+        resubscribe_interval: Duration,
+    ) -> Self {
+        self.resubscribe_intervals
+            .insert(exchange, resubscribe_interval);
+        self
+    }
+
+    /// Re-fetches the exchange's REST snapshot at the interval and resyncs its levels, so that a dropped update without a detectable gap does not leave the order book drifting.
+    /// Exchanges that only send a snapshot when subscribing ignore the interval.
+    pub fn with_snapshot_refresh_interval(
+        mut self,
+        exchange: Exchange,
+        snapshot_refresh_interval: Duration,
+    ) -> Self {
+        self.snapshot_refresh_intervals
+            .insert(exchange, snapshot_refresh_interval);
+        self
+    }
+
+    /// Streams the exchange's price level updates from the order book service instead of the exchange's own integration,
+    /// ie. to replay recorded updates through a mock exchange. The service is started and stopped like any other exchange.
+    pub fn with_order_book_service(
+        mut self,
+        exchange: Exchange,
+        order_book_service: impl OrderBookService + Send + Sync + 'static,
+    ) -> Self {
+        self.order_book_services
+            .insert(exchange, Arc::new(order_book_service));
+        self
+    }
+
+    /// Backs off between attempts to reconnect each exchange's stream, doubling the delay up to the max delay of the backoff with jitter.
+    /// Failed reconnects are retried after the backoff delay, while a failure to connect the first time fails the exchange's service.
+    #[cfg(feature = "exchanges")]
+    pub fn with_reconnect_backoff(mut self, reconnect_backoff: ReconnectBackoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+
+    /// Republishes the last summary, flagged as a heartbeat, when no summary has been published within the interval.
+    /// This lets consumers confirm that the service is alive during quiet markets when no updates arrive.
+    pub fn with_heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = Some(heartbeat_interval);
+        self
+    }
+
+    /// Publishes at most one summary per interval, holding the summary built from each update until the next tick so that only the latest state is published.
+    /// This bounds the publish rate on busy pairs for slow consumers. Snapshots are published immediately, and an interval of zero publishes every update.
+    pub fn with_publish_interval(mut self, publish_interval: Duration) -> Self {
+        self.publish_interval = (!publish_interval.is_zero()).then_some(publish_interval);
+        self
+    }
+
+    /// Applies the behavior once every exchange has been disconnected for longer than the timeout, publishing an all exchanges down event.
+    /// Exchanges are down until they first connect, and the order book recovers once any exchange reconnects.
+    pub fn with_all_exchanges_down(
+        mut self,
+        timeout: Duration,
+        behavior: AllExchangesDownBehavior,
+    ) -> Self {
+        self.all_exchanges_down = Some((timeout, behavior));
+        self
+    }
+
+    /// Decays each exchange's weight in the weighted mid by half for every half life since the exchange last sent an update.
+    /// Stale exchanges are smoothly down weighted rather than cut off, so the mid stays robust while some feeds are degraded.
+    pub fn with_mid_decay_half_life(mut self, mid_decay_half_life: Duration) -> Self {
+        self.mid_decay_half_life = Some(mid_decay_half_life);
+        self
+    }
+
+    /// Publishes a full depth snapshot of the order book, flagged as a snapshot, in place of the summary for every n price level updates.
+    /// This lets consumers that mirror the order book resync from the summary stream without a separate request.
+    pub fn with_snapshot_interval(mut self, snapshot_interval: usize) -> Self {
+        self.snapshot_interval = Some(snapshot_interval);
+        self
+    }
+
+    /// Records the price levels received from each exchange, the spread and the summary publish latency into the metrics.
+    /// The metrics are labeled by pair, so they can be shared between the aggregated order books of every pair.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Registers a callback that is invoked with each summary as it is published, for embedding the order book without consuming the summary channel.
+    /// The callback runs synchronously within the aggregation loop, so it must return quickly. Long running work should be sent to another task.
+    pub fn with_summary_callback(
+        mut self,
+        summary_callback: impl Fn(&Summary) + Send + Sync + 'static,
+    ) -> Self {
+        self.summary_callback = Some(Arc::new(summary_callback));
+        self
+    }
+
+    /// Swaps the exchanges of the aggregated order book. Once the exchange services are running, streams are started for the added exchanges
+    /// and the removed exchanges are stopped with their levels cleared from the aggregated order book, while exchanges in both sets keep running.
+    pub fn set_exchanges(&mut self, exchanges: Vec<Exchange>) {
+        self.exchanges = exchanges.clone();
+        self.exchanges_tx.send_replace(exchanges);
+    }
+
+    /// Stops the exchange services and the aggregation loop spawned by the aggregated order book, so that their join handles resolve to `Ok(())`.
+    /// Tasks spawned after shutdown has been requested stop immediately.
+    pub fn shutdown(&self) {
+        self.shutdown_tx.send_replace(true);
+    }
+
+    /// Verifies that each exchange has at most one level at each price on both sides of the aggregated order book.
+    /// Debug builds also check this after each update handled by the aggregated order book, logging any duplicates.
+    pub async fn verify_integrity(&self) -> Result<(), OrderBookError> {
+        let bids = self.bids.lock().await.duplicate_bids();
+        let asks = self.asks.lock().await.duplicate_asks();
+
+        if bids.is_empty() && asks.is_empty() {
+            Ok(())
+        } else {
+            Err(OrderBookError::DuplicateLevels { bids, asks })
         }
     }
 
+    /// Returns the sum of price * quantity across all bids in the aggregated order book
+    pub async fn total_notional_bids(&self) -> f64 {
+        self.bids.lock().await.total_notional_bids()
+    }
+
+    /// Returns the sum of price * quantity across all asks in the aggregated order book
+    pub async fn total_notional_asks(&self) -> f64 {
+        self.asks.lock().await.total_notional_asks()
+    }
+
+    /// Returns a summary of the best n bids and asks currently in the aggregated order book, with the spread and total notional of each side,
+    /// without waiting for a summary to be published. Levels are ordered by price as they are held in the order book.
+    pub async fn snapshot(&self, n: usize) -> Summary {
+        let bids = self.bids.lock().await;
+        let asks = self.asks.lock().await;
+        build_summary(&*bids, &*asks, n)
+    }
+
+    /// Returns the volume weighted average price to fill the quantity against one side of the aggregated order book, ie. the asks to price a buy.
+    /// If the side is too thin to fill the quantity, the quote is a partial fill flagged as exhausted.
+    /// The levels are walked in place from the best level, stopping once the quantity is filled.
+    pub async fn quote(&self, side: OrderType, quantity: f64) -> Option<Quote>
+    where
+        B: BuySideView,
+        S: SellSideView,
+    {
+        match side {
+            OrderType::Bid => quote(self.bids.lock().await.iter_bids(), quantity),
+            OrderType::Ask => quote(self.asks.lock().await.iter_asks(), quantity),
+        }
+    }
+
+    /// Returns the bid and ask (price, cumulative quantity) points of a depth chart, walking outward from the best price of each side
+    /// for up to n prices. Levels from different exchanges at the same price are summed into one point, and an empty side has no points.
+    /// The levels are walked in place from the best level, stopping after the nth price.
+    pub async fn depth_curve(&self, n: usize) -> (Vec<(f64, f64)>, Vec<(f64, f64)>)
+    where
+        B: BuySideView,
+        S: SellSideView,
+    {
+        let bids = self.bids.lock().await;
+        let asks = self.asks.lock().await;
+        (
+            depth_curve(bids.iter_bids(), n),
+            depth_curve(asks.iter_asks(), n),
+        )
+    }
+
     /// Spawns the bid-ask service for the order book, with the specified configurations and channels,
-    /// returning a vec of join handles for each exchange service and orderbook update logic
+    /// returning a vec of join handles for each exchange service and orderbook update logic.
+    /// Each exchange streams its depth from the depth config, while the aggregated order book keeps up to the max depth on each side.
+    /// The tasks run until an exchange service fails or shutdown is requested with `shutdown`.
+    /// See `AggregatedOrderBookBuilder` to spawn the service with named settings and defaults.
+    #[cfg(feature = "exchanges")]
     pub fn spawn_bid_ask_service(
         &self,
-        max_order_book_depth: usize,
+        depth_config: DepthConfig,
         exchange_stream_buffer: usize,
         price_level_buffer: usize,
         best_n_orders: usize,
@@ -87,14 +972,45 @@ where
         let mut handles = vec![];
 
         //Spawn the order book service for each exchange, handling order book updates and sending them to the aggregated order book
-        for exchange in self.exchanges.iter() {
-            handles.extend(exchange.spawn_order_book_service(
-                [&self.pair[0], &self.pair[1]],
-                max_order_book_depth,
-                exchange_stream_buffer,
-                price_level_tx.clone(),
-            ))
-        }
+        let pair = self.pair.clone();
+        let event_tx = self.event_tx.clone();
+        let feed_quality = self.feed_quality.clone();
+        let credentials = self.credentials.clone();
+        let resubscribe_intervals = self.resubscribe_intervals.clone();
+        let snapshot_refresh_intervals = self.snapshot_refresh_intervals.clone();
+        let order_book_services = self.order_book_services.clone();
+        let reconnect_backoff = self.reconnect_backoff.clone();
+        let max_order_book_depth = depth_config.max_order_book_depth;
+        handles.push(self.spawn_exchange_services(
+            price_level_tx,
+            price_level_buffer,
+            move |exchange, exchange_price_level_tx, shutdown_rx| {
+                match order_book_services.get(exchange) {
+                    Some(order_book_service) => order_book_service.spawn_order_book_service(
+                        [&pair[0], &pair[1]],
+                        depth_config.exchange_depth(exchange),
+                        exchange_stream_buffer,
+                        exchange_price_level_tx,
+                        event_tx.clone(),
+                        shutdown_rx,
+                        feed_quality.clone(),
+                    ),
+                    None => exchange.spawn_order_book_service(
+                        [&pair[0], &pair[1]],
+                        depth_config.exchange_depth(exchange),
+                        exchange_stream_buffer,
+                        exchange_price_level_tx,
+                        event_tx.clone(),
+                        shutdown_rx,
+                        feed_quality.clone(),
+                        credentials.get(exchange).cloned(),
+                        resubscribe_intervals.get(exchange).copied(),
+                        reconnect_backoff.clone(),
+                        snapshot_refresh_intervals.get(exchange).copied(),
+                    ),
+                }
+            },
+        ));
 
         //Handle order book updates from the exchange streams, aggregating the order book and sending the summary to the gRPC server
         handles.push(self.handle_order_book_updates(
@@ -107,6 +1023,143 @@ where
         handles
     }
 
+    /// Spawns a task that runs the order book service of each exchange with the spawn function, sending the price level updates to the aggregated order book.
+    /// The task starts and stops exchange services as the exchanges are swapped, and fails with the first error from a running exchange service.
+    /// Each exchange service is passed a receiver that is signalled on `shutdown`, after which its tasks are expected to stop and return `Ok(())`.
+    pub fn spawn_exchange_services(
+        &self,
+        price_level_tx: tokio::sync::mpsc::Sender<PriceLevelUpdate>,
+        price_level_buffer: usize,
+        mut spawn_service: impl FnMut(
+                &Exchange,
+                tokio::sync::mpsc::Sender<PriceLevelUpdate>,
+                watch::Receiver<bool>,
+            ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>>
+            + Send
+            + 'static,
+    ) -> JoinHandle<Result<(), BidAskServiceError>> {
+        //Subscribe before spawning the task, so that exchanges swapped after this point are not missed
+        let mut exchanges_rx = self.exchanges_tx.subscribe();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        //Cloned into each exchange service, so that the services stop themselves on shutdown
+        let service_shutdown_rx = self.shutdown_tx.subscribe();
+        let initial_exchanges = self.exchanges.clone();
+        let backpressure_policy = self.backpressure_policy;
+        let feed_quality = self.feed_quality.clone();
+        let pair = self.pair.clone();
+        let event_tx = self.event_tx.clone();
+
+        tokio::spawn(async move {
+            let mut services = ExchangeServices::new();
+
+            //Unless blocking, each exchange sends to its own channel which is drained by a relay into the aggregated order book
+            let mut spawn_exchange = |exchange: &Exchange| {
+                let (exchange_price_level_tx, exchange_price_level_rx) =
+                    tokio::sync::mpsc::channel::<PriceLevelUpdate>(price_level_buffer);
+                let relay_handle = match backpressure_policy {
+                    BackpressurePolicy::Block => {
+                        return spawn_service(
+                            exchange,
+                            price_level_tx.clone(),
+                            service_shutdown_rx.clone(),
+                        )
+                    }
+                    BackpressurePolicy::Coalesce => price_level::spawn_coalescing_relay(
+                        exchange_price_level_rx,
+                        price_level_tx.clone(),
+                    ),
+                    BackpressurePolicy::Drop => price_level::spawn_dropping_relay(
+                        exchange_price_level_rx,
+                        price_level_tx.clone(),
+                        feed_quality.clone(),
+                        EventPublisher::new(
+                            Some(exchange.clone()),
+                            [&pair[0], &pair[1]],
+                            event_tx.clone(),
+                        ),
+                    ),
+                };
+
+                let mut handles = vec![relay_handle];
+                handles.extend(spawn_service(
+                    exchange,
+                    exchange_price_level_tx,
+                    service_shutdown_rx.clone(),
+                ));
+                handles
+            };
+
+            for exchange in initial_exchanges.iter() {
+                if !services.running().any(|running| running == exchange) {
+                    services.start(exchange, spawn_exchange(exchange));
+                }
+            }
+
+            //Stop watching for swapped exchanges once the aggregated order book has been dropped, leaving the running exchanges as they are
+            let mut watching = true;
+            loop {
+                tokio::select! {
+                    changed = exchanges_rx.changed(), if watching => {
+                        if changed.is_err() {
+                            watching = false;
+                            continue;
+                        }
+
+                        let exchanges = exchanges_rx.borrow_and_update().clone();
+                        let removed = services
+                            .running()
+                            .filter(|exchange| !exchanges.contains(exchange))
+                            .cloned()
+                            .collect::<Vec<_>>();
+
+                        //Stop the removed exchanges before clearing their levels, so that no update from a removed exchange follows the clear
+                        for exchange in removed {
+                            services.stop(&exchange);
+                            tracing::info!("Stopped {exchange} order book service");
+                            EventPublisher::new(Some(exchange.clone()), [&pair[0], &pair[1]], event_tx.clone())
+                                .publish(ServiceEventKind::Disconnected);
+
+                            if price_level_tx
+                                .send(PriceLevelUpdate::snapshot(exchange.clone(), vec![], vec![]))
+                                .await
+                                .is_err()
+                            {
+                                tracing::warn!("Could not clear {exchange} levels, the aggregated order book has stopped");
+                            }
+                        }
+
+                        for exchange in exchanges.iter() {
+                            if !services.running().any(|running| running == exchange) {
+                                tracing::info!("Starting {exchange} order book service");
+                                services.start(exchange, spawn_exchange(exchange));
+                            }
+                        }
+                    }
+
+                    (exchange, result) = services.next() => {
+                        tracing::error!("{exchange} order book service stopped");
+                        return flatten_task_result(result);
+                    }
+
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        //The exchange services unsubscribe and close their connections on shutdown, so they are waited on rather than aborted,
+                        //aborting any service that has not stopped within the timeout
+                        let result = match tokio::time::timeout(EXCHANGE_SHUTDOWN_TIMEOUT, services.join_all()).await {
+                            Ok(result) => result,
+                            Err(_) => {
+                                tracing::warn!("Exchange order book services did not stop within {EXCHANGE_SHUTDOWN_TIMEOUT:?}, aborting");
+                                services.stop_all();
+                                Ok(())
+                            }
+                        };
+                        tracing::info!("Stopped exchange order book services");
+                        return result;
+                    }
+                }
+            }
+        })
+    }
+
     pub fn handle_order_book_updates(
         &self,
         mut price_level_rx: Receiver<PriceLevelUpdate>,
@@ -116,6 +1169,39 @@ where
     ) -> JoinHandle<Result<(), BidAskServiceError>> {
         let bids = self.bids.clone();
         let asks = self.asks.clone();
+        let level_max_age = self.level_max_age;
+        let ranker = self.ranker.clone();
+        //Levels are only sorted by price when the ranker ranks by price, so that a custom ranker's order is streamed as is
+        let sort_by_price = ranker.as_ref().is_none_or(|ranker| ranker.ranks_by_price());
+        let summary_callback = self.summary_callback.clone();
+        let mut exchanges = self.exchanges.clone();
+        let quantity_semantics = self.quantity_semantics.clone();
+        let publish_on_change_epsilon = self.publish_on_change_epsilon;
+        let level_cap = self.level_cap.clone();
+        let max_distance_from_mid = self.max_distance_from_mid;
+        let emit_levels = self.emit_levels.unwrap_or(best_n_orders);
+        let merge_price_levels = self.merge_price_levels;
+        let metrics = self.metrics.clone();
+        let status = self.status.clone();
+        //The pair as base/quote, labelling the metrics and the structured fields of the logs
+        let pair_name = self.pair.join("/");
+        let price_tick_size = self.price_tick_size;
+        //Prices snapped to the tick grid are compared exactly, so that there is a single rounding of prices
+        let price_comparison = match (price_tick_size, self.price_epsilon) {
+            (None, Some(price_epsilon)) => PriceComparison::Epsilon(price_epsilon),
+            _ => PriceComparison::Exact,
+        };
+        let heartbeat_interval = self.heartbeat_interval;
+        let publish_interval = self.publish_interval;
+        let mid_decay_half_life = self.mid_decay_half_life;
+        let snapshot_interval = self.snapshot_interval;
+        let all_exchanges_down = self.all_exchanges_down;
+        //Subscribe to the exchange connection events before spawning the aggregation loop, so that no connection is missed
+        let mut event_rx =
+            (all_exchanges_down.is_some() || status.is_some()).then(|| self.event_tx.subscribe());
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let events =
+            EventPublisher::new(None, [&self.pair[0], &self.pair[1]], self.event_tx.clone());
         tokio::spawn(async move {
             let mut best_bid_price = 0.0;
             let mut best_ask_price = f64::MAX;
@@ -128,50 +1214,340 @@ where
             let mut last_bid = Bid::default();
             let mut last_ask = Ask::default();
 
-            while let Some(price_level_update) = price_level_rx.recv().await {
-                //Update the bids as a future
-                let bids_fut = async {
-                    //Add each bid to the aggregated order book, checking if the bid is better than the "worst" bid in the top n bids
-                    let mut update_best_bids = false;
-                    for bid in price_level_update.bids {
-                        if bid.cmp(&last_bid).is_ge() {
-                            update_best_bids = true;
-                        }
-                        bids.lock().await.update_bids(bid, max_order_book_depth);
-                    }
+            //Track the last published summary to determine if a new summary has changed
+            let mut last_summary: Option<Summary> = None;
 
-                    //If the bid is better than the "worst" bid in the top bids, update the best n bids
-                    if update_best_bids {
-                        let mut best_bids = bids.lock().await.get_best_n_bids(best_n_orders);
-                        if best_bids[0].is_some() {
-                            let mut best_n_levels = vec![];
-
-                            //Get the best "n" bids and add the level to the best n levels
-                            let mut last_bid = 0;
-                            for bid_option in best_bids.iter() {
-                                if let Some(bid) = bid_option {
-                                    best_n_levels.push(Level {
-                                        price: bid.price.0,
-                                        amount: bid.quantity.0,
-                                        exchange: bid.exchange.to_string(),
-                                    });
-
-                                    last_bid += 1;
-                                } else {
-                                    break;
-                                }
-                            }
+            //Track when each exchange last sent an update, to decay the weight of stale exchanges in the weighted mid
+            let mut exchange_updated: HashMap<Exchange, tokio::time::Instant> = HashMap::new();
 
-                            //Return the best levels, the first bid, and the last bid
-                            Some((
-                                best_n_levels,
-                                best_bids[0].take().unwrap().price.0,
-                                best_bids[last_bid - 1].take().unwrap(),
-                            ))
-                        } else {
-                            tracing::error!("No bids in aggregated order book");
-                            None
-                        }
+            //Track the newest exchange timestamp applied to the order book, published as the time that the summary is as of
+            let mut as_of: Option<u64> = None;
+
+            //Track the number of bids and asks counted towards the level cap
+            let mut capped_bids = 0;
+            let mut capped_asks = 0;
+
+            //The heartbeat is delayed each time a summary is published, so it only fires once the order book has been idle for the interval
+            let mut heartbeat = heartbeat_interval.map(|interval| {
+                let mut heartbeat =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                heartbeat
+            });
+            let mut heartbeat_summary: Option<Summary> = None;
+
+            //When the publish rate is capped, the latest summary is held until the next tick, along with when its update was received
+            let mut publish_tick = publish_interval.map(|interval| {
+                let mut publish_tick =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                publish_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                publish_tick
+            });
+            let mut pending_summary: Option<(Summary, std::time::Instant)> = None;
+
+            //Track the number of price level updates handled since the last full depth snapshot
+            let mut updates_since_snapshot = 0;
+
+            //Track the exchanges that are connected, starting the all exchanges down timeout once none are connected
+            let mut connected_exchanges: HashSet<Exchange> = HashSet::new();
+            let mut all_down_deadline =
+                all_exchanges_down.map(|(timeout, _)| Instant::now() + timeout);
+            let mut all_down = false;
+
+            //Whether summaries are withheld while every exchange is down, rather than republished as stale
+            let withhold_when_down = all_exchanges_down.map(|(_, behavior)| behavior)
+                == Some(AllExchangesDownBehavior::StopPublishing);
+
+            //Publish an empty summary without a spread on start, so that clients know the service is up while the order book is warming
+            let warming_summary = Summary {
+                schema_version: SUMMARY_SCHEMA_VERSION,
+                ..Default::default()
+            };
+            if let Some(summary_callback) = &summary_callback {
+                summary_callback(&warming_summary);
+            }
+            if let Err(SummaryError::NoSubscribers) =
+                summary_tx.send(warming_summary).map_err(SummaryError::from)
+            {
+                tracing::debug!("{}", SummaryError::NoSubscribers);
+            }
+
+            //Keep the last published summary to republish as stale once every exchange is down
+            let mut latest_summary = Summary {
+                schema_version: SUMMARY_SCHEMA_VERSION,
+                ..Default::default()
+            };
+
+            //Publish a summary, keeping it to republish as a heartbeat or as stale once every exchange is down
+            let publish_summary = |summary: Summary,
+                                   received_at: std::time::Instant,
+                                   heartbeat: &mut Option<Interval>,
+                                   heartbeat_summary: &mut Option<Summary>,
+                                   latest_summary: &mut Summary| {
+                tracing::info!(pair = %pair_name, ?summary, "Publishing summary");
+
+                if let Some(summary_callback) = &summary_callback {
+                    summary_callback(&summary);
+                }
+
+                //Keep the summary to republish as a heartbeat, delaying the heartbeat until the order book is idle again
+                if let Some(heartbeat) = heartbeat.as_mut() {
+                    heartbeat.reset();
+                    *heartbeat_summary = Some(Summary {
+                        heartbeat: true,
+                        ..summary.clone()
+                    });
+                }
+
+                if all_exchanges_down.is_some() {
+                    *latest_summary = summary.clone();
+                }
+
+                //Summaries are dropped until a client subscribes, without stopping the aggregated order book
+                if let Err(SummaryError::NoSubscribers) =
+                    summary_tx.send(summary).map_err(SummaryError::from)
+                {
+                    tracing::debug!("{}", SummaryError::NoSubscribers);
+                }
+                if let Some(status) = &status {
+                    status.record_published();
+                }
+
+                if let Some(metrics) = &metrics {
+                    metrics.observe_summary_publish_latency(&pair_name, received_at.elapsed());
+                }
+            };
+
+            loop {
+                let price_level_update = tokio::select! {
+                    price_level_update = price_level_rx.recv() => match price_level_update {
+                        Some(price_level_update) => price_level_update,
+                        None => break,
+                    },
+
+                    _ = shutdown_requested(&mut shutdown_rx) => {
+                        tracing::info!("Stopped aggregated order book");
+                        break;
+                    }
+
+                    _ = next_tick(&mut heartbeat) => {
+                        match &heartbeat_summary {
+                            Some(_) if all_down && withhold_when_down => {}
+                            Some(summary) => {
+                                tracing::debug!("Publishing heartbeat summary");
+                                if let Err(SummaryError::NoSubscribers) = summary_tx
+                                    .send(Summary { stale: all_down, ..summary.clone() })
+                                    .map_err(SummaryError::from)
+                                {
+                                    tracing::debug!("{}", SummaryError::NoSubscribers);
+                                }
+                            }
+                            None => {}
+                        }
+                        continue;
+                    }
+
+                    //Publish the latest summary held since the last tick, unless no update has been handled since
+                    _ = next_tick(&mut publish_tick) => {
+                        match pending_summary.take() {
+                            Some(_) if all_down && withhold_when_down => {}
+                            Some((summary, received_at)) => publish_summary(
+                                summary,
+                                received_at,
+                                &mut heartbeat,
+                                &mut heartbeat_summary,
+                                &mut latest_summary,
+                            ),
+                            None => {}
+                        }
+                        continue;
+                    }
+
+                    //Track the connection events published by the exchange streams, ignoring events published by the order book itself
+                    event = next_event(&mut event_rx) => {
+                        if let (Some(status), Ok(event)) = (&status, &event) {
+                            status.record_event(event);
+                        }
+
+                        match event {
+                            Ok(ServiceEvent { event, exchange: Some(exchange), .. }) => match event {
+                                ServiceEventKind::Connected => {
+                                    if all_down {
+                                        tracing::info!("{exchange} reconnected, resuming publishing");
+                                    }
+                                    connected_exchanges.insert(exchange);
+                                    all_down_deadline = None;
+                                    all_down = false;
+                                }
+                                ServiceEventKind::Disconnected => {
+                                    connected_exchanges.remove(&exchange);
+                                    if connected_exchanges.is_empty() && all_down_deadline.is_none() && !all_down {
+                                        all_down_deadline = all_exchanges_down
+                                            .map(|(timeout, _)| Instant::now() + timeout);
+                                    }
+                                }
+                                _ => {}
+                            },
+                            Ok(_) => {}
+                            Err(RecvError::Lagged(skipped)) => {
+                                tracing::warn!("Skipped {skipped} service events while tracking connections");
+                            }
+                            Err(RecvError::Closed) => event_rx = None,
+                        }
+                        continue;
+                    }
+
+                    _ = sleep_until_deadline(all_down_deadline) => {
+                        all_down_deadline = None;
+                        all_down = true;
+                        tracing::error!("Every exchange has been down for longer than the timeout");
+                        events.publish(ServiceEventKind::AllExchangesDown);
+
+                        if !withhold_when_down {
+                            let stale_summary = Summary {
+                                stale: true,
+                                ..latest_summary.clone()
+                            };
+                            if let Some(summary_callback) = &summary_callback {
+                                summary_callback(&stale_summary);
+                            }
+                            if let Err(SummaryError::NoSubscribers) =
+                                summary_tx.send(stale_summary).map_err(SummaryError::from)
+                            {
+                                tracing::debug!("{}", SummaryError::NoSubscribers);
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                let received_at = std::time::Instant::now();
+                let exchange = price_level_update.exchange;
+                if let Some(metrics) = &metrics {
+                    metrics.record_price_level_updates(
+                        &pair_name,
+                        &exchange,
+                        price_level_update.bids.len() + price_level_update.asks.len(),
+                    );
+                }
+                exchange_updated.insert(exchange.clone(), tokio::time::Instant::now());
+                if let Some(status) = &status {
+                    status.record_update();
+                }
+                as_of = as_of.max(price_level_update.exchange_timestamp);
+                //Quote exchanges that were added after the aggregated order book started
+                if !exchanges.contains(&exchange) {
+                    exchanges.push(exchange.clone());
+                }
+                let clear = price_level_update.clear;
+                let delta = quantity_semantics.get(&exchange) == Some(&QuantitySemantics::Delta);
+
+                //Get the mid price to filter levels against, once there is a best bid and ask
+                let mid_price = if best_bid_price > 0.0 && best_ask_price < f64::MAX && !delta {
+                    max_distance_from_mid
+                        .map(|max_distance| ((best_bid_price + best_ask_price) / 2.0, max_distance))
+                } else {
+                    None
+                };
+                let far_from_mid = |price: f64, quantity: f64| {
+                    mid_price.is_some_and(|(mid_price, max_distance)| {
+                        quantity != 0.0 && (price - mid_price).abs() / mid_price > max_distance
+                    })
+                };
+
+                //Whether the best n bids and asks hold n levels, otherwise any new level is within the best n
+                let best_bids_full = best_n_bids.len() >= best_n_orders;
+                let best_asks_full = best_n_asks.len() >= best_n_orders;
+
+                //Update the bids as a future
+                let bids_fut = async {
+                    //Add each bid to the aggregated order book, checking if the bid is better than the "worst" bid in the top n bids
+                    let mut update_best_bids = false;
+
+                    //Clear the exchange's existing bids if the update replaces them
+                    if clear {
+                        let removed = bids.lock().await.clear_exchange_bids(&exchange);
+                        tracing::info!(pair = %pair_name, %exchange, removed, "Cleared exchange bids");
+                        update_best_bids = true;
+                    }
+
+                    //Evict any bids that have gone stale, refreshing the best n bids if any were removed
+                    if let Some(max_age) = level_max_age {
+                        let removed = bids.lock().await.remove_stale_bids(max_age);
+                        if removed > 0 {
+                            tracing::warn!("Evicted {removed} stale bids");
+                            events.publish(ServiceEventKind::StaleLevelsEvicted);
+                            update_best_bids = true;
+                        }
+                    }
+
+                    for mut bid in price_level_update.bids {
+                        if let Some(tick_size) = price_tick_size {
+                            bid.price = OrderedFloat(snap_to_grid(bid.price.0, tick_size));
+                        }
+                        bid.price_comparison = price_comparison;
+
+                        if far_from_mid(bid.price.0, bid.quantity.0) {
+                            continue;
+                        }
+
+                        //Resolve a delta quantity to the absolute quantity at the price level
+                        if delta {
+                            let quantity = bids
+                                .lock()
+                                .await
+                                .get_exchange_bid_quantity(bid.price.0, &bid.exchange)
+                                .unwrap_or(0.0);
+                            bid.set_quantity(OrderedFloat((quantity + bid.quantity.0).max(0.0)));
+                        }
+
+                        //A custom ranker can rank any level into the best n, so the best n bids are always updated
+                        if bid_changes_best_n(&bid, &last_bid, best_bids_full) || ranker.is_some() {
+                            update_best_bids = true;
+                        }
+                        bids.lock().await.update_bids(bid, max_order_book_depth);
+                    }
+
+                    //Evict the least recently updated bids if the order books sharing the level cap hold more levels than the cap
+                    if let Some(level_cap) = &level_cap {
+                        let mut bids = bids.lock().await;
+                        let excess = level_cap.track(&mut capped_bids, bids.num_bids());
+                        if excess > 0 {
+                            let removed = bids.evict_bids(excess);
+                            level_cap.track(&mut capped_bids, bids.num_bids());
+                            tracing::warn!(
+                                "Evicted {removed} bids over the level cap of {}",
+                                level_cap.max_levels()
+                            );
+                            events.publish(ServiceEventKind::LevelCapExceeded);
+                            update_best_bids = true;
+                        }
+                    }
+
+                    //If the bid is better than the "worst" bid in the top bids, update the best n bids
+                    if update_best_bids {
+                        let mut best_bids = match &ranker {
+                            Some(ranker) => bids
+                                .lock()
+                                .await
+                                .get_top_bids_ranked(best_n_orders, ranker.as_ref()),
+                            None => bids.lock().await.get_top_bids(best_n_orders),
+                        };
+                        //Get the best "n" bids as the best n levels, holding fewer than n levels when the order book holds fewer than n bids
+                        let best_n_levels = best_bids.iter().map(bid_level).collect::<Vec<_>>();
+
+                        //The worst of the best bids is tracked to check whether later bids change the best n.
+                        //There is no worst bid if the order book has no bids, or no bids are tracked
+                        if let Some(worst_bid) = best_bids.pop() {
+                            let top_bid_price = best_n_levels[0].price;
+
+                            //Return the best levels, the first bid price, and the worst bid
+                            Some((best_n_levels, top_bid_price, worst_bid))
+                        } else {
+                            //Clearing or evicting every bid empties the best n, so that the removed levels are not republished
+                            tracing::warn!("No bids in aggregated order book");
+                            Some((vec![], 0.0, Bid::default()))
+                        }
                     } else {
                         None
                     }
@@ -181,45 +1557,90 @@ where
                 let asks_fut = async {
                     let mut update_best_asks = false;
 
-                    for ask in price_level_update.asks {
-                        if ask.cmp(&last_ask).is_le() {
+                    //Clear the exchange's existing asks if the update replaces them
+                    if clear {
+                        let removed = asks.lock().await.clear_exchange_asks(&exchange);
+                        tracing::info!(pair = %pair_name, %exchange, removed, "Cleared exchange asks");
+                        update_best_asks = true;
+                    }
+
+                    //Evict any asks that have gone stale, refreshing the best n asks if any were removed
+                    if let Some(max_age) = level_max_age {
+                        let removed = asks.lock().await.remove_stale_asks(max_age);
+                        if removed > 0 {
+                            tracing::warn!("Evicted {removed} stale asks");
+                            events.publish(ServiceEventKind::StaleLevelsEvicted);
+                            update_best_asks = true;
+                        }
+                    }
+
+                    for mut ask in price_level_update.asks {
+                        if let Some(tick_size) = price_tick_size {
+                            ask.price = OrderedFloat(snap_to_grid(ask.price.0, tick_size));
+                        }
+                        ask.price_comparison = price_comparison;
+
+                        if far_from_mid(ask.price.0, ask.quantity.0) {
+                            continue;
+                        }
+
+                        //Resolve a delta quantity to the absolute quantity at the price level
+                        if delta {
+                            let quantity = asks
+                                .lock()
+                                .await
+                                .get_exchange_ask_quantity(ask.price.0, &ask.exchange)
+                                .unwrap_or(0.0);
+                            ask.set_quantity(OrderedFloat((quantity + ask.quantity.0).max(0.0)));
+                        }
+
+                        //A custom ranker can rank any level into the best n, so the best n asks are always updated
+                        if ask_changes_best_n(&ask, &last_ask, best_asks_full) || ranker.is_some() {
                             update_best_asks = true;
                         }
                         asks.lock().await.update_asks(ask, max_order_book_depth);
                     }
 
+                    //Evict the least recently updated asks if the order books sharing the level cap hold more levels than the cap
+                    if let Some(level_cap) = &level_cap {
+                        let mut asks = asks.lock().await;
+                        let excess = level_cap.track(&mut capped_asks, asks.num_asks());
+                        if excess > 0 {
+                            let removed = asks.evict_asks(excess);
+                            level_cap.track(&mut capped_asks, asks.num_asks());
+                            tracing::warn!(
+                                "Evicted {removed} asks over the level cap of {}",
+                                level_cap.max_levels()
+                            );
+                            events.publish(ServiceEventKind::LevelCapExceeded);
+                            update_best_asks = true;
+                        }
+                    }
+
                     //If the ask is better than the "worst" ask in the top asks, update the best n bids
                     if update_best_asks {
-                        let mut best_asks = asks.lock().await.get_best_n_asks(best_n_orders);
-
-                        if best_asks[0].is_some() {
-                            let mut best_n_levels = vec![];
-
-                            //Get the best "n" asks and add the level to the best n levels
-                            let mut last_ask = 0;
-                            for ask_option in best_asks.iter() {
-                                if let Some(ask) = ask_option {
-                                    best_n_levels.push(Level {
-                                        price: ask.price.0,
-                                        amount: ask.quantity.0,
-                                        exchange: ask.exchange.to_string(),
-                                    });
-
-                                    last_ask += 1;
-                                } else {
-                                    break;
-                                }
-                            }
+                        let mut best_asks = match &ranker {
+                            Some(ranker) => asks
+                                .lock()
+                                .await
+                                .get_top_asks_ranked(best_n_orders, ranker.as_ref()),
+                            None => asks.lock().await.get_top_asks(best_n_orders),
+                        };
 
-                            //Return the best levels, the first ask, and the last ask
-                            Some((
-                                best_n_levels,
-                                best_asks[0].take().unwrap().price.0,
-                                best_asks[last_ask - 1].take().unwrap(),
-                            ))
+                        //Get the best "n" asks as the best n levels, holding fewer than n levels when the order book holds fewer than n asks
+                        let best_n_levels = best_asks.iter().map(ask_level).collect::<Vec<_>>();
+
+                        //The worst of the best asks is tracked to check whether later asks change the best n.
+                        //There is no worst ask if the order book has no asks, or no asks are tracked
+                        if let Some(worst_ask) = best_asks.pop() {
+                            let top_ask_price = best_n_levels[0].price;
+
+                            //Return the best levels, the first ask price, and the worst ask
+                            Some((best_n_levels, top_ask_price, worst_ask))
                         } else {
-                            tracing::error!("No asks in aggregated order book");
-                            None
+                            //Clearing or evicting every ask empties the best n, so that the removed levels are not republished
+                            tracing::warn!("No asks in aggregated order book");
+                            Some((vec![], f64::MAX, Ask::default()))
                         }
                     } else {
                         None
@@ -229,6 +1650,18 @@ where
                 //Join the futures so that the bids and asks can be updated concurrently
                 let (updated_bids, updated_asks) = tokio::join!(bids_fut, asks_fut);
 
+                //Check that no exchange has two levels at the same price, which would double count its liquidity
+                #[cfg(debug_assertions)]
+                {
+                    let duplicate_bids = bids.lock().await.duplicate_bids();
+                    let duplicate_asks = asks.lock().await.duplicate_asks();
+                    if !duplicate_bids.is_empty() || !duplicate_asks.is_empty() {
+                        tracing::error!(
+                            "Duplicate price levels in the aggregated order book, bids: {duplicate_bids:?}, asks: {duplicate_asks:?}"
+                        );
+                    }
+                }
+
                 //Update the best n bids and asks if they have been updated
                 if let Some((best_bids, top_bid_price, last)) = updated_bids {
                     best_n_bids = best_bids;
@@ -243,24 +1676,158 @@ where
                     last_ask = last;
                 }
 
-                //Calculate the bid-ask spread and send the updated summary to the gRPC server
-                let bid_ask_spread = best_ask_price - best_bid_price;
+                //Calculate the bid-ask spread and send the updated summary to the gRPC server.
+                //The spread is only set once there is a bid and an ask, since the best price of an empty side is a sentinel
+                let bid_ask_spread = (!best_n_bids.is_empty() && !best_n_asks.is_empty())
+                    .then_some(best_ask_price - best_bid_price);
 
                 tracing::info!(
-                    "Best bid price: {best_bid_price:?}, best ask price: {best_ask_price:?}, spread: {bid_ask_spread:?}"
+                    pair = %pair_name,
+                    best_bid_price,
+                    best_ask_price,
+                    spread = bid_ask_spread,
+                    "Updated best bid and ask"
                 );
+                if let (Some(metrics), Some(bid_ask_spread)) = (&metrics, bid_ask_spread) {
+                    metrics.set_spread(&pair_name, bid_ask_spread);
+                }
+
+                //A crossed book usually means a stale level on one exchange or a missed update, so the summary is flagged rather than silently publishing a negative spread
+                let crossed = bid_ask_spread.is_some_and(|spread| spread < 0.0);
+                if crossed {
+                    tracing::warn!(
+                        pair = %pair_name,
+                        best_bid_price,
+                        best_bid_exchange = best_n_bids.first().map_or("", |bid| bid.exchange.as_str()),
+                        best_ask_price,
+                        best_ask_exchange = best_n_asks.first().map_or("", |ask| ask.exchange.as_str()),
+                        spread = bid_ask_spread,
+                        "Crossed order book"
+                    );
+                }
+
+                //Get the best bid and ask from each exchange, skipping exchanges without any levels
+                let mut exchange_quotes = vec![];
+                let mut exchange_mids = vec![];
+                {
+                    let bids = bids.lock().await;
+                    let asks = asks.lock().await;
+                    for exchange in exchanges.iter() {
+                        let bid_price = bids.get_best_exchange_bid(exchange).map(|bid| bid.price.0);
+                        let ask_price = asks.get_best_exchange_ask(exchange).map(|ask| ask.price.0);
+
+                        //Exchanges quoting both sides contribute their mid to the weighted mid, aged by their last update
+                        if let (Some(bid_price), Some(ask_price), Some(updated)) =
+                            (bid_price, ask_price, exchange_updated.get(exchange))
+                        {
+                            exchange_mids.push(((bid_price + ask_price) / 2.0, updated.elapsed()));
+                        }
+
+                        if bid_price.is_some() || ask_price.is_some() {
+                            exchange_quotes.push(ExchangeQuote {
+                                exchange: exchange.to_string(),
+                                bid_price,
+                                ask_price,
+                            });
+                        }
+                    }
+                }
+
+                //Levels at the same price are merged before limiting the levels, so that each emitted level is a distinct price
+                let emitted_levels = |levels: &[Level]| {
+                    if merge_price_levels {
+                        let mut levels = merge_levels_by_price(levels.iter().cloned());
+                        levels.truncate(emit_levels);
+                        levels
+                    } else {
+                        levels.iter().take(emit_levels).cloned().collect()
+                    }
+                };
 
                 let summary = Summary {
                     spread: bid_ask_spread,
-                    bids: best_n_bids.clone(),
-                    asks: best_n_asks.clone(),
+                    bids: emitted_levels(&best_n_bids),
+                    asks: emitted_levels(&best_n_asks),
+                    total_notional_bids: bids.lock().await.total_notional_bids(),
+                    total_notional_asks: asks.lock().await.total_notional_asks(),
+                    exchange_quotes,
+                    heartbeat: false,
+                    weighted_mid: weighted_mid(exchange_mids.into_iter(), mid_decay_half_life),
+                    snapshot: false,
+                    schema_version: SUMMARY_SCHEMA_VERSION,
+                    stale: false,
+                    crossed,
+                    as_of,
+                    //Computed from every tracked best level rather than the emitted levels, so that the signals do not depend on how many levels clients receive
+                    imbalance: imbalance(&best_n_bids, &best_n_asks),
+                    microprice: microprice(&best_n_bids, &best_n_asks),
+                };
+
+                //Replace the summary with every level in the order book once the snapshot interval has been reached
+                updates_since_snapshot += 1;
+                let mut summary = match snapshot_interval {
+                    Some(snapshot_interval) if updates_since_snapshot >= snapshot_interval => {
+                        updates_since_snapshot = 0;
+
+                        let bids = bids.lock().await;
+                        let asks = asks.lock().await;
+                        let depth = bids.num_bids().max(asks.num_asks());
+                        let mut full_depth = build_summary(&*bids, &*asks, depth);
+                        if merge_price_levels {
+                            full_depth.bids = merge_levels_by_price(full_depth.bids);
+                            full_depth.asks = merge_levels_by_price(full_depth.asks);
+                        }
+                        Summary {
+                            bids: full_depth.bids,
+                            asks: full_depth.asks,
+                            snapshot: true,
+                            ..summary
+                        }
+                    }
+                    _ => summary,
                 };
 
-                tracing::info!("Publishing summary: {:?}", summary);
+                //Levels from different exchanges are interleaved in the best n, so the emitted levels are sorted by price before publishing
+                if sort_by_price {
+                    sort_summary_levels(&mut summary);
+                    debug_assert!(
+                        summary_is_ordered(&summary),
+                        "Summary levels are out of order: {summary:?}"
+                    );
+                }
+
+                //Skip publishing if nothing meaningful has changed since the last published summary. Snapshots are always published
+                if let (Some(epsilon), false) = (publish_on_change_epsilon, summary.snapshot) {
+                    if let Some(last_summary) = &last_summary {
+                        if !summary_changed(last_summary, &summary, epsilon) {
+                            tracing::debug!("Summary unchanged, skipping publish");
+                            continue;
+                        }
+                    }
+                    last_summary = Some(summary.clone());
+                }
+
+                //Summaries are not published while every exchange is down, since the order book can no longer be trusted
+                if all_down && withhold_when_down {
+                    tracing::debug!("Every exchange is down, skipping publish");
+                    continue;
+                }
+
+                //Hold the summary until the next tick when the publish rate is capped, replacing any summary that has not been published yet.
+                //Snapshots are published immediately so that consumers can resync, which also supersedes the held summary
+                if publish_tick.is_some() && !summary.snapshot {
+                    pending_summary = Some((summary, received_at));
+                    continue;
+                }
+                pending_summary = None;
 
-                summary_tx
-                    .send(summary)
-                    .map_err(OrderBookError::SummarySendError)?;
+                publish_summary(
+                    summary,
+                    received_at,
+                    &mut heartbeat,
+                    &mut heartbeat_summary,
+                    &mut latest_summary,
+                );
             }
 
             Ok::<(), BidAskServiceError>(())
@@ -270,19 +1837,101 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
-    use std::sync::atomic::AtomicU32;
-    use std::sync::atomic::Ordering;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::time::Duration;
+
     use std::sync::Arc;
 
-    use futures::FutureExt;
+    use tokio::task::JoinHandle;
 
-    use crate::error::BidAskServiceError;
+    use crate::error::{flatten_task_result, BidAskServiceError};
+    use crate::exchanges::services::shutdown_requested;
+    use crate::metrics::Metrics;
+    use crate::order_book::btree_map::LevelKey;
+    use crate::order_book::error::OrderBookError;
+    use crate::order_book::hashmap::HashMapOrderBook;
+    use crate::order_book::level_cap::LevelCap;
+    use crate::order_book::ranker::{
+        DefaultRanker, ExchangePreference, LevelRanker, MostRecentTieBreak, OrderPriority,
+        RankedLevel, TieBreak,
+    };
+    use crate::order_book::tick_set::TickSet;
+    use crate::order_book::AllExchangesDownBehavior;
     use crate::order_book::Ask;
     use crate::order_book::Bid;
+    use crate::order_book::DepthConfig;
+    use crate::order_book::PriceLevelUpdate;
+    use crate::order_book::QuantitySemantics;
+    use crate::order_book::{ask_changes_best_n, bid_changes_best_n};
+    use crate::order_book::{imbalance, microprice};
+    use crate::order_book::{BuySide, SellSide};
+    use crate::order_book::{BuySideView, SellSideView};
+    use crate::order_book::{OrderType, Quote};
+    use crate::server::orderbook_service::{ExchangeId, ExchangeQuote, Level, Side, Summary};
+    use crate::server::SUMMARY_SCHEMA_VERSION;
     use crate::{exchanges::Exchange, order_book::AggregatedOrderBook};
+
+    //Receive the warming summary published when the aggregation loop starts, before any price levels are handled
+    async fn skip_warming_summary(summary_rx: &mut tokio::sync::broadcast::Receiver<Summary>) {
+        let summary = summary_rx
+            .recv()
+            .await
+            .expect("Could not receive warming summary");
+        assert_eq!(
+            summary,
+            Summary {
+                schema_version: SUMMARY_SCHEMA_VERSION,
+                ..Default::default()
+            }
+        );
+    }
+
+    //An eth/btc aggregated order book of Bitstamp and Binance backed by ordered sets, to be configured by each test
+    fn test_order_book() -> AggregatedOrderBook<BTreeSet<Bid>, BTreeSet<Ask>> {
+        AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Bitstamp, Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        )
+    }
+
+    //Spawn the aggregation loop with a depth of 10 and the best n orders, returning the price level sender and the summary receiver after the warming summary
+    async fn spawn_test_loop<B, S>(
+        aggregated_order_book: &AggregatedOrderBook<B, S>,
+        best_n_orders: usize,
+    ) -> (
+        tokio::sync::mpsc::Sender<PriceLevelUpdate>,
+        tokio::sync::broadcast::Receiver<Summary>,
+        JoinHandle<Result<(), BidAskServiceError>>,
+    )
+    where
+        B: BuySide + Send + 'static,
+        S: SellSide + Send + 'static,
+    {
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+        let handle = aggregated_order_book.handle_order_book_updates(
+            price_level_rx,
+            10,
+            best_n_orders,
+            summary_tx,
+        );
+        skip_warming_summary(&mut summary_rx).await;
+
+        (price_level_tx, summary_rx, handle)
+    }
+
+    #[cfg(feature = "exchanges")]
     #[tokio::test]
     async fn test_bid_ask_service() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        use futures::FutureExt;
+
+        use crate::error::BidAskServiceError;
+
         let atomic_counter_0 = Arc::new(AtomicU32::new(0));
         let atomic_counter_1 = atomic_counter_0.clone();
         let target_counter = 50;
@@ -298,10 +1947,16 @@ mod tests {
 
         let (tx, mut rx) = tokio::sync::broadcast::channel(100);
 
-        let mut join_handles = aggregated_order_book.spawn_bid_ask_service(10, 1000, 100, 20, tx);
+        let mut join_handles = aggregated_order_book.spawn_bid_ask_service(
+            DepthConfig::uniform(10),
+            1000,
+            100,
+            20,
+            tx,
+        );
 
         let summary_handle = tokio::spawn(async move {
-            while let Ok(_) = rx.recv().await {
+            while rx.recv().await.is_ok() {
                 dbg!(atomic_counter_0.load(Ordering::Relaxed));
                 atomic_counter_0.fetch_add(1, Ordering::Relaxed);
                 if atomic_counter_0.load(Ordering::Relaxed) >= target_counter {
@@ -330,4 +1985,2558 @@ mod tests {
             panic!("Unexpected error");
         }
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_level_max_age_eviction() {
+        let aggregated_order_book = test_order_book().with_level_max_age(Duration::from_secs(10));
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Bitstamp),
+                    Bid::new(98.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Bitstamp),
+                    Ask::new(103.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 3);
+        assert_eq!(summary.asks.len(), 3);
+
+        //Refresh the Binance levels, leaving the Bitstamp levels to age
+        tokio::time::advance(Duration::from_secs(6)).await;
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 2.0, Exchange::Binance),
+                    Bid::new(98.0, 2.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 2.0, Exchange::Binance),
+                    Ask::new(103.0, 2.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 3);
+        assert_eq!(summary.bids[0].age_ms, 0);
+        assert_eq!(summary.bids[1].age_ms, 6000);
+
+        //Advance past the max age of the Bitstamp levels, which should be evicted on the next update
+        tokio::time::advance(Duration::from_secs(6)).await;
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 3.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 2);
+        assert_eq!(summary.asks.len(), 2);
+        for level in summary.bids.iter().chain(summary.asks.iter()) {
+            assert_eq!(level.exchange, Exchange::Binance.to_string());
+        }
+        assert_eq!(summary.asks[0].age_ms, 6000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_level_max_age_evicts_every_bid() {
+        let aggregated_order_book = test_order_book().with_level_max_age(Duration::from_secs(10));
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![Bid::new(100.0, 1.0, Exchange::Bitstamp)],
+                vec![Ask::new(101.0, 1.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 1);
+
+        //Only the asks are refreshed once the bid has gone stale, so every bid is evicted
+        for quantity in [2.0, 3.0] {
+            tokio::time::advance(Duration::from_secs(11)).await;
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    Exchange::Binance,
+                    vec![],
+                    vec![Ask::new(101.5, quantity, Exchange::Binance)],
+                ))
+                .await
+                .expect("Could not send price level update");
+
+            //The evicted bid should not be republished by this or any later summary
+            let summary = summary_rx.recv().await.expect("Could not receive summary");
+            assert!(summary.bids.is_empty());
+            assert_eq!(summary.asks.len(), 1);
+            assert_eq!(summary.asks[0].amount, quantity);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_total_notional() {
+        let aggregated_order_book = test_order_book();
+
+        {
+            let mut bids = aggregated_order_book.bids.lock().await;
+            bids.update_bids(Bid::new(100.0, 2.0, Exchange::Binance), 10);
+            bids.update_bids(Bid::new(99.5, 4.0, Exchange::Bitstamp), 10);
+            bids.update_bids(Bid::new(99.0, 0.5, Exchange::Binance), 10);
+
+            let mut asks = aggregated_order_book.asks.lock().await;
+            asks.update_asks(Ask::new(101.0, 1.5, Exchange::Binance), 10);
+            asks.update_asks(Ask::new(102.0, 3.0, Exchange::Bitstamp), 10);
+            asks.update_asks(Ask::new(103.25, 2.0, Exchange::Binance), 10);
+        }
+
+        //100 * 2 + 99.5 * 4 + 99 * 0.5
+        assert_eq!(aggregated_order_book.total_notional_bids().await, 647.5);
+        //101 * 1.5 + 102 * 3 + 103.25 * 2
+        assert_eq!(aggregated_order_book.total_notional_asks().await, 664.0);
+
+        let empty_order_book = test_order_book();
+
+        assert_eq!(empty_order_book.total_notional_bids().await, 0.0);
+        assert_eq!(empty_order_book.total_notional_asks().await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_quote() {
+        let aggregated_order_book = test_order_book();
+
+        //An empty book cannot be quoted
+        assert_eq!(aggregated_order_book.quote(OrderType::Ask, 1.0).await, None);
+
+        {
+            let mut bids = aggregated_order_book.bids.lock().await;
+            bids.update_bids(Bid::new(100.0, 1.0, Exchange::Binance), 10);
+            bids.update_bids(Bid::new(99.0, 2.0, Exchange::Bitstamp), 10);
+            bids.update_bids(Bid::new(98.0, 4.0, Exchange::Binance), 10);
+
+            let mut asks = aggregated_order_book.asks.lock().await;
+            asks.update_asks(Ask::new(101.0, 1.0, Exchange::Binance), 10);
+            asks.update_asks(Ask::new(102.0, 2.0, Exchange::Bitstamp), 10);
+            asks.update_asks(Ask::new(104.0, 4.0, Exchange::Binance), 10);
+        }
+
+        //Buying 4 consumes the asks at 101 and 102, and 1 of the ask at 104, ie. (101 + 102 * 2 + 104) / 4
+        assert_eq!(
+            aggregated_order_book.quote(OrderType::Ask, 4.0).await,
+            Some(Quote {
+                avg_price: 102.25,
+                filled_quantity: 4.0,
+                levels_consumed: 3,
+                exhausted: false,
+            })
+        );
+
+        //Selling 2 consumes the bid at 100 and 1 of the bid at 99
+        assert_eq!(
+            aggregated_order_book.quote(OrderType::Bid, 2.0).await,
+            Some(Quote {
+                avg_price: 99.5,
+                filled_quantity: 2.0,
+                levels_consumed: 2,
+                exhausted: false,
+            })
+        );
+
+        //Selling more than the bids hold fills every bid, ie. (100 + 99 * 2 + 98 * 4) / 7
+        assert_eq!(
+            aggregated_order_book.quote(OrderType::Bid, 10.0).await,
+            Some(Quote {
+                avg_price: 690.0 / 7.0,
+                filled_quantity: 7.0,
+                levels_consumed: 3,
+                exhausted: true,
+            })
+        );
+
+        assert_eq!(aggregated_order_book.quote(OrderType::Ask, 0.0).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_depth_curve() {
+        let aggregated_order_book = test_order_book();
+
+        //An empty book has no points on either side
+        assert_eq!(
+            aggregated_order_book.depth_curve(10).await,
+            (vec![], vec![])
+        );
+
+        {
+            let mut bids = aggregated_order_book.bids.lock().await;
+            bids.update_bids(Bid::new(100.0, 1.0, Exchange::Binance), 10);
+            bids.update_bids(Bid::new(100.0, 0.5, Exchange::Bitstamp), 10);
+            bids.update_bids(Bid::new(99.0, 2.0, Exchange::Bitstamp), 10);
+            bids.update_bids(Bid::new(98.0, 4.0, Exchange::Binance), 10);
+        }
+
+        //The bids at 100 from both exchanges are one point, and the asks are still empty
+        let (bid_curve, ask_curve) = aggregated_order_book.depth_curve(10).await;
+        assert_eq!(bid_curve, vec![(100.0, 1.5), (99.0, 3.5), (98.0, 7.5)]);
+        assert!(ask_curve.is_empty());
+
+        {
+            let mut asks = aggregated_order_book.asks.lock().await;
+            asks.update_asks(Ask::new(101.0, 1.0, Exchange::Binance), 10);
+            asks.update_asks(Ask::new(102.0, 2.0, Exchange::Bitstamp), 10);
+            asks.update_asks(Ask::new(104.0, 4.0, Exchange::Binance), 10);
+        }
+
+        //Each side walks outward from the best price, with the cumulative quantity increasing at each point
+        let (bid_curve, ask_curve) = aggregated_order_book.depth_curve(2).await;
+        assert_eq!(bid_curve, vec![(100.0, 1.5), (99.0, 3.5)]);
+        assert_eq!(ask_curve, vec![(101.0, 1.0), (102.0, 3.0)]);
+
+        let (bid_curve, ask_curve) = aggregated_order_book.depth_curve(10).await;
+        assert_eq!(ask_curve, vec![(101.0, 1.0), (102.0, 3.0), (104.0, 7.0)]);
+        for curve in [&bid_curve, &ask_curve] {
+            assert!(curve.windows(2).all(|points| points[1].1 > points[0].1));
+        }
+        assert!(bid_curve.windows(2).all(|points| points[1].0 < points[0].0));
+        assert!(ask_curve.windows(2).all(|points| points[1].0 > points[0].0));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let aggregated_order_book = test_order_book();
+
+        //The spread is not set until there are levels on both sides of the order book
+        let summary = aggregated_order_book.snapshot(5).await;
+        assert!(summary.bids.is_empty());
+        assert!(summary.asks.is_empty());
+        assert_eq!(summary.spread, None);
+
+        {
+            let mut bids = aggregated_order_book.bids.lock().await;
+            for (i, price) in [100.0, 99.5, 99.0, 98.5, 98.0, 97.5]
+                .into_iter()
+                .enumerate()
+            {
+                let exchange = if i % 2 == 0 {
+                    Exchange::Binance
+                } else {
+                    Exchange::Bitstamp
+                };
+                bids.update_bids(Bid::new(price, 1.0, exchange), 10);
+            }
+
+            let mut asks = aggregated_order_book.asks.lock().await;
+            for (i, price) in [101.0, 101.5, 102.0, 102.5, 103.0, 103.5]
+                .into_iter()
+                .enumerate()
+            {
+                let exchange = if i % 2 == 0 {
+                    Exchange::Bitstamp
+                } else {
+                    Exchange::Binance
+                };
+                asks.update_asks(Ask::new(price, 2.0, exchange), 10);
+            }
+        }
+
+        //The snapshot holds the best five levels of each side, without waiting for a summary to be published
+        let summary = aggregated_order_book.snapshot(5).await;
+        assert_eq!(
+            summary
+                .bids
+                .iter()
+                .map(|bid| (bid.price, bid.exchange.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (100.0, "binance"),
+                (99.5, "bitstamp"),
+                (99.0, "binance"),
+                (98.5, "bitstamp"),
+                (98.0, "binance")
+            ]
+        );
+        assert_eq!(
+            summary
+                .asks
+                .iter()
+                .map(|ask| (ask.price, ask.exchange.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (101.0, "bitstamp"),
+                (101.5, "binance"),
+                (102.0, "bitstamp"),
+                (102.5, "binance"),
+                (103.0, "bitstamp")
+            ]
+        );
+        assert_eq!(summary.spread, Some(1.0));
+        assert_eq!(
+            summary.total_notional_bids,
+            aggregated_order_book.total_notional_bids().await
+        );
+        assert_eq!(summary.schema_version, SUMMARY_SCHEMA_VERSION);
+    }
+
+    //Captures formatted log output in memory
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crossed_book() {
+        //The test runtime is single threaded, so the aggregation loop logs to the default subscriber of this thread
+        let captured_logs = CapturedLogs::default();
+        let writer = captured_logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::WARN)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Bitstamp),
+                    Bid::new(99.0, 1.0, Exchange::Bitstamp),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Bitstamp),
+                    Ask::new(102.0, 1.0, Exchange::Bitstamp),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.spread, Some(1.0));
+        assert!(!summary.crossed);
+
+        //A Binance bid above the best Bitstamp ask crosses the book
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(101.5, 1.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.spread, Some(-0.5));
+        assert!(summary.crossed);
+
+        let logs = String::from_utf8(captured_logs.0.lock().unwrap().clone())
+            .expect("Logs are not valid utf8");
+        assert!(logs.contains("Crossed order book"));
+        for field in [
+            "best_bid_price=101.5",
+            r#"best_bid_exchange="binance""#,
+            "best_ask_price=101.0",
+            r#"best_ask_exchange="bitstamp""#,
+            "spread=-0.5",
+        ] {
+            assert!(logs.contains(field), "Missing {field} in {logs}");
+        }
+
+        //The snapshot of the order book is also flagged as crossed
+        assert!(aggregated_order_book.snapshot(5).await.crossed);
+    }
+
+    //Ranks every level below the levels of all other exchanges if it is from the demoted exchange
+    #[derive(Debug)]
+    struct DemoteExchangeRanker(Exchange);
+
+    impl LevelRanker for DemoteExchangeRanker {
+        fn rank(&self, level: RankedLevel) -> OrderPriority {
+            let mut priority = DefaultRanker.rank(level);
+            if *level.exchange() == self.0 {
+                priority.tier -= 1;
+            }
+            priority
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_ranker() {
+        let aggregated_order_book =
+            test_order_book().with_ranker(Box::new(DemoteExchangeRanker(Exchange::Bitstamp)));
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 3).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Bitstamp),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                    Bid::new(98.0, 1.0, Exchange::Binance),
+                    Bid::new(97.0, 1.0, Exchange::Bitstamp),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Bitstamp),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                    Ask::new(103.0, 1.0, Exchange::Bitstamp),
+                    Ask::new(104.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+
+        //The Bitstamp levels should be ranked after the Binance levels, despite having better prices
+        let bid_prices = summary.bids.iter().map(|l| l.price).collect::<Vec<_>>();
+        let ask_prices = summary.asks.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(bid_prices, vec![99.0, 98.0, 100.0]);
+        assert_eq!(ask_prices, vec![102.0, 104.0, 101.0]);
+        assert_eq!(summary.spread, Some(3.0));
+
+        //The default ranker should match the order book's ordering
+        let bids = aggregated_order_book.bids.lock().await;
+        assert_eq!(
+            bids.get_best_n_bids_ranked(5, &DefaultRanker),
+            bids.get_best_n_bids(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_clears_exchange_levels() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        for exchange in [Exchange::Binance, Exchange::Bitstamp] {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    exchange.clone(),
+                    vec![
+                        Bid::new(100.0, 1.0, exchange.clone()),
+                        Bid::new(99.0, 1.0, exchange.clone()),
+                    ],
+                    vec![
+                        Ask::new(101.0, 1.0, exchange.clone()),
+                        Ask::new(102.0, 1.0, exchange.clone()),
+                    ],
+                ))
+                .await
+                .expect("Could not send price level update");
+            summary_rx.recv().await.expect("Could not receive summary");
+        }
+
+        //A snapshot from Bitstamp should replace all of the Bitstamp levels, leaving the Binance levels untouched
+        price_level_tx
+            .send(PriceLevelUpdate::snapshot(
+                Exchange::Bitstamp,
+                vec![Bid::new(98.0, 2.0, Exchange::Bitstamp)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let bids = summary
+            .bids
+            .iter()
+            .map(|l| (l.price, l.exchange.as_str()))
+            .collect::<Vec<_>>();
+        let asks = summary
+            .asks
+            .iter()
+            .map(|l| (l.price, l.exchange.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            bids,
+            vec![(100.0, "binance"), (99.0, "binance"), (98.0, "bitstamp")]
+        );
+        assert_eq!(asks, vec![(101.0, "binance"), (102.0, "binance")]);
+    }
+
+    #[tokio::test]
+    async fn test_summary_callback() {
+        let published = Arc::new(std::sync::Mutex::new(vec![]));
+        let callback_published = published.clone();
+
+        let aggregated_order_book =
+            test_order_book().with_summary_callback(move |summary: &Summary| {
+                callback_published.lock().unwrap().push(summary.spread);
+            });
+
+        //Drop the summary receiver so that summaries are only consumed through the callback
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, _) = tokio::sync::broadcast::channel(10);
+        let handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 10, summary_tx);
+
+        for price_level_update in [
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ),
+            PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![Bid::new(100.5, 1.0, Exchange::Bitstamp)],
+                vec![],
+            ),
+            PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![],
+                vec![Ask::new(100.75, 1.0, Exchange::Bitstamp)],
+            ),
+        ] {
+            price_level_tx
+                .send(price_level_update)
+                .await
+                .expect("Could not send price level update");
+        }
+
+        //Close the channel so that the aggregation loop exits once every update is handled
+        drop(price_level_tx);
+        handle
+            .await
+            .expect("Join handle error")
+            .expect("Aggregation loop failed");
+
+        //The warming summary published on start has no spread
+        assert_eq!(
+            *published.lock().unwrap(),
+            vec![None, Some(1.0), Some(0.5), Some(0.25)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_recency_tie_break() {
+        let aggregated_order_book = test_order_book().with_recency_tie_break();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Bitstamp),
+                    Bid::new(99.0, 1.0, Exchange::Bitstamp),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Bitstamp),
+                    Ask::new(102.0, 1.0, Exchange::Bitstamp),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Binance matches the Bitstamp levels at the touch a second later
+        tokio::time::advance(Duration::from_secs(1)).await;
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        //The newer Binance levels should be chosen at the touch
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids[0].exchange, Exchange::Binance.to_string());
+        assert_eq!(summary.bids[0].age_ms, 0);
+        assert_eq!(summary.bids[1].exchange, Exchange::Bitstamp.to_string());
+        assert_eq!(summary.bids[1].price, 100.0);
+        assert_eq!(summary.asks[0].exchange, Exchange::Binance.to_string());
+        assert_eq!(summary.asks[1].exchange, Exchange::Bitstamp.to_string());
+        assert_eq!(summary.asks[1].price, 101.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tie_break() {
+        let policies: Vec<(Box<dyn TieBreak>, Exchange)> = vec![
+            (
+                Box::new(ExchangePreference(vec![
+                    Exchange::Bitstamp,
+                    Exchange::Binance,
+                ])),
+                Exchange::Bitstamp,
+            ),
+            (
+                Box::new(ExchangePreference(vec![Exchange::Binance])),
+                Exchange::Binance,
+            ),
+            (Box::new(MostRecentTieBreak), Exchange::Binance),
+        ];
+
+        for (tie_break, preferred) in policies {
+            let aggregated_order_book = AggregatedOrderBook::new(
+                ["eth", "btc"],
+                vec![Exchange::Bitstamp, Exchange::Binance],
+                BTreeSet::<Bid>::new(),
+                BTreeSet::<Ask>::new(),
+            )
+            .with_tie_break(tie_break);
+
+            let (price_level_tx, mut summary_rx, _handle) =
+                spawn_test_loop(&aggregated_order_book, 10).await;
+
+            //Bitstamp's levels are updated first, then Binance matches them at the touch a second later
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    Exchange::Bitstamp,
+                    vec![
+                        Bid::new(100.0, 1.0, Exchange::Bitstamp),
+                        Bid::new(99.0, 1.0, Exchange::Bitstamp),
+                    ],
+                    vec![
+                        Ask::new(101.0, 1.0, Exchange::Bitstamp),
+                        Ask::new(102.0, 1.0, Exchange::Bitstamp),
+                    ],
+                ))
+                .await
+                .expect("Could not send price level update");
+            summary_rx.recv().await.expect("Could not receive summary");
+
+            tokio::time::advance(Duration::from_secs(1)).await;
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    Exchange::Binance,
+                    vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                    vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+                ))
+                .await
+                .expect("Could not send price level update");
+
+            let summary = summary_rx.recv().await.expect("Could not receive summary");
+
+            //The preferred exchange's levels should be chosen at the touch, with the other exchange's equal levels next
+            assert_eq!(summary.bids[0].exchange, preferred.to_string());
+            assert_eq!(summary.bids[1].price, 100.0);
+            assert_ne!(summary.bids[1].exchange, preferred.to_string());
+            assert_eq!(summary.asks[0].exchange, preferred.to_string());
+            assert_eq!(summary.asks[1].price, 101.0);
+            assert_ne!(summary.asks[1].exchange, preferred.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_as_of() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        //The summary is as of the newest exchange timestamp, so an exchange that lags behind does not move it backwards,
+        //and an update without a timestamp keeps the last timestamp
+        for (price_level_update, expected_as_of) in [
+            (
+                PriceLevelUpdate::new(
+                    Exchange::Binance,
+                    vec![
+                        Bid::new(100.0, 1.0, Exchange::Binance),
+                        Bid::new(99.0, 1.0, Exchange::Binance),
+                    ],
+                    vec![
+                        Ask::new(101.0, 1.0, Exchange::Binance),
+                        Ask::new(102.0, 1.0, Exchange::Binance),
+                    ],
+                )
+                .with_exchange_timestamp(2_000_000),
+                Some(2_000_000),
+            ),
+            (
+                PriceLevelUpdate::new(
+                    Exchange::Bitstamp,
+                    vec![Bid::new(99.5, 1.0, Exchange::Bitstamp)],
+                    vec![],
+                )
+                .with_exchange_timestamp(1_000_000),
+                Some(2_000_000),
+            ),
+            (
+                PriceLevelUpdate::new(
+                    Exchange::Bitstamp,
+                    vec![],
+                    vec![Ask::new(101.5, 1.0, Exchange::Bitstamp)],
+                ),
+                Some(2_000_000),
+            ),
+            (
+                PriceLevelUpdate::new(
+                    Exchange::Bitstamp,
+                    vec![Bid::new(99.6, 1.0, Exchange::Bitstamp)],
+                    vec![],
+                )
+                .with_exchange_timestamp(3_000_000),
+                Some(3_000_000),
+            ),
+        ] {
+            price_level_tx
+                .send(price_level_update)
+                .await
+                .expect("Could not send price level update");
+            let summary = summary_rx.recv().await.expect("Could not receive summary");
+            assert_eq!(summary.as_of, expected_as_of);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_imbalance_and_microprice() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 5.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 3.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+
+        //6 bid volume against 4 ask volume, and the best bid is weighted by the 3 quantity of the best ask
+        assert!((summary.imbalance - 0.2).abs() < 1e-9);
+        assert!((summary.microprice - (100.0 * 3.0 + 101.0 * 1.0) / 4.0).abs() < 1e-9);
+
+        //A larger best bid quantity moves the microprice towards the best ask
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![Bid::new(100.0, 5.0, Exchange::Bitstamp)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!((summary.imbalance - (11.0 - 4.0) / 15.0).abs() < 1e-9);
+        assert!(summary.microprice > 100.5 && summary.microprice < 101.0);
+    }
+
+    #[test]
+    fn test_imbalance_and_microprice_empty_book() {
+        let level = |price: f64, amount: f64| Level {
+            price,
+            amount,
+            ..Default::default()
+        };
+
+        //Without volume on both sides, the signals are 0.0 rather than NaN
+        assert_eq!(imbalance(&[], &[]), 0.0);
+        assert_eq!(microprice(&[], &[]), 0.0);
+        assert_eq!(microprice(&[level(100.0, 1.0)], &[]), 0.0);
+        assert_eq!(microprice(&[level(100.0, 0.0)], &[level(101.0, 0.0)]), 0.0);
+
+        //A book with only bids is fully imbalanced towards the bids
+        assert_eq!(imbalance(&[level(100.0, 1.0)], &[]), 1.0);
+        assert_eq!(imbalance(&[], &[level(101.0, 1.0)]), -1.0);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_quotes() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Bitstamp only has bids, below the Binance touch
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![
+                    Bid::new(99.5, 1.0, Exchange::Bitstamp),
+                    Bid::new(98.0, 1.0, Exchange::Bitstamp),
+                ],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(
+            summary.exchange_quotes,
+            vec![
+                ExchangeQuote {
+                    exchange: Exchange::Bitstamp.to_string(),
+                    bid_price: Some(99.5),
+                    ask_price: None,
+                },
+                ExchangeQuote {
+                    exchange: Exchange::Binance.to_string(),
+                    bid_price: Some(100.0),
+                    ask_price: Some(101.0),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delta_quantity_semantics() {
+        let aggregated_order_book =
+            test_order_book().with_quantity_semantics(Exchange::Binance, QuantitySemantics::Delta);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        let quantities = |levels: &[Level]| {
+            levels
+                .iter()
+                .map(|l| (l.price, l.amount))
+                .collect::<Vec<_>>()
+        };
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 2.0, Exchange::Binance),
+                    Bid::new(98.0, 2.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 2.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Deltas should accumulate onto the existing quantities
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 0.5, Exchange::Binance),
+                    Bid::new(99.0, -0.5, Exchange::Binance),
+                ],
+                vec![Ask::new(101.0, 2.5, Exchange::Binance)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(
+            quantities(&summary.bids),
+            vec![(100.0, 1.5), (99.0, 1.5), (98.0, 2.0)]
+        );
+        assert_eq!(quantities(&summary.asks), vec![(101.0, 3.5), (102.0, 2.0)]);
+
+        //A delta that removes the full quantity should remove the level
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, -1.5, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(quantities(&summary.bids), vec![(99.0, 1.5), (98.0, 2.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_publish_on_change() {
+        let aggregated_order_book = test_order_book().with_publish_on_change(1e-9);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 2).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                    Bid::new(98.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                    Ask::new(103.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Updates below the best 2 levels and a repeat of the same top levels do not change the summary
+        for price_level_update in [
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(98.0, 5.0, Exchange::Binance)],
+                vec![Ask::new(103.0, 5.0, Exchange::Binance)],
+            ),
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+            ),
+            //Moving the touch should be published
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.5, 1.0, Exchange::Binance)],
+                vec![],
+            ),
+        ] {
+            price_level_tx
+                .send(price_level_update)
+                .await
+                .expect("Could not send price level update");
+        }
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.spread, Some(0.5));
+        assert_eq!(summary.bids[0].price, 100.5);
+        assert!(summary_rx.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_level_cap_eviction() {
+        let level_cap = Arc::new(LevelCap::new(7));
+        let aggregated_order_book = test_order_book().with_level_cap(level_cap.clone());
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                    Bid::new(98.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                    Ask::new(103.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(level_cap.levels(), 6);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+
+        //Refresh the best bid and add two worse bids, pushing the book one level past the cap
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 2.0, Exchange::Binance),
+                    Bid::new(97.0, 1.0, Exchange::Binance),
+                    Bid::new(96.0, 1.0, Exchange::Binance),
+                ],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        //The oldest bids are 99 and 98, of which 98 is the worst priced
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let bid_prices = summary
+            .bids
+            .iter()
+            .map(|level| level.price)
+            .collect::<Vec<_>>();
+        assert_eq!(bid_prices, vec![100.0, 99.0, 97.0, 96.0]);
+        assert_eq!(summary.asks.len(), 3);
+        assert_eq!(level_cap.levels(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_max_distance_from_mid() {
+        let aggregated_order_book = test_order_book().with_max_distance_from_mid(0.05);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 2).await;
+
+        //There is no mid price until the book has a best bid and ask, so every level is kept
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(80.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //With a mid price of 100.5, levels more than 5% away are skipped while removals are still applied
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(99.5, 1.0, Exchange::Binance),
+                    Bid::new(90.0, 1.0, Exchange::Binance),
+                    Bid::new(80.0, 0.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(103.0, 1.0, Exchange::Binance),
+                    Ask::new(120.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        let bid_prices = aggregated_order_book
+            .bids
+            .lock()
+            .await
+            .iter()
+            .map(|bid| bid.price.0)
+            .collect::<Vec<_>>();
+        assert_eq!(bid_prices, vec![99.5, 100.0]);
+
+        let ask_prices = aggregated_order_book
+            .asks
+            .lock()
+            .await
+            .iter()
+            .map(|ask| ask.price.0)
+            .collect::<Vec<_>>();
+        assert_eq!(ask_prices, vec![101.0, 102.0, 103.0]);
+    }
+
+    #[tokio::test]
+    async fn test_no_summary_subscribers() {
+        let aggregated_order_book = test_order_book();
+
+        //Drop the only receiver so that summaries are published without any subscribers
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, _) = tokio::sync::broadcast::channel(10);
+        let handle = aggregated_order_book.handle_order_book_updates(
+            price_level_rx,
+            10,
+            2,
+            summary_tx.clone(),
+        );
+
+        for i in 0..50 {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    Exchange::Binance,
+                    vec![
+                        Bid::new(100.0, i as f64 + 1.0, Exchange::Binance),
+                        Bid::new(99.0, 1.0, Exchange::Binance),
+                    ],
+                    vec![
+                        Ask::new(101.0, 1.0, Exchange::Binance),
+                        Ask::new(102.0, 1.0, Exchange::Binance),
+                    ],
+                ))
+                .await
+                .expect("Could not send price level update");
+        }
+
+        //A client that subscribes late should receive the next summary
+        let mut summary_rx = summary_tx.subscribe();
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 100.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        //Updates sent before subscribing may still be handled after, so receive until the latest update is summarized
+        let mut summary = summary_rx.recv().await.expect("Could not receive summary");
+        while summary.bids[0].amount != 100.0 {
+            summary = summary_rx.recv().await.expect("Could not receive summary");
+        }
+        assert!(!handle.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_emit_levels() {
+        let aggregated_order_book = test_order_book().with_emit_levels(2);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                (0..5)
+                    .map(|i| Bid::new(100.0 - i as f64, 1.0, Exchange::Binance))
+                    .collect(),
+                (0..5)
+                    .map(|i| Ask::new(101.0 + i as f64, 1.0, Exchange::Binance))
+                    .collect(),
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Removing the best bid promotes the next best bid from the internally tracked levels
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 0.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let prices = |levels: &[Level]| levels.iter().map(|level| level.price).collect::<Vec<_>>();
+        assert_eq!(prices(&summary.bids), vec![99.0, 98.0]);
+        assert_eq!(prices(&summary.asks), vec![101.0, 102.0]);
+        assert_eq!(summary.spread, Some(2.0));
+        assert_eq!(summary.total_notional_bids, 99.0 + 98.0 + 97.0 + 96.0);
+    }
+
+    #[tokio::test]
+    async fn test_merge_price_levels() {
+        let aggregated_order_book = test_order_book()
+            .with_merge_price_levels()
+            .with_emit_levels(2);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        for (exchange, quantity) in [(Exchange::Binance, 1.5), (Exchange::Bitstamp, 2.0)] {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    exchange.clone(),
+                    vec![
+                        Bid::new(100.0, quantity, exchange.clone()),
+                        Bid::new(99.0, quantity, exchange.clone()),
+                    ],
+                    vec![
+                        Ask::new(101.0, quantity, exchange.clone()),
+                        Ask::new(102.0, quantity, exchange.clone()),
+                    ],
+                ))
+                .await
+                .expect("Could not send price level update");
+        }
+        //Levels from a single venue keep the venue's exchange id, and are marked with their side
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids[0].exchange_id(), ExchangeId::Binance);
+        assert_eq!(summary.bids[0].side(), Side::Bid);
+        assert_eq!(summary.asks[0].side(), Side::Ask);
+
+        //Both venues quote 100.0, which is merged into one level with the combined quantity
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let levels = |levels: &[Level]| {
+            levels
+                .iter()
+                .map(|level| (level.price, level.amount, level.exchange.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            levels(&summary.bids),
+            vec![
+                (100.0, 3.5, "bitstamp,binance".to_owned()),
+                (99.0, 3.5, "bitstamp,binance".to_owned())
+            ]
+        );
+        assert_eq!(
+            levels(&summary.asks),
+            vec![
+                (101.0, 3.5, "bitstamp,binance".to_owned()),
+                (102.0, 3.5, "bitstamp,binance".to_owned())
+            ]
+        );
+        assert_eq!(summary.spread, Some(1.0));
+        //A merged level is not attributed to a single exchange id
+        assert_eq!(summary.bids[0].exchange_id(), ExchangeId::Unspecified);
+        assert_eq!(summary.bids[0].side(), Side::Bid);
+    }
+
+    #[test]
+    fn test_changes_best_n() {
+        let worst_best_bid = Bid::new(100.0, 5.0, Exchange::Binance);
+
+        //Any bid can change the best n bids until there are n of them
+        assert!(bid_changes_best_n(
+            &Bid::new(90.0, 1.0, Exchange::Binance),
+            &worst_best_bid,
+            false
+        ));
+        assert!(!bid_changes_best_n(
+            &Bid::new(90.0, 1.0, Exchange::Binance),
+            &worst_best_bid,
+            true
+        ));
+        assert!(bid_changes_best_n(
+            &Bid::new(101.0, 1.0, Exchange::Binance),
+            &worst_best_bid,
+            true
+        ));
+
+        //Removing a level at the worst price may remove a level ranked above the worst bid
+        assert!(bid_changes_best_n(
+            &Bid::new(100.0, 0.0, Exchange::Bitstamp),
+            &worst_best_bid,
+            true
+        ));
+
+        let worst_best_ask = Ask::new(100.0, 5.0, Exchange::Binance);
+        assert!(!ask_changes_best_n(
+            &Ask::new(110.0, 1.0, Exchange::Binance),
+            &worst_best_ask,
+            true
+        ));
+        assert!(ask_changes_best_n(
+            &Ask::new(99.0, 1.0, Exchange::Binance),
+            &worst_best_ask,
+            true
+        ));
+        assert!(ask_changes_best_n(
+            &Ask::new(100.0, 0.0, Exchange::Bitstamp),
+            &worst_best_ask,
+            true
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_best_n_below_depth() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        let two_levels = PriceLevelUpdate::new(
+            Exchange::Binance,
+            vec![
+                Bid::new(100.0, 1.0, Exchange::Binance),
+                Bid::new(99.0, 1.0, Exchange::Binance),
+            ],
+            vec![
+                Ask::new(101.0, 1.0, Exchange::Binance),
+                Ask::new(102.0, 1.0, Exchange::Binance),
+            ],
+        );
+        price_level_tx
+            .send(two_levels)
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Levels worse than the current worst levels are still within the best n while there are fewer than n levels
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![Bid::new(98.0, 1.0, Exchange::Bitstamp)],
+                vec![Ask::new(103.0, 1.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let prices = |levels: &[Level]| levels.iter().map(|level| level.price).collect::<Vec<_>>();
+        assert_eq!(prices(&summary.bids), vec![100.0, 99.0, 98.0]);
+        assert_eq!(prices(&summary.asks), vec![101.0, 102.0, 103.0]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat() {
+        let aggregated_order_book =
+            test_order_book().with_heartbeat_interval(Duration::from_secs(5));
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(!summary.heartbeat);
+        let published_at = tokio::time::Instant::now();
+
+        //No updates arrive, so the last summary is republished as a heartbeat once the interval has elapsed
+        let heartbeat = summary_rx
+            .recv()
+            .await
+            .expect("Could not receive heartbeat");
+        assert!(heartbeat.heartbeat);
+        assert_eq!(published_at.elapsed(), Duration::from_secs(5));
+        assert_eq!(
+            heartbeat,
+            Summary {
+                heartbeat: true,
+                ..summary
+            }
+        );
+
+        let heartbeat = summary_rx
+            .recv()
+            .await
+            .expect("Could not receive heartbeat");
+        assert!(heartbeat.heartbeat);
+        assert_eq!(published_at.elapsed(), Duration::from_secs(10));
+    }
+
+    //Fill each side with levels at distinct prices and levels at the same price from different exchanges, checking that iterating the side
+    //visits every level in the same best-first order as the best n levels
+    fn assert_iterated_best_first(mut bids: impl BuySideView, mut asks: impl SellSideView) {
+        for (price, quantity, exchange) in [
+            (99.0, 1.0, Exchange::Binance),
+            (100.0, 1.0, Exchange::Binance),
+            (100.0, 2.0, Exchange::Bitstamp),
+            (98.0, 3.0, Exchange::Kraken),
+            (101.0, 1.0, Exchange::Bitstamp),
+        ] {
+            bids.update_bids(Bid::new(price, quantity, exchange.clone()), 10);
+            asks.update_asks(Ask::new(price + 5.0, quantity, exchange), 10);
+        }
+
+        let best_bids = bids.get_best_n_bids(bids.num_bids());
+        assert_eq!(
+            bids.iter_bids().cloned().collect::<Vec<_>>(),
+            best_bids.into_iter().flatten().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            bids.iter_bids().map(|bid| bid.price.0).collect::<Vec<_>>(),
+            vec![101.0, 100.0, 100.0, 99.0, 98.0]
+        );
+
+        let best_asks = asks.get_best_n_asks(asks.num_asks());
+        assert_eq!(
+            asks.iter_asks().cloned().collect::<Vec<_>>(),
+            best_asks.into_iter().flatten().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            asks.iter_asks().map(|ask| ask.price.0).collect::<Vec<_>>(),
+            vec![103.0, 104.0, 105.0, 105.0, 106.0]
+        );
+    }
+
+    #[test]
+    fn test_iter_levels() {
+        assert_iterated_best_first(BTreeSet::<Bid>::new(), BTreeSet::<Ask>::new());
+        assert_iterated_best_first(
+            BTreeMap::<LevelKey, Bid>::new(),
+            BTreeMap::<LevelKey, Ask>::new(),
+        );
+        assert_iterated_best_first(TickSet::<Bid>::new(2), TickSet::<Ask>::new(2));
+        assert_iterated_best_first(
+            HashMapOrderBook::<Bid>::new(),
+            HashMapOrderBook::<Ask>::new(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_update_into_empty_book() {
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 5).await;
+
+        //A single level on each side is both the best and the worst of the best n levels
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 1);
+        assert_eq!(summary.bids[0].price, 100.0);
+        assert_eq!(summary.asks.len(), 1);
+        assert_eq!(summary.asks[0].price, 101.0);
+        assert_eq!(summary.spread, Some(1.0));
+
+        //A better bid is added to the best n levels ahead of the tracked worst bid
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.5, 1.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(
+            summary.bids.iter().map(|bid| bid.price).collect::<Vec<_>>(),
+            vec![100.5, 100.0]
+        );
+
+        //Tracking no best levels leaves the best n levels empty rather than indexing into them
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+        let (price_level_tx, mut summary_rx, empty_handle) =
+            spawn_test_loop(&aggregated_order_book, 0).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(summary.bids.is_empty() && summary.asks.is_empty());
+
+        //The aggregation loop did not panic, so it stops cleanly once the price level channel is closed
+        drop(price_level_tx);
+        assert!(empty_handle
+            .await
+            .expect("Aggregation loop panicked")
+            .is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_publish_interval() {
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        )
+        .with_publish_interval(Duration::from_millis(50));
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(100);
+        let _handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 5, summary_tx);
+        skip_warming_summary(&mut summary_rx).await;
+
+        //Flood the order book with 50 updates every 10ms for 200ms, moving the best bid up with each update
+        let start = tokio::time::Instant::now();
+        let mut best_bid_price = 0.0;
+        for round in 0..20 {
+            for update in 0..50 {
+                best_bid_price = 100.0 + (round * 50 + update) as f64 * 0.01;
+                price_level_tx
+                    .send(PriceLevelUpdate::new(
+                        Exchange::Binance,
+                        vec![
+                            Bid::new(best_bid_price, 1.0, Exchange::Binance),
+                            Bid::new(50.0, 1.0, Exchange::Binance),
+                        ],
+                        vec![
+                            Ask::new(200.0, 1.0, Exchange::Binance),
+                            Ask::new(201.0, 1.0, Exchange::Binance),
+                        ],
+                    ))
+                    .await
+                    .expect("Could not send price level update");
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let elapsed = start.elapsed();
+
+        //Wait for the summary held since the last tick to be published
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut summaries = vec![];
+        while let Ok(summary) = summary_rx.try_recv() {
+            summaries.push(summary);
+        }
+
+        //At most one summary is published per interval rather than one per update, and the last summary holds the latest state
+        let max_summaries = (elapsed.as_millis() / 50 + 1) as usize;
+        assert!(
+            !summaries.is_empty() && summaries.len() <= max_summaries,
+            "Published {} summaries, expected at most {max_summaries}",
+            summaries.len()
+        );
+        let last_summary = summaries.last().unwrap();
+        assert_eq!(last_summary.bids[0].price, best_bid_price);
+        assert_eq!(last_summary.asks[0].price, 200.0);
+
+        //No further summaries are published once the held summary has been published
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), summary_rx.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_integrity() {
+        let aggregated_order_book = test_order_book();
+
+        {
+            let mut bids = aggregated_order_book.bids.lock().await;
+            bids.update_bids(Bid::new(100.0, 5.0, Exchange::Binance), 10);
+            bids.update_bids(Bid::new(100.0, 3.0, Exchange::Bitstamp), 10);
+            bids.update_bids(Bid::new(99.0, 1.0, Exchange::Binance), 10);
+        }
+        assert!(aggregated_order_book.verify_integrity().await.is_ok());
+
+        //Insert a second Binance level at the same price directly into the set, bypassing the update path
+        aggregated_order_book
+            .bids
+            .lock()
+            .await
+            .insert(Bid::new(100.0, 1.0, Exchange::Binance));
+
+        match aggregated_order_book.verify_integrity().await {
+            Err(OrderBookError::DuplicateLevels { bids, asks }) => {
+                assert_eq!(bids, vec![(100.0, Exchange::Binance)]);
+                assert!(asks.is_empty());
+            }
+            other => panic!("Expected duplicate levels, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_levels_ordered() {
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Bitstamp, Exchange::Binance, Exchange::Kraken],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 6).await;
+
+        //Each exchange quotes prices that interleave with the prices of the other exchanges
+        for (exchange, bid_prices, ask_prices) in [
+            (Exchange::Binance, [99.0, 96.0], [101.0, 104.0]),
+            (Exchange::Bitstamp, [98.0, 95.0], [102.0, 105.0]),
+            (Exchange::Kraken, [100.0, 97.0], [103.0, 106.0]),
+        ] {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    exchange.clone(),
+                    bid_prices
+                        .iter()
+                        .map(|price| Bid::new(*price, 1.0, exchange.clone()))
+                        .collect(),
+                    ask_prices
+                        .iter()
+                        .map(|price| Ask::new(*price, 1.0, exchange.clone()))
+                        .collect(),
+                ))
+                .await
+                .expect("Could not send price level update");
+        }
+
+        let mut summary = summary_rx.recv().await.expect("Could not receive summary");
+        for _ in 0..2 {
+            summary = summary_rx.recv().await.expect("Could not receive summary");
+        }
+
+        let bid_prices = summary.bids.iter().map(|bid| bid.price).collect::<Vec<_>>();
+        let ask_prices = summary.asks.iter().map(|ask| ask.price).collect::<Vec<_>>();
+        assert_eq!(bid_prices, vec![100.0, 99.0, 98.0, 97.0, 96.0, 95.0]);
+        assert_eq!(ask_prices, vec![101.0, 102.0, 103.0, 104.0, 105.0, 106.0]);
+        assert!(bid_prices.windows(2).all(|w| w[0] > w[1]));
+        assert!(ask_prices.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            summary.spread,
+            Some(summary.asks[0].price - summary.bids[0].price)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let aggregated_order_book = test_order_book().with_metrics(metrics.clone());
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 2).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![],
+                vec![Ask::new(100.5, 1.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //The publish latency is observed after the summary is sent, so wait for the aggregation loop to record it
+        let mut encoded = metrics.encode();
+        for _ in 0..50 {
+            if encoded.contains(
+                r#"bid_ask_service_summary_publish_latency_seconds_count{pair="eth/btc"} 2"#,
+            ) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            encoded = metrics.encode();
+        }
+
+        assert!(encoded.contains(
+            r#"bid_ask_service_price_level_updates_total{exchange="binance",pair="eth/btc"} 4"#
+        ));
+        assert!(encoded.contains(
+            r#"bid_ask_service_price_level_updates_total{exchange="bitstamp",pair="eth/btc"} 1"#
+        ));
+        assert!(encoded.contains(r#"bid_ask_service_spread{pair="eth/btc"} 0.5"#));
+        assert!(encoded.contains(
+            r#"bid_ask_service_summary_publish_latency_seconds_count{pair="eth/btc"} 2"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_one_sided_spread() {
+        let metrics = Arc::new(Metrics::new());
+        let aggregated_order_book = test_order_book().with_metrics(metrics.clone());
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 2).await;
+
+        //A book with only bids has no spread, rather than a spread from the sentinel best ask
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 1.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 1);
+        assert_eq!(summary.spread, None);
+        assert!(!summary.crossed);
+        assert!(!metrics.encode().contains("bid_ask_service_spread{"));
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![],
+                vec![Ask::new(100.5, 1.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.spread, Some(0.5));
+
+        //Removing the only ask leaves the book one sided again
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![],
+                vec![Ask::new(100.5, 0.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(summary.asks.is_empty());
+        assert_eq!(summary.spread, None);
+    }
+
+    #[tokio::test]
+    async fn test_price_tick_size() {
+        let aggregated_order_book = test_order_book().with_price_tick_size(0.01);
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 3).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //Near equal prices from another exchange and from the same exchange should snap to the existing price levels
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Bitstamp,
+                vec![Bid::new(100.00000000001, 2.0, Exchange::Bitstamp)],
+                vec![Ask::new(100.99999999999, 2.0, Exchange::Bitstamp)],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(99.999999999, 3.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let levels = |levels: &[Level]| {
+            levels
+                .iter()
+                .map(|level| (level.price, level.amount, level.exchange.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            levels(&summary.bids),
+            vec![
+                (100.0, 3.0, "binance".to_owned()),
+                (100.0, 2.0, "bitstamp".to_owned()),
+                (99.0, 1.0, "binance".to_owned()),
+            ]
+        );
+        assert_eq!(summary.asks[0].price, 101.0);
+        assert_eq!(summary.spread, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_price_epsilon() {
+        //The same updates are handled with exact prices and with prices compared within the epsilon
+        for (price_epsilon, expected_bids) in [
+            (None, vec![(100.1000000001, 2.0), (100.1, 1.0), (99.0, 1.0)]),
+            (Some(1e-8), vec![(100.1000000001, 2.0), (99.0, 1.0)]),
+        ] {
+            let mut aggregated_order_book = AggregatedOrderBook::new(
+                ["eth", "btc"],
+                vec![Exchange::Binance],
+                BTreeSet::<Bid>::new(),
+                BTreeSet::<Ask>::new(),
+            );
+            if let Some(price_epsilon) = price_epsilon {
+                aggregated_order_book = aggregated_order_book.with_price_epsilon(price_epsilon);
+            }
+
+            let (price_level_tx, mut summary_rx, _handle) =
+                spawn_test_loop(&aggregated_order_book, 3).await;
+
+            //The second update's bid is a float artifact of the first bid's price
+            for bids in [
+                vec![
+                    Bid::new(100.1, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![Bid::new(100.1000000001, 2.0, Exchange::Binance)],
+            ] {
+                price_level_tx
+                    .send(PriceLevelUpdate::new(
+                        Exchange::Binance,
+                        bids,
+                        vec![Ask::new(101.0, 1.0, Exchange::Binance)],
+                    ))
+                    .await
+                    .expect("Could not send price level update");
+            }
+
+            summary_rx.recv().await.expect("Could not receive summary");
+            let summary = summary_rx.recv().await.expect("Could not receive summary");
+
+            //Within the epsilon the update replaces the level, keeping the price that it was received at
+            assert_eq!(
+                summary
+                    .bids
+                    .iter()
+                    .map(|level| (level.price, level.amount))
+                    .collect::<Vec<_>>(),
+                expected_bids
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_price_epsilon_across_exchanges() {
+        let aggregated_order_book = test_order_book().with_price_epsilon(1e-8);
+
+        let (price_level_tx, mut summary_rx, handle) =
+            spawn_test_loop(&aggregated_order_book, 3).await;
+
+        //The bids are in the same tick, so the larger Bitstamp bid is ranked first despite its lower price
+        for (exchange, bid_price, quantity) in [
+            (Exchange::Bitstamp, 100.0, 5.0),
+            (Exchange::Binance, 100.0000000001, 1.0),
+        ] {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    exchange.clone(),
+                    vec![Bid::new(bid_price, quantity, exchange.clone())],
+                    vec![Ask::new(101.0, 1.0, exchange)],
+                ))
+                .await
+                .expect("Could not send price level update");
+        }
+
+        summary_rx.recv().await.expect("Could not receive summary");
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+
+        //The published levels are sorted by price, and the spread is from the highest bid
+        assert_eq!(
+            summary
+                .bids
+                .iter()
+                .map(|level| (level.price, level.amount))
+                .collect::<Vec<_>>(),
+            vec![(100.0000000001, 1.0), (100.0, 5.0)]
+        );
+        assert_eq!(summary.spread, Some(101.0 - 100.0000000001));
+        assert!(!handle.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_price_tick_size_aligns_venues() {
+        let aggregated_order_book = test_order_book()
+            .with_price_tick_size(0.01)
+            .with_merge_price_levels();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 4).await;
+
+        //Binance quotes with three decimals of precision, while Bitstamp quotes with two
+        for (exchange, bid_prices, ask_prices) in [
+            (Exchange::Binance, [100.001, 99.004], [101.002, 102.003]),
+            (Exchange::Bitstamp, [100.0, 99.0], [101.0, 102.0]),
+        ] {
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    exchange.clone(),
+                    bid_prices
+                        .into_iter()
+                        .map(|price| Bid::new(price, 1.0, exchange.clone()))
+                        .collect(),
+                    ask_prices
+                        .into_iter()
+                        .map(|price| Ask::new(price, 1.0, exchange.clone()))
+                        .collect(),
+                ))
+                .await
+                .expect("Could not send price level update");
+        }
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        //The levels of both venues collapse to the same price levels, so each level holds the amount of both venues
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        let levels = |levels: &[Level]| {
+            levels
+                .iter()
+                .map(|level| (level.price, level.amount))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(levels(&summary.bids), vec![(100.0, 2.0), (99.0, 2.0)]);
+        assert_eq!(levels(&summary.asks), vec![(101.0, 2.0), (102.0, 2.0)]);
+        assert_eq!(summary.bids[0].exchange, "binance,bitstamp");
+    }
+
+    #[tokio::test]
+    async fn test_warming_summary() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+        let _handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 10, summary_tx);
+
+        //The client receives an empty summary without a spread before any price levels arrive
+        let summary = summary_rx
+            .recv()
+            .await
+            .expect("Could not receive warming summary");
+        assert_eq!(summary.spread, None);
+        assert!(summary.bids.is_empty() && summary.asks.is_empty());
+        assert!(summary.exchange_quotes.is_empty());
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.spread, Some(1.0));
+        assert_eq!(summary.bids.len(), 2);
+        assert_eq!(summary.asks.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_weighted_mid_decay() {
+        let aggregated_order_book =
+            test_order_book().with_mid_decay_half_life(Duration::from_secs(1));
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        //Binance quotes a mid of 100.5 and Bitstamp quotes a mid of 110.5
+        let binance_update = || {
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            )
+        };
+        let bitstamp_update = PriceLevelUpdate::new(
+            Exchange::Bitstamp,
+            vec![
+                Bid::new(110.0, 1.0, Exchange::Bitstamp),
+                Bid::new(109.0, 1.0, Exchange::Bitstamp),
+            ],
+            vec![
+                Ask::new(111.0, 1.0, Exchange::Bitstamp),
+                Ask::new(112.0, 1.0, Exchange::Bitstamp),
+            ],
+        );
+
+        let mut weighted_mids = vec![];
+        for (price_level_update, elapsed) in [
+            (binance_update(), Duration::ZERO),
+            (bitstamp_update, Duration::ZERO),
+            (binance_update(), Duration::from_secs(1)),
+            (binance_update(), Duration::from_secs(10)),
+        ] {
+            tokio::time::advance(elapsed).await;
+            price_level_tx
+                .send(price_level_update)
+                .await
+                .expect("Could not send price level update");
+
+            let summary = summary_rx.recv().await.expect("Could not receive summary");
+            weighted_mids.push(summary.weighted_mid.expect("No weighted mid"));
+        }
+
+        //Both exchanges are equally weighted while they are fresh
+        assert_eq!(weighted_mids[0], 100.5);
+        assert_eq!(weighted_mids[1], 105.5);
+
+        //After one half life without an update, Bitstamp's weight has halved
+        assert!((weighted_mids[2] - (100.5 + 0.5 * 110.5) / 1.5).abs() < 1e-9);
+
+        //After eleven half lives, Bitstamp's contribution has decayed toward zero
+        assert!((weighted_mids[3] - 100.5).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_interval() {
+        let aggregated_order_book = test_order_book().with_snapshot_interval(3);
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+        let _handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 20, 2, summary_tx);
+        skip_warming_summary(&mut summary_rx).await;
+
+        let mut summaries = vec![];
+        for i in 0..7 {
+            let price = 100.0 - i as f64;
+            price_level_tx
+                .send(PriceLevelUpdate::new(
+                    Exchange::Binance,
+                    vec![
+                        Bid::new(price, 1.0, Exchange::Binance),
+                        Bid::new(price - 0.5, 1.0, Exchange::Binance),
+                    ],
+                    vec![
+                        Ask::new(price + 10.0, 1.0, Exchange::Binance),
+                        Ask::new(price + 10.5, 1.0, Exchange::Binance),
+                    ],
+                ))
+                .await
+                .expect("Could not send price level update");
+
+            summaries.push(summary_rx.recv().await.expect("Could not receive summary"));
+        }
+
+        //Every third summary is a snapshot carrying every level, while the others carry the best n levels
+        let snapshots = summaries
+            .iter()
+            .map(|summary| summary.snapshot)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            snapshots,
+            vec![false, false, true, false, false, true, false]
+        );
+
+        assert_eq!(summaries[1].bids.len(), 2);
+        assert_eq!(summaries[2].bids.len(), 6);
+        assert_eq!(summaries[2].asks.len(), 6);
+        assert_eq!(summaries[6].bids.len(), 2);
+
+        //The snapshot holds the levels of the whole order book, ordered from the best level
+        assert_eq!(summaries[5].bids.len(), 12);
+        assert_eq!(summaries[5].asks.len(), 12);
+        assert_eq!(summaries[5].bids[0].price, 100.0);
+        assert_eq!(summaries[5].bids[11].price, 94.5);
+        assert_eq!(summaries[5].asks[0].price, 105.0);
+        assert_eq!(summaries[5].asks[11].price, 110.5);
+        assert_eq!(summaries[5].spread, Some(5.0));
+    }
+
+    #[tokio::test]
+    async fn test_set_exchanges() {
+        let mut aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+
+        //Each exchange service sends two levels on both sides and then keeps running, recording which exchanges were spawned
+        let spawned = Arc::new(std::sync::Mutex::new(vec![]));
+        let spawned_exchanges = spawned.clone();
+        let _services_handle = aggregated_order_book.spawn_exchange_services(
+            price_level_tx,
+            10,
+            move |exchange, price_level_tx, _shutdown_rx| {
+                spawned_exchanges.lock().unwrap().push(exchange.clone());
+                let exchange = exchange.clone();
+                let offset = if exchange == Exchange::Binance {
+                    0.0
+                } else {
+                    1.0
+                };
+                vec![tokio::spawn(async move {
+                    price_level_tx
+                        .send(PriceLevelUpdate::new(
+                            exchange.clone(),
+                            vec![
+                                Bid::new(100.0 + offset, 1.0, exchange.clone()),
+                                Bid::new(99.5 + offset, 1.0, exchange.clone()),
+                            ],
+                            vec![
+                                Ask::new(110.0 + offset, 1.0, exchange.clone()),
+                                Ask::new(110.5 + offset, 1.0, exchange.clone()),
+                            ],
+                        ))
+                        .await
+                        .expect("Could not send price level update");
+                    std::future::pending().await
+                })]
+            },
+        );
+        let _handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 10, summary_tx);
+        skip_warming_summary(&mut summary_rx).await;
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 2);
+        assert!(summary.bids.iter().all(|bid| bid.exchange == "binance"));
+
+        //Swapping in Bitstamp starts its service, while Binance is stopped and its levels are cleared
+        aggregated_order_book.set_exchanges(vec![Exchange::Bitstamp]);
+        assert_eq!(aggregated_order_book.exchanges, vec![Exchange::Bitstamp]);
+
+        let mut summary = summary_rx.recv().await.expect("Could not receive summary");
+        while summary.bids.iter().any(|bid| bid.exchange == "binance") || summary.bids.is_empty() {
+            summary = summary_rx.recv().await.expect("Could not receive summary");
+        }
+
+        assert_eq!(summary.bids.len(), 2);
+        assert!(summary.bids.iter().all(|bid| bid.exchange == "bitstamp"));
+        assert!(summary.asks.iter().all(|ask| ask.exchange == "bitstamp"));
+        assert_eq!(
+            summary.exchange_quotes,
+            vec![ExchangeQuote {
+                exchange: "bitstamp".to_string(),
+                bid_price: Some(101.0),
+                ask_price: Some(111.0),
+            }]
+        );
+
+        //Swapping to the same exchanges leaves the running services as they are
+        aggregated_order_book.set_exchanges(vec![Exchange::Bitstamp]);
+        tokio::task::yield_now().await;
+        assert_eq!(
+            *spawned.lock().unwrap(),
+            vec![Exchange::Binance, Exchange::Bitstamp]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_exchanges_before_first_update() {
+        let mut aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+
+        //Only the Binance service sends levels, the Bitstamp service has not sent anything yet
+        let _services_handle = aggregated_order_book.spawn_exchange_services(
+            price_level_tx,
+            10,
+            |exchange, price_level_tx, _shutdown_rx| {
+                let exchange = exchange.clone();
+                vec![tokio::spawn(async move {
+                    if exchange == Exchange::Binance {
+                        price_level_tx
+                            .send(PriceLevelUpdate::new(
+                                exchange.clone(),
+                                vec![Bid::new(100.0, 1.0, exchange.clone())],
+                                vec![Ask::new(110.0, 1.0, exchange.clone())],
+                            ))
+                            .await
+                            .expect("Could not send price level update");
+                    }
+                    std::future::pending().await
+                })]
+            },
+        );
+        let _handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 10, summary_tx);
+        skip_warming_summary(&mut summary_rx).await;
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.bids.len(), 1);
+        assert_eq!(summary.asks.len(), 1);
+
+        //Clearing the Binance levels empties the order book, so the summary no longer holds the Binance levels
+        aggregated_order_book.set_exchanges(vec![Exchange::Bitstamp]);
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(summary.bids.is_empty());
+        assert!(summary.asks.is_empty());
+        assert!(summary.exchange_quotes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exchange_depth() {
+        let depth_config = DepthConfig::uniform(10).with_exchange_depth(Exchange::Binance, 2);
+        assert_eq!(depth_config.exchange_depth(&Exchange::Binance), 2);
+        assert_eq!(depth_config.exchange_depth(&Exchange::Bitstamp), 10);
+
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance, Exchange::Bitstamp],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+
+        //Each exchange service streams five levels on both sides, trimmed to the exchange's depth
+        let exchange_depth_config = depth_config.clone();
+        let _services_handle = aggregated_order_book.spawn_exchange_services(
+            price_level_tx,
+            10,
+            move |exchange, price_level_tx, _shutdown_rx| {
+                let exchange = exchange.clone();
+                let depth = exchange_depth_config.exchange_depth(&exchange);
+                let offset = if exchange == Exchange::Binance {
+                    0.0
+                } else {
+                    0.25
+                };
+                vec![tokio::spawn(async move {
+                    let bids = (0..5)
+                        .map(|i| Bid::new(100.0 + offset - i as f64, 1.0, exchange.clone()))
+                        .take(depth)
+                        .collect();
+                    let asks = (0..5)
+                        .map(|i| Ask::new(110.0 + offset + i as f64, 1.0, exchange.clone()))
+                        .take(depth)
+                        .collect();
+                    price_level_tx
+                        .send(PriceLevelUpdate::snapshot(exchange.clone(), bids, asks))
+                        .await
+                        .expect("Could not send price level update");
+                    std::future::pending().await
+                })]
+            },
+        );
+        let _handle = aggregated_order_book.handle_order_book_updates(
+            price_level_rx,
+            depth_config.max_order_book_depth,
+            10,
+            summary_tx,
+        );
+        skip_warming_summary(&mut summary_rx).await;
+
+        //The shallower Binance depth does not limit the aggregated order book, which holds the levels of both exchanges
+        let mut summary = summary_rx.recv().await.expect("Could not receive summary");
+        while summary.exchange_quotes.len() < 2 {
+            summary = summary_rx.recv().await.expect("Could not receive summary");
+        }
+
+        let count_exchange = |levels: &[Level], exchange: &str| {
+            levels
+                .iter()
+                .filter(|level| level.exchange == exchange)
+                .count()
+        };
+        assert_eq!(summary.bids.len(), 7);
+        assert_eq!(summary.asks.len(), 7);
+        assert_eq!(count_exchange(&summary.bids, "binance"), 2);
+        assert_eq!(count_exchange(&summary.bids, "bitstamp"), 5);
+        assert_eq!(count_exchange(&summary.asks, "binance"), 2);
+        assert_eq!(count_exchange(&summary.asks, "bitstamp"), 5);
+    }
+
+    #[tokio::test]
+    async fn test_summary_schema_version() {
+        let aggregated_order_book = test_order_book();
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert_eq!(summary.schema_version, SUMMARY_SCHEMA_VERSION);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_all_exchanges_down_publishes_stale() {
+        use crate::events::{EventPublisher, ServiceEventKind};
+
+        let aggregated_order_book = test_order_book().with_all_exchanges_down(
+            Duration::from_secs(5),
+            AllExchangesDownBehavior::PublishStale,
+        );
+        let mut event_rx = aggregated_order_book.subscribe_events();
+        let binance_events = EventPublisher::new(
+            Some(Exchange::Binance),
+            ["eth", "btc"],
+            aggregated_order_book.event_tx.clone(),
+        );
+        let bitstamp_events = EventPublisher::new(
+            Some(Exchange::Bitstamp),
+            ["eth", "btc"],
+            aggregated_order_book.event_tx.clone(),
+        );
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        binance_events.publish(ServiceEventKind::Connected);
+        bitstamp_events.publish(ServiceEventKind::Connected);
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(!summary.stale);
+
+        //The last summary is republished as stale once every exchange has been down for the timeout
+        binance_events.publish(ServiceEventKind::Disconnected);
+        bitstamp_events.publish(ServiceEventKind::Disconnected);
+        let disconnected_at = tokio::time::Instant::now();
+
+        let stale_summary = summary_rx
+            .recv()
+            .await
+            .expect("Could not receive stale summary");
+        assert_eq!(disconnected_at.elapsed(), Duration::from_secs(5));
+        assert_eq!(
+            stale_summary,
+            Summary {
+                stale: true,
+                ..summary
+            }
+        );
+
+        let events = std::iter::from_fn(|| event_rx.try_recv().ok())
+            .map(|event| event.event)
+            .collect::<Vec<_>>();
+        assert_eq!(events.last(), Some(&ServiceEventKind::AllExchangesDown));
+
+        //Once an exchange reconnects, summaries are no longer stale
+        binance_events.publish(ServiceEventKind::Connected);
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.0, 2.0, Exchange::Binance)],
+                vec![],
+            ))
+            .await
+            .expect("Could not send price level update");
+
+        let summary = summary_rx.recv().await.expect("Could not receive summary");
+        assert!(!summary.stale);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_all_exchanges_down_stops_publishing() {
+        use crate::events::{EventPublisher, ServiceEventKind};
+
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        )
+        .with_heartbeat_interval(Duration::from_secs(10))
+        .with_all_exchanges_down(
+            Duration::from_secs(5),
+            AllExchangesDownBehavior::StopPublishing,
+        );
+        let mut event_rx = aggregated_order_book.subscribe_events();
+        let binance_events = EventPublisher::new(
+            Some(Exchange::Binance),
+            ["eth", "btc"],
+            aggregated_order_book.event_tx.clone(),
+        );
+
+        let (price_level_tx, mut summary_rx, _handle) =
+            spawn_test_loop(&aggregated_order_book, 10).await;
+
+        binance_events.publish(ServiceEventKind::Connected);
+        price_level_tx
+            .send(PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 1.0, Exchange::Binance),
+                ],
+            ))
+            .await
+            .expect("Could not send price level update");
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        binance_events.publish(ServiceEventKind::Disconnected);
+
+        //Wait for the all exchanges down event, skipping the connection events
+        loop {
+            let event = event_rx.recv().await.expect("Could not receive event");
+            if event.event == ServiceEventKind::AllExchangesDown {
+                break;
+            }
+        }
+
+        //No summaries or heartbeats are published while every exchange is down
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(summary_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let aggregated_order_book = AggregatedOrderBook::new(
+            ["eth", "btc"],
+            vec![Exchange::Binance, Exchange::Bitstamp],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        );
+
+        let (price_level_tx, price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+
+        //Each exchange service streams its levels and then keeps running until shutdown, reporting how each of its tasks resolved.
+        //A task that is aborted rather than stopped never reports its result
+        let (result_tx, mut result_rx) = tokio::sync::mpsc::unbounded_channel::<(Exchange, bool)>();
+        let services_handle = aggregated_order_book.spawn_exchange_services(
+            price_level_tx,
+            10,
+            move |exchange, price_level_tx, mut shutdown_rx| {
+                let exchange = exchange.clone();
+                let service_exchange = exchange.clone();
+                let service_handle = tokio::spawn(async move {
+                    price_level_tx
+                        .send(PriceLevelUpdate::new(
+                            service_exchange.clone(),
+                            vec![
+                                Bid::new(100.0, 1.0, service_exchange.clone()),
+                                Bid::new(99.0, 1.0, service_exchange.clone()),
+                            ],
+                            vec![
+                                Ask::new(101.0, 1.0, service_exchange.clone()),
+                                Ask::new(102.0, 1.0, service_exchange.clone()),
+                            ],
+                        ))
+                        .await
+                        .expect("Could not send price level update");
+                    shutdown_requested(&mut shutdown_rx).await;
+                    Ok(())
+                });
+
+                let result_tx = result_tx.clone();
+                vec![tokio::spawn(async move {
+                    let result = flatten_task_result(service_handle.await);
+                    result_tx
+                        .send((exchange, result.is_ok()))
+                        .expect("Could not report result");
+                    result
+                })]
+            },
+        );
+        let handle =
+            aggregated_order_book.handle_order_book_updates(price_level_rx, 10, 10, summary_tx);
+        skip_warming_summary(&mut summary_rx).await;
+        summary_rx.recv().await.expect("Could not receive summary");
+
+        aggregated_order_book.shutdown();
+
+        //The supervisor and aggregation loop resolve cleanly
+        for handle in [services_handle, handle] {
+            tokio::time::timeout(Duration::from_secs(1), handle)
+                .await
+                .expect("Task did not stop")
+                .expect("Join handle error")
+                .expect("Task returned an error");
+        }
+        //Every exchange service stopped itself and resolved to Ok(()), rather than being aborted
+        let mut stopped = vec![];
+        for _ in 0..2 {
+            let (exchange, ok) = tokio::time::timeout(Duration::from_secs(1), result_rx.recv())
+                .await
+                .expect("Exchange service was not stopped")
+                .expect("Result channel closed");
+            assert!(ok, "{exchange} order book service returned an error");
+            stopped.push(exchange);
+        }
+        stopped.sort();
+        assert_eq!(stopped, vec![Exchange::Bitstamp, Exchange::Binance]);
+    }
 }
@@ -1,14 +1,21 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, time::Duration};
 
 use ordered_float::OrderedFloat;
+use tokio::time::Instant;
 
 use crate::{exchanges::Exchange, order_book::Order};
 
+use super::price::{PriceComparison, PriceKey};
+
 #[derive(Debug, Clone)]
 pub struct Bid {
     pub price: OrderedFloat<f64>,
     pub quantity: OrderedFloat<f64>,
     pub exchange: Exchange,
+    //Time at which the price level was last updated by the exchange, this is not considered when ordering or comparing levels
+    pub last_updated: Instant,
+    //How the price is compared to the prices of other levels, exactly unless the order book compares prices within an epsilon
+    pub price_comparison: PriceComparison,
 }
 
 impl Bid {
@@ -17,8 +24,25 @@ impl Bid {
             price: OrderedFloat(price),
             quantity: OrderedFloat(quantity),
             exchange,
+            last_updated: Instant::now(),
+            price_comparison: PriceComparison::Exact,
         }
     }
+
+    pub fn with_price_comparison(mut self, price_comparison: PriceComparison) -> Self {
+        self.price_comparison = price_comparison;
+        self
+    }
+
+    //Returns the key that the price is ordered and compared by
+    pub fn price_key(&self) -> PriceKey {
+        self.price_comparison.key(self.price.0)
+    }
+
+    //Returns the time elapsed since the price level was last updated
+    pub fn age(&self) -> Duration {
+        self.last_updated.elapsed()
+    }
 }
 
 impl Default for Bid {
@@ -34,6 +58,9 @@ impl Order for Bid {
     fn get_quantity(&self) -> &OrderedFloat<f64> {
         &self.quantity
     }
+    fn set_price(&mut self, price: OrderedFloat<f64>) {
+        self.price = price;
+    }
     fn set_quantity(&mut self, quantity: OrderedFloat<f64>) {
         self.quantity = quantity;
     }
@@ -44,7 +71,7 @@ impl Order for Bid {
 
 impl PartialEq for Bid {
     fn eq(&self, other: &Self) -> bool {
-        self.price == other.price
+        self.price_key() == other.price_key()
             && self.quantity == other.quantity
             && self.exchange == other.exchange
     }
@@ -60,7 +87,7 @@ impl PartialOrd for Bid {
 impl Ord for Bid {
     fn cmp(&self, other: &Self) -> Ordering {
         //First check if the price is equal
-        match self.price.cmp(&other.price) {
+        match self.price_key().cmp(&other.price_key()) {
             //If the price is equal, check the exchange, this allows the order book structure to know to replace the quantity for this value
             Ordering::Equal => match self.exchange.cmp(&other.exchange) {
                 Ordering::Equal => Ordering::Equal,
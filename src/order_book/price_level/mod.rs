@@ -1,5 +1,24 @@
 pub mod ask;
 pub mod bid;
+pub mod price;
+
+use std::sync::Arc;
+
+use tokio::{
+    sync::mpsc::{error::TrySendError, Receiver, Sender},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    error::BidAskServiceError,
+    events::{EventPublisher, ServiceEventKind},
+    exchanges::{
+        feed_quality::{FeedQuality, UpdateAnomaly},
+        Exchange,
+    },
+    order_book::error::OrderBookError,
+};
 
 use self::{ask::Ask, bid::Bid};
 
@@ -9,16 +28,405 @@ pub enum OrderType {
     Ask,
 }
 
+// How the quantity of each price level sent by an exchange should be applied to the order book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuantitySemantics {
+    //The quantity replaces the quantity at the price level
+    #[default]
+    Absolute,
+    //The quantity is added to the quantity at the price level, a negative quantity is subtracted
+    Delta,
+}
+
+// What each exchange's price level updates do when the aggregated order book's price level channel is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackpressurePolicy {
+    //The exchange's stream handler waits for capacity, which stops it reading its websocket stream until the aggregated order book catches up
+    #[default]
+    Block,
+    //Updates are merged into a single pending update until there is capacity, see spawn_coalescing_relay
+    Coalesce,
+    //Updates are dropped and counted until there is capacity, see spawn_dropping_relay
+    Drop,
+}
+
+//Snap the price to the nearest multiple of the tick size, so that prices from different exchanges that only differ by float noise are equal.
+//Unlike comparing prices within a tolerance, snapping every price to the same grid keeps the ordering of levels a valid total order
+pub fn snap_to_grid(price: f64, tick_size: f64) -> f64 {
+    let ticks = (price / tick_size).round();
+
+    //Decimal tick sizes such as 0.01 are not exact in binary, so multiplying by the tick size can land beside the decimal price, ie. 3 * 0.1 is 0.30000000000000004.
+    //Dividing by the whole number of ticks per unit instead gives the float nearest to the decimal price
+    let ticks_per_unit = (1.0 / tick_size).round();
+    if ticks_per_unit >= 1.0 && (ticks_per_unit * tick_size - 1.0).abs() < f64::EPSILON {
+        ticks / ticks_per_unit
+    } else {
+        ticks * tick_size
+    }
+}
+
 #[derive(Debug, Clone)]
 
 // Data type to be sent from an exchange's stream handler, to the aggregated order book
 pub struct PriceLevelUpdate {
+    //The exchange that the batch was received from, used for operations on all of the exchange's levels
+    pub exchange: Exchange,
     pub bids: Vec<Bid>,
     pub asks: Vec<Ask>,
+    //Whether the exchange's existing levels should be cleared before the batch is applied
+    pub clear: bool,
+    //Microseconds since the unix epoch at which the exchange produced the batch, if the exchange sends a timestamp
+    pub exchange_timestamp: Option<u64>,
+    //Time at which the batch was received from the exchange's stream
+    pub received_at: Instant,
 }
 
 impl PriceLevelUpdate {
-    pub fn new(bids: Vec<Bid>, asks: Vec<Ask>) -> Self {
-        PriceLevelUpdate { bids, asks }
+    pub fn new(exchange: Exchange, bids: Vec<Bid>, asks: Vec<Ask>) -> Self {
+        PriceLevelUpdate {
+            exchange,
+            bids,
+            asks,
+            clear: false,
+            exchange_timestamp: None,
+            received_at: Instant::now(),
+        }
+    }
+
+    //Creates an update that replaces all of the exchange's levels, ie. with an order book snapshot after a reconnect
+    pub fn snapshot(exchange: Exchange, bids: Vec<Bid>, asks: Vec<Ask>) -> Self {
+        PriceLevelUpdate {
+            exchange,
+            bids,
+            asks,
+            clear: true,
+            exchange_timestamp: None,
+            received_at: Instant::now(),
+        }
+    }
+
+    pub fn with_exchange_timestamp(mut self, exchange_timestamp: u64) -> Self {
+        self.exchange_timestamp = Some(exchange_timestamp);
+        self
+    }
+
+    //Merge a later update from the same exchange into this update, so that applying the merged update is equivalent to applying both in order
+    pub fn merge(&mut self, later: PriceLevelUpdate) {
+        if later.clear {
+            //The later update replaces all of the exchange's levels, including those in this update
+            *self = later;
+        } else {
+            self.bids.extend(later.bids);
+            self.asks.extend(later.asks);
+            self.exchange_timestamp = self.exchange_timestamp.max(later.exchange_timestamp);
+            self.received_at = later.received_at;
+        }
+    }
+}
+
+//Spawns a task that relays price level updates from an exchange to the aggregated order book without blocking the exchange.
+//When the aggregated order book's channel is full, updates are merged into a single pending update that is sent as soon as there is capacity,
+//so the exchange's stream handler keeps draining its websocket stream while the aggregated order book catches up.
+pub fn spawn_coalescing_relay(
+    mut exchange_price_level_rx: Receiver<PriceLevelUpdate>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let mut pending: Option<PriceLevelUpdate> = None;
+
+        loop {
+            if pending.is_none() {
+                match exchange_price_level_rx.recv().await {
+                    Some(price_level_update) => pending = Some(price_level_update),
+                    None => break,
+                }
+                continue;
+            }
+
+            //Send the pending update once there is capacity, merging any updates that arrive in the meantime
+            tokio::select! {
+                permit = price_level_tx.reserve() => {
+                    let permit = permit.map_err(|_| OrderBookError::PriceLevelChannelClosed)?;
+                    if let Some(price_level_update) = pending.take() {
+                        permit.send(price_level_update);
+                    }
+                }
+
+                price_level_update = exchange_price_level_rx.recv() => match price_level_update {
+                    Some(price_level_update) => {
+                        if let Some(pending) = pending.as_mut() {
+                            pending.merge(price_level_update);
+                        }
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        //Flush the pending update before exiting
+        if let Some(price_level_update) = pending {
+            price_level_tx
+                .send(price_level_update)
+                .await
+                .map_err(OrderBookError::PriceLevelUpdateSendError)?;
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+//Spawns a task that relays price level updates from an exchange to the aggregated order book without blocking the exchange.
+//When the aggregated order book's channel is full, the update is dropped and counted, and the exchange's stream is asked to resync from a new snapshot,
+//since its levels drift from the exchange's book after a dropped update. Snapshots replace the exchange's levels, so they wait for capacity rather than
+//being dropped, and a resync is only requested again once a snapshot has been relayed
+pub fn spawn_dropping_relay(
+    mut exchange_price_level_rx: Receiver<PriceLevelUpdate>,
+    price_level_tx: Sender<PriceLevelUpdate>,
+    feed_quality: Option<Arc<FeedQuality>>,
+    events: EventPublisher,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let mut dropped = 0_u64;
+        let mut resync_requested = false;
+
+        while let Some(price_level_update) = exchange_price_level_rx.recv().await {
+            if price_level_update.clear {
+                price_level_tx
+                    .send(price_level_update)
+                    .await
+                    .map_err(OrderBookError::PriceLevelUpdateSendError)?;
+                resync_requested = false;
+                continue;
+            }
+
+            match price_level_tx.try_send(price_level_update) {
+                Ok(_) => {}
+                Err(TrySendError::Full(price_level_update)) => {
+                    dropped += 1;
+                    tracing::warn!(
+                        "Price level channel is full, dropped {dropped} {} updates in total",
+                        price_level_update.exchange
+                    );
+                    if let Some(feed_quality) = &feed_quality {
+                        feed_quality
+                            .record(price_level_update.exchange, UpdateAnomaly::DroppedUpdate);
+                    }
+
+                    if !resync_requested {
+                        events.publish(ServiceEventKind::ResyncRequested);
+                        resync_requested = true;
+                    }
+                }
+                Err(TrySendError::Closed(_)) => {
+                    return Err(OrderBookError::PriceLevelChannelClosed.into())
+                }
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::{
+        events::{EventPublisher, ServiceEvent, ServiceEventKind},
+        exchanges::{feed_quality::FeedQuality, Exchange},
+        order_book::price_level::{
+            bid::Bid, snap_to_grid, spawn_coalescing_relay, spawn_dropping_relay, PriceLevelUpdate,
+        },
+    };
+
+    #[test]
+    fn test_snap_to_grid() {
+        //Prices from different venues with different precision snap to the same tick
+        assert_eq!(snap_to_grid(100.001, 0.01), 100.0);
+        assert_eq!(snap_to_grid(100.0, 0.01), 100.0);
+        assert_eq!(snap_to_grid(100.006, 0.01), 100.01);
+        assert_eq!(snap_to_grid(0.0649999999, 0.00001), 0.065);
+
+        //Snapped decimal prices are the same float as the decimal price, rather than a multiple of the inexact tick size
+        assert_eq!(snap_to_grid(0.29999999, 0.1), 0.3);
+        assert_eq!(snap_to_grid(1.1, 0.1), 1.1);
+
+        //Tick sizes that do not divide a unit are multiplied out
+        assert_eq!(snap_to_grid(101.0, 2.5), 100.0);
+        assert_eq!(snap_to_grid(0.34, 0.25), 0.25);
+    }
+
+    #[tokio::test]
+    async fn test_coalescing_relay() {
+        //The aggregated order book is saturated, its channel only has capacity for a single update and is not being read
+        let (price_level_tx, mut price_level_rx) = tokio::sync::mpsc::channel(1);
+        let (exchange_price_level_tx, exchange_price_level_rx) = tokio::sync::mpsc::channel(1);
+        let _relay_handle = spawn_coalescing_relay(exchange_price_level_rx, price_level_tx);
+
+        //The exchange side should be able to keep sending updates without blocking
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for i in 0..100 {
+                exchange_price_level_tx
+                    .send(PriceLevelUpdate::new(
+                        Exchange::Binance,
+                        vec![Bid::new(i as f64, 1.0, Exchange::Binance)],
+                        vec![],
+                    ))
+                    .await
+                    .expect("Could not send price level update");
+            }
+        })
+        .await
+        .expect("Exchange side was blocked by the saturated aggregated order book");
+        drop(exchange_price_level_tx);
+
+        //Every level should be delivered in order, with the updates that arrived while saturated merged together
+        let mut prices = vec![];
+        let mut updates = 0;
+        while let Some(price_level_update) = price_level_rx.recv().await {
+            prices.extend(price_level_update.bids.iter().map(|bid| bid.price.0));
+            updates += 1;
+        }
+
+        assert_eq!(prices, (0..100).map(|i| i as f64).collect::<Vec<_>>());
+        assert!(updates < 100);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_relay() {
+        //The aggregated order book is saturated, its channel only has capacity for a single update and is not being read
+        let (price_level_tx, mut price_level_rx) = tokio::sync::mpsc::channel(1);
+        let (exchange_price_level_tx, exchange_price_level_rx) = tokio::sync::mpsc::channel(1);
+        let (event_tx, mut event_rx) = tokio::sync::broadcast::channel(10);
+        let feed_quality = Arc::new(FeedQuality::new());
+        let relay_handle = spawn_dropping_relay(
+            exchange_price_level_rx,
+            price_level_tx,
+            Some(feed_quality.clone()),
+            EventPublisher::new(Some(Exchange::Binance), ["eth", "btc"], event_tx),
+        );
+
+        //The exchange side should be able to keep sending updates without blocking
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for i in 0..100 {
+                exchange_price_level_tx
+                    .send(PriceLevelUpdate::new(
+                        Exchange::Binance,
+                        vec![Bid::new(i as f64, 1.0, Exchange::Binance)],
+                        vec![],
+                    ))
+                    .await
+                    .expect("Could not send price level update");
+            }
+        })
+        .await
+        .expect("Exchange side was blocked by the saturated aggregated order book");
+        drop(exchange_price_level_tx);
+        relay_handle
+            .await
+            .expect("Join handle error")
+            .expect("Relay error");
+
+        //Only the first update fit in the channel, and every other update was dropped and counted
+        let price_level_update = price_level_rx.recv().await.expect("No update received");
+        assert_eq!(price_level_update.bids[0].price.0, 0.0);
+        assert!(price_level_rx.recv().await.is_none());
+        assert_eq!(feed_quality.counts(&Exchange::Binance).dropped_updates, 99);
+
+        //A single resync is requested until the exchange sends a new snapshot
+        assert_eq!(
+            event_rx.try_recv(),
+            Ok(ServiceEvent::new(
+                ServiceEventKind::ResyncRequested,
+                Some(Exchange::Binance),
+                "eth/btc"
+            ))
+        );
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dropping_relay_keeps_snapshots() {
+        let (price_level_tx, mut price_level_rx) = tokio::sync::mpsc::channel(1);
+        let (exchange_price_level_tx, exchange_price_level_rx) = tokio::sync::mpsc::channel(10);
+        let (event_tx, mut event_rx) = tokio::sync::broadcast::channel(10);
+        let relay_handle = spawn_dropping_relay(
+            exchange_price_level_rx,
+            price_level_tx,
+            None,
+            EventPublisher::new(Some(Exchange::Binance), ["eth", "btc"], event_tx),
+        );
+
+        //The first update fills the channel and the second is dropped, while the snapshot waits for capacity rather than being dropped
+        for price_level_update in [
+            PriceLevelUpdate::new(Exchange::Binance, vec![], vec![]),
+            PriceLevelUpdate::new(Exchange::Binance, vec![], vec![]),
+            PriceLevelUpdate::snapshot(
+                Exchange::Binance,
+                vec![Bid::new(1.0, 1.0, Exchange::Binance)],
+                vec![],
+            ),
+        ] {
+            exchange_price_level_tx
+                .send(price_level_update)
+                .await
+                .expect("Could not send price level update");
+        }
+
+        for clear in [false, true] {
+            let price_level_update = price_level_rx.recv().await.expect("No update received");
+            assert_eq!(price_level_update.clear, clear);
+        }
+
+        //Once the snapshot has been relayed, the next dropped update requests another resync
+        for _ in 0..2 {
+            exchange_price_level_tx
+                .send(PriceLevelUpdate::new(Exchange::Binance, vec![], vec![]))
+                .await
+                .expect("Could not send price level update");
+        }
+        drop(exchange_price_level_tx);
+        relay_handle
+            .await
+            .expect("Join handle error")
+            .expect("Relay error");
+
+        let mut resyncs = 0;
+        while let Ok(event) = event_rx.try_recv() {
+            assert_eq!(event.event, ServiceEventKind::ResyncRequested);
+            resyncs += 1;
+        }
+        assert_eq!(resyncs, 2);
+    }
+
+    #[test]
+    fn test_merge_snapshot() {
+        let mut price_level_update = PriceLevelUpdate::new(
+            Exchange::Binance,
+            vec![Bid::new(1.0, 1.0, Exchange::Binance)],
+            vec![],
+        );
+
+        //A snapshot replaces the exchange's levels, so the pending levels are dropped
+        price_level_update.merge(PriceLevelUpdate::snapshot(
+            Exchange::Binance,
+            vec![Bid::new(2.0, 1.0, Exchange::Binance)],
+            vec![],
+        ));
+        price_level_update.merge(PriceLevelUpdate::new(
+            Exchange::Binance,
+            vec![Bid::new(3.0, 1.0, Exchange::Binance)],
+            vec![],
+        ));
+
+        assert!(price_level_update.clear);
+        assert_eq!(
+            price_level_update
+                .bids
+                .iter()
+                .map(|bid| bid.price.0)
+                .collect::<Vec<_>>(),
+            vec![2.0, 3.0]
+        );
     }
 }
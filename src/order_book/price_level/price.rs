@@ -0,0 +1,101 @@
+use ordered_float::OrderedFloat;
+
+// How the prices of price levels are compared when ordering levels and checking whether a set holds a level
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PriceComparison {
+    //Prices are equal only when they are exactly the same float
+    #[default]
+    Exact,
+    //Prices are equal when they round to the same integer number of ticks of the epsilon, so that float artifacts from parsing do not split a level
+    Epsilon(f64),
+}
+
+impl PriceComparison {
+    //Get the key that the price is ordered and compared by
+    pub fn key(&self, price: f64) -> PriceKey {
+        match self {
+            PriceComparison::Exact => PriceKey::Exact(OrderedFloat(price)),
+            PriceComparison::Epsilon(epsilon) => PriceKey::Ticks((price / epsilon).round() as i64),
+        }
+    }
+}
+
+// The key that a price level's price is ordered and compared by, which keeps the price itself as it was received.
+// Rounding to ticks rather than comparing prices within the epsilon of each other keeps the comparison transitive, so the ordering is a
+// valid total order for ordered sets. The tradeoff is that two prices closer than the epsilon still compare as different when they fall either
+// side of a tick boundary, and prices up to the epsilon apart can compare as equal. Keys are only comparable between levels with the same
+// comparison, which the aggregated order book guarantees by applying its comparison to every incoming level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PriceKey {
+    Exact(OrderedFloat<f64>),
+    Ticks(i64),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        exchanges::Exchange,
+        order_book::{
+            price_level::{ask::Ask, bid::Bid, price::PriceComparison},
+            BuySide, SellSide,
+        },
+    };
+
+    #[test]
+    fn test_price_key() {
+        let epsilon = PriceComparison::Epsilon(1e-8);
+        assert_eq!(epsilon.key(100.10), epsilon.key(100.1000000001));
+        assert!(epsilon.key(100.10) < epsilon.key(100.11));
+        assert_ne!(
+            PriceComparison::Exact.key(100.10),
+            PriceComparison::Exact.key(100.1000000001)
+        );
+    }
+
+    #[test]
+    fn test_epsilon_levels_merge() {
+        //In exact mode, the exchange's update at a near equal price is a separate level
+        let mut bids = BTreeSet::<Bid>::new();
+        bids.update_bids(Bid::new(100.10, 1.0, Exchange::Binance), 10);
+        bids.update_bids(Bid::new(100.1000000001, 2.0, Exchange::Binance), 10);
+        assert_eq!(bids.num_bids(), 2);
+
+        //In epsilon mode, the update replaces the exchange's level at the near equal price
+        let epsilon = PriceComparison::Epsilon(1e-8);
+        let mut bids = BTreeSet::<Bid>::new();
+        for (price, quantity) in [(100.10, 1.0), (100.1000000001, 2.0)] {
+            bids.update_bids(
+                Bid::new(price, quantity, Exchange::Binance).with_price_comparison(epsilon),
+                10,
+            );
+        }
+        assert_eq!(bids.num_bids(), 1);
+        let best_bid = bids.get_best_bid().expect("No best bid");
+        assert_eq!(
+            (best_bid.price.0, best_bid.quantity.0),
+            (100.1000000001, 2.0)
+        );
+
+        //A zero quantity at a near equal price removes the level
+        let mut asks = BTreeSet::<Ask>::new();
+        for (price, quantity) in [(100.20, 1.0), (100.1999999999, 0.0)] {
+            asks.update_asks(
+                Ask::new(price, quantity, Exchange::Binance).with_price_comparison(epsilon),
+                10,
+            );
+        }
+        assert_eq!(asks.num_asks(), 0);
+
+        //Prices further apart than the epsilon are still separate levels
+        let mut asks = BTreeSet::<Ask>::new();
+        for price in [100.20, 100.21] {
+            asks.update_asks(
+                Ask::new(price, 1.0, Exchange::Binance).with_price_comparison(epsilon),
+                10,
+            );
+        }
+        assert_eq!(asks.num_asks(), 2);
+    }
+}
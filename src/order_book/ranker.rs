@@ -0,0 +1,200 @@
+use std::{fmt::Debug, sync::Arc};
+
+use ordered_float::OrderedFloat;
+use tokio::time::Instant;
+
+use crate::exchanges::Exchange;
+
+use super::{
+    price_level::{ask::Ask, bid::Bid},
+    Order,
+};
+
+//The priority of a price level when ranking the best n levels, higher priorities are ranked closer to the top of the book.
+//Priorities are compared by tier, then by score, then by tie break, then by exchange preference, then by last update time. A ranker can demote levels outright by lowering the tier,
+//or penalize them relative to other levels by adjusting the score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OrderPriority {
+    pub tier: i32,
+    pub score: OrderedFloat<f64>,
+    pub tie_break: OrderedFloat<f64>,
+    //Higher preferences rank best among levels that are otherwise equal, ie. to prefer the exchange with the lowest fees
+    pub preference: i64,
+    //When set, the most recently updated level ranks best among levels that are otherwise equal
+    pub last_updated: Option<Instant>,
+}
+
+impl OrderPriority {
+    pub fn new(tier: i32, score: f64, tie_break: f64) -> Self {
+        OrderPriority {
+            tier,
+            score: OrderedFloat(score),
+            tie_break: OrderedFloat(tie_break),
+            preference: 0,
+            last_updated: None,
+        }
+    }
+
+    pub fn with_preference(mut self, preference: i64) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    pub fn with_last_updated(mut self, last_updated: Instant) -> Self {
+        self.last_updated = Some(last_updated);
+        self
+    }
+}
+
+// A price level from either side of the book that is being ranked
+#[derive(Debug, Clone, Copy)]
+pub enum RankedLevel<'a> {
+    Bid(&'a Bid),
+    Ask(&'a Ask),
+}
+
+impl<'a> RankedLevel<'a> {
+    pub fn price(&self) -> f64 {
+        match self {
+            RankedLevel::Bid(bid) => bid.get_price().0,
+            RankedLevel::Ask(ask) => ask.get_price().0,
+        }
+    }
+
+    pub fn quantity(&self) -> f64 {
+        match self {
+            RankedLevel::Bid(bid) => bid.get_quantity().0,
+            RankedLevel::Ask(ask) => ask.get_quantity().0,
+        }
+    }
+
+    pub fn exchange(&self) -> &'a Exchange {
+        match self {
+            RankedLevel::Bid(bid) => bid.get_exchange(),
+            RankedLevel::Ask(ask) => ask.get_exchange(),
+        }
+    }
+
+    pub fn last_updated(&self) -> Instant {
+        match self {
+            RankedLevel::Bid(bid) => bid.last_updated,
+            RankedLevel::Ask(ask) => ask.last_updated,
+        }
+    }
+}
+
+// Custom ranking logic consulted by the aggregated order book when selecting the best n levels of each side
+pub trait LevelRanker: Debug + Send + Sync {
+    fn rank(&self, level: RankedLevel) -> OrderPriority;
+
+    //Whether the ranker always ranks a better price above a worse price, so that the ranked levels are already ordered by price.
+    //Rankers that can rank a worse price above a better price keep the default, and their ranked order is streamed as is
+    fn ranks_by_price(&self) -> bool {
+        false
+    }
+}
+
+// Ranks levels the same way as the order book's ordering, by best price and then by largest quantity.
+// Levels with the same priority keep their order in the book, which breaks ties by exchange.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRanker;
+
+impl LevelRanker for DefaultRanker {
+    fn rank(&self, level: RankedLevel) -> OrderPriority {
+        match level {
+            RankedLevel::Bid(bid) => OrderPriority::new(0, bid.price.0, bid.quantity.0),
+            //The lowest ask is the best ask, so the price is negated to rank it highest
+            RankedLevel::Ask(ask) => OrderPriority::new(0, -ask.price.0, ask.quantity.0),
+        }
+    }
+
+    fn ranks_by_price(&self) -> bool {
+        true
+    }
+}
+
+// Ranks levels with the inner ranker, breaking ties between otherwise equal levels in favor of the most recently updated level
+#[derive(Debug, Clone)]
+pub struct RecencyTieBreak(pub Arc<dyn LevelRanker>);
+
+impl LevelRanker for RecencyTieBreak {
+    fn rank(&self, level: RankedLevel) -> OrderPriority {
+        self.0.rank(level).with_last_updated(level.last_updated())
+    }
+
+    fn ranks_by_price(&self) -> bool {
+        self.0.ranks_by_price()
+    }
+}
+
+// Policy for choosing between levels that the ranker ranks equally, ie. levels from different exchanges with the same price and quantity.
+// The policy adjusts the priority of each level, so that it is applied when selecting the best n levels rather than in the order book's ordering
+pub trait TieBreak: Debug + Send + Sync {
+    fn break_tie(&self, level: RankedLevel, priority: OrderPriority) -> OrderPriority;
+}
+
+// Prefers the most recently updated level
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MostRecentTieBreak;
+
+impl TieBreak for MostRecentTieBreak {
+    fn break_tie(&self, level: RankedLevel, priority: OrderPriority) -> OrderPriority {
+        priority.with_last_updated(level.last_updated())
+    }
+}
+
+// Prefers exchanges in the order that they are listed, ranking exchanges that are not listed last
+#[derive(Debug, Clone, Default)]
+pub struct ExchangePreference(pub Vec<Exchange>);
+
+impl TieBreak for ExchangePreference {
+    fn break_tie(&self, level: RankedLevel, priority: OrderPriority) -> OrderPriority {
+        let position = self
+            .0
+            .iter()
+            .position(|exchange| exchange == level.exchange())
+            .unwrap_or(self.0.len());
+        priority.with_preference(-(position as i64))
+    }
+}
+
+// Ranks levels with the inner ranker, applying the tie break policy to the priority of each level
+#[derive(Debug, Clone)]
+pub struct TieBreakRanker {
+    pub ranker: Arc<dyn LevelRanker>,
+    pub tie_break: Arc<dyn TieBreak>,
+}
+
+impl LevelRanker for TieBreakRanker {
+    fn rank(&self, level: RankedLevel) -> OrderPriority {
+        self.tie_break.break_tie(level, self.ranker.rank(level))
+    }
+
+    fn ranks_by_price(&self) -> bool {
+        self.ranker.ranks_by_price()
+    }
+}
+
+//Rank the levels, returning the n levels with the highest priority, or every level if there are less than n levels.
+//The sort is stable, so levels with the same priority keep the order that they are passed in.
+pub fn rank_best_n<'a, O, F>(
+    levels: impl Iterator<Item = &'a O>,
+    n: usize,
+    ranker: &dyn LevelRanker,
+    to_ranked: F,
+) -> Vec<O>
+where
+    O: Order + Clone + 'a,
+    F: Fn(&'a O) -> RankedLevel<'a>,
+{
+    let mut ranked = levels
+        .map(|level| (ranker.rank(to_ranked(level)), level))
+        .collect::<Vec<_>>();
+    ranked.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    ranked
+        .into_iter()
+        .take(n)
+        .map(|(_, level)| level.clone())
+        .collect()
+}
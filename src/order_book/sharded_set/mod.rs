@@ -0,0 +1,519 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{self, AtomicUsize},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::exchanges::Exchange;
+
+use super::{
+    best_n_by_exchange, duplicate_levels,
+    price_level::{ask::Ask, bid::Bid},
+    ranker::{rank_best_n, LevelRanker, RankedLevel},
+    total_notional, BuySide, Order, SellSide,
+};
+
+// A side of the order book that can be held in a sharded set, defining which end of each shard holds the best level
+pub trait ShardedLevel: Order + Clone {
+    //Compare the levels, ordering the better level first
+    fn cmp_best(&self, other: &Self) -> Ordering;
+
+    //Iterate over the levels in the shard from the best level to the worst level
+    fn best_first(levels: &BTreeSet<Self>) -> Box<dyn DoubleEndedIterator<Item = &Self> + '_>;
+}
+
+impl ShardedLevel for Bid {
+    fn cmp_best(&self, other: &Self) -> Ordering {
+        other.cmp(self)
+    }
+
+    fn best_first(levels: &BTreeSet<Self>) -> Box<dyn DoubleEndedIterator<Item = &Self> + '_> {
+        Box::new(levels.iter().rev())
+    }
+}
+
+impl ShardedLevel for Ask {
+    fn cmp_best(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn best_first(levels: &BTreeSet<Self>) -> Box<dyn DoubleEndedIterator<Item = &Self> + '_> {
+        Box::new(levels.iter())
+    }
+}
+
+// An alternative representation of one side of the order book, partitioning the price levels across multiple locks by price bucket,
+// so that updates to unrelated price regions can be applied concurrently through a shared reference. Adjacent buckets are held by
+// different shards so that updates around the top of the book are spread across the locks, and the best levels are merged across shards.
+#[derive(Debug)]
+pub struct ShardedSet<O> {
+    bucket_width: f64,
+    shards: Vec<Mutex<BTreeSet<O>>>,
+    len: AtomicUsize,
+}
+
+impl<O: ShardedLevel> ShardedSet<O> {
+    //Create a sharded set with the number of shards, holding each price bucket of the bucket width in one shard.
+    //The bucket width should be close to the pair's tick size times the levels expected per bucket, since every level of a pair priced
+    //well below the bucket width falls in the same bucket. Panics if the bucket width is not a positive finite number
+    pub fn new(num_shards: usize, bucket_width: f64) -> Self {
+        assert!(
+            bucket_width.is_finite() && bucket_width > 0.0,
+            "Invalid bucket width {bucket_width}, expected a positive number"
+        );
+
+        ShardedSet {
+            bucket_width,
+            shards: (0..num_shards.max(1))
+                .map(|_| Mutex::new(BTreeSet::new()))
+                .collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(atomic::Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //Get the shard holding the price bucket of the price. Levels at the same price from the same exchange are always in the same shard
+    fn shard_index(&self, price: f64) -> usize {
+        let bucket = (price / self.bucket_width).floor() as i64;
+        bucket.rem_euclid(self.shards.len() as i64) as usize
+    }
+
+    //The lock is only held while a shard is updated, so a poisoned shard still holds valid levels
+    fn lock_shard(&self, index: usize) -> MutexGuard<'_, BTreeSet<O>> {
+        self.shards[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    //Lock every shard, always in the same order so that concurrent updates can not deadlock
+    fn lock_all(&self) -> Vec<MutexGuard<'_, BTreeSet<O>>> {
+        (0..self.shards.len())
+            .map(|index| self.lock_shard(index))
+            .collect()
+    }
+
+    //Update the level in its shard. Replacing or removing a level and inserting a level below the max depth only lock the level's shard,
+    //while inserting a level at the max depth locks every shard to replace the worst level across the shards
+    pub fn update(&self, level: O, max_depth: usize) {
+        let index = self.shard_index(level.get_price().0);
+
+        {
+            let mut shard = self.lock_shard(index);
+
+            if level.get_quantity().0 == 0.0 {
+                if shard.remove(&level) {
+                    self.len.fetch_sub(1, atomic::Ordering::SeqCst);
+                }
+                return;
+            }
+
+            //We have to remove and insert so that the level is reordered by its new quantity, see the BTreeSet implementation
+            if shard.remove(&level) {
+                shard.insert(level);
+                return;
+            }
+
+            //The length is only changed while a shard is locked, so it can not change while every shard is locked below
+            let reserved = self
+                .len
+                .fetch_update(atomic::Ordering::SeqCst, atomic::Ordering::SeqCst, |len| {
+                    (len < max_depth).then_some(len + 1)
+                })
+                .is_ok();
+
+            if reserved {
+                shard.insert(level);
+                return;
+            }
+        }
+
+        let mut shards = self.lock_all();
+
+        //The level could have been inserted or levels removed between releasing the shard and locking every shard
+        if shards[index].remove(&level) {
+            shards[index].insert(level);
+            return;
+        } else if self.len() < max_depth {
+            shards[index].insert(level);
+            self.len.fetch_add(1, atomic::Ordering::SeqCst);
+            return;
+        }
+
+        let worst_level = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shard)| {
+                O::best_first(shard).next_back().map(|worst| (index, worst))
+            })
+            .max_by(|(_, a), (_, b)| a.cmp_best(b))
+            .map(|(index, worst)| (index, worst.clone()));
+
+        if let Some((worst_index, worst_level)) = worst_level {
+            if level.cmp_best(&worst_level) == Ordering::Less {
+                shards[worst_index].remove(&worst_level);
+                shards[index].insert(level);
+            }
+        }
+    }
+
+    //Get the best level across the shards
+    pub fn best(&self) -> Option<O> {
+        self.lock_all()
+            .iter()
+            .filter_map(|shard| O::best_first(shard).next())
+            .min_by(|a, b| a.cmp_best(b))
+            .cloned()
+    }
+
+    //Get the best n levels, merging the best n levels of each shard
+    pub fn best_n(&self, n: usize) -> Vec<O> {
+        let mut levels = self
+            .lock_all()
+            .iter()
+            .flat_map(|shard| O::best_first(shard).take(n).cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        levels.sort_by(|a, b| a.cmp_best(b));
+        levels.truncate(n);
+        levels
+    }
+
+    //Get the best level from the exchange across the shards
+    pub fn best_exchange(&self, exchange: &Exchange) -> Option<O> {
+        self.lock_all()
+            .iter()
+            .filter_map(|shard| O::best_first(shard).find(|level| level.get_exchange() == exchange))
+            .min_by(|a, b| a.cmp_best(b))
+            .cloned()
+    }
+
+    //Get every level across the shards, from the best level to the worst level
+    pub fn levels(&self) -> Vec<O> {
+        let mut levels = self
+            .lock_all()
+            .iter()
+            .flat_map(|shard| shard.iter().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        levels.sort_by(|a, b| a.cmp_best(b));
+        levels
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has a level at the price
+    fn exchange_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.lock_shard(self.shard_index(price))
+            .iter()
+            .find(|level| level.get_price().0 == price && level.get_exchange() == exchange)
+            .map(|level| level.get_quantity().0)
+    }
+
+    //Keep only the levels matching the predicate, returning the number of levels removed
+    fn retain(&self, mut f: impl FnMut(&O) -> bool) -> usize {
+        let mut removed = 0;
+        for mut shard in self.lock_all() {
+            let len = shard.len();
+            shard.retain(&mut f);
+            removed += len - shard.len();
+        }
+
+        self.len.fetch_sub(removed, atomic::Ordering::SeqCst);
+        removed
+    }
+
+    //Find each price and exchange with more than one level. Duplicates are at the same price, so they are always in the same shard
+    fn duplicates(&self) -> Vec<(f64, Exchange)> {
+        self.lock_all()
+            .iter()
+            .flat_map(|shard| duplicate_levels(shard.iter()))
+            .collect()
+    }
+
+    //Remove the n least recently updated levels, evicting the worst priced levels first when they were updated at the same time
+    fn evict(&self, n: usize, last_updated: impl Fn(&O) -> tokio::time::Instant) -> usize {
+        //The levels are ordered from the worst level, and the stable sort keeps that order between levels updated at the same time
+        let mut levels = self.levels();
+        levels.reverse();
+        levels.sort_by_key(|level| last_updated(level));
+
+        let evicted = levels.into_iter().take(n).collect::<Vec<_>>();
+        self.retain(|level| !evicted.contains(level))
+    }
+
+    //Get the sum of price * quantity across all levels
+    fn total_notional(&self) -> f64 {
+        total_notional(self.lock_all().iter().flat_map(|shard| shard.iter()))
+    }
+}
+
+impl BuySide for ShardedSet<Bid> {
+    //Update the bids in the order book with the new bid
+    fn update_bids(&mut self, bid: Bid, max_depth: usize) {
+        self.update(bid, max_depth);
+    }
+
+    //Get the best bid in the data structure
+    fn get_best_bid(&self) -> Option<Bid> {
+        self.best()
+    }
+
+    //Get the best "n" bids in the data structure
+    fn get_top_bids(&self, n: usize) -> Vec<Bid> {
+        self.best_n(n)
+    }
+
+    //Get the best bid from the exchange
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid> {
+        self.best_exchange(exchange)
+    }
+
+    //Get the best "n" bids from each exchange in the data structure
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>> {
+        best_n_by_exchange(self.levels().iter(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has a bid at the price
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.exchange_quantity(price, exchange)
+    }
+
+    //Get the best n bids ranked by a custom ranker. Bids with equal priority keep the order of the best bids in the data structure
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid> {
+        rank_best_n(self.levels().iter(), n, ranker, RankedLevel::Bid)
+    }
+
+    //Remove all bids that have not been updated within the max age, returning the number of bids removed
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize {
+        self.retain(|bid| bid.age() <= max_age)
+    }
+
+    //Remove all bids from the exchange, returning the number of bids removed
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize {
+        self.retain(|bid| bid.exchange != *exchange)
+    }
+
+    //Get the number of bids in the data structure
+    fn num_bids(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one bid
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)> {
+        self.duplicates()
+    }
+
+    //Remove the n least recently updated bids, evicting the worst priced bids first when they were updated at the same time.
+    //Returns the number of bids removed
+    fn evict_bids(&mut self, n: usize) -> usize {
+        self.evict(n, |bid| bid.last_updated)
+    }
+
+    //Get the sum of price * quantity across all bids in the data structure
+    fn total_notional_bids(&self) -> f64 {
+        self.total_notional()
+    }
+}
+
+impl SellSide for ShardedSet<Ask> {
+    //Update the asks in the order book with the new ask
+    fn update_asks(&mut self, ask: Ask, max_depth: usize) {
+        self.update(ask, max_depth);
+    }
+
+    //Get the best ask in the data structure
+    fn get_best_ask(&self) -> Option<Ask> {
+        self.best()
+    }
+
+    //Get the best "n" asks in the data structure
+    fn get_top_asks(&self, n: usize) -> Vec<Ask> {
+        self.best_n(n)
+    }
+
+    //Get the best ask from the exchange
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask> {
+        self.best_exchange(exchange)
+    }
+
+    //Get the best "n" asks from each exchange in the data structure
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>> {
+        best_n_by_exchange(self.levels().iter(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an ask at the price
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.exchange_quantity(price, exchange)
+    }
+
+    //Get the best n asks ranked by a custom ranker. Asks with equal priority keep the order of the best asks in the data structure
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask> {
+        rank_best_n(self.levels().iter(), n, ranker, RankedLevel::Ask)
+    }
+
+    //Remove all asks that have not been updated within the max age, returning the number of asks removed
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize {
+        self.retain(|ask| ask.age() <= max_age)
+    }
+
+    //Remove all asks from the exchange, returning the number of asks removed
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize {
+        self.retain(|ask| ask.exchange != *exchange)
+    }
+
+    //Get the number of asks in the data structure
+    fn num_asks(&self) -> usize {
+        self.len()
+    }
+
+    //Find each price and exchange with more than one ask
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)> {
+        self.duplicates()
+    }
+
+    //Remove the n least recently updated asks, evicting the worst priced asks first when they were updated at the same time.
+    //Returns the number of asks removed
+    fn evict_asks(&mut self, n: usize) -> usize {
+        self.evict(n, |ask| ask.last_updated)
+    }
+
+    //Get the sum of price * quantity across all asks in the data structure
+    fn total_notional_asks(&self) -> f64 {
+        self.total_notional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        exchanges::Exchange,
+        order_book::{
+            price_level::{ask::Ask, bid::Bid},
+            sharded_set::ShardedSet,
+            BuySide, SellSide,
+        },
+    };
+
+    #[test]
+    #[should_panic(expected = "Invalid bucket width")]
+    fn test_invalid_bucket_width() {
+        ShardedSet::<Bid>::new(4, 0.0);
+    }
+
+    #[test]
+    fn test_sharded_set_bids() {
+        let mut bids = ShardedSet::<Bid>::new(4, 1.0);
+
+        //Each bid is in a different price bucket, spreading the bids across the shards
+        for price in [100.0, 101.0, 102.0, 103.0, 104.0] {
+            bids.update_bids(Bid::new(price, 50.0, Exchange::Binance), 4);
+        }
+
+        //The worst bid across the shards is replaced at the max depth
+        assert_eq!(bids.num_bids(), 4);
+        assert_eq!(
+            bids.get_best_n_bids(5),
+            vec![
+                Some(Bid::new(104.0, 50.0, Exchange::Binance)),
+                Some(Bid::new(103.0, 50.0, Exchange::Binance)),
+                Some(Bid::new(102.0, 50.0, Exchange::Binance)),
+                Some(Bid::new(101.0, 50.0, Exchange::Binance)),
+                None,
+            ]
+        );
+
+        //A worse bid is not inserted at the max depth, while an existing level is updated in place
+        bids.update_bids(Bid::new(99.0, 50.0, Exchange::Bitstamp), 4);
+        bids.update_bids(Bid::new(101.0, 25.0, Exchange::Binance), 4);
+        assert_eq!(bids.num_bids(), 4);
+        assert_eq!(
+            bids.get_exchange_bid_quantity(101.0, &Exchange::Binance),
+            Some(25.0)
+        );
+
+        bids.update_bids(Bid::new(104.0, 0.0, Exchange::Binance), 4);
+        assert_eq!(
+            bids.get_best_bid(),
+            Some(Bid::new(103.0, 50.0, Exchange::Binance))
+        );
+        assert_eq!(bids.get_best_exchange_bid(&Exchange::Bitstamp), None);
+        assert_eq!(bids.clear_exchange_bids(&Exchange::Binance), 3);
+        assert!(bids.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_set_asks() {
+        let mut asks = ShardedSet::<Ask>::new(2, 0.5);
+
+        asks.update_asks(Ask::new(101.0, 50.0, Exchange::Binance), 3);
+        asks.update_asks(Ask::new(100.0, 50.0, Exchange::Binance), 3);
+        asks.update_asks(Ask::new(100.0, 75.0, Exchange::Bitstamp), 3);
+        asks.update_asks(Ask::new(100.5, 50.0, Exchange::Bitstamp), 3);
+
+        assert_eq!(
+            asks.get_best_n_asks(3),
+            vec![
+                Some(Ask::new(100.0, 75.0, Exchange::Bitstamp)),
+                Some(Ask::new(100.0, 50.0, Exchange::Binance)),
+                Some(Ask::new(100.5, 50.0, Exchange::Bitstamp)),
+            ]
+        );
+        assert_eq!(
+            asks.get_best_exchange_ask(&Exchange::Binance),
+            Some(Ask::new(100.0, 50.0, Exchange::Binance))
+        );
+        assert_eq!(asks.total_notional_asks(), 100.0 * 125.0 + 100.5 * 50.0);
+    }
+
+    #[test]
+    fn test_concurrent_updates() {
+        let sharded_bids = ShardedSet::<Bid>::new(8, 1.0);
+        let mut bids = BTreeSet::<Bid>::new();
+
+        //Each exchange updates the sharded set from its own thread, quoting prices that the other exchange does not quote
+        let exchange_bids =
+            [(Exchange::Binance, 0.0), (Exchange::Bitstamp, 0.25)].map(|(exchange, offset)| {
+                (0..200)
+                    .map(|i| {
+                        Bid::new(
+                            100.0 + (i % 50) as f64 * 0.5 + offset,
+                            i as f64,
+                            exchange.clone(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+        std::thread::scope(|s| {
+            for updates in exchange_bids.iter() {
+                let sharded_bids = &sharded_bids;
+                s.spawn(move || {
+                    for bid in updates.iter() {
+                        sharded_bids.update(bid.clone(), 1000);
+                    }
+                });
+            }
+        });
+
+        for bid in exchange_bids.iter().flatten() {
+            bids.update_bids(bid.clone(), 1000);
+        }
+
+        //Updates from different exchanges are to different price levels, so the result does not depend on the interleaving of the threads
+        assert_eq!(sharded_bids.len(), bids.len());
+        assert_eq!(sharded_bids.get_best_n_bids(20), bids.get_best_n_bids(20));
+    }
+}
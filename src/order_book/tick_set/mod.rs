@@ -0,0 +1,418 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use ordered_float::OrderedFloat;
+
+use crate::exchanges::Exchange;
+
+use super::{
+    best_n_by_exchange, duplicate_levels,
+    price_level::{ask::Ask, bid::Bid},
+    ranker::{rank_best_n, LevelRanker, RankedLevel},
+    total_notional, BuySide, BuySideView, Order, SellSide, SellSideView,
+};
+
+// Scales prices to integer ticks, ie. price * 10^decimals rounded to the nearest integer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceScale {
+    decimals: u32,
+    multiplier: f64,
+}
+
+impl PriceScale {
+    pub fn new(decimals: u32) -> Self {
+        PriceScale {
+            decimals,
+            multiplier: 10_f64.powi(decimals as i32),
+        }
+    }
+
+    pub fn decimals(&self) -> u32 {
+        self.decimals
+    }
+
+    //Convert the price to the nearest number of ticks
+    pub fn to_ticks(&self, price: f64) -> i64 {
+        (price * self.multiplier).round() as i64
+    }
+
+    //Convert a number of ticks back to a price
+    pub fn to_price(&self, ticks: i64) -> f64 {
+        ticks as f64 / self.multiplier
+    }
+}
+
+// A price level keyed by its price in integer ticks, so that levels at different prices are ordered by an integer comparison.
+// Levels with the same number of ticks fall back to the ordering of the order, which breaks ties by exchange and quantity.
+#[derive(Debug, Clone)]
+pub struct TickLevel<O> {
+    pub ticks: i64,
+    pub order: O,
+}
+
+impl<O: Order> PartialEq for TickLevel<O> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ticks == other.ticks
+            && self.order.get_quantity() == other.order.get_quantity()
+            && self.order.get_exchange() == other.order.get_exchange()
+    }
+}
+
+impl<O: Order> Eq for TickLevel<O> {}
+
+impl<O: Order> PartialOrd for TickLevel<O> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<O: Order> Ord for TickLevel<O> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.ticks.cmp(&other.ticks) {
+            //The price of each order is snapped to its ticks, so orders with the same ticks have exactly the same price
+            Ordering::Equal => self.order.cmp(&other.order),
+            other => other,
+        }
+    }
+}
+
+// An alternative representation of one side of the order book, holding each price level by its price in integer ticks
+// with a configured number of decimals. Prices that round to the same tick are the same price level.
+#[derive(Debug, Clone)]
+pub struct TickSet<O> {
+    scale: PriceScale,
+    levels: BTreeSet<TickLevel<O>>,
+}
+
+impl<O: Order + Clone> TickSet<O> {
+    pub fn new(decimals: u32) -> Self {
+        TickSet {
+            scale: PriceScale::new(decimals),
+            levels: BTreeSet::new(),
+        }
+    }
+
+    pub fn scale(&self) -> PriceScale {
+        self.scale
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    //Iterate over the orders from the lowest to the highest price
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &O> {
+        self.levels.iter().map(|level| &level.order)
+    }
+
+    //Key the order by its price in ticks, snapping the order's price to the tick
+    pub fn level(&self, mut order: O) -> TickLevel<O> {
+        let ticks = self.scale.to_ticks(order.get_price().0);
+        order.set_price(OrderedFloat(self.scale.to_price(ticks)));
+        TickLevel { ticks, order }
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an order at the price
+    fn exchange_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        let ticks = self.scale.to_ticks(price);
+        self.levels
+            .iter()
+            .find(|level| level.ticks == ticks && level.order.get_exchange() == exchange)
+            .map(|level| level.order.get_quantity().0)
+    }
+
+    //Remove all orders from the exchange, returning the number of orders removed
+    fn clear_exchange(&mut self, exchange: &Exchange) -> usize {
+        let len = self.levels.len();
+        self.levels
+            .retain(|level| level.order.get_exchange() != exchange);
+        len - self.levels.len()
+    }
+}
+
+impl BuySide for TickSet<Bid> {
+    //Update the bids in the order book with the new bid
+    fn update_bids(&mut self, bid: Bid, max_depth: usize) {
+        let level = self.level(bid);
+
+        if level.order.get_quantity().0 == 0.0 {
+            self.levels.remove(&level);
+        } else if self.levels.len() < max_depth {
+            //Remove and insert so that the level at the same price from the same exchange is replaced, see the BTreeSet implementation
+            self.levels.remove(&level);
+            self.levels.insert(level);
+        } else {
+            //We can unwrap this because the bids are at the max depth, signifying that there is at least one value
+            let level_is_better = level > *self.levels.first().unwrap();
+
+            if level_is_better {
+                self.levels.pop_first();
+                self.levels.insert(level);
+            }
+        }
+    }
+
+    //Get the best bid in the data structure
+    fn get_best_bid(&self) -> Option<Bid> {
+        self.levels.last().map(|level| level.order.clone())
+    }
+
+    //Get the best "n" bids in the data structure
+    fn get_top_bids(&self, n: usize) -> Vec<Bid> {
+        self.iter().rev().take(n).cloned().collect()
+    }
+
+    //Get the best bid from the exchange
+    fn get_best_exchange_bid(&self, exchange: &Exchange) -> Option<Bid> {
+        self.iter()
+            .rev()
+            .find(|bid| bid.exchange == *exchange)
+            .cloned()
+    }
+
+    //Get the best "n" bids from each exchange in the data structure
+    fn get_best_n_bids_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Bid>> {
+        best_n_by_exchange(self.iter().rev(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has a bid at the price
+    fn get_exchange_bid_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.exchange_quantity(price, exchange)
+    }
+
+    //Get the best n bids ranked by a custom ranker. Bids with equal priority keep the order of the best bids in the data structure
+    fn get_top_bids_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Bid> {
+        rank_best_n(self.iter().rev(), n, ranker, RankedLevel::Bid)
+    }
+
+    //Remove all bids that have not been updated within the max age, returning the number of bids removed
+    fn remove_stale_bids(&mut self, max_age: Duration) -> usize {
+        let len = self.levels.len();
+        self.levels.retain(|level| level.order.age() <= max_age);
+        len - self.levels.len()
+    }
+
+    //Remove all bids from the exchange, returning the number of bids removed
+    fn clear_exchange_bids(&mut self, exchange: &Exchange) -> usize {
+        self.clear_exchange(exchange)
+    }
+
+    //Get the number of bids in the data structure
+    fn num_bids(&self) -> usize {
+        self.levels.len()
+    }
+
+    //Find each price and exchange with more than one bid
+    fn duplicate_bids(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.iter())
+    }
+
+    //Remove the n least recently updated bids, evicting the worst priced bids first when they were updated at the same time.
+    //Returns the number of bids removed
+    fn evict_bids(&mut self, n: usize) -> usize {
+        //The set is iterated from the worst bid, and the stable sort keeps that order between bids updated at the same time
+        let mut levels = self.levels.iter().cloned().collect::<Vec<_>>();
+        levels.sort_by_key(|level| level.order.last_updated);
+
+        let mut removed = 0;
+        for level in levels.iter().take(n) {
+            if self.levels.remove(level) {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    //Get the sum of price * quantity across all bids in the data structure
+    fn total_notional_bids(&self) -> f64 {
+        total_notional(self.iter())
+    }
+}
+
+impl SellSide for TickSet<Ask> {
+    //Update the asks in the order book with the new ask
+    fn update_asks(&mut self, ask: Ask, max_depth: usize) {
+        let level = self.level(ask);
+
+        if level.order.get_quantity().0 == 0.0 {
+            self.levels.remove(&level);
+        } else if self.levels.len() < max_depth {
+            //Remove and insert so that the level at the same price from the same exchange is replaced, see the BTreeSet implementation
+            self.levels.remove(&level);
+            self.levels.insert(level);
+        } else {
+            //We can unwrap this because the asks are at the max depth, signifying that there is at least one value
+            let level_is_better = level < *self.levels.last().unwrap();
+
+            if level_is_better {
+                self.levels.pop_last();
+                self.levels.insert(level);
+            }
+        }
+    }
+
+    //Get the best ask in the data structure
+    fn get_best_ask(&self) -> Option<Ask> {
+        self.levels.first().map(|level| level.order.clone())
+    }
+
+    //Get the best "n" asks in the data structure
+    fn get_top_asks(&self, n: usize) -> Vec<Ask> {
+        self.iter().take(n).cloned().collect()
+    }
+
+    //Get the best ask from the exchange
+    fn get_best_exchange_ask(&self, exchange: &Exchange) -> Option<Ask> {
+        self.iter().find(|ask| ask.exchange == *exchange).cloned()
+    }
+
+    //Get the best "n" asks from each exchange in the data structure
+    fn get_best_n_asks_by_exchange(&self, n: usize) -> HashMap<Exchange, Vec<Ask>> {
+        best_n_by_exchange(self.iter(), n)
+    }
+
+    //Get the exchange's quantity at the price level, if the exchange has an ask at the price
+    fn get_exchange_ask_quantity(&self, price: f64, exchange: &Exchange) -> Option<f64> {
+        self.exchange_quantity(price, exchange)
+    }
+
+    //Get the best n asks ranked by a custom ranker. Asks with equal priority keep the order of the best asks in the data structure
+    fn get_top_asks_ranked(&self, n: usize, ranker: &dyn LevelRanker) -> Vec<Ask> {
+        rank_best_n(self.iter(), n, ranker, RankedLevel::Ask)
+    }
+
+    //Remove all asks that have not been updated within the max age, returning the number of asks removed
+    fn remove_stale_asks(&mut self, max_age: Duration) -> usize {
+        let len = self.levels.len();
+        self.levels.retain(|level| level.order.age() <= max_age);
+        len - self.levels.len()
+    }
+
+    //Remove all asks from the exchange, returning the number of asks removed
+    fn clear_exchange_asks(&mut self, exchange: &Exchange) -> usize {
+        self.clear_exchange(exchange)
+    }
+
+    //Get the number of asks in the data structure
+    fn num_asks(&self) -> usize {
+        self.levels.len()
+    }
+
+    //Find each price and exchange with more than one ask
+    fn duplicate_asks(&self) -> Vec<(f64, Exchange)> {
+        duplicate_levels(self.iter())
+    }
+
+    //Remove the n least recently updated asks, evicting the worst priced asks first when they were updated at the same time.
+    //Returns the number of asks removed
+    fn evict_asks(&mut self, n: usize) -> usize {
+        //The set is iterated in reverse from the worst ask, and the stable sort keeps that order between asks updated at the same time
+        let mut levels = self.levels.iter().rev().cloned().collect::<Vec<_>>();
+        levels.sort_by_key(|level| level.order.last_updated);
+
+        let mut removed = 0;
+        for level in levels.iter().take(n) {
+            if self.levels.remove(level) {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    //Get the sum of price * quantity across all asks in the data structure
+    fn total_notional_asks(&self) -> f64 {
+        total_notional(self.iter())
+    }
+}
+
+impl BuySideView for TickSet<Bid> {
+    //Iterate over the bids from the best bid to the worst bid
+    fn iter_bids(&self) -> impl Iterator<Item = &Bid> {
+        self.iter().rev()
+    }
+}
+
+impl SellSideView for TickSet<Ask> {
+    //Iterate over the asks from the best ask to the worst ask
+    fn iter_asks(&self) -> impl Iterator<Item = &Ask> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchanges::Exchange,
+        order_book::{
+            price_level::{ask::Ask, bid::Bid},
+            tick_set::TickSet,
+            BuySide, SellSide,
+        },
+    };
+
+    #[test]
+    fn test_tick_set_bids() {
+        let mut bids = TickSet::<Bid>::new(2);
+
+        bids.update_bids(Bid::new(100.001, 50.0, Exchange::Binance), 10);
+        bids.update_bids(Bid::new(101.0, 50.0, Exchange::Bitstamp), 10);
+        bids.update_bids(Bid::new(99.0, 50.0, Exchange::Binance), 10);
+
+        //A price that rounds to the same tick replaces the exchange's level at that tick
+        bids.update_bids(Bid::new(99.999, 25.0, Exchange::Binance), 10);
+
+        assert_eq!(
+            bids.get_best_n_bids(4),
+            vec![
+                Some(Bid::new(101.0, 50.0, Exchange::Bitstamp)),
+                Some(Bid::new(100.0, 25.0, Exchange::Binance)),
+                Some(Bid::new(99.0, 50.0, Exchange::Binance)),
+                None,
+            ]
+        );
+        assert_eq!(
+            bids.get_exchange_bid_quantity(100.0, &Exchange::Binance),
+            Some(25.0)
+        );
+
+        //Removing the level at the tick removes it regardless of float noise in the price
+        bids.update_bids(Bid::new(100.0004, 0.0, Exchange::Binance), 10);
+        assert_eq!(bids.num_bids(), 2);
+        assert!(bids.duplicate_bids().is_empty());
+    }
+
+    #[test]
+    fn test_tick_set_asks() {
+        let mut asks = TickSet::<Ask>::new(2);
+
+        asks.update_asks(Ask::new(101.0, 50.0, Exchange::Binance), 2);
+        asks.update_asks(Ask::new(100.0, 50.0, Exchange::Binance), 2);
+
+        //Asks at the same price are ordered with the highest quantity first
+        asks.update_asks(Ask::new(100.004, 75.0, Exchange::Bitstamp), 2);
+
+        assert_eq!(
+            asks.get_best_n_asks(2),
+            vec![
+                Some(Ask::new(100.0, 75.0, Exchange::Bitstamp)),
+                Some(Ask::new(100.0, 50.0, Exchange::Binance)),
+            ]
+        );
+
+        //A worse ask is not inserted at the max depth
+        asks.update_asks(Ask::new(102.0, 50.0, Exchange::Bitstamp), 2);
+        assert_eq!(asks.num_asks(), 2);
+        assert_eq!(asks.clear_exchange_asks(&Exchange::Binance), 1);
+        assert_eq!(
+            asks.get_best_ask(),
+            Some(Ask::new(100.0, 75.0, Exchange::Bitstamp))
+        );
+    }
+}
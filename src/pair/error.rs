@@ -0,0 +1,11 @@
+#[derive(thiserror::Error, Debug)]
+pub enum PairError {
+    #[error("Invalid pair {0:?}, expected two alphanumeric tickers separated by a comma, slash or dash, ie. eth,btc")]
+    InvalidPair(String),
+    #[error("Invalid pair {pair:?}, expected exactly two tickers separated by a comma, slash or dash but found {count}, ie. eth,btc")]
+    TickerCount { pair: String, count: usize },
+    #[error("Pair file contains no valid pairs")]
+    NoValidPairs,
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+}
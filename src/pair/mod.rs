@@ -0,0 +1,214 @@
+pub mod error;
+
+use std::path::Path;
+
+use self::error::PairError;
+
+//Separators accepted between the tickers of a pair
+pub const PAIR_SEPARATORS: [char; 3] = [',', '/', '-'];
+
+//Parse a trading pair with the tickers separated by a comma, slash or dash, ie. "eth,btc", "ETH/BTC" or "eth-btc", into lowercase tickers
+pub fn parse_pair(pair: &str) -> Result<[String; 2], PairError> {
+    let tickers = pair
+        .split(PAIR_SEPARATORS)
+        .map(|s| s.replace(' ', "").to_lowercase())
+        .collect::<Vec<String>>();
+
+    //Report a missing or extra separator separately from a malformed ticker, since it is the more likely mistake
+    if tickers.len() != 2 {
+        return Err(PairError::TickerCount {
+            pair: pair.to_owned(),
+            count: tickers.len(),
+        });
+    }
+
+    match tickers.as_slice() {
+        [base, quote]
+            if !base.is_empty()
+                && !quote.is_empty()
+                && base.chars().all(|c| c.is_ascii_alphanumeric())
+                && quote.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Ok([base.clone(), quote.clone()])
+        }
+        _ => Err(PairError::InvalidPair(pair.to_owned())),
+    }
+}
+
+//Parse a semicolon separated list of pairs, ie. "eth,btc;eth,usdt", returning an error for the first invalid pair. Duplicate pairs are only listed once
+pub fn parse_pairs(pairs: &str) -> Result<Vec<[String; 2]>, PairError> {
+    let mut parsed_pairs = vec![];
+
+    for pair in pairs.split(';').map(str::trim) {
+        let pair = parse_pair(pair)?;
+        if !parsed_pairs.contains(&pair) {
+            parsed_pairs.push(pair);
+        }
+    }
+
+    Ok(parsed_pairs)
+}
+
+// Pairs listed in a pair file, along with the line number and contents of each line that could not be parsed
+#[derive(Debug, Default, PartialEq)]
+pub struct PairList {
+    pub pairs: Vec<[String; 2]>,
+    pub invalid_lines: Vec<(usize, String)>,
+}
+
+//Parse a list of pairs, one per line. Blank lines and lines starting with # are ignored, and duplicate pairs are only listed once
+pub fn parse_pair_list(contents: &str) -> PairList {
+    let mut pair_list = PairList::default();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_pair(line) {
+            Ok(pair) => {
+                if !pair_list.pairs.contains(&pair) {
+                    pair_list.pairs.push(pair);
+                }
+            }
+            Err(_) => pair_list
+                .invalid_lines
+                .push((line_number + 1, line.to_owned())),
+        }
+    }
+
+    pair_list
+}
+
+//Load the pairs listed in a pair file, returning an error if the file does not contain any valid pairs
+pub fn load_pair_file(path: impl AsRef<Path>) -> Result<PairList, PairError> {
+    let pair_list = parse_pair_list(&std::fs::read_to_string(path)?);
+
+    if pair_list.pairs.is_empty() {
+        return Err(PairError::NoValidPairs);
+    }
+
+    Ok(pair_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pair::{error::PairError, load_pair_file, parse_pair, parse_pairs};
+
+    #[test]
+    fn test_parse_pair() {
+        assert_eq!(
+            parse_pair("eth,btc").expect("Could not parse pair"),
+            ["eth".to_owned(), "btc".to_owned()]
+        );
+        assert_eq!(
+            parse_pair("ETH / USDT").expect("Could not parse pair"),
+            ["eth".to_owned(), "usdt".to_owned()]
+        );
+
+        //Each separator splits the pair into the same tickers
+        for pair in ["eth,btc", "eth/btc", "eth-btc", "ETH-BTC", " eth / btc "] {
+            assert_eq!(
+                parse_pair(pair).expect("Could not parse pair"),
+                ["eth".to_owned(), "btc".to_owned()],
+                "{pair:?}"
+            );
+        }
+
+        for invalid_pair in ["eth,", "et$h,btc", "eth_btc,usdt", "/btc"] {
+            assert!(matches!(
+                parse_pair(invalid_pair),
+                Err(PairError::InvalidPair(_))
+            ));
+        }
+
+        //Pairs without exactly two tickers are reported with the number of tickers found
+        for (invalid_pair, tickers) in [
+            ("eth", 1),
+            ("ethbtc", 1),
+            ("", 1),
+            ("eth,btc,usdt", 3),
+            ("eth-btc/usdt", 3),
+        ] {
+            match parse_pair(invalid_pair) {
+                Err(PairError::TickerCount { pair, count }) => {
+                    assert_eq!((pair.as_str(), count), (invalid_pair, tickers));
+                }
+                other => {
+                    panic!("Expected a ticker count error for {invalid_pair:?}, got {other:?}")
+                }
+            }
+        }
+
+        //A single ticker should be reported with the expected format
+        let err = parse_pair("eth").expect_err("Parsed a single ticker");
+        assert_eq!(
+            err.to_string(),
+            "Invalid pair \"eth\", expected exactly two tickers separated by a comma, slash or dash but found 1, ie. eth,btc"
+        );
+    }
+
+    #[test]
+    fn test_parse_pairs() {
+        assert_eq!(
+            parse_pairs("eth,btc; ETH/USDT;eth,btc").expect("Could not parse pairs"),
+            vec![
+                ["eth".to_owned(), "btc".to_owned()],
+                ["eth".to_owned(), "usdt".to_owned()],
+            ]
+        );
+
+        //A single pair is still accepted
+        assert_eq!(
+            parse_pairs("eth,btc").expect("Could not parse pairs"),
+            vec![["eth".to_owned(), "btc".to_owned()]]
+        );
+
+        //Any invalid pair in the list should be rejected, including an empty pair from a trailing semicolon
+        for invalid_pairs in ["eth,btc;eth", "eth,btc;", ";", ""] {
+            assert!(matches!(
+                parse_pairs(invalid_pairs),
+                Err(PairError::TickerCount { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_load_pair_file() {
+        let path = std::env::temp_dir().join(format!("pair_file_{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# pairs to subscribe to\neth,btc\n\nbtc/usdt\nnot a pair\neth,btc,usdt\nETH,BTC\nsol,\n",
+        )
+        .expect("Could not write pair file");
+
+        let pair_list = load_pair_file(&path).expect("Could not load pair file");
+        std::fs::remove_file(&path).expect("Could not remove pair file");
+
+        //Only the valid pairs should be subscribed to, with duplicates removed
+        assert_eq!(
+            pair_list.pairs,
+            vec![
+                ["eth".to_owned(), "btc".to_owned()],
+                ["btc".to_owned(), "usdt".to_owned()],
+            ]
+        );
+        assert_eq!(
+            pair_list.invalid_lines,
+            vec![
+                (5, "not a pair".to_owned()),
+                (6, "eth,btc,usdt".to_owned()),
+                (8, "sol,".to_owned()),
+            ]
+        );
+
+        //A pair file without any valid pairs should be rejected
+        let path = std::env::temp_dir().join(format!("empty_pair_file_{}.txt", std::process::id()));
+        std::fs::write(&path, "not a pair\n").expect("Could not write pair file");
+        let result = load_pair_file(&path);
+        std::fs::remove_file(&path).expect("Could not remove pair file");
+
+        assert!(matches!(result, Err(PairError::NoValidPairs)));
+    }
+}
@@ -0,0 +1,7 @@
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("Profiler error")]
+    ProfilerError(#[from] pprof::Error),
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+}
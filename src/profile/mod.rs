@@ -0,0 +1,97 @@
+pub mod error;
+
+use std::{fs::File, path::Path, time::Duration};
+
+use pprof::{ProfilerGuard, ProfilerGuardBuilder};
+
+use self::error::ProfileError;
+
+//Sampling frequency in hertz, offset from round frequencies so that samples do not line up with periodic work such as timers
+pub const DEFAULT_PROFILE_FREQUENCY: i32 = 99;
+
+// Samples the call stacks of every thread in the process with pprof while it is held, covering the exchange streams and the aggregation loop,
+// so that the hot path can be rendered as a flamegraph. Sampling stops once the profiler is dropped
+pub struct CpuProfiler {
+    guard: ProfilerGuard<'static>,
+}
+
+impl CpuProfiler {
+    //Start sampling the process at the frequency in hertz. Only one profiler can run in a process at a time
+    pub fn start(frequency: i32) -> Result<Self, ProfileError> {
+        let guard = ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            //Unwinding through these libraries from the signal handler can deadlock, so samples taken in them are skipped
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+
+        Ok(CpuProfiler { guard })
+    }
+
+    //Write the samples collected so far to the path as a flamegraph svg
+    pub fn write_flamegraph(&self, path: impl AsRef<Path>) -> Result<(), ProfileError> {
+        let report = self.guard.report().build()?;
+        let file = File::create(path)?;
+        report.flamegraph(file)?;
+        Ok(())
+    }
+}
+
+//Sample the process for the duration, then write the samples to the path as a flamegraph svg
+pub async fn profile_for(
+    duration: Duration,
+    frequency: i32,
+    path: impl AsRef<Path>,
+) -> Result<(), ProfileError> {
+    let profiler = CpuProfiler::start(frequency)?;
+    tokio::time::sleep(duration).await;
+    profiler.write_flamegraph(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::profile::{profile_for, DEFAULT_PROFILE_FREQUENCY};
+
+    #[tokio::test]
+    async fn test_profile_for() {
+        //Keep a thread busy while sampling, so that there are samples to render
+        let busy = Arc::new(AtomicBool::new(true));
+        let busy_thread = {
+            let busy = busy.clone();
+            std::thread::spawn(move || {
+                let mut x = 0_u64;
+                while busy.load(Ordering::Relaxed) {
+                    x = std::hint::black_box(x.wrapping_mul(31).wrapping_add(1));
+                }
+            })
+        };
+
+        let profile_path = std::env::temp_dir().join(format!(
+            "bid_ask_service_profile_{}.svg",
+            std::process::id()
+        ));
+        profile_for(
+            Duration::from_millis(500),
+            DEFAULT_PROFILE_FREQUENCY,
+            &profile_path,
+        )
+        .await
+        .expect("Could not profile");
+
+        busy.store(false, Ordering::Relaxed);
+        busy_thread.join().expect("Busy thread panicked");
+
+        let flamegraph = std::fs::read_to_string(&profile_path).expect("Could not read profile");
+        std::fs::remove_file(&profile_path).ok();
+
+        assert!(!flamegraph.is_empty());
+        assert!(flamegraph.contains("<svg"));
+    }
+}
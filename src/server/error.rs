@@ -2,4 +2,15 @@
 pub enum ServerError {
     #[error("Transport error")]
     TransportError(#[from] tonic::transport::Error),
+    #[error("Invalid socket address {address:?}, expected an ip address and port, ie. [::1]:50051 or 127.0.0.1:50051")]
+    InvalidSocketAddress {
+        address: String,
+        source: std::net::AddrParseError,
+    },
+    #[error("Ws server error")]
+    WsServerError(#[source] std::io::Error),
+    #[error("Recorder error")]
+    RecorderError(#[source] std::io::Error),
+    #[error("Metrics server error")]
+    MetricsServerError(#[source] hyper::Error),
 }
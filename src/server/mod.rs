@@ -1,12 +1,27 @@
+//tonic::Status is large, but is the error type expected by the generated gRPC service traits
+#![allow(clippy::result_large_err)]
+
 pub mod error;
+pub mod recorder;
+pub mod status;
+#[cfg(feature = "ws")]
+pub mod ws;
 
 use futures::Stream;
 use futures::StreamExt;
-use orderbook_service::{Empty, Summary};
+use orderbook_service::{
+    BookSummaryRequest, Empty, ExchangeFeedQuality, ExchangeId, ExchangeStatus, FeedQualityReport,
+    ServingStatus, StatusReport, Summary,
+};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use self::error::ServerError;
+use self::status::ServiceStatus;
 use crate::error::BidAskServiceError;
+use crate::exchanges::feed_quality::FeedQuality;
+use crate::exchanges::{Exchange, ParseExchangeError};
 use std::pin::Pin;
 use tokio::sync::broadcast::{Receiver, Sender};
 use tokio::task::JoinHandle;
@@ -19,6 +34,51 @@ pub mod orderbook_service {
     tonic::include_proto!("orderbookservice");
 }
 
+//Version of the summary schema set on each published summary, so that clients can detect a server with fields they do not understand.
+//Bump the version whenever a field is added to the summary or the meaning of an existing field changes
+pub const SUMMARY_SCHEMA_VERSION: u32 = 6;
+
+//Map each exchange to its id in the proto, which is stable so that clients can match on the id rather than the exchange name
+impl From<Exchange> for ExchangeId {
+    fn from(exchange: Exchange) -> Self {
+        match exchange {
+            Exchange::Bitstamp => ExchangeId::Bitstamp,
+            Exchange::Binance => ExchangeId::Binance,
+            Exchange::Kraken => ExchangeId::Kraken,
+            Exchange::Bybit => ExchangeId::Bybit,
+            Exchange::Okx => ExchangeId::Okx,
+            Exchange::Gemini => ExchangeId::Gemini,
+        }
+    }
+}
+
+//Map an exchange id back to its exchange, failing for an unspecified id
+impl TryFrom<ExchangeId> for Exchange {
+    type Error = ParseExchangeError;
+
+    fn try_from(exchange_id: ExchangeId) -> Result<Self, Self::Error> {
+        match exchange_id {
+            ExchangeId::Bitstamp => Ok(Exchange::Bitstamp),
+            ExchangeId::Binance => Ok(Exchange::Binance),
+            ExchangeId::Kraken => Ok(Exchange::Kraken),
+            ExchangeId::Bybit => Ok(Exchange::Bybit),
+            ExchangeId::Okx => Ok(Exchange::Okx),
+            ExchangeId::Gemini => Ok(Exchange::Gemini),
+            ExchangeId::Unspecified => Err(ParseExchangeError::UnrecognizedExchange),
+        }
+    }
+}
+
+//Parse the socket address for the gRPC server, so that an invalid address is reported before the service starts
+pub fn parse_socket_address(socket_address: &str) -> Result<SocketAddr, ServerError> {
+    socket_address
+        .parse()
+        .map_err(|source| ServerError::InvalidSocketAddress {
+            address: socket_address.to_owned(),
+            source,
+        })
+}
+
 pub fn spawn_grpc_server(
     router: Router,
     socket_address: SocketAddr,
@@ -34,17 +94,94 @@ pub fn spawn_grpc_server(
 
 #[derive(Debug)]
 pub struct OrderbookAggregatorService {
-    summary_rx: Receiver<Summary>,
+    //Summary receivers for each pair served, keyed by the parsed pair so that tickers are never merged, ie. eth/btc and et/hbtc stay separate
+    summary_rxs: HashMap<[String; 2], Receiver<Summary>>,
+    //Update anomaly counters shared with the exchange stream handlers, reported through GetFeedQuality
+    feed_quality: Option<Arc<FeedQuality>>,
+    //Connection and publishing state shared with the aggregated order books, reported through GetStatus
+    status: Option<Arc<ServiceStatus>>,
+    //The max number of levels a client can request on each side of the book, ie. the levels published by the aggregated order books
+    max_levels: Option<usize>,
 }
 
 impl OrderbookAggregatorService {
-    pub fn new(summary_buffer: usize) -> (Self, Sender<Summary>) {
+    pub fn new(pair: [&str; 2], summary_buffer: usize) -> (Self, Sender<Summary>) {
+        let mut service = OrderbookAggregatorService {
+            summary_rxs: HashMap::new(),
+            feed_quality: None,
+            status: None,
+            max_levels: None,
+        };
+        let summary_tx = service.add_pair(pair, summary_buffer);
+        (service, summary_tx)
+    }
+
+    //Add a pair to the service, returning the sender that the pair's aggregated order book should publish summaries to
+    pub fn add_pair(&mut self, pair: [&str; 2], summary_buffer: usize) -> Sender<Summary> {
         // Create a broadcast channel with a predefined buffer size (summary_buffer).
         // If a receiver is slow and the buffer gets full, the oldest unprocessed message is discarded.
         // If a slow receiver tries to receive this discarded message, it gets a RecvError::Lagged error instead.
         // This error updates the receiver's position to the oldest message still in the buffer.
         let (summary_tx, summary_rx) = tokio::sync::broadcast::channel(summary_buffer);
-        (OrderbookAggregatorService { summary_rx }, summary_tx)
+        self.summary_rxs
+            .insert([pair[0].to_lowercase(), pair[1].to_lowercase()], summary_rx);
+        summary_tx
+    }
+
+    //Report the feed quality counters recorded by the exchange stream handlers through GetFeedQuality
+    pub fn with_feed_quality(mut self, feed_quality: Arc<FeedQuality>) -> Self {
+        self.feed_quality = Some(feed_quality);
+        self
+    }
+
+    //Report the connection and publishing state recorded by the aggregated order books through GetStatus
+    pub fn with_status(mut self, status: Arc<ServiceStatus>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    //Clamp the levels requested through BookSummary to the number of levels published by the aggregated order books
+    pub fn with_max_levels(mut self, max_levels: usize) -> Self {
+        self.max_levels = Some(max_levels);
+        self
+    }
+
+    //Get the number of levels to stream on each side of the book, rejecting a request for zero levels
+    fn requested_levels(&self, levels: Option<u32>) -> Result<Option<usize>, Status> {
+        match levels {
+            Some(0) => Err(Status::invalid_argument(
+                "At least one level must be requested",
+            )),
+            Some(levels) => Ok(Some(
+                self.max_levels.map_or(levels as usize, |max_levels| {
+                    max_levels.min(levels as usize)
+                }),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    //Get the summary receiver for the requested pair. If no pair is requested and the service only serves one pair, that pair is used.
+    fn summary_rx(&self, pair: &str) -> Result<&Receiver<Summary>, Status> {
+        if pair.trim().is_empty() {
+            if self.summary_rxs.len() == 1 {
+                if let Some(summary_rx) = self.summary_rxs.values().next() {
+                    return Ok(summary_rx);
+                }
+            }
+
+            return Err(Status::invalid_argument(
+                "A pair must be specified when the server serves multiple pairs",
+            ));
+        }
+
+        //Separators and casing do not affect which summary stream is selected, ie. "ETH/BTC" and "eth,btc" select the same pair
+        let pair = crate::pair::parse_pair(pair)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        self.summary_rxs
+            .get(&pair)
+            .ok_or_else(|| Status::not_found(format!("Pair {} is not served", pair.join("/"))))
     }
 }
 
@@ -58,15 +195,24 @@ impl orderbook_service::orderbook_aggregator_server::OrderbookAggregator
     //Send a stream receiver to the client that will send the latest summary of the aggregated order book on each update
     async fn book_summary(
         &self,
-        _request: Request<Empty>,
+        request: Request<BookSummaryRequest>,
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
-        tracing::info!("New client connected to book summary stream");
+        let BookSummaryRequest { pair, levels } = request.into_inner();
+        tracing::info!("New client connected to book summary stream for {pair:?}");
 
-        let rx = self.summary_rx.resubscribe();
+        let levels = self.requested_levels(levels)?;
+        let rx = self.summary_rx(&pair)?.resubscribe();
 
+        //Books with fewer levels than requested are streamed as is, rather than padded with empty levels
         let stream =
-            tokio_stream::wrappers::BroadcastStream::new(rx).map(|summary| match summary {
-                Ok(summary) => Ok(summary),
+            tokio_stream::wrappers::BroadcastStream::new(rx).map(move |summary| match summary {
+                Ok(mut summary) => {
+                    if let Some(levels) = levels {
+                        summary.bids.truncate(levels);
+                        summary.asks.truncate(levels);
+                    }
+                    Ok(summary)
+                }
                 Err(e) => match e {
                     BroadcastStreamRecvError::Lagged(_) => {
                         Err(Status::internal("Stream lagged too far behind"))
@@ -76,4 +222,333 @@ impl orderbook_service::orderbook_aggregator_server::OrderbookAggregator
 
         Ok(Response::new(Box::pin(stream)))
     }
+
+    //Send the update anomaly counts recorded for each exchange
+    async fn get_feed_quality(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<FeedQualityReport>, Status> {
+        let feed_quality = self
+            .feed_quality
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Feed quality tracking is not enabled"))?;
+
+        let mut exchanges = feed_quality
+            .all_counts()
+            .into_iter()
+            .map(|(exchange, counts)| ExchangeFeedQuality {
+                exchange: exchange.to_string(),
+                gaps: counts.gaps,
+                resets: counts.resets,
+                duplicates: counts.duplicates,
+                short_snapshots: counts.short_snapshots,
+                dropped_updates: counts.dropped_updates,
+            })
+            .collect::<Vec<_>>();
+        exchanges.sort_by(|a, b| a.exchange.cmp(&b.exchange));
+
+        Ok(Response::new(FeedQualityReport { exchanges }))
+    }
+
+    //Send the connection state of each exchange and when the last summary was published, reporting the service as not serving until an exchange has sent an update
+    async fn get_status(&self, _request: Request<Empty>) -> Result<Response<StatusReport>, Status> {
+        let status = self
+            .status
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Status tracking is not enabled"))?;
+
+        let serving_status = if status.is_serving() {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+
+        let exchanges = status
+            .connections()
+            .into_iter()
+            .map(|(pair, exchange, connected)| ExchangeStatus {
+                exchange: exchange.to_string(),
+                exchange_id: ExchangeId::from(exchange).into(),
+                pair,
+                connected,
+            })
+            .collect();
+
+        Ok(Response::new(StatusReport {
+            status: serving_status.into(),
+            exchanges,
+            last_published_at: status.last_published_at(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tonic::{Code, Request};
+
+    use crate::{
+        exchanges::Exchange,
+        server::{
+            error::ServerError,
+            orderbook_service::{
+                orderbook_aggregator_server::OrderbookAggregator, BookSummaryRequest, ExchangeId,
+                Level, Summary,
+            },
+            parse_socket_address, OrderbookAggregatorService,
+        },
+    };
+
+    #[test]
+    fn test_exchange_id() {
+        //Every exchange maps to a distinct id that maps back to the exchange
+        let mut exchange_ids = vec![];
+        for exchange in Exchange::all_exchanges() {
+            let exchange_id = ExchangeId::from(exchange.clone());
+            assert_ne!(exchange_id, ExchangeId::Unspecified);
+            assert!(!exchange_ids.contains(&exchange_id));
+            assert_eq!(Exchange::try_from(exchange_id).ok(), Some(exchange));
+            exchange_ids.push(exchange_id);
+        }
+
+        //The numeric ids are part of the proto, so they must not change
+        assert_eq!(ExchangeId::from(Exchange::Bitstamp) as i32, 1);
+        assert_eq!(ExchangeId::from(Exchange::Binance) as i32, 2);
+        assert_eq!(ExchangeId::from(Exchange::Kraken) as i32, 3);
+        assert_eq!(ExchangeId::from(Exchange::Bybit) as i32, 4);
+        assert_eq!(ExchangeId::from(Exchange::Okx) as i32, 5);
+        assert_eq!(ExchangeId::from(Exchange::Gemini) as i32, 6);
+        assert!(Exchange::try_from(ExchangeId::Unspecified).is_err());
+    }
+
+    #[test]
+    fn test_parse_socket_address() {
+        assert_eq!(
+            parse_socket_address("[::1]:50051")
+                .expect("Could not parse socket address")
+                .port(),
+            50051
+        );
+
+        //A missing port or host should be rejected with the address in the error
+        for invalid_address in ["[::1]", "localhost:50051", "127.0.0.1:port", ""] {
+            let err = parse_socket_address(invalid_address).expect_err("Parsed invalid address");
+            assert!(matches!(
+                &err,
+                ServerError::InvalidSocketAddress { address, .. } if address == invalid_address
+            ));
+            assert!(err.to_string().contains("expected an ip address and port"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book_summary_pair_selection() {
+        let (mut service, eth_btc_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+        let eth_usdt_tx = service.add_pair(["eth", "usdt"], 10);
+
+        let mut eth_btc_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "eth,btc".to_owned(),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe to eth,btc")
+            .into_inner();
+
+        //The pair should be matched regardless of separator or casing
+        let mut eth_usdt_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "ETH/USDT".to_owned(),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe to eth,usdt")
+            .into_inner();
+
+        eth_btc_tx
+            .send(Summary {
+                spread: Some(1.0),
+                ..Default::default()
+            })
+            .expect("Could not send eth,btc summary");
+        eth_usdt_tx
+            .send(Summary {
+                spread: Some(2.0),
+                ..Default::default()
+            })
+            .expect("Could not send eth,usdt summary");
+
+        let eth_btc_summary = eth_btc_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Could not receive eth,btc summary");
+        let eth_usdt_summary = eth_usdt_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Could not receive eth,usdt summary");
+
+        assert_eq!(eth_btc_summary.spread, Some(1.0));
+        assert_eq!(eth_usdt_summary.spread, Some(2.0));
+
+        //Unknown pairs should be rejected
+        match service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "xyz,abc".to_owned(),
+                ..Default::default()
+            }))
+            .await
+        {
+            Ok(_) => panic!("Expected unknown pair to be rejected"),
+            Err(status) => assert_eq!(status.code(), Code::NotFound),
+        }
+
+        //With multiple pairs served, the pair must be specified
+        match service
+            .book_summary(Request::new(BookSummaryRequest::default()))
+            .await
+        {
+            Ok(_) => panic!("Expected missing pair to be rejected"),
+            Err(status) => assert_eq!(status.code(), Code::InvalidArgument),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book_summary_pairs_do_not_collide() {
+        //Pairs whose tickers concatenate to the same string should be served as separate streams
+        let (mut service, eth_btc_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+        let et_hbtc_tx = service.add_pair(["et", "hbtc"], 10);
+
+        let mut eth_btc_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "eth,btc".to_owned(),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe to eth,btc")
+            .into_inner();
+
+        let mut et_hbtc_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "et,hbtc".to_owned(),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe to et,hbtc")
+            .into_inner();
+
+        eth_btc_tx
+            .send(Summary {
+                spread: Some(1.0),
+                ..Default::default()
+            })
+            .expect("Could not send eth,btc summary");
+        et_hbtc_tx
+            .send(Summary {
+                spread: Some(2.0),
+                ..Default::default()
+            })
+            .expect("Could not send et,hbtc summary");
+
+        let eth_btc_summary = eth_btc_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Could not receive eth,btc summary");
+        let et_hbtc_summary = et_hbtc_stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("Could not receive et,hbtc summary");
+
+        assert_eq!(eth_btc_summary.spread, Some(1.0));
+        assert_eq!(et_hbtc_summary.spread, Some(2.0));
+
+        //A pair without a separator is ambiguous and should be rejected
+        match service
+            .book_summary(Request::new(BookSummaryRequest {
+                pair: "ethbtc".to_owned(),
+                ..Default::default()
+            }))
+            .await
+        {
+            Ok(_) => panic!("Expected pair without a separator to be rejected"),
+            Err(status) => assert_eq!(status.code(), Code::InvalidArgument),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_book_summary_levels() {
+        let (service, summary_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+        let service = service.with_max_levels(5);
+
+        let mut three_level_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                levels: Some(3),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe with 3 levels")
+            .into_inner();
+
+        //Requests beyond the published levels are clamped to the max levels
+        let mut clamped_stream = service
+            .book_summary(Request::new(BookSummaryRequest {
+                levels: Some(100),
+                ..Default::default()
+            }))
+            .await
+            .expect("Could not subscribe with 100 levels")
+            .into_inner();
+
+        let level = |price| Level {
+            exchange: "binance".to_owned(),
+            price,
+            amount: 1.0,
+            age_ms: 0,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            summary_tx
+                .send(Summary {
+                    bids: (0..5).map(|i| level(100.0 - i as f64)).collect(),
+                    asks: (0..5).map(|i| level(101.0 + i as f64)).collect(),
+                    ..Default::default()
+                })
+                .expect("Could not send summary");
+        }
+
+        for _ in 0..2 {
+            let summary = three_level_stream
+                .next()
+                .await
+                .expect("Stream ended")
+                .expect("Could not receive summary");
+            assert_eq!(summary.bids.len(), 3);
+            assert_eq!(summary.asks.len(), 3);
+            assert_eq!(summary.bids[0].price, 100.0);
+            assert_eq!(summary.asks[0].price, 101.0);
+
+            let summary = clamped_stream
+                .next()
+                .await
+                .expect("Stream ended")
+                .expect("Could not receive summary");
+            assert_eq!(summary.bids.len(), 5);
+            assert_eq!(summary.asks.len(), 5);
+        }
+
+        //Zero levels should be rejected
+        match service
+            .book_summary(Request::new(BookSummaryRequest {
+                levels: Some(0),
+                ..Default::default()
+            }))
+            .await
+        {
+            Ok(_) => panic!("Expected zero levels to be rejected"),
+            Err(status) => assert_eq!(status.code(), Code::InvalidArgument),
+        }
+    }
 }
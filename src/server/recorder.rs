@@ -0,0 +1,215 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_derive::Serialize;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::TrySendError},
+    },
+    task::JoinHandle,
+};
+
+use super::{
+    error::ServerError,
+    orderbook_service::{Level, Summary},
+};
+use crate::error::BidAskServiceError;
+
+//Number of recorded rows buffered between the recorder and the file writer before rows are dropped
+pub const RECORDER_BUFFER: usize = 1024;
+//Interval that buffered rows are flushed to the file at, so that a crashed service loses at most this much of the recording
+pub const RECORDER_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// A summary recorded as a line of JSON, ie. {"timestamp":1690000000000000,"as_of":null,"spread":0.5,"bids":[...],"asks":[...]}
+#[derive(Serialize)]
+struct RecordedSummary<'a> {
+    //Microseconds since the unix epoch that the summary was received by the recorder at
+    timestamp: u64,
+    as_of: Option<u64>,
+    spread: Option<f64>,
+    bids: &'a [Level],
+    asks: &'a [Level],
+}
+
+//Spawns a task that appends each published summary to the file at the path as newline delimited JSON, for offline analysis and backtesting.
+//Rows are written by a separate task through a bounded buffer, so a slow disk drops rows with a warning rather than lagging the recorder behind the summaries.
+//Heartbeat summaries repeat the last summary, so they are not recorded
+pub fn spawn_recorder(
+    mut summary_rx: broadcast::Receiver<Summary>,
+    path: impl AsRef<Path>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    let path = path.as_ref().to_owned();
+    tokio::spawn(async move {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(ServerError::RecorderError)?;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(ServerError::RecorderError)?;
+
+        let (row_tx, row_rx) = mpsc::channel(RECORDER_BUFFER);
+        let writer_handle = tokio::spawn(write_rows(file, row_rx));
+
+        let mut dropped = 0_u64;
+        loop {
+            match summary_rx.recv().await {
+                Ok(summary) if summary.heartbeat => {}
+
+                Ok(summary) => {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |since_epoch| since_epoch.as_micros() as u64);
+                    let row = match serde_json::to_string(&RecordedSummary {
+                        timestamp,
+                        as_of: summary.as_of,
+                        spread: summary.spread,
+                        bids: &summary.bids,
+                        asks: &summary.asks,
+                    }) {
+                        Ok(row) => row,
+                        Err(err) => {
+                            tracing::error!("Could not serialize recorded summary: {err}");
+                            continue;
+                        }
+                    };
+
+                    match row_tx.try_send(row) {
+                        Ok(_) => {}
+                        Err(TrySendError::Full(_)) => {
+                            dropped += 1;
+                            tracing::warn!(
+                                "Recorder buffer is full, dropped {dropped} summaries in total"
+                            );
+                        }
+                        //The writer only stops after failing to write, which is returned below
+                        Err(TrySendError::Closed(_)) => break,
+                    }
+                }
+
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Recorder lagged, skipped {skipped} summaries");
+                }
+
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        //Close the buffer so that the writer flushes the remaining rows and exits
+        drop(row_tx);
+        writer_handle.await?
+    })
+}
+
+//Write each row to the file as a line, flushing on an interval rather than after every row and flushing the remaining rows once the buffer is closed
+async fn write_rows(
+    file: File,
+    mut row_rx: mpsc::Receiver<String>,
+) -> Result<(), BidAskServiceError> {
+    let mut writer = BufWriter::new(file);
+    let mut flush_interval = tokio::time::interval(RECORDER_FLUSH_INTERVAL);
+    flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            row = row_rx.recv() => match row {
+                Some(mut row) => {
+                    row.push('\n');
+                    writer
+                        .write_all(row.as_bytes())
+                        .await
+                        .map_err(ServerError::RecorderError)?;
+                }
+                None => break,
+            },
+
+            _ = flush_interval.tick() => {
+                writer.flush().await.map_err(ServerError::RecorderError)?;
+            }
+        }
+    }
+
+    writer.flush().await.map_err(ServerError::RecorderError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::{
+        orderbook_service::{Level, Summary},
+        recorder::spawn_recorder,
+    };
+
+    fn summary(spread: f64, bid_price: f64, ask_price: f64) -> Summary {
+        let level = |price: f64| Level {
+            exchange: "binance".to_owned(),
+            price,
+            amount: 1.0,
+            ..Default::default()
+        };
+        Summary {
+            spread: Some(spread),
+            bids: vec![level(bid_price)],
+            asks: vec![level(ask_price)],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recorder() {
+        let path = std::env::temp_dir()
+            .join(format!("recorder_{}", std::process::id()))
+            .join("eth_btc.jsonl");
+
+        let (summary_tx, summary_rx) = tokio::sync::broadcast::channel(10);
+        let recorder_handle = spawn_recorder(summary_rx, &path);
+
+        for (spread, bid_price, ask_price) in [(1.0, 100.0, 101.0), (2.0, 99.0, 101.0)] {
+            summary_tx
+                .send(summary(spread, bid_price, ask_price))
+                .expect("Could not send summary");
+        }
+        summary_tx
+            .send(Summary {
+                heartbeat: true,
+                ..summary(2.0, 99.0, 101.0)
+            })
+            .expect("Could not send heartbeat");
+        drop(summary_tx);
+
+        recorder_handle
+            .await
+            .expect("Join handle error")
+            .expect("Recorder error");
+
+        let recording = std::fs::read_to_string(&path).expect("Could not read recording");
+        std::fs::remove_dir_all(path.parent().expect("No parent")).expect("Could not remove dir");
+
+        //Each summary is recorded as a line of JSON in order, without the heartbeat
+        let rows = recording
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("Invalid row"))
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        for (row, (spread, bid_price, ask_price)) in
+            rows.iter().zip([(1.0, 100.0, 101.0), (2.0, 99.0, 101.0)])
+        {
+            assert!(row["timestamp"]
+                .as_u64()
+                .is_some_and(|timestamp| timestamp > 0));
+            assert_eq!(row["spread"], spread);
+            assert_eq!(row["bids"][0]["price"], bid_price);
+            assert_eq!(row["bids"][0]["exchange"], "binance");
+            assert_eq!(row["asks"][0]["price"], ask_price);
+        }
+    }
+}
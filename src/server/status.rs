@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    events::{ServiceEvent, ServiceEventKind},
+    exchanges::Exchange,
+};
+
+// Tracks whether the service is connected to its exchanges and publishing, so that it can be reported through GetStatus.
+// Shared between the aggregated order books of every pair and the gRPC server
+#[derive(Debug, Default)]
+pub struct ServiceStatus {
+    //Connection state of each exchange of each pair, keyed by the pair as base/quote and the exchange
+    connections: Mutex<HashMap<(String, Exchange), bool>>,
+    //Set once any exchange has sent a price level update to an aggregated order book
+    received_update: AtomicBool,
+    //Microseconds since the unix epoch that the last summary was published at, 0 until a summary is published
+    last_published_at: AtomicU64,
+}
+
+impl ServiceStatus {
+    pub fn new() -> Self {
+        ServiceStatus::default()
+    }
+
+    //Record the connection state of an exchange from its connection events, ignoring events published by the order book itself
+    pub fn record_event(&self, event: &ServiceEvent) {
+        let connected = match event.event {
+            ServiceEventKind::Connected => true,
+            ServiceEventKind::Disconnected => false,
+            _ => return,
+        };
+
+        if let Some(exchange) = &event.exchange {
+            //The lock is only held to set a flag, so a poisoned lock still holds valid connection states
+            self.connections
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert((event.pair.clone(), exchange.clone()), connected);
+        }
+    }
+
+    pub fn record_update(&self) {
+        self.received_update.store(true, Ordering::Relaxed);
+    }
+
+    pub fn record_published(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_micros() as u64);
+        self.last_published_at.store(now, Ordering::Relaxed);
+    }
+
+    //The service is serving once any exchange has sent an update, so that a service that never received data is not reported as healthy
+    pub fn is_serving(&self) -> bool {
+        self.received_update.load(Ordering::Relaxed)
+    }
+
+    pub fn last_published_at(&self) -> Option<u64> {
+        match self.last_published_at.load(Ordering::Relaxed) {
+            0 => None,
+            last_published_at => Some(last_published_at),
+        }
+    }
+
+    //Get the connection state of each exchange that has connected, ordered by pair and exchange
+    pub fn connections(&self) -> Vec<(String, Exchange, bool)> {
+        let mut connections = self
+            .connections
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|((pair, exchange), connected)| (pair.clone(), exchange.clone(), *connected))
+            .collect::<Vec<_>>();
+        connections.sort();
+        connections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        events::{ServiceEvent, ServiceEventKind},
+        exchanges::Exchange,
+        server::status::ServiceStatus,
+    };
+
+    #[test]
+    fn test_service_status() {
+        let status = ServiceStatus::new();
+        assert!(!status.is_serving());
+        assert_eq!(status.last_published_at(), None);
+        assert!(status.connections().is_empty());
+
+        for (event, exchange) in [
+            (ServiceEventKind::Connected, Exchange::Binance),
+            (ServiceEventKind::Connected, Exchange::Bitstamp),
+            (ServiceEventKind::Disconnected, Exchange::Bitstamp),
+            (ServiceEventKind::StaleLevelsEvicted, Exchange::Binance),
+        ] {
+            status.record_event(&ServiceEvent::new(event, Some(exchange), "eth/btc"));
+        }
+        //Events without an exchange are published by the order book, not by a connection
+        status.record_event(&ServiceEvent::new(
+            ServiceEventKind::Disconnected,
+            None,
+            "eth/btc",
+        ));
+
+        assert_eq!(
+            status.connections(),
+            vec![
+                ("eth/btc".to_owned(), Exchange::Bitstamp, false),
+                ("eth/btc".to_owned(), Exchange::Binance, true),
+            ]
+        );
+
+        status.record_update();
+        status.record_published();
+        assert!(status.is_serving());
+        assert!(status.last_published_at().is_some());
+    }
+}
@@ -0,0 +1,91 @@
+use std::net::SocketAddr;
+
+use futures::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast::{error::RecvError, Receiver},
+    task::JoinHandle,
+};
+use tungstenite::Message;
+
+use super::{error::ServerError, orderbook_service::Summary};
+use crate::error::BidAskServiceError;
+
+//Serve the summaries over a websocket server as JSON, as an alternative to the gRPC server for clients that do not speak protobuf.
+//Each client is sent every summary published after it connects, and a client disconnecting does not stop the server
+pub fn spawn_ws_server(
+    summary_rx: Receiver<Summary>,
+    socket_address: SocketAddr,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        let listener = TcpListener::bind(socket_address)
+            .await
+            .map_err(ServerError::WsServerError)?;
+
+        loop {
+            let (stream, client_address) = listener
+                .accept()
+                .await
+                .map_err(ServerError::WsServerError)?;
+            tracing::info!("New client connected to ws summary stream from {client_address}");
+
+            //Each client gets its own receiver, so a slow client only lags itself
+            tokio::spawn(handle_ws_client(stream, summary_rx.resubscribe()));
+        }
+    })
+}
+
+//Push each summary to the client as JSON until the client disconnects or the summary channel is closed
+async fn handle_ws_client(stream: TcpStream, mut summary_rx: Receiver<Summary>) {
+    let mut ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            tracing::warn!("Could not complete ws handshake: {err}");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            summary = summary_rx.recv() => match summary {
+                Ok(summary) => {
+                    let message = match serde_json::to_string(&summary) {
+                        Ok(message) => message,
+                        Err(err) => {
+                            tracing::error!("Could not serialize summary: {err}");
+                            continue;
+                        }
+                    };
+
+                    if let Err(err) = ws_stream.send(Message::Text(message)).await {
+                        tracing::info!("Ws client disconnected: {err}");
+                        break;
+                    }
+                }
+
+                //Skip the summaries that the client missed, sending the latest summaries from here on
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Ws client lagged behind, skipped {skipped} summaries");
+                }
+
+                Err(RecvError::Closed) => {
+                    ws_stream.close(None).await.ok();
+                    break;
+                }
+            },
+
+            //Messages from the client are ignored, it is only read to respond to pings and detect when the client disconnects
+            message = ws_stream.next() => match message {
+                Some(Ok(Message::Close(_))) | None => {
+                    tracing::info!("Ws client disconnected");
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    tracing::info!("Ws client disconnected: {err}");
+                    break;
+                }
+            },
+        }
+    }
+}
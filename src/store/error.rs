@@ -0,0 +1,7 @@
+#[derive(thiserror::Error, Debug)]
+pub enum SummaryStoreError {
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+    #[error("Could not decode summary")]
+    DecodeError(#[from] prost::DecodeError),
+}
@@ -0,0 +1,189 @@
+pub mod error;
+
+use std::path::Path;
+
+use async_trait::async_trait;
+use prost::Message;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::broadcast::{error::RecvError, Receiver},
+    task::JoinHandle,
+};
+
+use crate::{error::BidAskServiceError, server::orderbook_service::Summary};
+
+use self::error::SummaryStoreError;
+
+// A backend that published summaries are archived to, ie. a local file, object storage or a database
+#[async_trait]
+pub trait SummaryStore: Send {
+    async fn append(&mut self, summary: &Summary) -> Result<(), SummaryStoreError>;
+}
+
+// Archives summaries to a local file, with each summary encoded as a length delimited protobuf message
+#[derive(Debug)]
+pub struct FileSummaryStore {
+    file: tokio::fs::File,
+}
+
+impl FileSummaryStore {
+    //Open the file to append summaries to, creating the file and its parent directories if they do not exist
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, SummaryStoreError> {
+        if let Some(parent) = path.as_ref().parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(FileSummaryStore { file })
+    }
+}
+
+#[async_trait]
+impl SummaryStore for FileSummaryStore {
+    async fn append(&mut self, summary: &Summary) -> Result<(), SummaryStoreError> {
+        self.file
+            .write_all(&summary.encode_length_delimited_to_vec())
+            .await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+}
+
+//Decode the summaries archived by a file summary store
+pub fn read_summaries(mut bytes: &[u8]) -> Result<Vec<Summary>, SummaryStoreError> {
+    let mut summaries = vec![];
+    while !bytes.is_empty() {
+        summaries.push(Summary::decode_length_delimited(&mut bytes)?);
+    }
+    Ok(summaries)
+}
+
+//Spawns a task that appends each published summary to the store. Heartbeat summaries repeat the last summary, so they are not archived
+pub fn spawn_summary_archiver(
+    mut store: impl SummaryStore + 'static,
+    mut summary_rx: Receiver<Summary>,
+) -> JoinHandle<Result<(), BidAskServiceError>> {
+    tokio::spawn(async move {
+        loop {
+            match summary_rx.recv().await {
+                Ok(summary) if summary.heartbeat => {}
+
+                Ok(summary) => {
+                    //A failed append should not bring down the service, so errors are only logged
+                    if let Err(e) = store.append(&summary).await {
+                        tracing::error!("Could not append summary to the summary store: {e:?}");
+                    }
+                }
+
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Summary archiver lagged, skipped {skipped} summaries");
+                }
+
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        Ok::<(), BidAskServiceError>(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    use crate::{
+        server::orderbook_service::Summary,
+        store::{
+            error::SummaryStoreError, read_summaries, spawn_summary_archiver, FileSummaryStore,
+            SummaryStore,
+        },
+    };
+
+    #[derive(Debug, Default, Clone)]
+    struct InMemorySummaryStore {
+        summaries: Arc<Mutex<Vec<Summary>>>,
+    }
+
+    #[async_trait]
+    impl SummaryStore for InMemorySummaryStore {
+        async fn append(&mut self, summary: &Summary) -> Result<(), SummaryStoreError> {
+            self.summaries
+                .lock()
+                .expect("Could not lock summaries")
+                .push(summary.clone());
+            Ok(())
+        }
+    }
+
+    fn summary(spread: f64) -> Summary {
+        Summary {
+            spread: Some(spread),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_archiver() {
+        let store = InMemorySummaryStore::default();
+        let (summary_tx, summary_rx) = tokio::sync::broadcast::channel(10);
+        let archiver_handle = spawn_summary_archiver(store.clone(), summary_rx);
+
+        for spread in [1.0, 2.0, 3.0] {
+            summary_tx
+                .send(summary(spread))
+                .expect("Could not send summary");
+        }
+        summary_tx
+            .send(Summary {
+                heartbeat: true,
+                ..summary(3.0)
+            })
+            .expect("Could not send heartbeat");
+        drop(summary_tx);
+
+        archiver_handle
+            .await
+            .expect("Join handle error")
+            .expect("Archiver error");
+
+        //Every published summary should be appended in order, without the heartbeat
+        assert_eq!(
+            *store.summaries.lock().expect("Could not lock summaries"),
+            vec![summary(1.0), summary(2.0), summary(3.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_summary_store() {
+        let path = std::env::temp_dir()
+            .join(format!("summary_store_{}", std::process::id()))
+            .join("eth_btc.summaries");
+
+        let mut store = FileSummaryStore::open(&path)
+            .await
+            .expect("Could not open summary store");
+        store.append(&summary(1.0)).await.expect("Could not append");
+        store.append(&summary(2.0)).await.expect("Could not append");
+
+        //Reopening the store appends to the existing summaries
+        let mut store = FileSummaryStore::open(&path)
+            .await
+            .expect("Could not open summary store");
+        store.append(&summary(3.0)).await.expect("Could not append");
+
+        let bytes = std::fs::read(&path).expect("Could not read summary store");
+        std::fs::remove_dir_all(path.parent().expect("No parent")).expect("Could not remove dir");
+
+        assert_eq!(
+            read_summaries(&bytes).expect("Could not read summaries"),
+            vec![summary(1.0), summary(2.0), summary(3.0)]
+        );
+    }
+}
@@ -12,13 +12,14 @@ use bid_ask_service::{
     error::BidAskServiceError,
     exchanges::Exchange,
     order_book::{
+        builder::AggregatedOrderBookBuilder,
         price_level::{ask::Ask, bid::Bid},
         AggregatedOrderBook,
     },
     server::{
         self, orderbook_service::orderbook_aggregator_client::OrderbookAggregatorClient,
         orderbook_service::orderbook_aggregator_server::OrderbookAggregatorServer,
-        orderbook_service::Empty, spawn_grpc_server,
+        orderbook_service::BookSummaryRequest, spawn_grpc_server,
     },
 };
 use futures::FutureExt;
@@ -68,11 +69,7 @@ async fn test_bid_ask_service() {
 fn spawn_bid_ask_service(
     server_address: String,
 ) -> Vec<JoinHandle<Result<(), BidAskServiceError>>> {
-    let order_book_depth = 25;
     let summary_buffer = 100;
-    let order_book_stream_buffer = 100;
-    let price_level_channel_buffer = 100;
-    let best_n_orders = 10;
 
     let socket_address = server_address
         .parse::<SocketAddr>()
@@ -80,7 +77,7 @@ fn spawn_bid_ask_service(
 
     //Create a new orderbook aggregator service and build the gRPC server
     let (order_book_aggregator_service, summary_tx) =
-        server::OrderbookAggregatorService::new(summary_buffer);
+        server::OrderbookAggregatorService::new(["eth", "btc"], summary_buffer);
     let router = Server::builder().add_service(OrderbookAggregatorServer::new(
         order_book_aggregator_service,
     ));
@@ -95,13 +92,9 @@ fn spawn_bid_ask_service(
 
     //Spawn the bid ask service from the orderbook and the gRPC server
     let mut join_handles = vec![];
-    join_handles.extend(aggregated_order_book.spawn_bid_ask_service(
-        order_book_depth,
-        order_book_stream_buffer,
-        price_level_channel_buffer,
-        best_n_orders,
-        summary_tx,
-    ));
+    //The default depth, buffers and best n orders of the cli
+    join_handles
+        .extend(AggregatedOrderBookBuilder::new().spawn(&aggregated_order_book, summary_tx));
 
     join_handles.push(spawn_grpc_server(router, socket_address));
 
@@ -125,7 +118,10 @@ async fn spawn_client(
 
         // call the BookSummary endpoint
         let mut stream = client
-            .book_summary(tonic::Request::new(Empty {}))
+            .book_summary(tonic::Request::new(BookSummaryRequest {
+                pair: "eth,btc".to_owned(),
+                ..Default::default()
+            }))
             .await
             .expect("could not make request")
             .into_inner();
@@ -0,0 +1,278 @@
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use bid_ask_service::{
+    exchanges::{mock::MockExchange, Exchange},
+    order_book::{
+        builder::AggregatedOrderBookBuilder,
+        price_level::{ask::Ask, bid::Bid, PriceLevelUpdate},
+        AggregatedOrderBook,
+    },
+    server::{
+        orderbook_service::{
+            orderbook_aggregator_server::OrderbookAggregator, BookSummaryRequest, Empty,
+            ServingStatus, Summary,
+        },
+        status::ServiceStatus,
+        OrderbookAggregatorService,
+    },
+};
+use futures::StreamExt;
+use tonic::Request;
+
+//Time is paused, so each mock exchange's updates are replayed at exact intervals and the order of updates across exchanges is deterministic
+#[tokio::test(start_paused = true)]
+async fn test_mock_exchanges() {
+    let binance = MockExchange::from_updates(
+        vec![
+            PriceLevelUpdate::snapshot(
+                Exchange::Binance,
+                vec![
+                    Bid::new(100.0, 1.0, Exchange::Binance),
+                    Bid::new(99.0, 2.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(101.0, 1.0, Exchange::Binance),
+                    Ask::new(102.0, 2.0, Exchange::Binance),
+                ],
+            ),
+            PriceLevelUpdate::new(
+                Exchange::Binance,
+                vec![Bid::new(100.5, 1.5, Exchange::Binance)],
+                vec![],
+            ),
+        ],
+        Duration::from_millis(10),
+    );
+    let bitstamp = MockExchange::from_updates(
+        vec![PriceLevelUpdate::snapshot(
+            Exchange::Bitstamp,
+            vec![Bid::new(100.2, 3.0, Exchange::Bitstamp)],
+            vec![
+                Ask::new(100.8, 1.0, Exchange::Bitstamp),
+                Ask::new(103.0, 1.0, Exchange::Bitstamp),
+            ],
+        )],
+        Duration::from_millis(15),
+    );
+
+    let aggregated_order_book = AggregatedOrderBook::new(
+        ["eth", "btc"],
+        vec![Exchange::Binance, Exchange::Bitstamp],
+        BTreeSet::<Bid>::new(),
+        BTreeSet::<Ask>::new(),
+    )
+    .with_order_book_service(Exchange::Binance, binance)
+    .with_order_book_service(Exchange::Bitstamp, bitstamp);
+
+    let (summary_tx, mut summary_rx) = tokio::sync::broadcast::channel(10);
+    let _handles = mock_builder().spawn(&aggregated_order_book, summary_tx);
+
+    //The warming summary is published before any update is received
+    let warming_summary = summary_rx.recv().await.expect("No summary received");
+    assert!(warming_summary.bids.is_empty() && warming_summary.asks.is_empty());
+
+    //Binance's snapshot at 10ms, Bitstamp's snapshot at 15ms, then Binance's update at 20ms
+    let expected = [
+        (
+            vec![(100.0, "binance"), (99.0, "binance")],
+            vec![(101.0, "binance"), (102.0, "binance")],
+        ),
+        (
+            vec![(100.2, "bitstamp"), (100.0, "binance"), (99.0, "binance")],
+            vec![(100.8, "bitstamp"), (101.0, "binance"), (102.0, "binance")],
+        ),
+        (
+            vec![(100.5, "binance"), (100.2, "bitstamp"), (100.0, "binance")],
+            vec![(100.8, "bitstamp"), (101.0, "binance"), (102.0, "binance")],
+        ),
+    ];
+
+    for (expected_bids, expected_asks) in expected {
+        let summary = summary_rx.recv().await.expect("No summary received");
+        assert_eq!(levels(&summary, true), expected_bids);
+        assert_eq!(levels(&summary, false), expected_asks);
+        assert_eq!(
+            summary.spread,
+            Some(expected_asks[0].0 - expected_bids[0].0)
+        );
+    }
+
+    //Every update has been replayed, so no further summaries are published
+    assert!(
+        tokio::time::timeout(Duration::from_secs(1), summary_rx.recv())
+            .await
+            .is_err()
+    );
+}
+
+//Each pair is aggregated by its own order book and streamed to clients that select it, without summaries leaking between pairs
+#[tokio::test(start_paused = true)]
+async fn test_multiple_pairs() {
+    let (mut service, eth_btc_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+    let eth_usdt_tx = service.add_pair(["eth", "usdt"], 10);
+
+    let mut eth_btc_stream = book_summary_stream(&service, "eth,btc").await;
+    let mut eth_usdt_stream = book_summary_stream(&service, "eth,usdt").await;
+
+    let mut handles = vec![];
+    for (pair, summary_tx, mid_price) in [
+        (["eth", "btc"], eth_btc_tx, 0.06),
+        (["eth", "usdt"], eth_usdt_tx, 1800.0),
+    ] {
+        let binance = MockExchange::from_updates(
+            vec![PriceLevelUpdate::snapshot(
+                Exchange::Binance,
+                vec![
+                    Bid::new(mid_price - 0.01, 1.0, Exchange::Binance),
+                    Bid::new(mid_price - 0.02, 1.0, Exchange::Binance),
+                ],
+                vec![
+                    Ask::new(mid_price + 0.01, 1.0, Exchange::Binance),
+                    Ask::new(mid_price + 0.02, 1.0, Exchange::Binance),
+                ],
+            )],
+            Duration::from_millis(10),
+        );
+
+        let aggregated_order_book = AggregatedOrderBook::new(
+            pair,
+            vec![Exchange::Binance],
+            BTreeSet::<Bid>::new(),
+            BTreeSet::<Ask>::new(),
+        )
+        .with_order_book_service(Exchange::Binance, binance);
+
+        handles.push(mock_builder().spawn(&aggregated_order_book, summary_tx));
+    }
+
+    for (stream, mid_price) in [(&mut eth_btc_stream, 0.06), (&mut eth_usdt_stream, 1800.0)] {
+        //The warming summary is published before the snapshot is received
+        let warming_summary = stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("No summary received");
+        assert!(warming_summary.bids.is_empty() && warming_summary.asks.is_empty());
+
+        let summary = stream
+            .next()
+            .await
+            .expect("Stream ended")
+            .expect("No summary received");
+        assert_eq!(summary.bids[0].price, mid_price - 0.01);
+        assert_eq!(summary.asks[0].price, mid_price + 0.01);
+    }
+
+    //Each pair's snapshot has been published once, so neither stream receives a further summary
+    for stream in [&mut eth_btc_stream, &mut eth_usdt_stream] {
+        assert!(tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .is_err());
+    }
+}
+
+//The status reports the service as not serving until an exchange sends an update, then reports each exchange's connection and the last publish
+#[tokio::test(start_paused = true)]
+async fn test_status() {
+    let status = Arc::new(ServiceStatus::new());
+    let (service, summary_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+    let service = service.with_status(status.clone());
+
+    let binance = MockExchange::from_updates(
+        vec![PriceLevelUpdate::snapshot(
+            Exchange::Binance,
+            vec![
+                Bid::new(100.0, 1.0, Exchange::Binance),
+                Bid::new(99.0, 1.0, Exchange::Binance),
+            ],
+            vec![
+                Ask::new(101.0, 1.0, Exchange::Binance),
+                Ask::new(102.0, 1.0, Exchange::Binance),
+            ],
+        )],
+        Duration::from_millis(10),
+    );
+    let aggregated_order_book = AggregatedOrderBook::new(
+        ["eth", "btc"],
+        vec![Exchange::Binance],
+        BTreeSet::<Bid>::new(),
+        BTreeSet::<Ask>::new(),
+    )
+    .with_order_book_service(Exchange::Binance, binance)
+    .with_status(status);
+
+    let mut stream = book_summary_stream(&service, "eth,btc").await;
+    let _handles = mock_builder().spawn(&aggregated_order_book, summary_tx);
+
+    //Only the warming summary has been published, since the snapshot is replayed after 10ms
+    stream
+        .next()
+        .await
+        .expect("Stream ended")
+        .expect("No summary received");
+    let report = service
+        .get_status(Request::new(Empty {}))
+        .await
+        .expect("Could not get status")
+        .into_inner();
+    assert_eq!(report.status(), ServingStatus::NotServing);
+    assert_eq!(report.last_published_at, None);
+
+    stream
+        .next()
+        .await
+        .expect("Stream ended")
+        .expect("No summary received");
+    let report = service
+        .get_status(Request::new(Empty {}))
+        .await
+        .expect("Could not get status")
+        .into_inner();
+    assert_eq!(report.status(), ServingStatus::Serving);
+    assert!(report.last_published_at.is_some());
+    assert_eq!(report.exchanges.len(), 1);
+    assert_eq!(report.exchanges[0].exchange, "binance");
+    assert_eq!(report.exchanges[0].pair, "eth/btc");
+    assert!(report.exchanges[0].connected);
+
+    //A service without status tracking reports that it is unavailable
+    let (service, _summary_tx) = OrderbookAggregatorService::new(["eth", "btc"], 10);
+    let status = service
+        .get_status(Request::new(Empty {}))
+        .await
+        .expect_err("Got status without status tracking");
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+}
+
+//Configure a small book with the best 3 levels, which is enough for the few levels replayed by the mock exchanges
+fn mock_builder() -> AggregatedOrderBookBuilder {
+    AggregatedOrderBookBuilder::new()
+        .depth(10)
+        .stream_buffer(10)
+        .price_level_buffer(10)
+        .best_n(3)
+}
+
+//Subscribe to the summaries of a pair through the BookSummary RPC
+async fn book_summary_stream(
+    service: &OrderbookAggregatorService,
+    pair: &str,
+) -> <OrderbookAggregatorService as OrderbookAggregator>::BookSummaryStream {
+    service
+        .book_summary(Request::new(BookSummaryRequest {
+            pair: pair.to_owned(),
+            ..Default::default()
+        }))
+        .await
+        .expect("Could not subscribe to pair")
+        .into_inner()
+}
+
+//Get the price and exchange of each bid or ask in the summary
+fn levels(summary: &Summary, bids: bool) -> Vec<(f64, &str)> {
+    let levels = if bids { &summary.bids } else { &summary.asks };
+    levels
+        .iter()
+        .map(|level| (level.price, level.exchange.as_str()))
+        .collect()
+}
@@ -0,0 +1,91 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bid_ask_service::server::{
+    orderbook_service::{Level, Summary},
+    ws::spawn_ws_server,
+    SUMMARY_SCHEMA_VERSION,
+};
+use futures::StreamExt;
+use tokio::time;
+use tungstenite::Message;
+
+#[tokio::test]
+async fn test_ws_server() {
+    //Bind to a free port, then release it for the ws server
+    let socket_address = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Could not find a free port");
+
+    let (summary_tx, summary_rx) = tokio::sync::broadcast::channel::<Summary>(10);
+    let _server_handle = spawn_ws_server(summary_rx, socket_address);
+
+    //Connect two clients, so that each client is checked to receive every summary
+    let mut first_client = connect(socket_address).await;
+    let mut second_client = connect(socket_address).await;
+
+    //A client that disconnects should not stop the server from pushing summaries to the other clients
+    let mut disconnected_client = connect(socket_address).await;
+    disconnected_client.close(None).await.ok();
+    drop(disconnected_client);
+
+    //Publish summaries until both clients have received one, since the clients subscribe once their connection is accepted
+    let summary = Summary {
+        spread: Some(0.5),
+        bids: vec![Level {
+            exchange: "binance".to_owned(),
+            price: 100.0,
+            amount: 1.5,
+            age_ms: 0,
+            ..Default::default()
+        }],
+        asks: vec![Level {
+            exchange: "bitstamp".to_owned(),
+            price: 100.5,
+            amount: 2.0,
+            age_ms: 0,
+            ..Default::default()
+        }],
+        schema_version: SUMMARY_SCHEMA_VERSION,
+        ..Default::default()
+    };
+    let publisher = tokio::spawn(async move {
+        loop {
+            summary_tx.send(summary.clone()).ok();
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    });
+
+    for client in [&mut first_client, &mut second_client] {
+        let message = time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .expect("No summary received")
+            .expect("Ws stream closed")
+            .expect("Ws error");
+
+        let Message::Text(message) = message else {
+            panic!("Unexpected message: {message:?}");
+        };
+        let summary: serde_json::Value =
+            serde_json::from_str(&message).expect("Summary is not valid JSON");
+        assert_eq!(summary["spread"], 0.5);
+        assert_eq!(summary["bids"][0]["exchange"], "binance");
+        assert_eq!(summary["bids"][0]["price"], 100.0);
+        assert_eq!(summary["asks"][0]["amount"], 2.0);
+        assert_eq!(summary["schema_version"], SUMMARY_SCHEMA_VERSION);
+    }
+
+    publisher.abort();
+}
+
+//Connect a ws client to the server, retrying until the server is listening
+async fn connect(
+    socket_address: SocketAddr,
+) -> tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
+    for _ in 0..50 {
+        match tokio_tungstenite::connect_async(format!("ws://{socket_address}")).await {
+            Ok((ws_stream, _)) => return ws_stream,
+            Err(_) => time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+    panic!("Could not connect to the ws server");
+}